@@ -0,0 +1,81 @@
+//! Fill and drain a flow, against the sorted `Vec` flows used to be kept
+//! in: every arrival re-sorted the packets and every pop shifted them all
+//! to the front, so a trace took quadratic time.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rnetv::scheduling::{
+    flow::{Flow, VariableLengthFlow},
+    Packet,
+};
+
+/// Packets per trace. The sorted `Vec` only runs the smaller traces,
+/// the largest would take minutes per sample.
+const TRACE_SIZES: [usize; 3] = [1_000, 10_000, 1_000_000];
+
+/// Largest trace given to the sorted `Vec`.
+const SORTED_VEC_LIMIT: usize = 10_000;
+
+/// The packets of a flow as they used to be kept.
+#[derive(Default)]
+struct SortedVecFlow {
+    packet_states: Vec<(Packet, usize)>,
+}
+
+impl SortedVecFlow {
+    fn packet_arrive(&mut self, packet: Packet, time: usize) {
+        self.packet_states.push((packet, time));
+        self.packet_states.sort_by_key(|a| a.1);
+    }
+
+    fn peek_packet(&self, time: usize) -> Option<&Packet> {
+        self.packet_states
+            .first()
+            .filter(|(_, arrive_time)| *arrive_time <= time)
+            .map(|(packet, _)| packet)
+    }
+
+    fn pop_packet(&mut self) -> Packet {
+        self.packet_states.remove(0).0
+    }
+}
+
+fn bench_flows(c: &mut Criterion) {
+    let mut group = c.benchmark_group("flows");
+    group.sample_size(10);
+    for size in TRACE_SIZES {
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::new("vec_deque", size), &size, |b, &size| {
+            b.iter(|| {
+                let mut flow = VariableLengthFlow::new();
+                for t in 0..size {
+                    flow.packet_arrive(Packet::new("p", 1), t);
+                }
+                for t in 0..size {
+                    assert!(flow.peek_packet(t).is_some());
+                    flow.pop_packet();
+                }
+                flow
+            })
+        });
+        if size > SORTED_VEC_LIMIT {
+            continue;
+        }
+        group.bench_with_input(BenchmarkId::new("sorted_vec", size), &size, |b, &size| {
+            b.iter(|| {
+                let mut flow = SortedVecFlow::default();
+                for t in 0..size {
+                    flow.packet_arrive(Packet::new("p", 1), t);
+                }
+                for t in 0..size {
+                    assert!(flow.peek_packet(t).is_some());
+                    flow.pop_packet();
+                }
+                flow
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_flows);
+criterion_main!(benches);
@@ -0,0 +1,162 @@
+//! Compare the scheduling disciplines on synthetic workloads.
+//!
+//! Every discipline runs the same Poisson arrivals of Internet-mix
+//! packets, split across flows of different weights, on a link loaded
+//! to about 85%. Timings are reported in simulated ticks per second,
+//! and the heap allocations of one run are printed next to them, with
+//! the worst Jain fairness index over windows of the run.
+//!
+//! New disciplines are compared by adding them to [`SCHEDULERS`].
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rnetv::scheduling::{
+    flow::VariableLengthFlow,
+    schedulers::{drr::DRRScheduler, qfq::QFQScheduler, wfq::WFQScheduler, wrr::WRRScheduler},
+    traffic::{PoissonSource, SizeDistribution, TrafficSource},
+    Scheduler,
+};
+
+/// The system allocator, counting the allocations made through it.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Total number of packets of a workload.
+const WORKLOAD_SIZES: [usize; 3] = [10_000, 100_000, 1_000_000];
+
+/// Link rate in bytes per tick, a packet takes from 1 to 15 ticks.
+const BANDWIDTH: usize = 100;
+
+/// Relative weights of the flows of a workload.
+const WEIGHTS: [usize; 4] = [1, 2, 3, 4];
+
+/// Mean packets per tick of each flow, which loads the link to about 85%
+/// with Internet-mix packets, taking 3.8 ticks on average.
+const FLOW_RATE: f64 = 0.055;
+
+/// Ticks per window of the fairness report.
+const FAIRNESS_WINDOW: usize = 1000;
+
+/// A discipline under comparison: its name, how to build it on a link of
+/// the given bandwidth, and the unit its weights are counted in.
+struct Entry {
+    name: &'static str,
+    build: fn(usize) -> Box<dyn Scheduler>,
+    weight_unit: usize,
+}
+
+/// The disciplines compared.
+const SCHEDULERS: [Entry; 4] = [
+    Entry {
+        name: "wfq",
+        build: |bandwidth| Box::new(WFQScheduler::new(bandwidth)),
+        weight_unit: 1,
+    },
+    Entry {
+        name: "qfq",
+        build: |bandwidth| Box::new(QFQScheduler::new(bandwidth)),
+        weight_unit: 1,
+    },
+    // DRR weights are quanta in bytes, at least a full-size packet.
+    Entry {
+        name: "drr",
+        build: |bandwidth| Box::new(DRRScheduler::new(bandwidth)),
+        weight_unit: 1500,
+    },
+    Entry {
+        name: "wrr",
+        build: |bandwidth| Box::new(WRRScheduler::new(bandwidth)),
+        weight_unit: 1,
+    },
+];
+
+/// The flows of a workload of `size` packets in total.
+fn workload(size: usize) -> Vec<VariableLengthFlow> {
+    (0..WEIGHTS.len())
+        .map(|idx| {
+            PoissonSource::new(FLOW_RATE, SizeDistribution::internet_mix())
+                .with_seed(idx as u64)
+                .flow_of(size / WEIGHTS.len())
+        })
+        .collect()
+}
+
+fn build(entry: &Entry, flows: &[VariableLengthFlow]) -> Box<dyn Scheduler> {
+    let mut scheduler = (entry.build)(BANDWIDTH);
+    for (flow, weight) in flows.iter().zip(WEIGHTS) {
+        scheduler.add_flow(Box::new(flow.clone()), (weight * entry.weight_unit) as f64);
+    }
+    scheduler
+}
+
+fn bench_schedulers(c: &mut Criterion) {
+    for size in WORKLOAD_SIZES {
+        let flows = workload(size);
+        let mut group = c.benchmark_group(format!("schedulers/{}", size));
+        group.sample_size(10);
+        for entry in &SCHEDULERS {
+            // One run up front gives the ticks to report a rate in,
+            // and the allocations and fairness of a run.
+            let mut scheduler = build(entry, &flows);
+            let before = ALLOCATIONS.load(Ordering::Relaxed);
+            scheduler.run();
+            let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+            let weights = WEIGHTS.map(|w| w as f64);
+            let fairness = scheduler
+                .scheduler_stats()
+                .fairness(&weights, FAIRNESS_WINDOW);
+            println!(
+                "{}/{}: {} ticks, {} allocations, worst jain index {:.4}",
+                entry.name,
+                size,
+                scheduler.timer(),
+                allocations,
+                fairness
+                    .windows
+                    .iter()
+                    .map(|w| w.jain_index)
+                    .fold(1f64, f64::min)
+            );
+
+            group.throughput(Throughput::Elements(scheduler.timer() as u64));
+            group.bench_function(BenchmarkId::from_parameter(entry.name), |b| {
+                b.iter_batched(
+                    || build(entry, &flows),
+                    |mut scheduler| {
+                        scheduler.run();
+                        scheduler
+                    },
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_schedulers);
+criterion_main!(benches);
@@ -0,0 +1,493 @@
+//! Command-line interface running scenario files, so that the simulator
+//! can be used without writing Rust.
+//!
+//! See [`USAGE`] for the commands. Errors are printed to stderr, and the
+//! exit code is 2 for a wrong command line and 1 for a failed run.
+
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use rnetv::scheduling::{
+    config::{Scenario, SchedulerConfig},
+    stats::{fairness::jain_index, fct::FctReport, FlowStats},
+    viz, FlowId, Scheduler,
+};
+
+pub const USAGE: &str = "\
+usage:
+  rnetv run <scenario> [-o <dir>]
+      run a scenario file and write its trace, statistics,
+      result and timeline to <dir>, rnetv-out by default
+  rnetv compare <scenario> [<scheduler>...] [-o <dir>]
+      run a scenario with every scheduler given, all by default,
+      and compare them, writing the comparison to <dir> if given
+  rnetv validate <scenario>...
+      check that scenario files load and build
+  rnetv view [<scheduler>]
+      show a small demo in the terminal, with the tui feature";
+
+/// Default output directory of `run`.
+const DEFAULT_OUTPUT: &str = "rnetv-out";
+
+/// A parsed command line.
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    Run {
+        scenario: PathBuf,
+        output: PathBuf,
+    },
+    Compare {
+        scenario: PathBuf,
+        schedulers: Vec<SchedulerConfig>,
+        output: Option<PathBuf>,
+    },
+    Validate {
+        scenarios: Vec<PathBuf>,
+    },
+    View {
+        scheduler: String,
+    },
+}
+
+/// Run the command line, without the program name.
+/// Returns the exit code.
+pub fn main(args: &[String]) -> i32 {
+    let command = match parse(args) {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            return 2;
+        }
+    };
+    let mut stdout = io::stdout().lock();
+    match execute(command, &mut stdout) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            1
+        }
+    }
+}
+
+fn parse(args: &[String]) -> Result<Command, String> {
+    let (name, rest) = args.split_first().ok_or("no command given")?;
+
+    // Split off the output directory, the only option.
+    let mut output = None;
+    let mut positional = Vec::new();
+    let mut rest = rest.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "-o" | "--output" => {
+                let dir = rest.next().ok_or(format!("{} needs a directory", arg))?;
+                output = Some(PathBuf::from(dir));
+            }
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {}", arg)),
+            _ => positional.push(arg.as_str()),
+        }
+    }
+    if output.is_some() && !matches!(name.as_str(), "run" | "compare") {
+        return Err(format!("{} takes no output directory", name));
+    }
+
+    match name.as_str() {
+        "run" => match positional[..] {
+            [scenario] => Ok(Command::Run {
+                scenario: scenario.into(),
+                output: output.unwrap_or_else(|| DEFAULT_OUTPUT.into()),
+            }),
+            _ => Err("run takes one scenario".to_string()),
+        },
+        "compare" => {
+            let (scenario, names) = positional.split_first().ok_or("compare needs a scenario")?;
+            let schedulers = if names.is_empty() {
+                SchedulerConfig::ALL.to_vec()
+            } else {
+                names
+                    .iter()
+                    .map(|name| name.parse().map_err(|e: io::Error| e.to_string()))
+                    .collect::<Result<_, _>>()?
+            };
+            Ok(Command::Compare {
+                scenario: scenario.into(),
+                schedulers,
+                output,
+            })
+        }
+        "validate" if !positional.is_empty() => Ok(Command::Validate {
+            scenarios: positional.iter().map(PathBuf::from).collect(),
+        }),
+        "validate" => Err("validate needs at least one scenario".to_string()),
+        "view" => match positional[..] {
+            [] => Ok(Command::View {
+                scheduler: "drr".to_string(),
+            }),
+            [scheduler] => Ok(Command::View {
+                scheduler: scheduler.to_string(),
+            }),
+            _ => Err("view takes at most one scheduler".to_string()),
+        },
+        _ => Err(format!("unknown command {}", name)),
+    }
+}
+
+/// Execute a command, reporting to `out`.
+/// Returns false if it failed in a way already reported.
+fn execute(command: Command, out: &mut impl io::Write) -> io::Result<bool> {
+    match command {
+        Command::Run { scenario, output } => {
+            let scenario = Scenario::load(&scenario)?;
+            let scheduler = scenario.run()?;
+            write_outputs(&scenario, scheduler.as_ref(), &output)?;
+            write!(out, "{}", stats_table(&scenario, scheduler.as_ref()))?;
+            writeln!(out, "outputs written to {}", output.display())?;
+            Ok(true)
+        }
+        Command::Compare {
+            scenario,
+            schedulers,
+            output,
+        } => {
+            let scenario = Scenario::load(&scenario)?;
+            let table = compare(&scenario, &schedulers)?;
+            write!(out, "{}", table)?;
+            if let Some(dir) = output {
+                fs::create_dir_all(&dir)?;
+                fs::write(dir.join("compare.txt"), &table)?;
+            }
+            Ok(true)
+        }
+        Command::Validate { scenarios } => {
+            let mut valid = true;
+            for path in scenarios {
+                match Scenario::load(&path).and_then(|s| s.build().map(|_| s)) {
+                    Ok(scenario) => writeln!(
+                        out,
+                        "{}: ok, {} flows on {}",
+                        path.display(),
+                        scenario.flows.len(),
+                        scenario.scheduler
+                    )?,
+                    Err(e) => {
+                        writeln!(out, "{}: {}", path.display(), e)?;
+                        valid = false;
+                    }
+                }
+            }
+            Ok(valid)
+        }
+        Command::View { scheduler } => view(&scheduler),
+    }
+}
+
+#[cfg(feature = "tui")]
+fn view(name: &str) -> io::Result<bool> {
+    match crate::view::demo(name) {
+        Some(app) => crate::view::run(app).map(|()| true),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "unknown scheduler {}, expected one of: {}",
+                name,
+                crate::view::DEMO_SCHEDULERS.join(", ")
+            ),
+        )),
+    }
+}
+
+#[cfg(not(feature = "tui"))]
+fn view(_name: &str) -> io::Result<bool> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "rnetv was built without the tui feature",
+    ))
+}
+
+/// Write the trace, the statistics, the result and the timeline
+/// of a run to a directory.
+fn write_outputs(scenario: &Scenario, scheduler: &dyn Scheduler, dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let trace = scheduler.trace();
+    trace.write_csv(io::BufWriter::new(fs::File::create(dir.join("trace.csv"))?))?;
+    fs::write(dir.join("stats.csv"), stats_csv(scenario, scheduler))?;
+    serde_json::to_writer(
+        io::BufWriter::new(fs::File::create(dir.join("result.json"))?),
+        &scheduler.result(),
+    )
+    .map_err(io::Error::from)?;
+    viz::render_timeline(&trace, dir.join("timeline.svg"))
+}
+
+/// The statistics of every flow and of all of them, as CSV.
+/// The slowdown is left out for flows that did not complete,
+/// and averaged over the completed flows on the last line.
+fn stats_csv(scenario: &Scenario, scheduler: &dyn Scheduler) -> String {
+    let stats = scheduler.scheduler_stats();
+    let fct = FctReport::compute(&stats, scenario.bandwidth);
+    let mut csv = String::from(
+        "flow,weight,packets,bytes,mean_delay,p99_delay,max_delay,jitter,throughput,completion_time,slowdown,dropped\n",
+    );
+    let mut row = |flow: &str, weight: String, s: &FlowStats, slowdown: String| {
+        writeln!(
+            csv,
+            "{},{},{},{},{},{},{},{},{},{},{},{}",
+            flow,
+            weight,
+            s.packets,
+            s.bytes,
+            s.mean_delay,
+            s.p99_delay,
+            s.max_delay,
+            s.jitter,
+            s.throughput,
+            s.completion_time,
+            slowdown,
+            s.dropped
+        )
+        .unwrap();
+    };
+    for (idx, (flow, spec)) in stats.flows.iter().zip(&scenario.flows).enumerate() {
+        let slowdown = fct.flow(FlowId(idx)).map(|f| f.slowdown.to_string());
+        row(
+            &idx.to_string(),
+            spec.weight.to_string(),
+            flow,
+            slowdown.unwrap_or_default(),
+        );
+    }
+    row(
+        "all",
+        String::new(),
+        &stats.aggregate,
+        fct.mean_slowdown.to_string(),
+    );
+    csv
+}
+
+/// The statistics of every flow and of all of them, as a table,
+/// followed by the flow completion times.
+fn stats_table(scenario: &Scenario, scheduler: &dyn Scheduler) -> String {
+    let stats = scheduler.scheduler_stats();
+    let fct = FctReport::compute(&stats, scenario.bandwidth);
+    let mut table = format!(
+        "{:<6}{:>8}{:>9}{:>12}{:>11}{:>12}{:>7}{:>10}{:>9}\n",
+        "flow",
+        "weight",
+        "packets",
+        "mean delay",
+        "p99 delay",
+        "throughput",
+        "fct",
+        "slowdown",
+        "dropped"
+    );
+    let mut row = |flow: &str, weight: String, s: &FlowStats, slowdown: Option<f64>| {
+        let slowdown = slowdown.map_or("-".to_string(), |s| format!("{:.3}", s));
+        writeln!(
+            table,
+            "{:<6}{:>8}{:>9}{:>12.3}{:>11}{:>12.3}{:>7}{:>10}{:>9}",
+            flow,
+            weight,
+            s.packets,
+            s.mean_delay,
+            s.p99_delay,
+            s.throughput,
+            s.completion_time,
+            slowdown,
+            s.dropped
+        )
+        .unwrap();
+    };
+    for (idx, (flow, spec)) in stats.flows.iter().zip(&scenario.flows).enumerate() {
+        let slowdown = fct.flow(FlowId(idx)).map(|f| f.slowdown);
+        row(&idx.to_string(), spec.weight.to_string(), flow, slowdown);
+    }
+    row(
+        "all",
+        String::new(),
+        &stats.aggregate,
+        Some(fct.mean_slowdown).filter(|_| !fct.flows.is_empty()),
+    );
+    writeln!(
+        table,
+        "fct of {} completed flows: mean {:.3}, median {}, p95 {}, p99 {}, max {}",
+        fct.flows.len(),
+        fct.mean,
+        fct.median,
+        fct.p95,
+        fct.p99,
+        fct.max
+    )
+    .unwrap();
+    table
+}
+
+/// Run the scenario with each scheduler and tabulate their statistics.
+/// The tie break of the scenario is kept for the schedulers taking one.
+fn compare(scenario: &Scenario, schedulers: &[SchedulerConfig]) -> io::Result<String> {
+    let mut table = format!(
+        "{:<15}{:>9}{:>12}{:>11}{:>11}{:>10}{:>10}{:>9}\n",
+        "scheduler",
+        "packets",
+        "mean delay",
+        "p99 delay",
+        "max delay",
+        "mean fct",
+        "fairness",
+        "dropped"
+    );
+    let weights: Vec<f64> = scenario.flows.iter().map(|f| f.weight).collect();
+    for &kind in schedulers {
+        let mut scenario = scenario.clone();
+        scenario.scheduler = kind;
+        scenario.tie_break = scenario.tie_break.filter(|_| kind.takes_tie_break());
+        let mut scheduler = scenario.build()?;
+        scheduler.run();
+
+        let stats = scheduler.scheduler_stats();
+        let normalized: Vec<f64> = stats
+            .flows
+            .iter()
+            .zip(&weights)
+            .filter(|(flow, _)| flow.packets > 0)
+            .map(|(flow, weight)| flow.throughput / weight)
+            .collect();
+        let all = &stats.aggregate;
+        writeln!(
+            table,
+            "{:<15}{:>9}{:>12.3}{:>11}{:>11}{:>10.3}{:>10.3}{:>9}",
+            kind.name(),
+            all.packets,
+            all.mean_delay,
+            all.p99_delay,
+            all.max_delay,
+            FctReport::compute(&stats, scenario.bandwidth).mean,
+            jain_index(&normalized),
+            all.dropped
+        )
+        .unwrap();
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::PathBuf};
+
+    use rnetv::scheduling::config::SchedulerConfig;
+
+    use super::{execute, parse, Command};
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn cli_parse_test() {
+        assert_eq!(
+            parse(&args("run s.toml")),
+            Ok(Command::Run {
+                scenario: "s.toml".into(),
+                output: "rnetv-out".into()
+            })
+        );
+        assert_eq!(
+            parse(&args("compare s.yaml wfq drr -o out")),
+            Ok(Command::Compare {
+                scenario: "s.yaml".into(),
+                schedulers: vec![SchedulerConfig::Wfq, SchedulerConfig::Drr],
+                output: Some("out".into())
+            })
+        );
+        match parse(&args("compare s.toml")).unwrap() {
+            Command::Compare { schedulers, .. } => assert_eq!(schedulers.len(), 18),
+            command => panic!("parsed as {:?}", command),
+        }
+
+        assert!(parse(&[]).is_err());
+        assert!(parse(&args("run")).is_err());
+        assert!(parse(&args("run a.toml b.toml")).is_err());
+        assert!(parse(&args("run a.toml -o")).is_err());
+        assert!(parse(&args("compare s.toml magic")).is_err());
+        assert!(parse(&args("validate")).is_err());
+        assert!(parse(&args("validate s.toml -o out")).is_err());
+        assert!(parse(&args("launch s.toml")).is_err());
+    }
+
+    #[test]
+    fn cli_run_test() {
+        let dir = std::env::temp_dir().join(format!("rnetv-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let scenario = dir.join("scenario.toml");
+        fs::write(
+            &scenario,
+            "bandwidth = 1\n\
+             scheduler = \"wfq\"\n\
+             tie_break = \"RoundRobin\"\n\
+             [[flows]]\n\
+             weight = 2\n\
+             count = 6\n\
+             source = { cbr = { interval = 1, len = 1 } }\n\
+             [[flows]]\n\
+             count = 6\n\
+             source = { cbr = { interval = 1, len = 1 } }\n",
+        )
+        .unwrap();
+        let broken = dir.join("broken.toml");
+        fs::write(
+            &broken,
+            "bandwidth = 1\nscheduler = \"magic\"\nflows = []\n",
+        )
+        .unwrap();
+
+        let output = dir.join("out");
+        let mut out = Vec::new();
+        let run = Command::Run {
+            scenario: scenario.clone(),
+            output: output.clone(),
+        };
+        assert!(execute(run, &mut out).unwrap());
+        let report = String::from_utf8(out).unwrap();
+        assert!(report
+            .lines()
+            .any(|l| l.starts_with("all") && l.contains("12")));
+        assert!(report.contains("fct of 2 completed flows"));
+        for file in ["trace.csv", "stats.csv", "result.json", "timeline.svg"] {
+            assert!(output.join(file).exists(), "{} was not written", file);
+        }
+        let stats = fs::read_to_string(output.join("stats.csv")).unwrap();
+        assert_eq!(stats.lines().count(), 4);
+
+        // Schedulers without a tie break are compared too.
+        let mut out = Vec::new();
+        let compare = Command::Compare {
+            scenario: scenario.clone(),
+            schedulers: vec![SchedulerConfig::Wfq, SchedulerConfig::Fifo],
+            output: Some(output.clone()),
+        };
+        assert!(execute(compare, &mut out).unwrap());
+        let table = String::from_utf8(out).unwrap();
+        assert_eq!(table.lines().count(), 3);
+        assert_eq!(
+            fs::read_to_string(output.join("compare.txt")).unwrap(),
+            table
+        );
+
+        let mut out = Vec::new();
+        let validate = Command::Validate {
+            scenarios: vec![scenario.clone(), broken, PathBuf::from("missing.toml")],
+        };
+        assert!(!execute(validate, &mut out).unwrap());
+        let report = String::from_utf8(out).unwrap();
+        assert!(report.starts_with(&format!("{}: ok, 2 flows on wfq\n", scenario.display())));
+        assert!(report.contains("unknown variant `magic`"));
+        assert!(report.contains("missing.toml: "));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,4 @@
+//! Packet scheduling simulator: flows, scheduling disciplines, ports
+//! and the statistics of their runs.
+
+pub mod scheduling;
@@ -1,46 +1,31 @@
-use std::{
-    io::{self},
-    thread,
-    time::Duration,
-};
+#[cfg(feature = "cli")]
+mod cli;
+#[cfg(feature = "tui")]
+mod view;
 
-use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture},
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-};
-use tui::{
-    backend::CrosstermBackend,
-    widgets::{Block, Borders},
-    Terminal,
-};
-
-#[allow(unused)]
-mod scheduling;
-
-fn main() -> Result<(), io::Error> {
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
-
-    terminal.draw(|f| {
-        let size = f.size();
-        let block = Block::default().title("Block").borders(Borders::ALL);
-        f.render_widget(block, size);
-    })?;
-
-    thread::sleep(Duration::from_millis(5000));
+#[cfg(feature = "cli")]
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    std::process::exit(cli::main(&args));
+}
 
-    // restore terminal
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
+#[cfg(all(feature = "tui", not(feature = "cli")))]
+fn main() -> std::io::Result<()> {
+    let name = std::env::args().nth(1).unwrap_or_else(|| "drr".to_string());
+    match view::demo(&name) {
+        Some(app) => view::run(app),
+        None => {
+            eprintln!(
+                "unknown scheduler {}, expected one of: {}",
+                name,
+                view::DEMO_SCHEDULERS.join(", ")
+            );
+            std::process::exit(2);
+        }
+    }
+}
 
-    Ok(())
+#[cfg(not(any(feature = "tui", feature = "cli")))]
+fn main() {
+    eprintln!("rnetv was built without the tui feature");
 }
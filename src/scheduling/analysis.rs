@@ -0,0 +1,366 @@
+//! Network calculus: bounds on the delay and the backlog of a flow,
+//! from an arrival curve bounding what it sends and a service curve
+//! bounding what it is served.
+//!
+//! Curves are piecewise linear, in bytes over ticks. An arrival curve
+//! `a` bounds the bytes a flow sends in any window of `t` ticks by `a(t)`,
+//! such as the [`Curve::token_bucket`] of a shaped flow. A service curve
+//! `b` guarantees that the bytes served by `t` are at least those arrived
+//! by some `s` plus `b(t - s)`, such as the [`Curve::rate_latency`] of a
+//! link or of a flow of a fair scheduler. Servers in tandem offer the
+//! min-plus convolution of their service curves, see [`Curve::convolve`].
+
+/// Tolerance on the times and values of breakpoints.
+const EPSILON: f64 = 1e-9;
+
+/// A non-decreasing piecewise-linear function of time, from 0.
+///
+/// The value at 0 is the limit from the right, so that the burst of a
+/// token bucket is its value at 0 even though no bytes arrive in an empty
+/// window.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Curve {
+    /// Breakpoints as times and values, in time order from time 0.
+    points: Vec<(f64, f64)>,
+    /// Slope after the last breakpoint.
+    tail_rate: f64,
+}
+
+impl Curve {
+    /// The curve through the breakpoints, then growing at `tail_rate`.
+    pub fn new(points: Vec<(f64, f64)>, tail_rate: f64) -> Curve {
+        assert!(
+            points.first().is_some_and(|&(t, _)| t == 0f64),
+            "a curve starts at time 0"
+        );
+        assert!(
+            points
+                .windows(2)
+                .all(|w| w[0].0 < w[1].0 && w[0].1 <= w[1].1),
+            "a curve is non-decreasing, with increasing breakpoint times"
+        );
+        assert!(tail_rate >= 0f64, "a curve is non-decreasing");
+        Curve { points, tail_rate }.simplified()
+    }
+
+    /// The arrival curve of a flow shaped by a token bucket:
+    /// at most `burst` bytes at once, then `rate` bytes per tick.
+    pub fn token_bucket(rate: f64, burst: f64) -> Curve {
+        Curve::new(vec![(0f64, burst)], rate)
+    }
+
+    /// The service curve of a server sending at least `rate` bytes per
+    /// tick to a backlogged flow, after a delay of at most `latency`.
+    pub fn rate_latency(rate: f64, latency: f64) -> Curve {
+        if latency == 0f64 {
+            Curve::new(vec![(0f64, 0f64)], rate)
+        } else {
+            Curve::new(vec![(0f64, 0f64), (latency, 0f64)], rate)
+        }
+    }
+
+    /// The value of the curve at `time`.
+    pub fn at(&self, time: f64) -> f64 {
+        let after = self.points.partition_point(|&(t, _)| t <= time);
+        let (t0, v0) = self.points[after.max(1) - 1];
+        match self.points.get(after) {
+            Some(&(t1, v1)) => v0 + (v1 - v0) * (time - t0) / (t1 - t0),
+            None => v0 + self.tail_rate * (time - t0),
+        }
+    }
+
+    /// The first time the curve reaches `value`, infinite if never.
+    pub fn inverse(&self, value: f64) -> f64 {
+        if self.points[0].1 >= value {
+            return 0f64;
+        }
+        for w in self.points.windows(2) {
+            let ((t0, v0), (t1, v1)) = (w[0], w[1]);
+            if v1 >= value {
+                return t0 + (t1 - t0) * (value - v0) / (v1 - v0);
+            }
+        }
+        let (t, v) = *self.points.last().unwrap();
+        if self.tail_rate > 0f64 {
+            t + (value - v) / self.tail_rate
+        } else {
+            f64::INFINITY
+        }
+    }
+
+    /// The long-term rate of the curve.
+    pub fn tail_rate(&self) -> f64 {
+        self.tail_rate
+    }
+
+    pub fn points(&self) -> &[(f64, f64)] {
+        &self.points
+    }
+
+    /// Whether the slopes never increase, as for arrival curves.
+    pub fn is_concave(&self) -> bool {
+        self.slopes().windows(2).all(|w| w[1] <= w[0] + EPSILON)
+    }
+
+    /// Whether the curve starts at 0 and its slopes never decrease,
+    /// as for service curves.
+    pub fn is_convex(&self) -> bool {
+        self.points[0].1 == 0f64 && self.slopes().windows(2).all(|w| w[0] <= w[1] + EPSILON)
+    }
+
+    /// The pointwise sum, such as the arrival curve of an aggregate of flows.
+    pub fn add(&self, other: &Curve) -> Curve {
+        let points = self
+            .breakpoints(other)
+            .into_iter()
+            .map(|t| (t, self.at(t) + other.at(t)))
+            .collect();
+        Curve::new(points, self.tail_rate + other.tail_rate)
+    }
+
+    /// The pointwise minimum, such as the arrival curve of a flow
+    /// shaped by several token buckets.
+    pub fn min(&self, other: &Curve) -> Curve {
+        let times = self.breakpoints(other);
+        let mut points = Vec::with_capacity(times.len());
+        for (idx, &t) in times.iter().enumerate() {
+            points.push((t, self.at(t).min(other.at(t))));
+            // Add where the curves cross before the next breakpoint.
+            let d0 = self.at(t) - other.at(t);
+            let cross = match times.get(idx + 1) {
+                Some(&next) => {
+                    let d1 = self.at(next) - other.at(next);
+                    (d0 * d1 < 0f64).then(|| t + (next - t) * d0 / (d0 - d1))
+                }
+                None => {
+                    let slope = self.tail_rate - other.tail_rate;
+                    (d0 * slope < 0f64).then(|| t - d0 / slope)
+                }
+            };
+            if let Some(cross) = cross {
+                points.push((cross, self.at(cross)));
+            }
+        }
+        Curve::new(points, self.tail_rate.min(other.tail_rate))
+    }
+
+    /// The min-plus convolution `(f * g)(t) = inf_{0 <= s <= t} f(s) + g(t - s)`,
+    /// of two convex curves, such as the service curves of servers in
+    /// tandem, or of two concave ones, such as arrival curves.
+    pub fn convolve(&self, other: &Curve) -> Curve {
+        if self.is_concave() && other.is_concave() {
+            // Concave curves are 0 at 0 itself, so the infimum is at an end.
+            return self.min(other);
+        }
+        assert!(
+            self.is_convex() && other.is_convex(),
+            "only convex or concave curves can be convolved"
+        );
+
+        // The segments of both curves by increasing slope,
+        // up to the smaller long-term rate.
+        let tail_rate = self.tail_rate.min(other.tail_rate);
+        let mut segments: Vec<(f64, f64)> = self
+            .segments()
+            .chain(other.segments())
+            .filter(|&(_, slope)| slope < tail_rate)
+            .collect();
+        segments.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let mut points = vec![(0f64, 0f64)];
+        let (mut t, mut v) = (0f64, 0f64);
+        for (duration, slope) in segments {
+            t += duration;
+            v += duration * slope;
+            points.push((t, v));
+        }
+        Curve::new(points, tail_rate)
+    }
+
+    /// The slopes of the segments between breakpoints, then of the tail.
+    fn slopes(&self) -> Vec<f64> {
+        self.segments()
+            .map(|(_, slope)| slope)
+            .chain([self.tail_rate])
+            .collect()
+    }
+
+    /// The finite segments as durations and slopes.
+    fn segments(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.points
+            .windows(2)
+            .map(|w| (w[1].0 - w[0].0, (w[1].1 - w[0].1) / (w[1].0 - w[0].0)))
+    }
+
+    /// The breakpoint times of both curves, in order.
+    fn breakpoints(&self, other: &Curve) -> Vec<f64> {
+        let mut times: Vec<f64> = self
+            .points
+            .iter()
+            .chain(&other.points)
+            .map(|&(t, _)| t)
+            .collect();
+        times.sort_by(f64::total_cmp);
+        times.dedup_by(|a, b| (*a - *b).abs() < EPSILON);
+        times
+    }
+
+    /// Drop the breakpoints in the middle of a straight line.
+    fn simplified(mut self) -> Curve {
+        let slope = |(t0, v0): (f64, f64), (t1, v1): (f64, f64)| (v1 - v0) / (t1 - t0);
+        let mut points: Vec<(f64, f64)> = Vec::with_capacity(self.points.len());
+        for &point in &self.points {
+            if let [.., before, middle] = points[..] {
+                if (slope(before, middle) - slope(middle, point)).abs() < EPSILON {
+                    points.pop();
+                }
+            }
+            points.push(point);
+        }
+        if let [.., before, last] = points[..] {
+            if (slope(before, last) - self.tail_rate).abs() < EPSILON {
+                points.pop();
+            }
+        }
+        self.points = points;
+        self
+    }
+}
+
+/// The largest delay of a flow with the arrival curve through a server
+/// with the service curve: their largest horizontal distance.
+/// Infinite if the flow sends faster than it is served in the long run.
+pub fn delay_bound(arrival: &Curve, service: &Curve) -> f64 {
+    if arrival.tail_rate > service.tail_rate + EPSILON {
+        return f64::INFINITY;
+    }
+    // The distance is linear between these times.
+    let times = arrival
+        .points
+        .iter()
+        .map(|&(t, _)| t)
+        .chain(service.points.iter().map(|&(_, v)| arrival.inverse(v)))
+        .filter(|t| t.is_finite());
+    times
+        .map(|t| service.inverse(arrival.at(t)) - t)
+        .fold(0f64, f64::max)
+}
+
+/// The largest backlog of a flow with the arrival curve at a server
+/// with the service curve: their largest vertical distance.
+/// Infinite if the flow sends faster than it is served in the long run.
+pub fn backlog_bound(arrival: &Curve, service: &Curve) -> f64 {
+    if arrival.tail_rate > service.tail_rate + EPSILON {
+        return f64::INFINITY;
+    }
+    arrival
+        .breakpoints(service)
+        .into_iter()
+        .map(|t| arrival.at(t) - service.at(t))
+        .fold(0f64, f64::max)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        schedulers::fifo::FIFOScheduler,
+        Packet, Scheduler,
+    };
+
+    use super::{backlog_bound, delay_bound, Curve};
+
+    #[test]
+    fn curve_test() {
+        let bucket = Curve::token_bucket(2f64, 10f64);
+        assert_eq!(bucket.at(0f64), 10f64);
+        assert_eq!(bucket.at(3f64), 16f64);
+        assert_eq!(bucket.inverse(14f64), 2f64);
+        assert!(bucket.is_concave() && !bucket.is_convex());
+
+        let server = Curve::rate_latency(4f64, 3f64);
+        assert_eq!(server.at(2f64), 0f64);
+        assert_eq!(server.at(5f64), 8f64);
+        assert_eq!(server.inverse(8f64), 5f64);
+        assert!(server.is_convex() && !server.is_concave());
+
+        // A peak rate of 5 up to 20 bytes, then 2 bytes per tick.
+        let shaped = Curve::token_bucket(5f64, 0f64).min(&Curve::token_bucket(2f64, 12f64));
+        assert_eq!(shaped.points(), &[(0f64, 0f64), (4f64, 20f64)]);
+        assert_eq!(shaped.tail_rate(), 2f64);
+        assert!(shaped.is_concave());
+
+        let both = bucket.add(&Curve::token_bucket(1f64, 5f64));
+        assert_eq!(both.at(0f64), 15f64);
+        assert_eq!(both.at(2f64), 21f64);
+    }
+
+    #[test]
+    fn convolution_test() {
+        // Rate-latency servers in tandem add their latencies
+        // and serve at the slower rate.
+        let tandem = Curve::rate_latency(4f64, 3f64).convolve(&Curve::rate_latency(6f64, 1f64));
+        assert_eq!(tandem, Curve::rate_latency(4f64, 4f64));
+
+        let stepped = Curve::new(vec![(0f64, 0f64), (2f64, 2f64)], 8f64);
+        let convolved = stepped.convolve(&Curve::rate_latency(4f64, 1f64));
+        assert_eq!(
+            convolved.points(),
+            &[(0f64, 0f64), (1f64, 0f64), (3f64, 2f64)]
+        );
+        assert_eq!(convolved.tail_rate(), 4f64);
+
+        let buckets = Curve::token_bucket(2f64, 10f64).convolve(&Curve::token_bucket(4f64, 2f64));
+        assert_eq!(buckets.at(0f64), 2f64);
+        assert_eq!(buckets.at(10f64), 30f64);
+    }
+
+    #[test]
+    fn bounds_test() {
+        // The classical bounds: delay T + b / R, backlog b + r T.
+        let arrival = Curve::token_bucket(2f64, 10f64);
+        let service = Curve::rate_latency(4f64, 3f64);
+        assert_eq!(delay_bound(&arrival, &service), 3f64 + 10f64 / 4f64);
+        assert_eq!(backlog_bound(&arrival, &service), 10f64 + 2f64 * 3f64);
+
+        // Paying the burst once over a tandem beats adding the delays.
+        let second = Curve::rate_latency(3f64, 2f64);
+        let end_to_end = delay_bound(&arrival, &service.convolve(&second));
+        assert_eq!(end_to_end, 5f64 + 10f64 / 3f64);
+        assert!(end_to_end < delay_bound(&arrival, &service) + 2f64 + 16f64 / 3f64);
+
+        let overloaded = Curve::token_bucket(5f64, 1f64);
+        assert_eq!(delay_bound(&overloaded, &service), f64::INFINITY);
+        assert_eq!(backlog_bound(&overloaded, &service), f64::INFINITY);
+    }
+
+    #[test]
+    fn bounds_simulation_test() {
+        // Three flows bursting 4 bytes every 8 ticks, each within a token
+        // bucket of rate 0.5 and burst 4, on a link of 2 bytes per tick.
+        let mut fifo = FIFOScheduler::new(2);
+        let mut arrival = Curve::token_bucket(0f64, 0f64);
+        for idx in 0..3 {
+            let mut flow = VariableLengthFlow::new();
+            for burst in 0..10 {
+                for p in 0..2 {
+                    let name = format!("f{}_{}_{}", idx, burst, p);
+                    flow.packet_arrive(Packet::new(name, 2), burst * 8 + idx);
+                }
+            }
+            fifo.add_flow(flow);
+            arrival = arrival.add(&Curve::token_bucket(0.5f64, 4f64));
+        }
+        fifo.run();
+
+        // The link sends a packet at its rate, a tick at most after it is
+        // picked, and the simulated delays stay within the bound.
+        let service = Curve::rate_latency(2f64, 1f64);
+        let bound = delay_bound(&arrival, &service);
+        assert_eq!(bound, 7f64);
+        let stats = fifo.scheduler_stats();
+        let worst = stats.packets.iter().map(|r| r.delay()).max().unwrap();
+        assert!(worst as f64 <= bound, "{} > {}", worst, bound);
+        assert_eq!(stats.aggregate.packets, 60);
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::VecDeque;
+
+use crate::scheduling::Packet;
+
+/// Default target sojourn time, in ticks.
+pub const DEFAULT_CODEL_TARGET: usize = 5;
+
+/// Default interval over which the sojourn time must stay above the target
+/// before dropping starts, in ticks.
+pub const DEFAULT_CODEL_INTERVAL: usize = 100;
+
+/// A packet waiting in a queue, stamped with the tick it was enqueued at.
+pub trait Stamped {
+    fn packet(&self) -> &Packet;
+
+    fn packet_mut(&mut self) -> &mut Packet;
+
+    fn enqueue_time(&self) -> usize;
+}
+
+impl Stamped for (Packet, usize) {
+    fn packet(&self) -> &Packet {
+        &self.0
+    }
+
+    fn packet_mut(&mut self) -> &mut Packet {
+        &mut self.0
+    }
+
+    fn enqueue_time(&self) -> usize {
+        self.1
+    }
+}
+
+/// Controlled Delay (CoDel) queue management, as in RFC 8289.
+///
+/// Packets are dropped at the head of the queue, based on how long they
+/// waited. Once the sojourn time has stayed above `target` for `interval`,
+/// CoDel enters a dropping state and drops at intervals shrinking with the
+/// square root of the number of drops, until the sojourn time falls back
+/// below the target. A queue holding at most one packet is never dropped
+/// from. ECN-capable packets are marked instead of dropped.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CoDel {
+    target: usize,
+    interval: usize,
+    /// When the sojourn time will have been above the target for an
+    /// interval, None while it is below.
+    first_above_time: Option<usize>,
+    dropping: bool,
+    /// When to drop next in the dropping state.
+    drop_next: usize,
+    count: usize,
+    /// Drop count at the end of the previous dropping state.
+    last_count: usize,
+    /// Length of the longest packet seen.
+    max_packet: usize,
+}
+
+impl CoDel {
+    pub fn new(target: usize, interval: usize) -> CoDel {
+        assert!(interval > 0, "the CoDel interval must be positive");
+        CoDel {
+            target,
+            interval,
+            first_above_time: None,
+            dropping: false,
+            drop_next: 0,
+            count: 0,
+            last_count: 0,
+            max_packet: 0,
+        }
+    }
+
+    pub fn is_dropping(&self) -> bool {
+        self.dropping
+    }
+
+    /// Dequeue the packet at the head of `queue` at tick `now`,
+    /// moving the packets CoDel drops on the way to `dropped`.
+    pub fn dequeue<T: Stamped>(
+        &mut self,
+        queue: &mut VecDeque<T>,
+        now: usize,
+        dropped: &mut Vec<T>,
+    ) -> Option<T> {
+        let (mut head, ok_to_drop) = self.dequeue_head(queue, now);
+        if head.is_none() {
+            self.dropping = false;
+            return None;
+        }
+
+        if self.dropping {
+            if !ok_to_drop {
+                self.dropping = false;
+            }
+            while self.dropping && now >= self.drop_next {
+                self.count += 1;
+                if Self::mark(&mut head) {
+                    self.drop_next = self.control_law(self.drop_next);
+                    break;
+                }
+                dropped.extend(head.take());
+                let (next, ok_to_drop) = self.dequeue_head(queue, now);
+                head = next;
+                if ok_to_drop {
+                    self.drop_next = self.control_law(self.drop_next);
+                } else {
+                    self.dropping = false;
+                }
+            }
+        } else if ok_to_drop {
+            if !Self::mark(&mut head) {
+                dropped.extend(head.take());
+                head = self.dequeue_head(queue, now).0;
+            }
+            self.dropping = true;
+            // Resume near the previous drop rate if the last dropping
+            // state ended recently.
+            let delta = self.count - self.last_count;
+            self.count = if delta > 1 && now.saturating_sub(self.drop_next) < 16 * self.interval {
+                delta
+            } else {
+                1
+            };
+            self.drop_next = self.control_law(now);
+            self.last_count = self.count;
+        }
+        head
+    }
+
+    /// Mark the packet if it is ECN-capable.
+    /// Returns false if it has to be dropped instead.
+    fn mark<T: Stamped>(head: &mut Option<T>) -> bool {
+        head.as_mut().is_some_and(|h| h.packet_mut().mark_ce())
+    }
+
+    /// Take the head packet and tell whether it may be dropped.
+    fn dequeue_head<T: Stamped>(
+        &mut self,
+        queue: &mut VecDeque<T>,
+        now: usize,
+    ) -> (Option<T>, bool) {
+        let Some(head) = queue.pop_front() else {
+            self.first_above_time = None;
+            return (None, false);
+        };
+        let sojourn = now - head.enqueue_time();
+        self.max_packet = self.max_packet.max(head.packet().len);
+        let backlog: usize = queue.iter().map(|p| p.packet().len).sum();
+
+        if sojourn < self.target || backlog <= self.max_packet {
+            self.first_above_time = None;
+            return (Some(head), false);
+        }
+        match self.first_above_time {
+            None => {
+                self.first_above_time = Some(now + self.interval);
+                (Some(head), false)
+            }
+            Some(time) => (Some(head), now >= time),
+        }
+    }
+
+    fn control_law(&self, time: usize) -> usize {
+        time + (self.interval as f64 / (self.count as f64).sqrt()).max(1f64) as usize
+    }
+}
+
+impl Default for CoDel {
+    fn default() -> Self {
+        CoDel::new(DEFAULT_CODEL_TARGET, DEFAULT_CODEL_INTERVAL)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::VecDeque;
+
+    use crate::scheduling::{Ecn, Packet};
+
+    use super::CoDel;
+
+    #[test]
+    fn codel_test() {
+        // Two packets arrive per tick and one leaves, so the queue
+        // and the sojourn time keep growing.
+        let mut codel = CoDel::new(2, 10);
+        let mut queue = VecDeque::new();
+        let mut dropped = Vec::new();
+        let mut first_drop = None;
+        for now in 0..100 {
+            for p in 0..2 {
+                queue.push_back((Packet::new(format!("p{}_{}", now, p), 1), now));
+            }
+            let before = dropped.len();
+            assert!(codel.dequeue(&mut queue, now, &mut dropped).is_some());
+            if dropped.len() > before {
+                first_drop.get_or_insert(now);
+            }
+        }
+
+        // The sojourn time reaches the target at tick 3, and nothing is
+        // dropped until it has stayed above it for a whole interval.
+        assert_eq!(first_drop, Some(13));
+        assert!(codel.is_dropping());
+        assert!(dropped.len() > 5);
+    }
+
+    #[test]
+    fn codel_single_packet_test() {
+        // A lone packet is never dropped however long it waited.
+        let mut codel = CoDel::new(0, 1);
+        let mut dropped = Vec::new();
+        for now in 0..10 {
+            let mut queue = VecDeque::from([(Packet::new("p", 1), 0)]);
+            assert!(codel.dequeue(&mut queue, now, &mut dropped).is_some());
+        }
+        assert!(dropped.is_empty());
+    }
+
+    #[test]
+    fn codel_ecn_test() {
+        // Same overload as above, with ECN-capable packets.
+        let mut codel = CoDel::new(2, 10);
+        let mut queue = VecDeque::new();
+        let mut dropped = Vec::new();
+        let mut marked = 0;
+        for now in 0..100 {
+            for p in 0..2 {
+                let packet = Packet::new(format!("p{}_{}", now, p), 1).with_ecn(Ecn::Ect);
+                queue.push_back((packet, now));
+            }
+            let (packet, _) = codel.dequeue(&mut queue, now, &mut dropped).unwrap();
+            if packet.ecn == Ecn::Ce {
+                marked += 1;
+            }
+        }
+        assert!(dropped.is_empty());
+        assert!(marked > 5);
+    }
+}
@@ -0,0 +1,229 @@
+//! Active queue management: policies that drop packets before a queue
+//! is full, to keep it short and to signal congestion early.
+//!
+//! [`Red`], [`Wred`] and [`Pie`] drop packets on arrival. They are applied
+//! to the queue of a [`Port`](super::Port) with
+//! [`Port::set_red`](super::Port::set_red), [`Port::set_wred`](super::Port::set_wred)
+//! and [`Port::set_pie`](super::Port::set_pie), and RED also to the queue
+//! of a single flow with [`BoundedFlow::with_red`](super::flow::BoundedFlow::with_red).
+//! [`CoDel`] drops at the head of a queue of stamped packets, as done
+//! by the [`FQCoDelScheduler`](super::schedulers::fq_codel::FQCoDelScheduler).
+//! Schedulers count the packets dropped from each of their flows.
+
+pub mod codel;
+pub mod pie;
+pub mod red;
+pub mod wred;
+
+pub use codel::CoDel;
+pub use pie::Pie;
+pub use red::Red;
+pub use wred::{Wred, WredProfile};
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{BoundedFlow, Flow, VariableLengthFlow},
+        schedulers::{fifo::FIFOScheduler, fq_codel::FQCoDelScheduler, sp::SPScheduler},
+        Ecn, FlowId, Packet, Port, Scheduler,
+    };
+
+    use super::{Pie, Red, Wred, WredProfile};
+
+    fn flow(prefix: &str, times: impl Iterator<Item = usize>) -> VariableLengthFlow {
+        let mut flow = VariableLengthFlow::new();
+        for t in times {
+            flow.packet_arrive(Packet::new(format!("{}{}", prefix, t), 1), t);
+        }
+        flow
+    }
+
+    #[test]
+    fn red_port_fairness_test() {
+        let mut fifo = FIFOScheduler::new(1);
+        fifo.get_output_port()
+            .set_red(Some(Red::new(5f64, 15f64, 0.5).with_weight(0.2)));
+        fifo.add_flow(flow("heavy", (0..400).filter(|t| t % 4 != 3)));
+        fifo.add_flow(flow("light", (0..400).step_by(2)));
+        fifo.run();
+
+        // Early drops hit the flows in proportion to their rates,
+        // 300 and 200 packets offered.
+        let heavy = fifo.dropped_count(FlowId(0));
+        let light = fifo.dropped_count(FlowId(1));
+        assert_eq!(heavy + light, fifo.get_output_port().dropped_count());
+        assert!(heavy > light);
+        let heavy_rate = heavy as f64 / 300f64;
+        let light_rate = light as f64 / 200f64;
+        assert!(
+            (heavy_rate - light_rate).abs() < 0.05,
+            "drop rates {} and {}",
+            heavy_rate,
+            light_rate
+        );
+    }
+
+    #[test]
+    fn red_flow_queue_test() {
+        // The high priority takes 0.75 of the link, so the queue of the
+        // low priority builds up and is kept short by early drops.
+        let mut sp = SPScheduler::new(1);
+        sp.add_flow(flow("a", (0..400).filter(|t| t % 4 != 3)), 1);
+        let queue = BoundedFlow::new(flow("b", (0..400).step_by(2)), None, None)
+            .with_red(Red::new(2f64, 6f64, 0.5).with_weight(0.2));
+        sp.add_flow(queue, 0);
+        sp.run();
+
+        assert_eq!(sp.get_output_port().dropped_count(), 0);
+        assert_eq!(sp.dropped_count(FlowId(0)), 0);
+        let stats = sp.stats();
+        assert_eq!(stats[1].packets + sp.dropped_count(FlowId(1)), 200);
+        assert!(stats[1].max_delay < 40, "max delay {}", stats[1].max_delay);
+    }
+
+    /// Mean queueing delay of the last `count` packets of a port,
+    /// whose names end with their arrival time.
+    fn late_delay(port: &mut Port, count: usize) -> f64 {
+        let output = port.get_output();
+        let skip = output.len() - count;
+        let total: usize = output[skip..]
+            .iter()
+            .zip(&port.get_departure_times()[skip..])
+            .map(|(p, departure)| departure - p.name[1..].parse::<usize>().unwrap())
+            .sum();
+        total as f64 / count as f64
+    }
+
+    #[test]
+    fn aqm_comparison_test() {
+        // 1.25 packets per tick on a link of 1 for 2000 ticks.
+        let overload = || {
+            let mut flow = VariableLengthFlow::new();
+            for t in 0..2000 {
+                flow.packet_arrive(Packet::new(format!("p{}", t), 1), t);
+                if t % 4 == 0 {
+                    flow.packet_arrive(Packet::new(format!("q{}", t), 1), t);
+                }
+            }
+            flow
+        };
+
+        let mut plain = FIFOScheduler::new(1);
+        plain.add_flow(overload());
+        plain.run();
+
+        let mut red = FIFOScheduler::new(1);
+        red.get_output_port()
+            .set_red(Some(Red::new(5f64, 15f64, 0.5).with_weight(0.2)));
+        red.add_flow(overload());
+        red.run();
+
+        let mut pie = FIFOScheduler::new(1);
+        pie.get_output_port()
+            .set_pie(Some(Pie::new(15, 15).with_gains(0.00125, 0.0125)));
+        pie.add_flow(overload());
+        pie.run();
+
+        let mut codel = FQCoDelScheduler::new(1);
+        codel.set_queue_count(1);
+        codel.set_codel(5, 20);
+        codel.add_flow(overload());
+        codel.run();
+
+        // Without AQM the queue grows for as long as the overload lasts.
+        assert!(late_delay(plain.get_output_port(), 500) > 400f64);
+
+        // Each AQM drops about the excess and keeps the queue short,
+        // PIE around its target delay.
+        let pie_delay = late_delay(pie.get_output_port(), 500);
+        assert!(
+            (10f64..25f64).contains(&pie_delay),
+            "PIE delay {}",
+            pie_delay
+        );
+        assert!(late_delay(red.get_output_port(), 500) < 25f64);
+        assert!(late_delay(codel.get_output_port(), 500) < 25f64);
+        for drops in [
+            red.dropped_count(FlowId(0)),
+            pie.dropped_count(FlowId(0)),
+            codel.dropped_count(FlowId(0)),
+        ] {
+            assert!((400..=600).contains(&drops), "{} drops", drops);
+        }
+    }
+
+    /// Run a FIFO over a port with an AQM twice, resetting in between.
+    fn assert_identical_rerun(set_aqm: impl Fn(&mut Port)) {
+        let mut fifo = FIFOScheduler::new(1);
+        set_aqm(fifo.get_output_port());
+        fifo.add_flow(flow("heavy", (0..400).filter(|t| t % 4 != 3)));
+        fifo.add_flow(flow("light", (0..400).step_by(2)));
+        fifo.run();
+        let result = fifo.result();
+        assert!(fifo.get_output_port().dropped_count() > 0);
+
+        // The drop decisions restart from the seed.
+        fifo.reset();
+        fifo.run();
+        assert_eq!(fifo.result(), result);
+    }
+
+    #[test]
+    fn aqm_reset_test() {
+        assert_identical_rerun(|port| {
+            port.set_red(Some(Red::new(5f64, 15f64, 0.5).with_weight(0.2)))
+        });
+        assert_identical_rerun(|port| {
+            port.set_wred(Some(
+                Wred::new(WredProfile::new(5f64, 15f64, 0.5)).with_weight(0.2),
+            ))
+        });
+        assert_identical_rerun(|port| port.set_pie(Some(Pie::new(5, 5).with_gains(0.01, 0.1))));
+    }
+
+    #[test]
+    fn ecn_marking_test() {
+        let mut fifo = FIFOScheduler::new(1);
+        fifo.get_output_port()
+            .set_red(Some(Red::new(5f64, 15f64, 0.5).with_weight(0.2)));
+        let mut capable = VariableLengthFlow::new();
+        for t in (0..400).filter(|t| t % 4 != 3) {
+            capable.packet_arrive(Packet::new(format!("e{}", t), 1).with_ecn(Ecn::Ect), t);
+        }
+        fifo.add_flow(capable);
+        fifo.add_flow(flow("n", (0..400).step_by(2)));
+        fifo.run();
+
+        // The same early decisions mark the ECN-capable flow
+        // and drop the other one.
+        let stats = fifo.stats();
+        assert!(stats[0].marked > 0);
+        assert_eq!(stats[0].dropped, 0);
+        assert_eq!(stats[1].marked, 0);
+        assert!(stats[1].dropped > 0);
+        assert_eq!(stats[0].marked, fifo.get_output_port().marked_count());
+        assert_eq!(stats[1].dropped, fifo.get_output_port().dropped_count());
+    }
+
+    #[test]
+    fn wred_class_test() {
+        // Expedited forwarding tolerates a longer queue than best effort.
+        let wred = Wred::new(WredProfile::new(5f64, 15f64, 0.5))
+            .with_profile(46, WredProfile::new(20f64, 30f64, 0.1))
+            .with_weight(0.2);
+        let mut fifo = FIFOScheduler::new(1);
+        fifo.get_output_port().set_wred(Some(wred));
+
+        let mut expedited = VariableLengthFlow::new();
+        for t in (0..400).step_by(2) {
+            expedited.packet_arrive(Packet::new(format!("e{}", t), 1).with_dscp(46), t);
+        }
+        fifo.add_flow(expedited);
+        fifo.add_flow(flow("b", (0..400).filter(|t| t % 4 != 3)));
+        fifo.run();
+
+        // The best-effort drops keep the queue below the expedited thresholds.
+        assert_eq!(fifo.dropped_count(FlowId(0)), 0);
+        assert!(fifo.dropped_count(FlowId(1)) > 50);
+    }
+}
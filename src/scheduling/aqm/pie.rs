@@ -0,0 +1,167 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+/// Default target queueing delay, in ticks.
+pub const DEFAULT_PIE_TARGET: usize = 15;
+
+/// Default number of ticks between updates of the drop probability.
+pub const DEFAULT_PIE_UPDATE_INTERVAL: usize = 15;
+
+/// Default gain on the distance of the delay to the target, per tick.
+pub const DEFAULT_PIE_ALPHA: f64 = 0.000125;
+
+/// Default gain on the change of the delay since the last update, per tick.
+pub const DEFAULT_PIE_BETA: f64 = 0.00125;
+
+/// Default time during which bursts pass undropped, in ticks.
+pub const DEFAULT_PIE_MAX_BURST: usize = 150;
+
+/// Drop probability above which ECN-capable packets are dropped
+/// rather than marked.
+pub const PIE_MAX_MARK_PROBABILITY: f64 = 0.1;
+
+/// Seed of the drop decisions used by [`Pie::new`].
+pub const DEFAULT_PIE_SEED: u64 = 0;
+
+/// Proportional Integral controller Enhanced (PIE) drop policy,
+/// as in RFC 8033.
+///
+/// Every `update_interval` ticks, the drop probability moves by `alpha`
+/// times the distance of the queueing delay to `target`, plus `beta` times
+/// the change of the delay since the last update, with smaller steps while
+/// the probability is low. Arriving packets are dropped with that
+/// probability, except during the burst allowance, while the delay is
+/// well below the target or when the queue is nearly empty.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pie {
+    target: f64,
+    update_interval: usize,
+    alpha: f64,
+    beta: f64,
+    max_burst: usize,
+    burst_allowance: usize,
+    probability: f64,
+    /// Queueing delay at the last update.
+    old_delay: f64,
+    /// Ticks since the last update.
+    elapsed: usize,
+    seed: u64,
+    rng: ChaCha12Rng,
+}
+
+impl Pie {
+    pub fn new(target: usize, update_interval: usize) -> Pie {
+        assert!(update_interval > 0, "PIE must update its probability");
+        Pie {
+            target: target as f64,
+            update_interval,
+            alpha: DEFAULT_PIE_ALPHA,
+            beta: DEFAULT_PIE_BETA,
+            max_burst: DEFAULT_PIE_MAX_BURST,
+            burst_allowance: DEFAULT_PIE_MAX_BURST,
+            probability: 0f64,
+            old_delay: 0f64,
+            elapsed: 0,
+            seed: DEFAULT_PIE_SEED,
+            rng: ChaCha12Rng::seed_from_u64(DEFAULT_PIE_SEED),
+        }
+    }
+
+    /// Set the gains of the controller.
+    pub fn with_gains(mut self, alpha: f64, beta: f64) -> Pie {
+        self.alpha = alpha;
+        self.beta = beta;
+        self
+    }
+
+    /// Set the time during which bursts pass undropped.
+    pub fn with_max_burst(mut self, max_burst: usize) -> Pie {
+        self.max_burst = max_burst;
+        self.burst_allowance = max_burst;
+        self
+    }
+
+    /// Make the drop decisions reproducible from `seed`.
+    pub fn with_seed(mut self, seed: u64) -> Pie {
+        self.seed = seed;
+        self.rng = ChaCha12Rng::seed_from_u64(seed);
+        self
+    }
+
+    /// The current drop probability.
+    pub fn probability(&self) -> f64 {
+        self.probability
+    }
+
+    /// Decide whether a packet arriving at a queue of `queue_len` packets
+    /// should be dropped.
+    pub fn should_drop(&mut self, queue_len: usize) -> bool {
+        if self.burst_allowance > 0 {
+            return false;
+        }
+        if self.old_delay < self.target / 2f64 && self.probability < 0.2 {
+            return false;
+        }
+        if queue_len <= 2 {
+            return false;
+        }
+        self.rng.gen_bool(self.probability)
+    }
+
+    /// Advance by one tick, with `delay` the current queueing delay
+    /// in ticks, updating the drop probability when due.
+    pub fn tick(&mut self, delay: f64) {
+        self.elapsed += 1;
+        if self.elapsed < self.update_interval {
+            return;
+        }
+        self.elapsed = 0;
+
+        // Smaller steps at low probabilities keep the controller stable.
+        let scale = match self.probability {
+            p if p < 0.000001 => 1f64 / 2048f64,
+            p if p < 0.00001 => 1f64 / 512f64,
+            p if p < 0.0001 => 1f64 / 128f64,
+            p if p < 0.001 => 1f64 / 32f64,
+            p if p < 0.01 => 1f64 / 8f64,
+            p if p < 0.1 => 1f64 / 2f64,
+            _ => 1f64,
+        };
+        let mut delta =
+            scale * (self.alpha * (delay - self.target) + self.beta * (delay - self.old_delay));
+        if self.probability >= 0.1 {
+            delta = delta.min(0.02);
+        }
+        self.probability += delta;
+        if delay == 0f64 && self.old_delay == 0f64 {
+            self.probability *= 0.98;
+        }
+        self.probability = self.probability.clamp(0f64, 1f64);
+
+        self.burst_allowance = self.burst_allowance.saturating_sub(self.update_interval);
+        if self.probability == 0f64
+            && delay < self.target / 2f64
+            && self.old_delay < self.target / 2f64
+        {
+            self.burst_allowance = self.max_burst;
+        }
+        self.old_delay = delay;
+    }
+
+    /// Forget the drop probability and the delay history, and restart
+    /// the drop decisions from the seed.
+    pub fn reset(&mut self) {
+        self.burst_allowance = self.max_burst;
+        self.probability = 0f64;
+        self.old_delay = 0f64;
+        self.elapsed = 0;
+        self.rng = ChaCha12Rng::seed_from_u64(self.seed);
+    }
+}
+
+impl Default for Pie {
+    fn default() -> Self {
+        Pie::new(DEFAULT_PIE_TARGET, DEFAULT_PIE_UPDATE_INTERVAL)
+    }
+}
@@ -0,0 +1,103 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+/// Default weight of the instantaneous queue length in the average.
+pub const DEFAULT_RED_WEIGHT: f64 = 0.002;
+
+/// Seed of the drop decisions used by [`Red::new`].
+pub const DEFAULT_RED_SEED: u64 = 0;
+
+/// Random Early Detection (RED) drop policy.
+///
+/// Keeps an exponentially-weighted average of the queue length and drops
+/// arriving packets with a probability growing linearly from 0 at `min_th`
+/// to `max_p` at `max_th`, and always above `max_th`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Red {
+    min_th: f64,
+    max_th: f64,
+    max_p: f64,
+    weight: f64,
+    average: f64,
+    seed: u64,
+    rng: ChaCha12Rng,
+}
+
+impl Red {
+    pub fn new(min_th: f64, max_th: f64, max_p: f64) -> Red {
+        assert!(min_th < max_th, "RED requires min_th < max_th");
+        assert!(
+            (0f64..=1f64).contains(&max_p),
+            "RED max_p must be within [0, 1]"
+        );
+        Red {
+            min_th,
+            max_th,
+            max_p,
+            weight: DEFAULT_RED_WEIGHT,
+            average: 0f64,
+            seed: DEFAULT_RED_SEED,
+            rng: ChaCha12Rng::seed_from_u64(DEFAULT_RED_SEED),
+        }
+    }
+
+    /// Drop, or mark, every packet arriving while at least `threshold`
+    /// packets are queued, on the instantaneous queue length, as DCTCP
+    /// switches do.
+    pub fn threshold(threshold: usize) -> Red {
+        Red {
+            min_th: threshold as f64,
+            max_th: threshold as f64,
+            max_p: 1f64,
+            weight: 1f64,
+            average: 0f64,
+            seed: DEFAULT_RED_SEED,
+            rng: ChaCha12Rng::seed_from_u64(DEFAULT_RED_SEED),
+        }
+    }
+
+    /// Set the weight of the instantaneous queue length in the average.
+    pub fn with_weight(mut self, weight: f64) -> Red {
+        self.weight = weight;
+        self
+    }
+
+    /// Make the drop decisions reproducible from `seed`.
+    pub fn with_seed(mut self, seed: u64) -> Red {
+        self.seed = seed;
+        self.rng = ChaCha12Rng::seed_from_u64(seed);
+        self
+    }
+
+    /// The current average queue length.
+    pub fn average(&self) -> f64 {
+        self.average
+    }
+
+    /// Update the average with the queue length seen by an arriving packet
+    /// and decide whether that packet should be dropped.
+    pub fn should_drop(&mut self, queue_len: usize) -> bool {
+        self.average = (1f64 - self.weight) * self.average + self.weight * queue_len as f64;
+        let p = drop_probability(self.average, self.min_th, self.max_th, self.max_p);
+        p > 0f64 && self.rng.gen_bool(p)
+    }
+
+    /// Forget the average queue length and restart the drop decisions
+    /// from the seed.
+    pub fn reset(&mut self) {
+        self.average = 0f64;
+        self.rng = ChaCha12Rng::seed_from_u64(self.seed);
+    }
+}
+
+/// The RED drop probability at an average queue length.
+pub(super) fn drop_probability(average: f64, min_th: f64, max_th: f64, max_p: f64) -> f64 {
+    if average < min_th {
+        0f64
+    } else if average >= max_th {
+        1f64
+    } else {
+        max_p * (average - min_th) / (max_th - min_th)
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+use super::red::{drop_probability, DEFAULT_RED_SEED, DEFAULT_RED_WEIGHT};
+
+/// Drop thresholds of a class of packets under [`Wred`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WredProfile {
+    pub min_th: f64,
+    pub max_th: f64,
+    pub max_p: f64,
+}
+
+impl WredProfile {
+    pub fn new(min_th: f64, max_th: f64, max_p: f64) -> WredProfile {
+        assert!(min_th < max_th, "RED requires min_th < max_th");
+        assert!(
+            (0f64..=1f64).contains(&max_p),
+            "RED max_p must be within [0, 1]"
+        );
+        WredProfile {
+            min_th,
+            max_th,
+            max_p,
+        }
+    }
+}
+
+/// Weighted Random Early Detection (WRED) drop policy.
+///
+/// Like [`Red`](super::Red), with one average queue length shared by all
+/// packets but drop thresholds chosen by the DSCP of each packet. Giving
+/// a class higher thresholds lets its packets survive longer under
+/// congestion. Packets of a DSCP without a profile use the default one.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Wred {
+    default_profile: WredProfile,
+    profiles: BTreeMap<u8, WredProfile>,
+    weight: f64,
+    average: f64,
+    seed: u64,
+    rng: ChaCha12Rng,
+}
+
+impl Wred {
+    pub fn new(default_profile: WredProfile) -> Wred {
+        Wred {
+            default_profile,
+            profiles: BTreeMap::new(),
+            weight: DEFAULT_RED_WEIGHT,
+            average: 0f64,
+            seed: DEFAULT_RED_SEED,
+            rng: ChaCha12Rng::seed_from_u64(DEFAULT_RED_SEED),
+        }
+    }
+
+    /// Use `profile` for the packets with the given DSCP.
+    pub fn with_profile(mut self, dscp: u8, profile: WredProfile) -> Wred {
+        self.profiles.insert(dscp, profile);
+        self
+    }
+
+    /// Set the weight of the instantaneous queue length in the average.
+    pub fn with_weight(mut self, weight: f64) -> Wred {
+        self.weight = weight;
+        self
+    }
+
+    /// Make the drop decisions reproducible from `seed`.
+    pub fn with_seed(mut self, seed: u64) -> Wred {
+        self.seed = seed;
+        self.rng = ChaCha12Rng::seed_from_u64(seed);
+        self
+    }
+
+    /// The profile applied to the packets with the given DSCP.
+    pub fn profile(&self, dscp: u8) -> &WredProfile {
+        self.profiles.get(&dscp).unwrap_or(&self.default_profile)
+    }
+
+    /// The current average queue length.
+    pub fn average(&self) -> f64 {
+        self.average
+    }
+
+    /// Update the average with the queue length seen by an arriving packet
+    /// and decide whether that packet should be dropped.
+    pub fn should_drop(&mut self, queue_len: usize, dscp: u8) -> bool {
+        self.average = (1f64 - self.weight) * self.average + self.weight * queue_len as f64;
+        let profile = *self.profile(dscp);
+        let p = drop_probability(self.average, profile.min_th, profile.max_th, profile.max_p);
+        p > 0f64 && self.rng.gen_bool(p)
+    }
+
+    /// Forget the average queue length and restart the drop decisions
+    /// from the seed.
+    pub fn reset(&mut self) {
+        self.average = 0f64;
+        self.rng = ChaCha12Rng::seed_from_u64(self.seed);
+    }
+}
@@ -0,0 +1,220 @@
+//! A byte pool shared by the queues of a [`Port`](super::Port), as in
+//! the packet buffer of a switch ASIC.
+
+/// How many bytes of a [`SharedBuffer`] a queue may hold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BufferThreshold {
+    /// At most this many bytes, whatever the other queues hold.
+    Static(usize),
+    /// At most `alpha` times the free bytes of the pool, as in the
+    /// Dynamic Threshold algorithm of Choudhury and Hahne: a queue gets
+    /// less of the pool as the pool fills up, keeping some for the others.
+    Dynamic(f64),
+}
+
+/// Why a [`SharedBuffer`] refused a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferDrop {
+    /// The queue reached its threshold.
+    Threshold,
+    /// The pool had no room left for the packet.
+    Exhausted,
+}
+
+/// A pool of bytes the queues of a port draw from, each up to its
+/// threshold. Bytes are taken when a packet is queued and given back
+/// once it has been transmitted.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SharedBuffer {
+    size: usize,
+    /// Threshold of the queues without one of their own.
+    threshold: BufferThreshold,
+    thresholds: Vec<Option<BufferThreshold>>,
+    /// Bytes held by each queue.
+    occupancy: Vec<usize>,
+    /// Packets of each queue dropped at its threshold.
+    threshold_drops: Vec<usize>,
+    /// Packets of each queue dropped because the pool was exhausted.
+    exhaustion_drops: Vec<usize>,
+}
+
+impl SharedBuffer {
+    /// A pool of `size` bytes where every queue is limited by `threshold`.
+    pub fn new(size: usize, threshold: BufferThreshold) -> SharedBuffer {
+        if let BufferThreshold::Dynamic(alpha) = threshold {
+            assert!(alpha > 0f64, "a dynamic threshold needs a positive alpha");
+        }
+        SharedBuffer {
+            size,
+            threshold,
+            thresholds: Vec::new(),
+            occupancy: Vec::new(),
+            threshold_drops: Vec::new(),
+            exhaustion_drops: Vec::new(),
+        }
+    }
+
+    /// Give a queue a threshold of its own.
+    pub fn with_threshold(mut self, queue: usize, threshold: BufferThreshold) -> SharedBuffer {
+        self.set_threshold(queue, threshold);
+        self
+    }
+
+    pub fn set_threshold(&mut self, queue: usize, threshold: BufferThreshold) {
+        self.track(queue);
+        self.thresholds[queue] = Some(threshold);
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The bytes held by all queues.
+    pub fn used(&self) -> usize {
+        self.occupancy.iter().sum()
+    }
+
+    pub fn free(&self) -> usize {
+        self.size.saturating_sub(self.used())
+    }
+
+    /// The bytes held by a queue.
+    pub fn occupancy(&self, queue: usize) -> usize {
+        self.occupancy.get(queue).copied().unwrap_or(0)
+    }
+
+    /// The bytes a queue may hold right now.
+    pub fn limit(&self, queue: usize) -> usize {
+        let threshold = self
+            .thresholds
+            .get(queue)
+            .copied()
+            .flatten()
+            .unwrap_or(self.threshold);
+        match threshold {
+            BufferThreshold::Static(bytes) => bytes,
+            BufferThreshold::Dynamic(alpha) => (alpha * self.free() as f64) as usize,
+        }
+    }
+
+    /// Take `len` bytes for a packet of a queue, or tell why not,
+    /// counting the drop.
+    pub fn admit(&mut self, queue: usize, len: usize) -> Result<(), BufferDrop> {
+        self.track(queue);
+        if len > self.free() {
+            self.exhaustion_drops[queue] += 1;
+            return Err(BufferDrop::Exhausted);
+        }
+        if self.occupancy[queue] + len > self.limit(queue) {
+            self.threshold_drops[queue] += 1;
+            return Err(BufferDrop::Threshold);
+        }
+        self.occupancy[queue] += len;
+        Ok(())
+    }
+
+    /// Give back the bytes of a packet of a queue that left the port.
+    pub fn release(&mut self, queue: usize, len: usize) {
+        self.track(queue);
+        self.occupancy[queue] = self.occupancy[queue].saturating_sub(len);
+    }
+
+    /// The packets of a queue dropped at its threshold.
+    pub fn threshold_drops(&self, queue: usize) -> usize {
+        self.threshold_drops.get(queue).copied().unwrap_or(0)
+    }
+
+    /// The packets of a queue dropped because the pool was exhausted.
+    pub fn exhaustion_drops(&self, queue: usize) -> usize {
+        self.exhaustion_drops.get(queue).copied().unwrap_or(0)
+    }
+
+    /// Empty the pool and clear the drop counts, keeping the thresholds.
+    pub fn reset(&mut self) {
+        self.occupancy.fill(0);
+        self.threshold_drops.fill(0);
+        self.exhaustion_drops.fill(0);
+    }
+
+    /// Make room for the counters of a queue.
+    fn track(&mut self, queue: usize) {
+        if queue >= self.occupancy.len() {
+            self.thresholds.resize(queue + 1, None);
+            self.occupancy.resize(queue + 1, 0);
+            self.threshold_drops.resize(queue + 1, 0);
+            self.exhaustion_drops.resize(queue + 1, 0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{Packet, Port, QueueService};
+
+    use super::{BufferDrop, BufferThreshold, SharedBuffer};
+
+    #[test]
+    fn dynamic_threshold_test() {
+        // With alpha 1, a lone queue stops at half of the pool,
+        // leaving the other half to the next queue to get busy.
+        let mut buffer = SharedBuffer::new(100, BufferThreshold::Dynamic(1f64));
+        while buffer.admit(0, 10).is_ok() {}
+        assert_eq!(buffer.occupancy(0), 50);
+        assert_eq!(buffer.threshold_drops(0), 1);
+        assert_eq!(buffer.limit(1), 50);
+        assert_eq!(buffer.admit(1, 10), Ok(()));
+
+        buffer.release(0, 50);
+        assert_eq!(buffer.used(), 10);
+        buffer.reset();
+        assert_eq!((buffer.used(), buffer.threshold_drops(0)), (0, 0));
+    }
+
+    #[test]
+    fn static_threshold_test() {
+        // Oversubscribed static thresholds run the pool dry.
+        let mut buffer = SharedBuffer::new(30, BufferThreshold::Static(20))
+            .with_threshold(1, BufferThreshold::Static(5));
+        assert_eq!(buffer.admit(0, 20), Ok(()));
+        assert_eq!(buffer.admit(0, 1), Err(BufferDrop::Threshold));
+        assert_eq!(buffer.admit(1, 6), Err(BufferDrop::Threshold));
+        assert_eq!(buffer.admit(2, 10), Ok(()));
+        assert_eq!(buffer.admit(3, 1), Err(BufferDrop::Exhausted));
+        assert_eq!(buffer.exhaustion_drops(3), 1);
+        assert_eq!(buffer.free(), 0);
+    }
+
+    #[test]
+    fn port_shared_buffer_test() {
+        let mut port = Port::new(0, 1);
+        port.set_queues(2, QueueService::Strict);
+        port.set_shared_buffer(Some(SharedBuffer::new(8, BufferThreshold::Dynamic(1f64))));
+        for p in 0..6 {
+            let _ = port.submit_to(0, Packet::new(format!("a{}", p), 1));
+        }
+        port.submit_to(1, Packet::new("b0", 1)).unwrap();
+
+        // The first queue stops at 4 bytes, the second still gets in.
+        let buffer = port.get_shared_buffer().unwrap();
+        assert_eq!((buffer.occupancy(0), buffer.occupancy(1)), (4, 1));
+        assert_eq!(buffer.threshold_drops(0), 2);
+        assert_eq!(port.dropped_count(), 2);
+
+        // Transmitted packets give their bytes back.
+        port.proceed_rest();
+        assert_eq!(port.get_output().len(), 5);
+        assert_eq!(port.get_shared_buffer().unwrap().used(), 0);
+
+        // The pool applies along with the capacity of the port.
+        port.reset();
+        port.set_capacity(Some(2));
+        for p in 0..3 {
+            let _ = port.submit_to(1, Packet::new(format!("b{}", p), 1));
+        }
+        assert_eq!(port.get_shared_buffer().unwrap().occupancy(1), 2);
+        assert_eq!(port.get_shared_buffer().unwrap().threshold_drops(1), 0);
+        assert_eq!(port.dropped_count(), 1);
+    }
+}
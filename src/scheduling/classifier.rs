@@ -0,0 +1,192 @@
+//! Assignment of the packets of a merged trace to scheduler queues.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use crate::scheduling::{
+    flow::{Flow, VariableLengthFlow},
+    Packet,
+};
+
+/// Tags of a packet making up its 5-tuple, hashed by [`HashClassifier`].
+pub const FIVE_TUPLE: [&str; 5] = ["src", "dst", "sport", "dport", "proto"];
+
+/// Decides which queue of a scheduler a packet goes to.
+pub trait Classifier: Debug {
+    /// The index of the queue of `packet`.
+    fn classify(&self, packet: &Packet) -> usize;
+
+    /// Split a trace of packets and their arrival times into one flow per
+    /// queue. There are as many flows as the highest queue used plus one,
+    /// queues no packet went to are empty flows.
+    fn split(&self, trace: impl IntoIterator<Item = (Packet, usize)>) -> Vec<VariableLengthFlow>
+    where
+        Self: Sized,
+    {
+        let mut flows = Vec::new();
+        for (packet, time) in trace {
+            let idx = self.classify(&packet);
+            if flows.len() <= idx {
+                flows.resize_with(idx + 1, VariableLengthFlow::new);
+            }
+            flows[idx].packet_arrive(packet, time);
+        }
+        flows
+    }
+}
+
+/// Classify by the flow id of the packets, the id being the queue.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FlowIdClassifier {
+    /// Queue of the packets without a flow id.
+    pub default: usize,
+}
+
+impl FlowIdClassifier {
+    pub fn new(default: usize) -> FlowIdClassifier {
+        FlowIdClassifier { default }
+    }
+}
+
+impl Classifier for FlowIdClassifier {
+    fn classify(&self, packet: &Packet) -> usize {
+        packet.flow_id.unwrap_or(self.default)
+    }
+}
+
+/// Classify by the DSCP of the packets, through a table of classes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DSCPClassifier {
+    classes: BTreeMap<u8, usize>,
+    /// Queue of the packets of a DSCP without a class.
+    pub default: usize,
+}
+
+impl DSCPClassifier {
+    pub fn new(default: usize) -> DSCPClassifier {
+        DSCPClassifier {
+            classes: BTreeMap::new(),
+            default,
+        }
+    }
+
+    /// Send the packets with the given DSCP to `queue`.
+    pub fn with_class(mut self, dscp: u8, queue: usize) -> DSCPClassifier {
+        self.classes.insert(dscp, queue);
+        self
+    }
+}
+
+impl Classifier for DSCPClassifier {
+    fn classify(&self, packet: &Packet) -> usize {
+        self.classes
+            .get(&packet.dscp)
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// Classify by hashing some tags of the packets, by default their
+/// [`FIVE_TUPLE`], into a fixed number of queues.
+///
+/// Packets agreeing on the tags always share a queue, and a missing tag
+/// hashes like any other value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashClassifier {
+    queues: usize,
+    fields: Vec<String>,
+    seed: u64,
+}
+
+impl HashClassifier {
+    pub fn new(queues: usize) -> HashClassifier {
+        assert!(queues > 0, "a hash classifier needs at least one queue");
+        HashClassifier {
+            queues,
+            fields: FIVE_TUPLE.iter().map(|f| f.to_string()).collect(),
+            seed: 0,
+        }
+    }
+
+    /// Hash the given tags instead of the 5-tuple.
+    pub fn with_fields<S: Into<String>>(
+        mut self,
+        fields: impl IntoIterator<Item = S>,
+    ) -> HashClassifier {
+        self.fields = fields.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Mix `seed` into the hash, which changes which packets share a queue.
+    pub fn with_seed(mut self, seed: u64) -> HashClassifier {
+        self.seed = seed;
+        self
+    }
+}
+
+impl Classifier for HashClassifier {
+    fn classify(&self, packet: &Packet) -> usize {
+        let mut hasher = DefaultHasher::new();
+        self.seed.hash(&mut hasher);
+        for field in &self.fields {
+            packet.tag(field).hash(&mut hasher);
+        }
+        (hasher.finish() % self.queues as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{flow::Flow, Packet};
+
+    use super::{Classifier, DSCPClassifier, FlowIdClassifier, HashClassifier};
+
+    #[test]
+    fn classifier_test() {
+        let trace = vec![
+            (Packet::new("a0", 1).with_flow_id(0).with_dscp(46), 0),
+            (Packet::new("c0", 1).with_flow_id(2), 1),
+            (Packet::new("x", 1), 1),
+            (Packet::new("a1", 1).with_flow_id(0).with_dscp(46), 2),
+        ];
+
+        let flows = FlowIdClassifier::new(1).split(trace.clone());
+        assert_eq!(flows.len(), 3);
+        assert_eq!(flows[0].packet_states.len(), 2);
+        assert_eq!(flows[1].peek_packet(1), Some(Packet::new("x", 1)));
+        assert_eq!(flows[2].next_arrival(), Some(1));
+
+        let dscp = DSCPClassifier::new(1).with_class(46, 0);
+        let flows = dscp.split(trace);
+        assert_eq!(flows.len(), 2);
+        assert_eq!(flows[0].packet_states.len(), 2);
+        assert_eq!(flows[1].packet_states.len(), 2);
+
+        // Packets of one connection stay together, whatever else they carry.
+        let hash = HashClassifier::new(8);
+        let packet = |name: &str, sport: &str| {
+            Packet::new(name, 1)
+                .with_tag("src", "10.0.0.1")
+                .with_tag("dst", "10.0.0.2")
+                .with_tag("sport", sport)
+                .with_tag("dport", "80")
+                .with_tag("proto", "tcp")
+        };
+        let queue = hash.classify(&packet("p0", "1000"));
+        assert_eq!(hash.classify(&packet("p1", "1000").with_dscp(10)), queue);
+        let queues: Vec<usize> = (0..64)
+            .map(|port| hash.classify(&packet("q", &port.to_string())))
+            .collect();
+        assert!(queues.iter().all(|&q| q < 8));
+        assert!(queues.iter().any(|&q| q != queue));
+
+        let by_src = HashClassifier::new(8).with_fields(["src"]);
+        assert_eq!(
+            by_src.classify(&packet("p0", "1000")),
+            by_src.classify(&packet("p1", "2000"))
+        );
+    }
+}
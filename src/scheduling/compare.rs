@@ -0,0 +1,263 @@
+//! Side-by-side comparison of schedulers on one workload.
+//!
+//! A [`Comparison`] gives every scheduler its own copy of the same flows,
+//! runs them, and lines up the departures of every packet by name, so that
+//! the first scheduler can be diffed against the others.
+
+use std::{collections::HashMap, fmt};
+
+use crate::scheduling::{
+    flow::Flow,
+    stats::{fairness::Fairness, SchedulerStats},
+    FlowId, Scheduler,
+};
+
+/// Schedulers run on the same flows.
+pub struct Comparison {
+    flows: Vec<(Box<dyn Flow>, f64)>,
+    schedulers: Vec<(String, Box<dyn Scheduler>)>,
+}
+
+/// A packet of the workload and when each scheduler sent it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComparedPacket {
+    pub flow: FlowId,
+    pub name: String,
+    pub len: usize,
+    pub arrival: usize,
+    /// The departure time under each scheduler, in the order they were
+    /// added, None if the scheduler did not send the packet.
+    pub departures: Vec<Option<usize>>,
+}
+
+impl ComparedPacket {
+    /// How much later the packet left under a scheduler than under the
+    /// first one, None if either did not send it.
+    pub fn delta(&self, run: usize) -> Option<isize> {
+        Some(self.departures[run]? as isize - self.departures[0]? as isize)
+    }
+
+    /// Whether the schedulers did not all send the packet at the same time.
+    pub fn differs(&self) -> bool {
+        self.departures.iter().any(|d| *d != self.departures[0])
+    }
+}
+
+/// The run of one scheduler of a [`Comparison`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComparedRun {
+    pub name: String,
+    pub stats: SchedulerStats,
+    /// Jain's index of the weighted max-min fairness over the whole run,
+    /// see [`Fairness`].
+    pub fairness: f64,
+}
+
+/// The result of [`Comparison::run`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComparisonReport {
+    pub runs: Vec<ComparedRun>,
+    /// Every packet of the workload, by arrival time, then flow.
+    pub packets: Vec<ComparedPacket>,
+}
+
+impl Comparison {
+    pub fn new() -> Comparison {
+        Comparison {
+            flows: Vec::new(),
+            schedulers: Vec::new(),
+        }
+    }
+
+    /// Add a flow with a weight. The flow is only read,
+    /// every scheduler gets a copy of it.
+    pub fn add_flow(&mut self, flow: &dyn Flow, weight: f64) {
+        self.flows.push((flow.clone_box(), weight));
+    }
+
+    /// Add a scheduler without flows, the first one added being
+    /// the baseline the others are diffed against.
+    pub fn add_scheduler(&mut self, name: impl Into<String>, scheduler: Box<dyn Scheduler>) {
+        self.schedulers.push((name.into(), scheduler));
+    }
+
+    /// Run every scheduler on its copy of the flows and line up the runs.
+    ///
+    /// Packets are matched by name, so names must be unique
+    /// across the flows.
+    pub fn run(self) -> ComparisonReport {
+        let weights: Vec<f64> = self.flows.iter().map(|(_, weight)| *weight).collect();
+
+        let runs: Vec<ComparedRun> = self
+            .schedulers
+            .into_iter()
+            .map(|(name, mut scheduler)| {
+                for (flow, weight) in &self.flows {
+                    scheduler.add_flow(flow.clone_box(), *weight);
+                }
+                scheduler.run();
+                let stats = scheduler.scheduler_stats();
+                let end = stats.packets.iter().map(|r| r.departure + 1).max();
+                let fairness = Fairness::compute(&stats, &weights, 0, end.unwrap_or(0)).jain_index;
+                ComparedRun {
+                    name,
+                    stats,
+                    fairness,
+                }
+            })
+            .collect();
+
+        let departures: Vec<HashMap<&str, usize>> = runs
+            .iter()
+            .map(|run| {
+                run.stats
+                    .packets
+                    .iter()
+                    .map(|r| (r.name.as_str(), r.departure))
+                    .collect()
+            })
+            .collect();
+        let mut packets = Vec::new();
+        for (idx, (flow, _)) in self.flows.iter().enumerate() {
+            let mut flow = flow.clone_box();
+            while let Some(arrival) = flow.next_arrival() {
+                let packet = flow.pop_packet();
+                packets.push(ComparedPacket {
+                    flow: FlowId(idx),
+                    departures: departures
+                        .iter()
+                        .map(|d| d.get(packet.name.as_str()).copied())
+                        .collect(),
+                    name: packet.name,
+                    len: packet.len,
+                    arrival,
+                });
+            }
+        }
+        packets.sort_by_key(|p| (p.arrival, p.flow));
+
+        ComparisonReport { runs, packets }
+    }
+}
+
+impl Default for Comparison {
+    fn default() -> Comparison {
+        Comparison::new()
+    }
+}
+
+impl ComparisonReport {
+    /// The mean delay of each flow under each scheduler.
+    pub fn mean_delays(&self) -> Vec<Vec<f64>> {
+        self.runs
+            .iter()
+            .map(|run| run.stats.flows.iter().map(|f| f.mean_delay).collect())
+            .collect()
+    }
+}
+
+impl fmt::Display for ComparisonReport {
+    /// A summary of every run with the mean delay of each flow,
+    /// followed by the packets sent at different times,
+    /// with their delta to the first scheduler.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flow_count = self.runs.first().map_or(0, |run| run.stats.flows.len());
+        write!(
+            f,
+            "{:<12}{:>10}{:>12}",
+            "scheduler", "fairness", "mean delay"
+        )?;
+        for idx in 0..flow_count {
+            write!(f, "{:>10}", format!("flow {}", idx))?;
+        }
+        for run in &self.runs {
+            write!(
+                f,
+                "\n{:<12}{:>10.3}{:>12.3}",
+                run.name, run.fairness, run.stats.aggregate.mean_delay
+            )?;
+            for flow in &run.stats.flows {
+                write!(f, "{:>10.3}", flow.mean_delay)?;
+            }
+        }
+
+        let differing: Vec<&ComparedPacket> = self.packets.iter().filter(|p| p.differs()).collect();
+        write!(
+            f,
+            "\n\n{} of {} packets differ",
+            differing.len(),
+            self.packets.len()
+        )?;
+        for packet in differing {
+            write!(
+                f,
+                "\n{} (flow {}, arrival {}):",
+                packet.name, packet.flow, packet.arrival
+            )?;
+            for (run, departure) in self.runs.iter().zip(&packet.departures) {
+                match departure {
+                    Some(time) => write!(f, " {} {}", run.name, time)?,
+                    None => write!(f, " {} -", run.name)?,
+                }
+            }
+            let deltas: Vec<String> = (1..self.runs.len())
+                .map(|run| match packet.delta(run) {
+                    Some(delta) => format!("{:+}", delta),
+                    None => "-".to_string(),
+                })
+                .collect();
+            write!(f, " ({})", deltas.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        schedulers::{fifo::FIFOScheduler, wfq::WFQScheduler},
+        FlowId, Packet,
+    };
+
+    use super::Comparison;
+
+    #[test]
+    fn compare_test() {
+        let mut comparison = Comparison::new();
+        let mut heavy = VariableLengthFlow::new();
+        for idx in 0..4 {
+            heavy.packet_arrive(Packet::new(format!("a{}", idx), 2), 0);
+        }
+        comparison.add_flow(&heavy, 1f64);
+        let mut light = VariableLengthFlow::new();
+        light.packet_arrive(Packet::new("b0", 1), 1);
+        comparison.add_flow(&light, 1f64);
+        comparison.add_scheduler("fifo", Box::new(FIFOScheduler::new(1)));
+        comparison.add_scheduler("wfq", Box::new(WFQScheduler::new(1)));
+
+        let report = comparison.run();
+        assert_eq!(report.runs.len(), 2);
+        assert_eq!(report.packets.len(), 5);
+        let names: Vec<&str> = report.packets.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["a0", "a1", "a2", "a3", "b0"]);
+
+        // FIFO makes b0 wait behind the burst, WFQ sends it right after a0.
+        let b0 = &report.packets[4];
+        assert_eq!(b0.flow, FlowId(1));
+        assert_eq!(b0.departures, vec![Some(9), Some(3)]);
+        assert_eq!(b0.delta(1), Some(-6));
+        assert!(report.packets[0].delta(1) == Some(0) && !report.packets[0].differs());
+
+        let delays = report.mean_delays();
+        assert!(delays[1][1] < delays[0][1]);
+        assert!(report.runs[1].fairness >= report.runs[0].fairness);
+
+        // The flows given to the comparison are left untouched.
+        assert_eq!(heavy.queue_len(0), 4);
+
+        let text = report.to_string();
+        assert!(text.contains("b0 (flow 1, arrival 1): fifo 9 wfq 3 (-6)"));
+        assert!(text.contains("4 of 5 packets differ"));
+    }
+}
@@ -0,0 +1,688 @@
+//! Simulations described in TOML or YAML files.
+//!
+//! A [`Scenario`] declares the link, the scheduler and its parameters,
+//! the flows with the traffic sources generating their packets, and what
+//! to write out once the run is over, so that a setup can be shared and
+//! replayed without writing Rust:
+//!
+//! ```toml
+//! bandwidth = 1
+//! seed = 7
+//! scheduler = "wfq"
+//! tie_break = "RoundRobin"
+//!
+//! [[flows]]
+//! weight = 2
+//! count = 100
+//! source = { poisson = { rate = 0.3, size = { fixed = 1 } } }
+//!
+//! [[flows]]
+//! until = 200
+//! source = { cbr = { interval = 4, len = 1 } }
+//!
+//! [output]
+//! trace_csv = "trace.csv"
+//! ```
+//!
+//! The same fields make up the YAML form, with enums written as maps of a
+//! single key like in TOML rather than as YAML tags. Output paths are relative to
+//! the working directory.
+
+use std::{fmt, fs, io, path::Path, path::PathBuf, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::scheduling::{
+    flow::{Flow, VariableLengthFlow},
+    schedulers::{
+        cbs::CBSScheduler, drr::DRRScheduler, dwrr::DWRRScheduler, edf::EDFScheduler,
+        fifo::FIFOScheduler, fq_codel::FQCoDelScheduler, lstf::LSTFScheduler,
+        pfabric::PFabricScheduler, qfq::QFQScheduler, rr::RRScheduler, scfq::SCFQScheduler,
+        sfq::SFQScheduler, sp::SPScheduler, stochastic_fq::StochasticFQScheduler,
+        tie_break::TieBreak, vc::VirtualClockScheduler, wf2q::WF2QPlusScheduler, wfq::WFQScheduler,
+        wrr::WRRScheduler,
+    },
+    traffic::{CbrSource, OnOffSource, Period, PoissonSource, SizeDistribution, TrafficSource},
+    Packet, Scheduler,
+};
+
+/// The scheduler of a [`Scenario`].
+///
+/// What the weight of a flow means depends on the scheduler,
+/// see the `add_flow` of each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchedulerConfig {
+    Fifo,
+    Rr,
+    Wrr,
+    Drr,
+    Dwrr,
+    Wfq,
+    Wf2q,
+    Sfq,
+    Scfq,
+    VirtualClock,
+    Edf,
+    Sp,
+    Cbs,
+    FqCodel,
+    Pfabric,
+    Lstf,
+    StochasticFq,
+    Qfq,
+}
+
+impl SchedulerConfig {
+    /// Every scheduler a scenario can use.
+    pub const ALL: [SchedulerConfig; 18] = [
+        SchedulerConfig::Fifo,
+        SchedulerConfig::Rr,
+        SchedulerConfig::Wrr,
+        SchedulerConfig::Drr,
+        SchedulerConfig::Dwrr,
+        SchedulerConfig::Wfq,
+        SchedulerConfig::Wf2q,
+        SchedulerConfig::Sfq,
+        SchedulerConfig::Scfq,
+        SchedulerConfig::VirtualClock,
+        SchedulerConfig::Edf,
+        SchedulerConfig::Sp,
+        SchedulerConfig::Cbs,
+        SchedulerConfig::FqCodel,
+        SchedulerConfig::Pfabric,
+        SchedulerConfig::Lstf,
+        SchedulerConfig::StochasticFq,
+        SchedulerConfig::Qfq,
+    ];
+
+    /// The name of the scheduler in a scenario file.
+    pub fn name(self) -> &'static str {
+        match self {
+            SchedulerConfig::Fifo => "fifo",
+            SchedulerConfig::Rr => "rr",
+            SchedulerConfig::Wrr => "wrr",
+            SchedulerConfig::Drr => "drr",
+            SchedulerConfig::Dwrr => "dwrr",
+            SchedulerConfig::Wfq => "wfq",
+            SchedulerConfig::Wf2q => "wf2q",
+            SchedulerConfig::Sfq => "sfq",
+            SchedulerConfig::Scfq => "scfq",
+            SchedulerConfig::VirtualClock => "virtual_clock",
+            SchedulerConfig::Edf => "edf",
+            SchedulerConfig::Sp => "sp",
+            SchedulerConfig::Cbs => "cbs",
+            SchedulerConfig::FqCodel => "fq_codel",
+            SchedulerConfig::Pfabric => "pfabric",
+            SchedulerConfig::Lstf => "lstf",
+            SchedulerConfig::StochasticFq => "stochastic_fq",
+            SchedulerConfig::Qfq => "qfq",
+        }
+    }
+
+    /// Whether the scheduler ranks flows by a key and takes a tie break.
+    pub fn takes_tie_break(self) -> bool {
+        matches!(
+            self,
+            SchedulerConfig::Wfq
+                | SchedulerConfig::Wf2q
+                | SchedulerConfig::Sfq
+                | SchedulerConfig::Scfq
+                | SchedulerConfig::VirtualClock
+                | SchedulerConfig::Edf
+                | SchedulerConfig::Lstf
+        )
+    }
+
+    /// Check the weight of a flow for the scheduler: a share must be
+    /// positive, and at least 1 once rounded for the schedulers counting
+    /// shares in whole packets or quanta, while a priority level or a
+    /// delay budget may be 0.
+    fn check_weight(self, weight: f64) -> Result<(), String> {
+        if !weight.is_finite() {
+            return Err(format!("the weight must be finite, not {}", weight));
+        }
+        match self {
+            SchedulerConfig::Sp
+            | SchedulerConfig::Cbs
+            | SchedulerConfig::Edf
+            | SchedulerConfig::Lstf => {
+                if weight < 0f64 {
+                    return Err(format!("the weight must not be negative, not {}", weight));
+                }
+            }
+            SchedulerConfig::Wrr | SchedulerConfig::Drr | SchedulerConfig::Dwrr => {
+                if weight.round() < 1f64 {
+                    return Err(format!(
+                        "{} rounds weights to whole numbers, {} rounds to 0",
+                        self, weight
+                    ));
+                }
+            }
+            _ => {
+                if weight <= 0f64 {
+                    return Err(format!("the weight must be positive, not {}", weight));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Build the scheduler, without flows, on a link of `bandwidth`,
+    /// see [`SchedulerConfig::takes_tie_break`].
+    pub fn build(
+        self,
+        bandwidth: usize,
+        tie_break: Option<TieBreak>,
+    ) -> io::Result<Box<dyn Scheduler>> {
+        fn tied<S: Scheduler + 'static>(
+            mut scheduler: S,
+            tie_break: Option<TieBreak>,
+            set: fn(&mut S, TieBreak),
+        ) -> Box<dyn Scheduler> {
+            if let Some(tie_break) = tie_break {
+                set(&mut scheduler, tie_break);
+            }
+            Box::new(scheduler)
+        }
+        let scheduler: Box<dyn Scheduler> = match self {
+            SchedulerConfig::Wfq => tied(
+                WFQScheduler::new(bandwidth),
+                tie_break,
+                WFQScheduler::set_tie_break,
+            ),
+            SchedulerConfig::Wf2q => tied(
+                WF2QPlusScheduler::new(bandwidth),
+                tie_break,
+                WF2QPlusScheduler::set_tie_break,
+            ),
+            SchedulerConfig::Sfq => tied(
+                SFQScheduler::new(bandwidth),
+                tie_break,
+                SFQScheduler::set_tie_break,
+            ),
+            SchedulerConfig::Scfq => tied(
+                SCFQScheduler::new(bandwidth),
+                tie_break,
+                SCFQScheduler::set_tie_break,
+            ),
+            SchedulerConfig::VirtualClock => tied(
+                VirtualClockScheduler::new(bandwidth),
+                tie_break,
+                VirtualClockScheduler::set_tie_break,
+            ),
+            SchedulerConfig::Edf => tied(
+                EDFScheduler::new(bandwidth),
+                tie_break,
+                EDFScheduler::set_tie_break,
+            ),
+            SchedulerConfig::Lstf => tied(
+                LSTFScheduler::new(bandwidth),
+                tie_break,
+                LSTFScheduler::set_tie_break,
+            ),
+            _ if tie_break.is_some() => {
+                return Err(invalid(format!("{} takes no tie break", self)));
+            }
+            SchedulerConfig::Fifo => Box::new(FIFOScheduler::new(bandwidth)),
+            SchedulerConfig::Rr => Box::new(RRScheduler::new(bandwidth)),
+            SchedulerConfig::Wrr => Box::new(WRRScheduler::new(bandwidth)),
+            SchedulerConfig::Drr => Box::new(DRRScheduler::new(bandwidth)),
+            SchedulerConfig::Dwrr => Box::new(DWRRScheduler::new(bandwidth)),
+            SchedulerConfig::Sp => Box::new(SPScheduler::new(bandwidth)),
+            SchedulerConfig::Cbs => Box::new(CBSScheduler::new(bandwidth)),
+            SchedulerConfig::FqCodel => Box::new(FQCoDelScheduler::new(bandwidth)),
+            SchedulerConfig::Pfabric => Box::new(PFabricScheduler::new(bandwidth)),
+            SchedulerConfig::StochasticFq => Box::new(StochasticFQScheduler::new(bandwidth)),
+            SchedulerConfig::Qfq => Box::new(QFQScheduler::new(bandwidth)),
+        };
+        Ok(scheduler)
+    }
+}
+
+impl fmt::Display for SchedulerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for SchedulerConfig {
+    type Err = io::Error;
+
+    fn from_str(name: &str) -> io::Result<SchedulerConfig> {
+        SchedulerConfig::ALL
+            .into_iter()
+            .find(|s| s.name() == name)
+            .ok_or_else(|| invalid(format!("unknown scheduler {}", name)))
+    }
+}
+
+/// A packet listed in a [`SourceConfig::Packets`] source.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PacketConfig {
+    /// Named after the flow and its position if left out.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub len: usize,
+    pub arrival: usize,
+}
+
+/// Where the packets of a flow come from, see [`crate::scheduling::traffic`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum SourceConfig {
+    /// `rate` packets per tick on average.
+    Poisson { rate: f64, size: SizeDistribution },
+    /// A packet of `len` bytes every `interval` ticks.
+    Cbr {
+        interval: usize,
+        len: usize,
+        #[serde(default)]
+        offset: usize,
+        #[serde(default)]
+        jitter: usize,
+    },
+    /// `rate` packets per tick during the ON periods.
+    OnOff {
+        rate: f64,
+        on: Period,
+        off: Period,
+        size: SizeDistribution,
+    },
+    /// The packets listed, in any order.
+    Packets { packets: Vec<PacketConfig> },
+}
+
+/// A flow of a [`Scenario`].
+///
+/// A generated source is cut off after `count` packets or at tick
+/// `until`, exactly one of which must be given. Both are ignored for
+/// listed packets.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FlowConfig {
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    #[serde(with = "serde_yaml::with::singleton_map_recursive")]
+    pub source: SourceConfig,
+    #[serde(default)]
+    pub count: Option<usize>,
+    #[serde(default)]
+    pub until: Option<usize>,
+    /// Seed of the source, the seed of the scenario plus the index of
+    /// the flow if left out.
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+fn default_weight() -> f64 {
+    1f64
+}
+
+impl FlowConfig {
+    /// Generate the packets of the flow with the given index.
+    pub fn build(&self, flow_idx: usize, seed: u64) -> io::Result<VariableLengthFlow> {
+        let prefix = format!("f{}_", flow_idx);
+        let seed = self.seed.unwrap_or(seed.wrapping_add(flow_idx as u64));
+        let positive = |value: f64, what: &str| {
+            if value > 0f64 {
+                Ok(())
+            } else {
+                Err(invalid(format!(
+                    "flow {}: {} must be positive",
+                    flow_idx, what
+                )))
+            }
+        };
+        match &self.source {
+            SourceConfig::Poisson { rate, size } => {
+                positive(*rate, "rate")?;
+                let source = PoissonSource::new(*rate, size.clone())
+                    .with_seed(seed)
+                    .with_prefix(prefix);
+                self.cut(flow_idx, source)
+            }
+            SourceConfig::Cbr {
+                interval,
+                len,
+                offset,
+                jitter,
+            } => {
+                positive(*interval as f64, "interval")?;
+                let source = CbrSource::new(*interval, *len)
+                    .with_offset(*offset)
+                    .with_jitter(*jitter)
+                    .with_seed(seed)
+                    .with_prefix(prefix);
+                self.cut(flow_idx, source)
+            }
+            SourceConfig::OnOff {
+                rate,
+                on,
+                off,
+                size,
+            } => {
+                positive(*rate, "rate")?;
+                let source = OnOffSource::new(*rate, *on, *off, size.clone())
+                    .with_seed(seed)
+                    .with_prefix(prefix);
+                self.cut(flow_idx, source)
+            }
+            SourceConfig::Packets { packets } => {
+                let mut flow = VariableLengthFlow::new();
+                for (idx, p) in packets.iter().enumerate() {
+                    let name = p
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| format!("{}{}", prefix, idx));
+                    flow.packet_arrive(Packet::new(name, p.len), p.arrival);
+                }
+                Ok(flow)
+            }
+        }
+    }
+
+    /// Cut off a generated source by `count` or `until`.
+    fn cut(
+        &self,
+        flow_idx: usize,
+        mut source: impl TrafficSource,
+    ) -> io::Result<VariableLengthFlow> {
+        match (self.count, self.until) {
+            (Some(count), None) => Ok(source.flow_of(count)),
+            (None, Some(end)) => Ok(source.flow_until(end)),
+            _ => Err(invalid(format!(
+                "flow {}: a generated source needs exactly one of count and until",
+                flow_idx
+            ))),
+        }
+    }
+}
+
+/// What a [`Scenario`] writes out once the run is over.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Every event of the run, as CSV.
+    #[serde(default)]
+    pub trace_csv: Option<PathBuf>,
+    /// Every event of the run, as JSON.
+    #[serde(default)]
+    pub trace_json: Option<PathBuf>,
+    /// The departed packets with their flows and departure times, as JSON.
+    #[serde(default)]
+    pub result_json: Option<PathBuf>,
+    /// Sample the queue lengths every so many ticks.
+    #[serde(default)]
+    pub queue_sampling: Option<usize>,
+}
+
+/// A full simulation, see the [module documentation](self) for the format.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    /// Link rate of the output port in bytes per tick.
+    pub bandwidth: usize,
+    pub scheduler: SchedulerConfig,
+    /// How the scheduler breaks ties, its default if left out.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "serde_yaml::with::singleton_map_recursive"
+    )]
+    pub tie_break: Option<TieBreak>,
+    pub flows: Vec<FlowConfig>,
+    /// Seed of the traffic sources.
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub output: OutputConfig,
+}
+
+impl Scenario {
+    pub fn from_toml(text: &str) -> io::Result<Scenario> {
+        toml::from_str(text).map_err(|e| invalid(e.to_string()))
+    }
+
+    pub fn from_yaml(text: &str) -> io::Result<Scenario> {
+        serde_yaml::from_str(text).map_err(|e| invalid(e.to_string()))
+    }
+
+    pub fn to_toml(&self) -> io::Result<String> {
+        toml::to_string(self).map_err(|e| invalid(e.to_string()))
+    }
+
+    pub fn to_yaml(&self) -> io::Result<String> {
+        serde_yaml::to_string(self).map_err(|e| invalid(e.to_string()))
+    }
+
+    /// Read a scenario from a `.toml`, `.yaml` or `.yml` file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Scenario> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Scenario::from_toml(&text),
+            Some("yaml" | "yml") => Scenario::from_yaml(&text),
+            _ => Err(invalid(format!(
+                "{}: expected a .toml, .yaml or .yml file",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Build the scheduler with every flow added, ready to run.
+    pub fn build(&self) -> io::Result<Box<dyn Scheduler>> {
+        if self.bandwidth == 0 {
+            return Err(invalid("the bandwidth must be positive".to_string()));
+        }
+        let mut scheduler = self.scheduler.build(self.bandwidth, self.tie_break)?;
+        for (idx, spec) in self.flows.iter().enumerate() {
+            self.scheduler
+                .check_weight(spec.weight)
+                .map_err(|e| invalid(format!("flow {}: {}", idx, e)))?;
+            let flow: Box<dyn Flow> = Box::new(spec.build(idx, self.seed)?);
+            scheduler.add_flow(flow, spec.weight);
+        }
+        scheduler.set_queue_sampling(self.output.queue_sampling);
+        Ok(scheduler)
+    }
+
+    /// Build the scheduler, run it to the end and write the outputs.
+    /// Returns the scheduler for further inspection.
+    pub fn run(&self) -> io::Result<Box<dyn Scheduler>> {
+        let mut scheduler = self.build()?;
+        scheduler.run();
+
+        let output = &self.output;
+        if let Some(path) = &output.trace_csv {
+            scheduler
+                .trace()
+                .write_csv(io::BufWriter::new(fs::File::create(path)?))?;
+        }
+        if let Some(path) = &output.trace_json {
+            scheduler
+                .trace()
+                .write_json(io::BufWriter::new(fs::File::create(path)?))
+                .map_err(io::Error::from)?;
+        }
+        if let Some(path) = &output.result_json {
+            serde_json::to_writer(
+                io::BufWriter::new(fs::File::create(path)?),
+                &scheduler.result(),
+            )
+            .map_err(io::Error::from)?;
+        }
+        Ok(scheduler)
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        schedulers::tie_break::TieBreak, traffic::SizeDistribution, FlowId, SchedulerOutput,
+    };
+
+    use super::{Scenario, SchedulerConfig, SourceConfig};
+
+    const TOML: &str = r#"
+        bandwidth = 1
+        seed = 7
+        scheduler = "wfq"
+        tie_break = "RoundRobin"
+
+        [[flows]]
+        weight = 2
+        count = 20
+        source = { poisson = { rate = 0.3, size = { fixed = 1 } } }
+
+        [[flows]]
+        until = 40
+        source = { cbr = { interval = 4, len = 1 } }
+
+        [[flows]]
+        source = { packets = { packets = [
+            { len = 2, arrival = 0 },
+            { name = "late", len = 1, arrival = 30 },
+        ] } }
+    "#;
+
+    #[test]
+    fn scenario_toml_test() {
+        let scenario = Scenario::from_toml(TOML).unwrap();
+        assert_eq!(scenario.scheduler, SchedulerConfig::Wfq);
+        assert_eq!(scenario.tie_break, Some(TieBreak::RoundRobin));
+        assert_eq!(scenario.flows[1].weight, 1f64);
+        assert_eq!(
+            scenario.flows[0].source,
+            SourceConfig::Poisson {
+                rate: 0.3,
+                size: SizeDistribution::Fixed(1)
+            }
+        );
+
+        let scheduler = scenario.run().unwrap();
+        let stats = scheduler.stats();
+        assert_eq!(stats[0].packets, 20);
+        assert_eq!(stats[1].packets, 10);
+        assert_eq!(stats[2].packets, 2);
+        assert!(scheduler.output().iter().any(|p| p.name == "late"));
+        assert!(scheduler.output().iter().any(|p| p.name == "f2_0"));
+
+        // The same file replays the same run, and so does its YAML form.
+        assert_eq!(scenario.run().unwrap().result(), scheduler.result());
+        let yaml = Scenario::from_yaml(&scenario.to_yaml().unwrap()).unwrap();
+        assert_eq!(yaml, scenario);
+        let toml = Scenario::from_toml(&scenario.to_toml().unwrap()).unwrap();
+        assert_eq!(toml, scenario);
+    }
+
+    #[test]
+    fn scenario_output_test() {
+        let dir = std::env::temp_dir().join(format!("rnetv-scenario-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("scenario.yaml");
+        std::fs::write(
+            &path,
+            format!(
+                "bandwidth: 1\n\
+                 scheduler: drr\n\
+                 flows:\n  \
+                   - {{ weight: 2, count: 5, source: {{ cbr: {{ interval: 1, len: 1 }} }} }}\n\
+                 output:\n  \
+                   trace_csv: {}\n  \
+                   result_json: {}\n",
+                dir.join("trace.csv").display(),
+                dir.join("result.json").display()
+            ),
+        )
+        .unwrap();
+
+        let scheduler = Scenario::load(&path).unwrap().run().unwrap();
+        let csv = std::fs::read_to_string(dir.join("trace.csv")).unwrap();
+        assert_eq!(csv, scheduler.trace().to_csv());
+        let json = std::fs::read_to_string(dir.join("result.json")).unwrap();
+        let result: SchedulerOutput = serde_json::from_str(&json).unwrap();
+        assert_eq!(result.flows, vec![FlowId(0); 5]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn scenario_invalid_test() {
+        // Neither count nor until.
+        let scenario = Scenario::from_toml(
+            r#"
+            bandwidth = 1
+            scheduler = "fifo"
+            flows = [{ source = { poisson = { rate = 1.0, size = { fixed = 1 } } } }]
+            "#,
+        )
+        .unwrap();
+        let error = scenario.build().err().unwrap();
+        assert!(error.to_string().contains("count and until"));
+
+        // Unknown schedulers and fields are rejected.
+        assert!(Scenario::from_toml("bandwidth = 1\nflows = []\nscheduler = \"magic\"").is_err());
+        assert!(
+            Scenario::from_toml("bandwidth = 1\nflows = []\nlink = 3\nscheduler = \"rr\"").is_err()
+        );
+        assert!(Scenario::load("scenario.json").is_err());
+    }
+
+    #[test]
+    fn scenario_weight_test() {
+        let scenario = |scheduler: &str, weight: &str| {
+            Scenario::from_toml(&format!(
+                r#"
+                bandwidth = 1
+                scheduler = "{}"
+                flows = [{{ weight = {}, count = 5, source = {{ cbr = {{ interval = 1, len = 1 }} }} }}]
+                "#,
+                scheduler, weight
+            ))
+            .unwrap()
+        };
+        let error = |scheduler: &str, weight: &str| {
+            scenario(scheduler, weight)
+                .build()
+                .err()
+                .unwrap_or_else(|| panic!("{} takes a weight of {}", scheduler, weight))
+                .to_string()
+        };
+
+        // Shares must be positive and finite.
+        assert!(error("virtual_clock", "0").contains("positive"));
+        assert!(error("wfq", "-1.0").contains("positive"));
+        assert!(error("wf2q", "nan").contains("finite"));
+        assert!(error("qfq", "inf").contains("finite"));
+
+        // Whole shares must not round to 0.
+        for scheduler in ["wrr", "drr", "dwrr"] {
+            assert!(error(scheduler, "0.3").contains("rounds to 0"));
+            assert_eq!(scenario(scheduler, "0.6").run().unwrap().output().len(), 5);
+        }
+
+        // A priority level or a delay budget may be 0, but not negative.
+        assert_eq!(scenario("sp", "0").run().unwrap().output().len(), 5);
+        assert_eq!(scenario("edf", "0").run().unwrap().output().len(), 5);
+        assert!(error("sp", "-1").contains("negative"));
+    }
+
+    #[test]
+    fn scheduler_config_name_test() {
+        // The names are those of the scenario files.
+        for scheduler in SchedulerConfig::ALL {
+            assert_eq!(
+                scheduler.name().parse::<SchedulerConfig>().unwrap(),
+                scheduler
+            );
+            let toml = format!("bandwidth = 1\nflows = []\nscheduler = \"{}\"", scheduler);
+            assert_eq!(Scenario::from_toml(&toml).unwrap().scheduler, scheduler);
+        }
+        assert!("magic".parse::<SchedulerConfig>().is_err());
+    }
+}
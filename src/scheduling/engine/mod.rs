@@ -0,0 +1,478 @@
+//! Discrete-event simulation core.
+//!
+//! Instead of stepping through every tick, the [`Engine`] jumps from one
+//! event to the next: packet arrivals, the end of a transmission, and
+//! timers set by the scheduler. Time is continuous, so the work done
+//! grows with the number of packets rather than with the simulated time,
+//! and long simulations of sparse traffic run fast.
+//!
+//! Schedulers are ported to the engine by implementing [`EventScheduler`],
+//! as done by the [`FIFOScheduler`](super::schedulers::fifo::FIFOScheduler),
+//! the [`DRRScheduler`](super::schedulers::drr::DRRScheduler), the
+//! [`SCFQScheduler`](super::schedulers::scfq::SCFQScheduler) and the
+//! [`TokenBucket`](super::shaping::TokenBucket) shaper. They are given one
+//! [`OpenFlow`](super::flow::OpenFlow) per flow of the engine beforehand,
+//! with its weight, and see the times of the engine rounded down to ticks,
+//! while the link transmits in continuous time. Their output port is left
+//! unused.
+
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use crate::scheduling::{flow::VariableLengthFlow, Packet};
+
+/// What happens in an [`Event`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventKind {
+    /// A packet of a flow arrives at the scheduler.
+    Arrival { flow_idx: usize, packet: Packet },
+    /// The link finished transmitting the packet in service.
+    TransmissionEnd,
+    /// A timer set by the scheduler expired.
+    Timer,
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub time: f64,
+    /// Insertion order, which orders events of the same time.
+    seq: u64,
+    pub kind: EventKind,
+}
+
+impl PartialEq for Event {
+    fn eq(&self, other: &Event) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Event {}
+
+impl PartialOrd for Event {
+    fn partial_cmp(&self, other: &Event) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Event {
+    /// Reversed, so that the heap of the event list pops the earliest event.
+    fn cmp(&self, other: &Event) -> Ordering {
+        other
+            .time
+            .total_cmp(&self.time)
+            .then(other.seq.cmp(&self.seq))
+    }
+}
+
+/// The events still to happen, earliest first.
+/// Events of the same time come out in the order they were pushed.
+#[derive(Debug, Clone, Default)]
+pub struct FutureEventList {
+    heap: BinaryHeap<Event>,
+    seq: u64,
+}
+
+impl FutureEventList {
+    pub fn new() -> FutureEventList {
+        FutureEventList::default()
+    }
+
+    pub fn push(&mut self, time: f64, kind: EventKind) {
+        assert!(time.is_finite(), "events must happen at a finite time");
+        self.heap.push(Event {
+            time,
+            seq: self.seq,
+            kind,
+        });
+        self.seq += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<Event> {
+        self.heap.pop()
+    }
+
+    /// The time of the earliest event.
+    pub fn next_time(&self) -> Option<f64> {
+        self.heap.peek().map(|e| e.time)
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+}
+
+/// The answer of an [`EventScheduler`] asked for a packet.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Dequeue {
+    /// Transmit this packet of the flow with the given index.
+    Packet(usize, Packet),
+    /// Nothing to send until a packet arrives.
+    Idle,
+    /// Nothing to send now, ask again at the given time,
+    /// or when a packet arrives.
+    WaitUntil(f64),
+}
+
+/// The tag in which a packet carries its arrival time through the
+/// scheduler of an [`Engine`], so that the engine matches every packet
+/// leaving the scheduler with its own arrival.
+pub const ARRIVAL_TAG: &str = "engine.arrival";
+
+/// A scheduler driven by the [`Engine`].
+///
+/// The engine hands over the packets as they arrive and asks for the
+/// next packet whenever the link is free, at times that never go back.
+/// A scheduler may drop or reorder packets, but has to keep the
+/// [`ARRIVAL_TAG`] of those it hands back.
+///
+/// A scheduler working in ticks may round the times down to ticks: it
+/// then tells apart the packets arriving within a tick only as it would
+/// on that tick, and waits until whole ticks. The records of the engine
+/// keep the exact times.
+pub trait EventScheduler {
+    fn enqueue(&mut self, flow_idx: usize, packet: Packet, time: f64);
+
+    fn dequeue(&mut self, time: f64) -> Dequeue;
+}
+
+/// The life of a packet through the [`Engine`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineRecord {
+    pub flow_idx: usize,
+    pub name: String,
+    pub len: usize,
+    pub arrival: f64,
+    /// When the packet started transmitting.
+    pub start: f64,
+    pub departure: f64,
+}
+
+impl EngineRecord {
+    /// Time between the arrival and the departure of the packet.
+    pub fn delay(&self) -> f64 {
+        self.departure - self.arrival
+    }
+}
+
+/// A discrete-event simulation of a scheduler feeding a link.
+pub struct Engine<S: EventScheduler> {
+    scheduler: S,
+    /// Bytes the link transmits per unit of time.
+    rate: f64,
+    time: f64,
+    events: FutureEventList,
+    in_service: Option<EngineRecord>,
+    /// Time of the earliest pending timer.
+    timer: Option<f64>,
+    records: Vec<EngineRecord>,
+    processed: usize,
+}
+
+impl<S: EventScheduler> Engine<S> {
+    pub fn new(scheduler: S, rate: f64) -> Engine<S> {
+        assert!(rate > 0f64, "the link needs a positive rate");
+        Engine {
+            scheduler,
+            rate,
+            time: 0f64,
+            events: FutureEventList::new(),
+            in_service: None,
+            timer: None,
+            records: Vec::new(),
+            processed: 0,
+        }
+    }
+
+    /// Schedule the arrival of a packet of a flow.
+    pub fn add_arrival(&mut self, flow_idx: usize, packet: Packet, time: f64) {
+        self.events
+            .push(time, EventKind::Arrival { flow_idx, packet });
+    }
+
+    /// Schedule the packets of a flow, arriving at their ticks.
+    pub fn add_flow(&mut self, flow_idx: usize, flow: &VariableLengthFlow) {
+        for (packet, time) in &flow.packet_states {
+            self.add_arrival(flow_idx, packet.clone(), *time as f64);
+        }
+    }
+
+    pub fn scheduler(&self) -> &S {
+        &self.scheduler
+    }
+
+    /// The time of the last event processed.
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// The packets that left the link, in departure order.
+    pub fn records(&self) -> &[EngineRecord] {
+        &self.records
+    }
+
+    /// The number of events processed so far.
+    pub fn processed(&self) -> usize {
+        self.processed
+    }
+
+    /// Process every event.
+    pub fn run(&mut self) -> &[EngineRecord] {
+        self.run_until(f64::INFINITY)
+    }
+
+    /// Process the events up to and including time `end`.
+    ///
+    /// All events of the same time are processed before the scheduler is
+    /// asked for a packet, so that it chooses among every packet that
+    /// arrived at that time.
+    pub fn run_until(&mut self, end: f64) -> &[EngineRecord] {
+        while let Some(time) = self.events.next_time().filter(|&t| t <= end) {
+            self.time = time;
+            while self.events.next_time() == Some(time) {
+                let event = self.events.pop().unwrap();
+                self.process(event);
+            }
+            self.transmit();
+        }
+        &self.records
+    }
+
+    fn process(&mut self, event: Event) {
+        self.processed += 1;
+        match event.kind {
+            EventKind::Arrival { flow_idx, packet } => {
+                let packet = packet.with_tag(ARRIVAL_TAG, event.time.to_string());
+                self.scheduler.enqueue(flow_idx, packet, event.time);
+            }
+            EventKind::TransmissionEnd => {
+                let mut record = self.in_service.take().unwrap();
+                record.departure = event.time;
+                self.records.push(record);
+            }
+            EventKind::Timer => {
+                if self.timer == Some(event.time) {
+                    self.timer = None;
+                }
+            }
+        }
+    }
+
+    /// Start transmitting the next packet if the link is free.
+    fn transmit(&mut self) {
+        if self.in_service.is_some() {
+            return;
+        }
+        match self.scheduler.dequeue(self.time) {
+            Dequeue::Packet(flow_idx, packet) => {
+                let arrival = packet
+                    .tag(ARRIVAL_TAG)
+                    .and_then(|time| time.parse().ok())
+                    .expect("a dequeued packet lost its arrival time");
+                let departure = self.time + packet.len as f64 / self.rate;
+                self.events.push(departure, EventKind::TransmissionEnd);
+                self.in_service = Some(EngineRecord {
+                    flow_idx,
+                    name: packet.name,
+                    len: packet.len,
+                    arrival,
+                    start: self.time,
+                    departure,
+                });
+            }
+            Dequeue::WaitUntil(time) => {
+                if self.timer.is_none_or(|timer| time < timer) {
+                    self.timer = Some(time);
+                    self.events.push(time, EventKind::Timer);
+                }
+            }
+            Dequeue::Idle => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, OpenFlow, VariableLengthFlow},
+        schedulers::{drr::DRRScheduler, fifo::FIFOScheduler, scfq::SCFQScheduler, Scheduler},
+        shaping::TokenBucket,
+        Packet, Port,
+    };
+
+    use super::{Engine, EngineRecord, EventKind, FutureEventList};
+
+    /// A FIFO scheduler ready for the engine, with `count` flows.
+    fn open_fifo(count: usize) -> FIFOScheduler {
+        let mut fifo = FIFOScheduler::new(1);
+        for _ in 0..count {
+            fifo.add_flow(OpenFlow::new());
+        }
+        fifo
+    }
+
+    fn engine_names(records: &[EngineRecord]) -> Vec<String> {
+        records.iter().map(|r| r.name.clone()).collect()
+    }
+
+    fn port_names(port: &Port) -> Vec<String> {
+        port.get_output().iter().map(|p| p.name.clone()).collect()
+    }
+
+    #[test]
+    fn future_event_list_test() {
+        let mut events = FutureEventList::new();
+        events.push(2.5, EventKind::Timer);
+        events.push(1f64, EventKind::TransmissionEnd);
+        events.push(2.5, EventKind::TransmissionEnd);
+        events.push(0.5, EventKind::Timer);
+        let popped: Vec<(f64, EventKind)> =
+            std::iter::from_fn(|| events.pop().map(|e| (e.time, e.kind))).collect();
+        assert_eq!(
+            popped,
+            vec![
+                (0.5, EventKind::Timer),
+                (1f64, EventKind::TransmissionEnd),
+                (2.5, EventKind::Timer),
+                (2.5, EventKind::TransmissionEnd),
+            ]
+        );
+    }
+
+    #[test]
+    fn engine_test() {
+        let mut a = VariableLengthFlow::new();
+        a.packet_arrive(Packet::new("a0", 2), 0);
+        a.packet_arrive(Packet::new("a1", 1), 1);
+        a.packet_arrive(Packet::new("a2", 3), 9);
+        let mut b = VariableLengthFlow::new();
+        b.packet_arrive(Packet::new("b0", 1), 0);
+        b.packet_arrive(Packet::new("b1", 2), 4);
+
+        // On integer times, the engine departs packets when the ticks do.
+        let mut fifo = FIFOScheduler::new(1);
+        fifo.add_flow(a.clone());
+        fifo.add_flow(b.clone());
+        fifo.run();
+        let mut engine = Engine::new(open_fifo(2), 1f64);
+        engine.add_flow(0, &a);
+        engine.add_flow(1, &b);
+        let records = engine.run();
+        let names: Vec<&str> = records.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["a0", "b0", "a1", "b1", "a2"]);
+        let departures: Vec<usize> = records.iter().map(|r| r.departure as usize).collect();
+        assert_eq!(
+            departures,
+            fifo.get_output_port().get_departure_times().to_vec()
+        );
+        assert_eq!(records[2].start, 3f64);
+        assert_eq!(records[2].delay(), 3f64);
+
+        // Sparse traffic costs a handful of events, whatever the time span.
+        let mut engine = Engine::new(open_fifo(1), 4f64);
+        engine.add_arrival(0, Packet::new("early", 2), 0.25);
+        engine.add_arrival(0, Packet::new("late", 2), 1e12);
+        engine.run_until(1f64);
+        assert_eq!(engine.records().len(), 1);
+        assert_eq!(engine.records()[0].departure, 0.75);
+        engine.run();
+        assert_eq!(engine.records()[1].departure, 1e12 + 0.5);
+        assert_eq!(engine.processed(), 4);
+
+        // The FIFO sees both arrivals on tick 0 and takes them in the
+        // order of their flows, while the records keep the exact times.
+        let mut engine = Engine::new(open_fifo(2), 1f64);
+        engine.add_arrival(0, Packet::new("first", 1), 0f64);
+        engine.add_arrival(0, Packet::new("a", 1), 0.7);
+        engine.add_arrival(1, Packet::new("b", 1), 0.2);
+        let records = engine.run();
+        assert_eq!(engine_names(records), ["first", "a", "b"]);
+        let arrivals: Vec<f64> = records.iter().map(|r| r.arrival).collect();
+        assert_eq!(arrivals, vec![0f64, 0.7, 0.2]);
+    }
+
+    #[test]
+    fn engine_drr_test() {
+        let mut a = VariableLengthFlow::new();
+        let mut b = VariableLengthFlow::new();
+        for p in 0..6 {
+            a.packet_arrive(Packet::new(format!("a{}", p), 1), 0);
+            b.packet_arrive(Packet::new(format!("b{}", p), 2), 0);
+        }
+
+        // The engine sends the rounds of the tick scheduler one packet
+        // at a time.
+        let mut drr = DRRScheduler::new(1);
+        drr.add_flow(a.clone(), 2);
+        drr.add_flow(b.clone(), 1);
+        drr.run();
+        let mut open = DRRScheduler::new(1);
+        open.add_flow(OpenFlow::new(), 2);
+        open.add_flow(OpenFlow::new(), 1);
+        let mut engine = Engine::new(open, 1f64);
+        engine.add_flow(0, &a);
+        engine.add_flow(1, &b);
+        let records = engine.run();
+        assert_eq!(records.len(), 12);
+        assert_eq!(engine_names(records), port_names(drr.get_output_port()));
+    }
+
+    #[test]
+    fn engine_scfq_test() {
+        let mut a = VariableLengthFlow::new();
+        let mut b = VariableLengthFlow::new();
+        for p in 0..8 {
+            a.packet_arrive(Packet::new(format!("a{}", p), 1), p / 2);
+            b.packet_arrive(Packet::new(format!("b{}", p), 1), p);
+        }
+
+        let mut scfq = SCFQScheduler::new(1);
+        scfq.add_flow(a.clone(), 3f64);
+        scfq.add_flow(b.clone(), 1f64);
+        scfq.run();
+        let mut open = SCFQScheduler::new(1);
+        open.add_flow(OpenFlow::new(), 3f64);
+        open.add_flow(OpenFlow::new(), 1f64);
+        let mut engine = Engine::new(open, 1f64);
+        engine.add_flow(0, &a);
+        engine.add_flow(1, &b);
+        let records = engine.run();
+        assert_eq!(engine_names(records), port_names(scfq.get_output_port()));
+
+        // Arrivals between ticks are stamped at the tick before, but the
+        // link starts them when they arrive.
+        let mut open = SCFQScheduler::new(1);
+        open.add_flow(OpenFlow::new(), 1f64);
+        let mut engine = Engine::new(open, 2f64);
+        engine.add_arrival(0, Packet::new("p", 1), 0.5);
+        let records = engine.run();
+        assert_eq!(records[0].start, 0.5);
+        assert_eq!(records[0].departure, 1f64);
+    }
+
+    #[test]
+    fn engine_token_bucket_test() {
+        // A burst of 2 bytes, then 2 bytes every 2 units of time.
+        let mut engine = Engine::new(TokenBucket::new(1, 2), 10f64);
+        for p in 0..4 {
+            engine.add_arrival(0, Packet::new(format!("p{}", p), 2), 0f64);
+        }
+        engine.add_arrival(1, Packet::new("q", 2), 7f64);
+        let records = engine.run();
+        let starts: Vec<f64> = records.iter().map(|r| r.start).collect();
+        assert_eq!(starts, vec![0f64, 2f64, 4f64, 6f64, 8f64]);
+        assert_eq!(records[4].flow_idx, 1);
+
+        // A packet longer than the bucket is dropped, and the next packet
+        // of its flow still leaves with its own arrival time.
+        let mut engine = Engine::new(TokenBucket::new(1, 2), 10f64);
+        engine.add_arrival(0, Packet::new("long", 3), 0f64);
+        engine.add_arrival(0, Packet::new("p", 2), 1.5);
+        let records = engine.run();
+        assert_eq!(engine_names(records), ["p"]);
+        assert_eq!(records[0].arrival, 1.5);
+        assert_eq!(engine.scheduler().dropped().len(), 1);
+    }
+}
@@ -0,0 +1,427 @@
+use std::{collections::HashMap, fmt};
+
+use crate::scheduling::{
+    flow::{Flow, VariableLengthFlow},
+    schedulers::{
+        drr::DRRScheduler, fifo::FIFOScheduler, rr::RRScheduler, wfq::WFQScheduler,
+        wrr::WRRScheduler,
+    },
+    stats::fairness::jain_index,
+    Packet, Port, Scheduler,
+};
+
+/// The schedulers that can be evaluated on a scenario.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SchedulerKind {
+    WFQ,
+    DRR,
+    WRR,
+    /// No-QoS baseline serving packets in arrival order.
+    FIFO,
+    /// No-QoS baseline serving one packet per flow in turn.
+    RR,
+}
+
+impl fmt::Display for SchedulerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchedulerKind::WFQ => write!(f, "WFQ"),
+            SchedulerKind::DRR => write!(f, "DRR"),
+            SchedulerKind::WRR => write!(f, "WRR"),
+            SchedulerKind::FIFO => write!(f, "FIFO"),
+            SchedulerKind::RR => write!(f, "RR"),
+        }
+    }
+}
+
+/// A flow of a scenario: its weight and its packets with arrival times.
+///
+/// The weight is used as the WFQ weight, the DRR quantum
+/// and the number of packets per WRR round. FIFO and RR ignore it.
+#[derive(Debug, Clone)]
+pub struct FlowSpec {
+    pub weight: usize,
+    pub packets: Vec<(Packet, usize)>,
+}
+
+/// A workload that can be replayed on any scheduler.
+///
+/// Packet names must be unique across the scenario,
+/// since they are used to match departures with arrivals.
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    pub bandwidth: usize,
+    pub flows: Vec<FlowSpec>,
+}
+
+impl Scenario {
+    pub fn new(bandwidth: usize) -> Scenario {
+        Scenario {
+            bandwidth,
+            flows: Vec::new(),
+        }
+    }
+
+    pub fn add_flow(&mut self, weight: usize, packets: Vec<(Packet, usize)>) {
+        self.flows.push(FlowSpec { weight, packets });
+    }
+}
+
+/// The metrics of one scheduler on a scenario.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchedulerMetrics {
+    pub kind: SchedulerKind,
+    pub mean_delay: f64,
+    pub p99_delay: f64,
+    /// Jain's fairness index of the weight-normalized flow throughputs.
+    pub fairness: f64,
+    /// Fraction of the link capacity used until the last departure.
+    pub utilization: f64,
+    pub drops: usize,
+    /// Value of the objective, lower is better.
+    pub score: f64,
+    /// Whether the fairness constraint of the objective is met.
+    pub feasible: bool,
+}
+
+/// A weighted objective to minimize, subject to a minimum fairness.
+///
+/// The score of a scheduler is
+/// `mean_delay_weight * mean_delay + p99_delay_weight * p99_delay
+///  + unfairness_weight * (1 - fairness) + idle_weight * (1 - utilization)
+///  + drop_weight * drops`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Objective {
+    pub mean_delay_weight: f64,
+    pub p99_delay_weight: f64,
+    pub unfairness_weight: f64,
+    pub idle_weight: f64,
+    pub drop_weight: f64,
+    pub min_fairness: f64,
+}
+
+impl Objective {
+    /// Minimize the p99 delay subject to `fairness >= min_fairness`.
+    pub fn minimize_p99_delay(min_fairness: f64) -> Objective {
+        Objective {
+            mean_delay_weight: 0f64,
+            p99_delay_weight: 1f64,
+            unfairness_weight: 0f64,
+            idle_weight: 0f64,
+            drop_weight: 0f64,
+            min_fairness,
+        }
+    }
+
+    fn score(&self, metrics: &SchedulerMetrics) -> f64 {
+        self.mean_delay_weight * metrics.mean_delay
+            + self.p99_delay_weight * metrics.p99_delay
+            + self.unfairness_weight * (1f64 - metrics.fairness)
+            + self.idle_weight * (1f64 - metrics.utilization)
+            + self.drop_weight * metrics.drops as f64
+    }
+}
+
+/// The result of [`evaluate`].
+#[derive(Debug, Clone)]
+pub struct EvaluationReport {
+    /// Metrics of every evaluated scheduler, best score first.
+    pub rows: Vec<SchedulerMetrics>,
+    /// The best scheduler meeting the fairness constraint, if any.
+    pub recommended: Option<SchedulerKind>,
+}
+
+impl fmt::Display for EvaluationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<6}{:>12}{:>12}{:>10}{:>13}{:>7}{:>10}",
+            "sched", "mean delay", "p99 delay", "fairness", "utilization", "drops", "score"
+        )?;
+        for row in &self.rows {
+            writeln!(
+                f,
+                "{:<6}{:>12.3}{:>12.3}{:>10.3}{:>13.3}{:>7}{:>10.3}{}",
+                row.kind.to_string(),
+                row.mean_delay,
+                row.p99_delay,
+                row.fairness,
+                row.utilization,
+                row.drops,
+                row.score,
+                if row.feasible { "" } else { " (infeasible)" }
+            )?;
+        }
+        match self.recommended {
+            Some(kind) => write!(f, "recommended: {}", kind),
+            None => write!(f, "recommended: none"),
+        }
+    }
+}
+
+/// Run each scheduler on the scenario and rank them by the objective.
+pub fn evaluate(
+    scenario: &Scenario,
+    schedulers: &[SchedulerKind],
+    objective: &Objective,
+) -> EvaluationReport {
+    let mut rows: Vec<SchedulerMetrics> = schedulers
+        .iter()
+        .map(|&kind| {
+            let mut metrics = metrics(scenario, kind);
+            metrics.score = objective.score(&metrics);
+            metrics.feasible = metrics.fairness >= objective.min_fairness;
+            metrics
+        })
+        .collect();
+    rows.sort_by(|a, b| a.score.total_cmp(&b.score));
+
+    let recommended = rows.iter().find(|row| row.feasible).map(|row| row.kind);
+    EvaluationReport { rows, recommended }
+}
+
+/// Run the scenario on a scheduler and measure the run,
+/// without scoring it against an objective.
+pub fn metrics(scenario: &Scenario, kind: SchedulerKind) -> SchedulerMetrics {
+    let departures = simulate(scenario, kind);
+    measure(scenario, kind, &departures)
+}
+
+/// Run the scenario on a scheduler and return every output packet
+/// with the tick at which it left the output port.
+fn simulate(scenario: &Scenario, kind: SchedulerKind) -> Vec<(Packet, usize)> {
+    match kind {
+        SchedulerKind::WFQ => {
+            let mut scheduler = WFQScheduler::new(scenario.bandwidth);
+            for spec in &scenario.flows {
+                let mut flow = VariableLengthFlow::new();
+                for (packet, time) in &spec.packets {
+                    flow.packet_arrive(packet.clone(), *time);
+                }
+                scheduler.add_flow(flow, spec.weight as f64);
+            }
+            drive(&mut scheduler, WFQScheduler::get_output_port)
+        }
+        SchedulerKind::DRR => {
+            let mut scheduler = DRRScheduler::new(scenario.bandwidth);
+            for spec in &scenario.flows {
+                let mut flow = VariableLengthFlow::new();
+                for (packet, time) in &spec.packets {
+                    flow.packet_arrive(packet.clone(), *time);
+                }
+                scheduler.add_flow(flow, spec.weight);
+            }
+            drive(&mut scheduler, DRRScheduler::get_output_port)
+        }
+        SchedulerKind::WRR => {
+            let mut scheduler = WRRScheduler::new(scenario.bandwidth);
+            for spec in &scenario.flows {
+                let mut flow = VariableLengthFlow::new();
+                for (packet, time) in &spec.packets {
+                    flow.packet_arrive(packet.clone(), *time);
+                }
+                scheduler.add_flow(flow, spec.weight);
+            }
+            drive(&mut scheduler, WRRScheduler::get_output_port)
+        }
+        SchedulerKind::FIFO => {
+            let mut scheduler = FIFOScheduler::new(scenario.bandwidth);
+            for spec in &scenario.flows {
+                let mut flow = VariableLengthFlow::new();
+                for (packet, time) in &spec.packets {
+                    flow.packet_arrive(packet.clone(), *time);
+                }
+                scheduler.add_flow(flow);
+            }
+            drive(&mut scheduler, FIFOScheduler::get_output_port)
+        }
+        SchedulerKind::RR => {
+            let mut scheduler = RRScheduler::new(scenario.bandwidth);
+            for spec in &scenario.flows {
+                let mut flow = VariableLengthFlow::new();
+                for (packet, time) in &spec.packets {
+                    flow.packet_arrive(packet.clone(), *time);
+                }
+                scheduler.add_flow(flow);
+            }
+            drive(&mut scheduler, RRScheduler::get_output_port)
+        }
+    }
+}
+
+/// Run the scheduler and pair each output packet with its departure time.
+fn drive<S: Scheduler>(
+    scheduler: &mut S,
+    output_port: fn(&mut S) -> &mut Port,
+) -> Vec<(Packet, usize)> {
+    scheduler.run();
+    let port = output_port(scheduler);
+    port.get_output()
+        .iter()
+        .cloned()
+        .zip(port.get_departure_times().iter().copied())
+        .collect()
+}
+
+fn measure(
+    scenario: &Scenario,
+    kind: SchedulerKind,
+    departures: &[(Packet, usize)],
+) -> SchedulerMetrics {
+    let arrivals: HashMap<&str, (usize, usize)> = scenario
+        .flows
+        .iter()
+        .enumerate()
+        .flat_map(|(idx, spec)| {
+            spec.packets
+                .iter()
+                .map(move |(p, t)| (p.name.as_str(), (idx, *t)))
+        })
+        .collect();
+
+    let mut delays = Vec::with_capacity(departures.len());
+    let mut bytes = vec![0usize; scenario.flows.len()];
+    let mut first_arrival = vec![usize::MAX; scenario.flows.len()];
+    let mut last_departure = vec![0usize; scenario.flows.len()];
+    for (packet, departure) in departures {
+        let (idx, arrival) = arrivals[packet.name.as_str()];
+        delays.push((departure - arrival) as f64);
+        bytes[idx] += packet.len;
+        first_arrival[idx] = first_arrival[idx].min(arrival);
+        last_departure[idx] = last_departure[idx].max(*departure);
+    }
+
+    let normalized: Vec<f64> = (0..scenario.flows.len())
+        .filter(|&idx| bytes[idx] > 0)
+        .map(|idx| {
+            let span = (last_departure[idx] - first_arrival[idx]).max(1) as f64;
+            bytes[idx] as f64 / span / scenario.flows[idx].weight.max(1) as f64
+        })
+        .collect();
+
+    let offered: usize = scenario.flows.iter().map(|f| f.packets.len()).sum();
+    let makespan = departures.iter().map(|(_, t)| *t).max().unwrap_or(0);
+    let total_bytes: usize = bytes.iter().sum();
+
+    SchedulerMetrics {
+        kind,
+        mean_delay: mean(&delays),
+        p99_delay: percentile(&mut delays, 0.99),
+        fairness: jain_index(&normalized),
+        utilization: if makespan == 0 {
+            0f64
+        } else {
+            total_bytes as f64 / (makespan * scenario.bandwidth) as f64
+        },
+        drops: offered - departures.len(),
+        score: 0f64,
+        feasible: false,
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0f64;
+    }
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Nearest-rank percentile.
+fn percentile(values: &mut [f64], p: f64) -> f64 {
+    if values.is_empty() {
+        return 0f64;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let rank = (p * values.len() as f64).ceil() as usize;
+    values[rank.clamp(1, values.len()) - 1]
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::Packet;
+
+    use super::{evaluate, simulate, Objective, Scenario, SchedulerKind};
+
+    fn scenario() -> Scenario {
+        let mut scenario = Scenario::new(1);
+        scenario.add_flow(
+            3,
+            vec![
+                (Packet::new("a1", 2), 0),
+                (Packet::new("a2", 2), 1),
+                (Packet::new("a3", 2), 6),
+            ],
+        );
+        scenario.add_flow(
+            2,
+            vec![(Packet::new("b1", 3), 0), (Packet::new("b2", 3), 4)],
+        );
+        scenario.add_flow(
+            1,
+            vec![
+                (Packet::new("c1", 1), 0),
+                (Packet::new("c2", 1), 2),
+                (Packet::new("c3", 1), 3),
+            ],
+        );
+        scenario
+    }
+
+    #[test]
+    fn evaluate_test() {
+        let scenario = scenario();
+        let kinds = [
+            SchedulerKind::WFQ,
+            SchedulerKind::DRR,
+            SchedulerKind::WRR,
+            SchedulerKind::FIFO,
+            SchedulerKind::RR,
+        ];
+        let objective = Objective::minimize_p99_delay(0f64);
+
+        let report = evaluate(&scenario, &kinds, &objective);
+        assert_eq!(report.rows.len(), 5);
+        for row in &report.rows {
+            assert_eq!(row.drops, 0);
+            assert!(row.fairness > 0f64 && row.fairness <= 1f64);
+            assert!(row.utilization > 0f64 && row.utilization <= 1f64);
+            assert_eq!(row.score, row.p99_delay);
+        }
+        assert!(report.rows.windows(2).all(|w| w[0].score <= w[1].score));
+        assert_eq!(report.recommended, Some(report.rows[0].kind));
+
+        // Evaluating the same scenario again ranks the schedulers the same way.
+        let again = evaluate(&scenario, &kinds, &objective);
+        let order = |r: &super::EvaluationReport| r.rows.iter().map(|m| m.kind).collect::<Vec<_>>();
+        assert_eq!(order(&report), order(&again));
+    }
+
+    #[test]
+    fn simulate_keeps_packet_lengths_test() {
+        // Every scheduler sends the packets as they came, without padding
+        // them to the longest of their flow.
+        let mut scenario = scenario();
+        scenario.add_flow(
+            1,
+            vec![(Packet::new("d1", 4), 0), (Packet::new("d2", 1), 0)],
+        );
+        for kind in [SchedulerKind::WRR, SchedulerKind::DRR, SchedulerKind::FIFO] {
+            let departures = simulate(&scenario, kind);
+            assert_eq!(departures.len(), 10);
+            assert_eq!(departures.iter().map(|(p, _)| p.len).sum::<usize>(), 20);
+        }
+    }
+
+    #[test]
+    fn evaluate_infeasible_test() {
+        let report = evaluate(
+            &scenario(),
+            &[SchedulerKind::WFQ, SchedulerKind::DRR],
+            &Objective::minimize_p99_delay(1.1f64),
+        );
+        assert!(report.rows.iter().all(|row| !row.feasible));
+        assert_eq!(report.recommended, None);
+        assert!(report.to_string().ends_with("recommended: none"));
+    }
+}
@@ -0,0 +1,283 @@
+//! Batches of experiments run in parallel.
+//!
+//! A [`Batch`] runs every scheduler on every workload with every seed,
+//! spreading the runs over the threads of the rayon pool, and gathers
+//! their metrics into one [`ExperimentReport`]. A parameter sweep is a
+//! batch with one workload per combination of parameters.
+
+use std::fmt;
+
+use rayon::prelude::*;
+
+use crate::scheduling::evaluation::{metrics, Scenario, SchedulerKind, SchedulerMetrics};
+
+/// Builds the scenario of a workload from a seed.
+type Generator<'a> = Box<dyn Fn(u64) -> Scenario + Send + Sync + 'a>;
+
+/// The metrics of one scheduler on one workload with one seed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExperimentResult {
+    pub workload: String,
+    pub seed: u64,
+    /// The metrics of the run, unscored.
+    pub metrics: SchedulerMetrics,
+}
+
+/// The metrics of one scheduler on one workload, averaged over the seeds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExperimentSummary {
+    pub workload: String,
+    pub kind: SchedulerKind,
+    pub runs: usize,
+    pub mean_delay: f64,
+    pub p99_delay: f64,
+    pub fairness: f64,
+    pub utilization: f64,
+    pub drops: f64,
+}
+
+/// The result of [`Batch::run`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExperimentReport {
+    /// Every run, by workload, then scheduler, then seed,
+    /// in the order they were added.
+    pub results: Vec<ExperimentResult>,
+    /// One summary per workload and scheduler, in the same order.
+    pub summaries: Vec<ExperimentSummary>,
+}
+
+impl ExperimentReport {
+    /// The summary of a scheduler on a workload.
+    pub fn summary(&self, workload: &str, kind: SchedulerKind) -> Option<&ExperimentSummary> {
+        self.summaries
+            .iter()
+            .find(|s| s.workload == workload && s.kind == kind)
+    }
+}
+
+impl fmt::Display for ExperimentReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .summaries
+            .iter()
+            .map(|s| s.workload.len())
+            .max()
+            .unwrap_or(0)
+            .max("workload".len())
+            + 2;
+        write!(
+            f,
+            "{:<width$}{:<6}{:>6}{:>12}{:>12}{:>10}{:>13}{:>8}",
+            "workload",
+            "sched",
+            "runs",
+            "mean delay",
+            "p99 delay",
+            "fairness",
+            "utilization",
+            "drops",
+            width = width
+        )?;
+        for s in &self.summaries {
+            write!(
+                f,
+                "\n{:<width$}{:<6}{:>6}{:>12.3}{:>12.3}{:>10.3}{:>13.3}{:>8.1}",
+                s.workload,
+                s.kind.to_string(),
+                s.runs,
+                s.mean_delay,
+                s.p99_delay,
+                s.fairness,
+                s.utilization,
+                s.drops,
+                width = width
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A batch of experiments: every scheduler on every workload,
+/// each workload generated once per seed.
+pub struct Batch<'a> {
+    schedulers: Vec<SchedulerKind>,
+    workloads: Vec<(String, Generator<'a>)>,
+    seeds: Vec<u64>,
+}
+
+impl<'a> Batch<'a> {
+    /// An empty batch, run with the single seed 0 unless seeds are given.
+    pub fn new() -> Batch<'a> {
+        Batch {
+            schedulers: Vec::new(),
+            workloads: Vec::new(),
+            seeds: vec![0],
+        }
+    }
+
+    pub fn with_schedulers(mut self, kinds: impl IntoIterator<Item = SchedulerKind>) -> Batch<'a> {
+        self.schedulers.extend(kinds);
+        self
+    }
+
+    /// Add a workload, built from each seed by `generate`.
+    pub fn with_workload(
+        mut self,
+        name: impl Into<String>,
+        generate: impl Fn(u64) -> Scenario + Send + Sync + 'a,
+    ) -> Batch<'a> {
+        self.workloads.push((name.into(), Box::new(generate)));
+        self
+    }
+
+    pub fn with_seeds(mut self, seeds: impl IntoIterator<Item = u64>) -> Batch<'a> {
+        self.seeds = seeds.into_iter().collect();
+        self
+    }
+
+    /// The number of runs in the batch.
+    pub fn len(&self) -> usize {
+        self.workloads.len() * self.schedulers.len() * self.seeds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Run the whole batch in parallel.
+    ///
+    /// Every scenario is generated once and shared by the schedulers,
+    /// and the report does not depend on how the runs were spread
+    /// over the threads.
+    pub fn run(&self) -> ExperimentReport {
+        let scenarios: Vec<Scenario> = self
+            .workloads
+            .par_iter()
+            .flat_map(|(_, generate)| self.seeds.par_iter().map(|&seed| generate(seed)))
+            .collect();
+
+        let runs: Vec<(usize, usize, usize)> = (0..self.workloads.len())
+            .flat_map(|w| {
+                (0..self.schedulers.len())
+                    .flat_map(move |k| (0..self.seeds.len()).map(move |s| (w, k, s)))
+            })
+            .collect();
+        let results: Vec<ExperimentResult> = runs
+            .into_par_iter()
+            .map(|(w, k, s)| ExperimentResult {
+                workload: self.workloads[w].0.clone(),
+                seed: self.seeds[s],
+                metrics: metrics(&scenarios[w * self.seeds.len() + s], self.schedulers[k]),
+            })
+            .collect();
+
+        let summaries = results
+            .chunks(self.seeds.len().max(1))
+            .filter(|runs| !runs.is_empty())
+            .map(summarize)
+            .collect();
+        ExperimentReport { results, summaries }
+    }
+}
+
+impl<'a> Default for Batch<'a> {
+    fn default() -> Batch<'a> {
+        Batch::new()
+    }
+}
+
+/// Average the runs of one scheduler on one workload.
+fn summarize(runs: &[ExperimentResult]) -> ExperimentSummary {
+    let mean = |value: fn(&SchedulerMetrics) -> f64| {
+        runs.iter().map(|r| value(&r.metrics)).sum::<f64>() / runs.len() as f64
+    };
+    ExperimentSummary {
+        workload: runs[0].workload.clone(),
+        kind: runs[0].metrics.kind,
+        runs: runs.len(),
+        mean_delay: mean(|m| m.mean_delay),
+        p99_delay: mean(|m| m.p99_delay),
+        fairness: mean(|m| m.fairness),
+        utilization: mean(|m| m.utilization),
+        drops: mean(|m| m.drops as f64),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        evaluation::{Scenario, SchedulerKind},
+        traffic::{PoissonSource, SizeDistribution},
+    };
+
+    use super::Batch;
+
+    /// Two Poisson flows with weights 1 and `ratio`, loading the link to `load`.
+    fn scenario(ratio: usize, load: f64, seed: u64) -> Scenario {
+        let mut scenario = Scenario::new(1);
+        for (idx, weight) in [1, ratio].into_iter().enumerate() {
+            let source = PoissonSource::new(load / 2f64, SizeDistribution::Fixed(1))
+                .with_seed(seed * 2 + idx as u64)
+                .with_prefix(format!("f{}_", idx));
+            scenario.add_flow(weight, source.take(50).collect());
+        }
+        scenario
+    }
+
+    #[test]
+    fn batch_sweep_test() {
+        let kinds = [SchedulerKind::WFQ, SchedulerKind::DRR, SchedulerKind::FIFO];
+        let mut batch = Batch::new().with_schedulers(kinds).with_seeds([1, 2, 3]);
+        for ratio in [1, 4] {
+            for load in [0.5, 0.9] {
+                batch = batch
+                    .with_workload(format!("ratio={} load={}", ratio, load), move |seed| {
+                        scenario(ratio, load, seed)
+                    });
+            }
+        }
+        assert_eq!(batch.len(), 36);
+
+        let report = batch.run();
+        assert_eq!(report.results.len(), 36);
+        assert_eq!(report.summaries.len(), 12);
+        let first: Vec<(&str, SchedulerKind, u64)> = report.results[..4]
+            .iter()
+            .map(|r| (r.workload.as_str(), r.metrics.kind, r.seed))
+            .collect();
+        assert_eq!(
+            first,
+            [
+                ("ratio=1 load=0.5", SchedulerKind::WFQ, 1),
+                ("ratio=1 load=0.5", SchedulerKind::WFQ, 2),
+                ("ratio=1 load=0.5", SchedulerKind::WFQ, 3),
+                ("ratio=1 load=0.5", SchedulerKind::DRR, 1),
+            ]
+        );
+        for summary in &report.summaries {
+            assert_eq!(summary.runs, 3);
+            assert_eq!(summary.drops, 0f64);
+        }
+
+        // A heavier load delays packets more.
+        let light = report
+            .summary("ratio=1 load=0.5", SchedulerKind::FIFO)
+            .unwrap();
+        let heavy = report
+            .summary("ratio=1 load=0.9", SchedulerKind::FIFO)
+            .unwrap();
+        assert!(heavy.mean_delay > light.mean_delay);
+
+        // The report is the same however the runs were spread.
+        assert_eq!(batch.run(), report);
+        assert_eq!(report.to_string().lines().count(), 13);
+    }
+
+    #[test]
+    fn empty_batch_test() {
+        let batch = Batch::new().with_schedulers([SchedulerKind::WFQ]);
+        assert!(batch.is_empty());
+        let report = batch.run();
+        assert!(report.results.is_empty() && report.summaries.is_empty());
+    }
+}
@@ -0,0 +1,317 @@
+//! Input-queued switch fabrics.
+//!
+//! The fabric moves fixed-size cells: in every time slot, each input sends
+//! at most one cell and each output receives at most one. To avoid
+//! head-of-line blocking, an input keeps a Virtual Output Queue (VOQ) per
+//! output, and an [`Arbiter`] matches inputs to outputs slot by slot.
+
+use std::collections::VecDeque;
+
+use crate::scheduling::{switch::ForwardingTable, Packet};
+
+/// Matches inputs to outputs for one time slot.
+pub trait Arbiter {
+    /// Given which outputs each input has cells for, `requests[input][output]`,
+    /// the output each input sends to, at most one input per output.
+    fn matching(&mut self, requests: &[Vec<bool>]) -> Vec<Option<usize>>;
+}
+
+/// Maximal matching baseline: inputs in index order take the first
+/// requested output still free.
+///
+/// No input-output pair could be added to the matching, but it may be
+/// smaller than a maximum one and it always favours low indices.
+#[derive(Debug, Clone, Default)]
+pub struct MaximalMatching;
+
+impl Arbiter for MaximalMatching {
+    fn matching(&mut self, requests: &[Vec<bool>]) -> Vec<Option<usize>> {
+        let outputs = requests.first().map_or(0, Vec::len);
+        let mut taken = vec![false; outputs];
+        requests
+            .iter()
+            .map(|wants| {
+                let output = (0..outputs).find(|&o| wants[o] && !taken[o])?;
+                taken[output] = true;
+                Some(output)
+            })
+            .collect()
+    }
+}
+
+/// iSLIP, as in McKeown, "The iSLIP scheduling algorithm for
+/// input-queued switches", 1999.
+///
+/// Every iteration, each free output grants the requesting free input
+/// next to its grant pointer, and each free input accepts the granting
+/// output next to its accept pointer. Pointers move one past the match,
+/// only for matches made in the first iteration, which desynchronizes
+/// them and gives 100% throughput under uniform traffic.
+#[derive(Debug, Clone)]
+pub struct ISlip {
+    iterations: usize,
+    grant: Vec<usize>,
+    accept: Vec<usize>,
+}
+
+impl ISlip {
+    pub fn new(ports: usize, iterations: usize) -> ISlip {
+        assert!(iterations > 0, "iSLIP needs at least one iteration");
+        ISlip {
+            iterations,
+            grant: vec![0; ports],
+            accept: vec![0; ports],
+        }
+    }
+
+    /// The input each output grants first.
+    pub fn grant_pointers(&self) -> &[usize] {
+        &self.grant
+    }
+
+    /// The output each input accepts first.
+    pub fn accept_pointers(&self) -> &[usize] {
+        &self.accept
+    }
+}
+
+/// The first index from `start` on, wrapping around, satisfying `pred`.
+fn round_robin(start: usize, len: usize, pred: impl Fn(usize) -> bool) -> Option<usize> {
+    (0..len).map(|i| (start + i) % len).find(|&i| pred(i))
+}
+
+impl Arbiter for ISlip {
+    fn matching(&mut self, requests: &[Vec<bool>]) -> Vec<Option<usize>> {
+        let inputs = requests.len();
+        let outputs = self.grant.len();
+        let mut matched: Vec<Option<usize>> = vec![None; inputs];
+        let mut output_matched = vec![false; outputs];
+        for iteration in 0..self.iterations {
+            let grants: Vec<Option<usize>> = (0..outputs)
+                .map(|o| {
+                    if output_matched[o] {
+                        return None;
+                    }
+                    round_robin(self.grant[o], inputs, |i| {
+                        matched[i].is_none() && requests[i][o]
+                    })
+                })
+                .collect();
+            let mut progress = false;
+            for (input, matched) in matched.iter_mut().enumerate() {
+                if matched.is_some() {
+                    continue;
+                }
+                let accepted =
+                    round_robin(self.accept[input], outputs, |o| grants[o] == Some(input));
+                if let Some(output) = accepted {
+                    *matched = Some(output);
+                    output_matched[output] = true;
+                    progress = true;
+                    if iteration == 0 {
+                        self.grant[output] = (input + 1) % inputs;
+                        self.accept[input] = (output + 1) % outputs;
+                    }
+                }
+            }
+            if !progress {
+                break;
+            }
+        }
+        matched
+    }
+}
+
+/// A cell through the fabric.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellRecord {
+    pub packet: Packet,
+    pub input: usize,
+    pub output: usize,
+    pub arrival: usize,
+    /// The end of the slot the cell crossed the fabric in.
+    pub departure: usize,
+}
+
+/// An input-queued switch with a VOQ per input and output, forwarding
+/// one packet per cell.
+pub struct VOQSwitch<A: Arbiter> {
+    ports: usize,
+    table: ForwardingTable,
+    arbiter: A,
+    timer: usize,
+    /// Packets still to arrive: time, input and packet, latest first.
+    arrivals: Vec<(usize, usize, Packet)>,
+    /// `voqs[input][output]`, the cells and their arrival times.
+    voqs: Vec<Vec<VecDeque<(Packet, usize)>>>,
+    records: Vec<CellRecord>,
+    unroutable: Vec<Packet>,
+}
+
+impl<A: Arbiter> VOQSwitch<A> {
+    /// A switch with `ports` inputs and as many outputs.
+    pub fn new(ports: usize, table: ForwardingTable, arbiter: A) -> VOQSwitch<A> {
+        VOQSwitch {
+            ports,
+            table,
+            arbiter,
+            timer: 0,
+            arrivals: Vec::new(),
+            voqs: vec![vec![VecDeque::new(); ports]; ports],
+            records: Vec::new(),
+            unroutable: Vec::new(),
+        }
+    }
+
+    /// Make a packet arrive on an input at `time`.
+    pub fn receive(&mut self, input: usize, packet: Packet, time: usize) {
+        assert!(input < self.ports, "no input {}", input);
+        let at = self.arrivals.partition_point(|&(t, _, _)| t > time);
+        self.arrivals.insert(at, (time, input, packet));
+    }
+
+    pub fn arbiter(&self) -> &A {
+        &self.arbiter
+    }
+
+    pub fn timer(&self) -> usize {
+        self.timer
+    }
+
+    /// The number of cells waiting at an input for an output.
+    pub fn voq_len(&self, input: usize, output: usize) -> usize {
+        self.voqs[input][output].len()
+    }
+
+    /// The cells that crossed the fabric, slot by slot.
+    pub fn records(&self) -> &[CellRecord] {
+        &self.records
+    }
+
+    /// The packets without a route to an output.
+    pub fn unroutable(&self) -> &[Packet] {
+        &self.unroutable
+    }
+
+    /// Run one time slot. Returns false once every packet has crossed.
+    pub fn step(&mut self) -> bool {
+        while self
+            .arrivals
+            .last()
+            .is_some_and(|&(time, _, _)| time <= self.timer)
+        {
+            let (time, input, packet) = self.arrivals.pop().unwrap();
+            match self.table.route(&packet).filter(|&o| o < self.ports) {
+                Some(output) => self.voqs[input][output].push_back((packet, time)),
+                None => self.unroutable.push(packet),
+            }
+        }
+
+        let requests: Vec<Vec<bool>> = self
+            .voqs
+            .iter()
+            .map(|voqs| voqs.iter().map(|q| !q.is_empty()).collect())
+            .collect();
+        let matching = self.arbiter.matching(&requests);
+        self.timer += 1;
+        for (input, output) in matching.into_iter().enumerate() {
+            let Some(output) = output else {
+                continue;
+            };
+            let (packet, arrival) = self.voqs[input][output]
+                .pop_front()
+                .expect("the arbiter matched an empty VOQ");
+            self.records.push(CellRecord {
+                packet,
+                input,
+                output,
+                arrival,
+                departure: self.timer,
+            });
+        }
+
+        !self.arrivals.is_empty() || self.voqs.iter().flatten().any(|q| !q.is_empty())
+    }
+
+    pub fn run(&mut self) {
+        while self.step() {}
+    }
+
+    /// The mean time from arrival to the end of the crossing.
+    pub fn mean_delay(&self) -> f64 {
+        if self.records.is_empty() {
+            return 0f64;
+        }
+        let total: usize = self.records.iter().map(|r| r.departure - r.arrival).sum();
+        total as f64 / self.records.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{switch::ForwardingTable, Packet};
+
+    use super::{Arbiter, ISlip, MaximalMatching, VOQSwitch};
+
+    #[test]
+    fn arbiter_test() {
+        let requests = vec![vec![true, true], vec![true, false]];
+        // Input 0 takes output 0 first and blocks input 1.
+        assert_eq!(MaximalMatching.matching(&requests), vec![Some(0), None]);
+        // One iSLIP iteration does no better, a second one adds nothing
+        // as input 1 only wants output 0.
+        let mut islip = ISlip::new(2, 2);
+        assert_eq!(islip.matching(&requests), vec![Some(0), None]);
+        assert_eq!(islip.grant_pointers(), &[1, 0]);
+        assert_eq!(islip.accept_pointers(), &[1, 0]);
+
+        // Everyone wants everything: the pointers desynchronize after one
+        // slot with a single iteration, or right away with two.
+        let full = vec![vec![true; 2]; 2];
+        let mut islip = ISlip::new(2, 1);
+        assert_eq!(islip.matching(&full), vec![Some(0), None]);
+        assert_eq!(islip.matching(&full), vec![Some(1), Some(0)]);
+        assert_eq!(islip.matching(&full), vec![Some(0), Some(1)]);
+        let mut islip = ISlip::new(2, 2);
+        assert_eq!(islip.matching(&full), vec![Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn voq_switch_test() {
+        let table = ForwardingTable::new(None)
+            .with_destination("0", 0)
+            .with_destination("1", 1);
+        let build = || {
+            let mut switch = VOQSwitch::new(2, table.clone(), ISlip::new(2, 1));
+            for input in 0..2 {
+                for output in 0..2 {
+                    for p in 0..4 {
+                        let packet = Packet::new(format!("{}{}{}", input, output, p), 1)
+                            .with_tag("dst", output.to_string());
+                        switch.receive(input, packet, 0);
+                    }
+                }
+            }
+            switch
+        };
+
+        // 16 cells take 9 slots: one slot to desynchronize, then two per slot.
+        let mut switch = build();
+        switch.receive(0, Packet::new("lost", 1), 2);
+        switch.run();
+        assert_eq!(switch.timer(), 9);
+        assert_eq!(switch.records().len(), 16);
+        assert_eq!(switch.unroutable(), &[Packet::new("lost", 1)]);
+        assert_eq!(switch.records()[0].packet.name, "000");
+        assert!(switch
+            .records()
+            .iter()
+            .all(|r| r.packet.tag("dst") == Some(&r.output.to_string())));
+        assert_eq!(switch.mean_delay(), 5f64);
+
+        let mut switch = build();
+        assert!(switch.step());
+        assert_eq!(switch.voq_len(0, 0), 3);
+        assert_eq!(switch.voq_len(1, 1), 4);
+    }
+}
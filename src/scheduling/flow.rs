@@ -1,6 +1,8 @@
-use crate::scheduling::Packet;
+use std::{cell::Cell, collections::VecDeque, fmt::Debug};
 
-pub trait Flow {
+use crate::scheduling::{aqm::Red, Packet};
+
+pub trait Flow: Debug + FlowClone {
     /// Add a packet to the flow.
     fn packet_arrive(&mut self, packet: Packet, time: usize);
 
@@ -11,43 +13,115 @@ pub trait Flow {
     /// If there is no packet available, return None.
     fn peek_packet(&self, time: usize) -> Option<Packet>;
 
+    /// Get the arrival time of the next packet in the flow,
+    /// whether or not it has arrived yet.
+    fn next_arrival(&self) -> Option<usize>;
+
     /// Check if the flow is empty.
     fn empty(&self) -> bool;
+
+    /// The number of packets that have arrived by `time`
+    /// and are still waiting to be served.
+    fn queue_len(&self, time: usize) -> usize;
+
+    /// The number of packets dropped by the flow itself,
+    /// for flows with a finite queue.
+    fn dropped_count(&self) -> usize {
+        0
+    }
+
+    /// Close the flow at `time`: the packets arriving after it are
+    /// discarded, including those added later, so that the flow drains.
+    fn close(&mut self, time: usize);
+
+    /// Whether the flow was closed.
+    fn is_closed(&self) -> bool;
+
+    /// Whether the flow was closed and has drained,
+    /// so that schedulers can stop sharing the link with it.
+    fn retired(&self) -> bool {
+        self.is_closed() && self.empty()
+    }
+}
+
+/// Clone a flow behind a trait object.
+///
+/// Implemented for every flow that is `Clone`.
+pub trait FlowClone {
+    fn clone_box(&self) -> Box<dyn Flow>;
+}
+
+impl<T: Flow + Clone + 'static> FlowClone for T {
+    fn clone_box(&self) -> Box<dyn Flow> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Flow> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Insert a packet after the packets arriving no later than it, so that
+/// packets arriving in order are appended in constant time.
+fn insert_by_arrival(packets: &mut VecDeque<(Packet, usize)>, packet: Packet, time: usize) {
+    let pos = packets.partition_point(|(_, t)| *t <= time);
+    packets.insert(pos, (packet, time));
 }
 
 /// A flow with variable-length packets.
-#[derive(Debug)]
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VariableLengthFlow {
-    pub packet_states: Vec<(Packet, usize)>,
+    /// The packets with their arrival times, in arrival order.
+    pub packet_states: VecDeque<(Packet, usize)>,
+    /// The time the flow was closed at, refusing later packets.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub closed: Option<usize>,
 }
 
 /// A flow with fixed-length packets.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FixedLengthFlow {
     pub packet_len: usize,
-    pub packet_states: Vec<(Packet, usize)>,
+    /// The packets with their arrival times, in arrival order.
+    pub packet_states: VecDeque<(Packet, usize)>,
+    /// The time the flow was closed at, refusing later packets.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub closed: Option<usize>,
 }
 
 impl VariableLengthFlow {
     pub fn new() -> VariableLengthFlow {
         VariableLengthFlow {
-            packet_states: Vec::new(),
+            packet_states: VecDeque::new(),
+            closed: None,
         }
     }
 }
 
 impl Flow for VariableLengthFlow {
     fn packet_arrive(&mut self, packet: Packet, time: usize) {
-        self.packet_states.push((packet, time));
-        self.packet_states.sort_by(|a, b| a.1.cmp(&b.1));
+        if self.closed.is_some_and(|closed| time > closed) {
+            return;
+        }
+        insert_by_arrival(&mut self.packet_states, packet, time);
     }
 
     fn pop_packet(&mut self) -> Packet {
-        self.packet_states.remove(0).0
+        self.packet_states.pop_front().unwrap().0
     }
 
     fn peek_packet(&self, time: usize) -> Option<Packet> {
-        if let Some((packet, arrive_time)) = self.packet_states.first() {
+        if let Some((packet, arrive_time)) = self.packet_states.front() {
             if arrive_time <= &time {
                 Some(packet.clone())
             } else {
@@ -58,8 +132,27 @@ impl Flow for VariableLengthFlow {
         }
     }
 
+    fn next_arrival(&self) -> Option<usize> {
+        self.packet_states
+            .front()
+            .map(|(_, arrive_time)| *arrive_time)
+    }
+
     fn empty(&self) -> bool {
-        self.packet_states.len() == 0
+        self.packet_states.is_empty()
+    }
+
+    fn queue_len(&self, time: usize) -> usize {
+        self.packet_states.partition_point(|(_, t)| *t <= time)
+    }
+
+    fn close(&mut self, time: usize) {
+        self.closed = Some(time);
+        self.packet_states.retain(|(_, t)| *t <= time);
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_some()
     }
 }
 
@@ -68,7 +161,8 @@ impl FixedLengthFlow {
     pub fn new(packet_len: usize) -> FixedLengthFlow {
         FixedLengthFlow {
             packet_len,
-            packet_states: Vec::new(),
+            packet_states: VecDeque::new(),
+            closed: None,
         }
     }
 
@@ -83,14 +177,9 @@ impl FixedLengthFlow {
         }
     }
 
-    fn ensure_packet_order(&mut self) {
-        self.packet_states.sort_by(|a, b| a.1.cmp(&b.1));
-    }
-
-    pub fn add_packet(&mut self, name: &'static str, arrive_time: usize) {
-        self.packet_states
-            .push((Packet::new(name, self.packet_len), arrive_time));
-        self.ensure_packet_order();
+    pub fn add_packet(&mut self, name: impl Into<String>, arrive_time: usize) {
+        let packet = Packet::new(name, self.packet_len);
+        insert_by_arrival(&mut self.packet_states, packet, arrive_time);
     }
 }
 
@@ -100,24 +189,19 @@ impl Flow for FixedLengthFlow {
     /// If the packet length is different from the flow's packet length,
     /// the packet will be resized to the flow's packet length.
     fn packet_arrive(&mut self, packet: Packet, time: usize) {
-        if packet.len != self.packet_len {
-            let packet = Packet {
-                len: self.packet_len,
-                ..packet
-            };
-            self.packet_states.push((packet, time));
-        } else {
-            self.packet_states.push((packet, time));
+        if self.closed.is_some_and(|closed| time > closed) {
+            return;
         }
-        self.ensure_packet_order();
+        let packet = self.ensure_packet_len(packet);
+        insert_by_arrival(&mut self.packet_states, packet, time);
     }
 
     fn pop_packet(&mut self) -> Packet {
-        self.packet_states.remove(0).0
+        self.packet_states.pop_front().unwrap().0
     }
 
     fn peek_packet(&self, time: usize) -> Option<Packet> {
-        if let Some((packet, arrive_time)) = self.packet_states.first() {
+        if let Some((packet, arrive_time)) = self.packet_states.front() {
             if arrive_time <= &time {
                 return Some(packet.clone());
             }
@@ -125,8 +209,234 @@ impl Flow for FixedLengthFlow {
         None
     }
 
+    fn next_arrival(&self) -> Option<usize> {
+        self.packet_states
+            .front()
+            .map(|(_, arrive_time)| *arrive_time)
+    }
+
     fn empty(&self) -> bool {
-        self.packet_states.len() == 0
+        self.packet_states.is_empty()
+    }
+
+    fn queue_len(&self, time: usize) -> usize {
+        self.packet_states.partition_point(|(_, t)| *t <= time)
+    }
+
+    fn close(&mut self, time: usize) {
+        self.closed = Some(time);
+        self.packet_states.retain(|(_, t)| *t <= time);
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_some()
+    }
+}
+
+/// A flow whose packets wait in a queue of finite capacity, in packets
+/// and/or bytes, until the scheduler serves them.
+///
+/// A packet arriving while the queue is full is dropped, drop-tail,
+/// and an early drop policy can be applied before the capacity check,
+/// which marks ECN-capable packets instead of dropping them.
+/// A packet is served at the latest time the flow was peeked at,
+/// or on its arrival if later, as schedulers look at a flow before
+/// serving it.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BoundedFlow {
+    capacity: Option<usize>,
+    byte_capacity: Option<usize>,
+    /// Packets that have not been offered to the queue yet.
+    pending: VecDeque<(Packet, usize)>,
+    /// Packets accepted in the queue, with their arrival times.
+    queue: VecDeque<(Packet, usize)>,
+    queue_bytes: usize,
+    /// Early drop policy applied before the capacity check.
+    red: Option<Red>,
+    dropped: Vec<Packet>,
+    /// The latest time the flow was peeked at.
+    now: Cell<usize>,
+    /// The time the flow was closed at, refusing later packets.
+    closed: Option<usize>,
+}
+
+impl BoundedFlow {
+    /// Put the packets of a flow behind a queue holding at most `capacity`
+    /// packets and `byte_capacity` bytes, unbounded if None.
+    pub fn new(
+        flow: impl Flow + 'static,
+        capacity: Option<usize>,
+        byte_capacity: Option<usize>,
+    ) -> BoundedFlow {
+        assert!(capacity != Some(0), "a queue must hold at least one packet");
+        let mut bounded = BoundedFlow {
+            capacity,
+            byte_capacity,
+            pending: VecDeque::new(),
+            queue: VecDeque::new(),
+            queue_bytes: 0,
+            red: None,
+            dropped: Vec::new(),
+            now: Cell::new(0),
+            closed: None,
+        };
+        let mut flow = flow.clone_box();
+        while let Some(time) = flow.next_arrival() {
+            bounded.packet_arrive(flow.pop_packet(), time);
+        }
+        bounded
+    }
+
+    /// Drop packets early with Random Early Detection,
+    /// on the number of packets in the queue.
+    pub fn with_red(mut self, red: Red) -> BoundedFlow {
+        self.red = Some(red);
+        self
+    }
+
+    /// The packets dropped so far, in arrival order.
+    pub fn dropped(&self) -> &[Packet] {
+        &self.dropped
+    }
+
+    /// Offer the packets arriving up to `time` to the queue.
+    fn admit(&mut self, time: usize) {
+        while self.pending.front().is_some_and(|(_, t)| *t <= time) {
+            let (mut packet, arrive_time) = self.pending.pop_front().unwrap();
+            let early = self
+                .red
+                .as_mut()
+                .is_some_and(|red| red.should_drop(self.queue.len()));
+            let full = self.capacity.is_some_and(|c| self.queue.len() >= c)
+                || self
+                    .byte_capacity
+                    .is_some_and(|c| self.queue_bytes + packet.len > c);
+            if full || (early && !packet.mark_ce()) {
+                self.dropped.push(packet);
+            } else {
+                self.queue_bytes += packet.len;
+                self.queue.push_back((packet, arrive_time));
+            }
+        }
+    }
+
+    /// The packet served next, which is never dropped
+    /// since it finds the queue empty.
+    fn head(&self) -> Option<&(Packet, usize)> {
+        self.queue.front().or(self.pending.front())
+    }
+}
+
+impl Flow for BoundedFlow {
+    /// Add a packet to the flow. A packet that exceeds the byte capacity
+    /// on its own is dropped at once.
+    fn packet_arrive(&mut self, packet: Packet, time: usize) {
+        if self.closed.is_some_and(|closed| time > closed) {
+            return;
+        }
+        if self.byte_capacity.is_some_and(|c| packet.len > c) {
+            self.dropped.push(packet);
+            return;
+        }
+        let pos = self.pending.partition_point(|(_, t)| *t <= time);
+        self.pending.insert(pos, (packet, time));
+    }
+
+    fn pop_packet(&mut self) -> Packet {
+        let arrive_time = self.head().unwrap().1;
+        self.admit(self.now.get().max(arrive_time));
+        let (packet, _) = self.queue.pop_front().unwrap();
+        self.queue_bytes -= packet.len;
+        packet
+    }
+
+    fn peek_packet(&self, time: usize) -> Option<Packet> {
+        self.now.set(self.now.get().max(time));
+        self.head()
+            .filter(|(_, arrive_time)| *arrive_time <= time)
+            .map(|(packet, _)| packet.clone())
+    }
+
+    fn next_arrival(&self) -> Option<usize> {
+        self.head().map(|(_, arrive_time)| *arrive_time)
+    }
+
+    fn empty(&self) -> bool {
+        self.queue.is_empty() && self.pending.is_empty()
+    }
+
+    /// Counts the packets arrived but not offered to the queue yet
+    /// as accepted, up to the capacity in packets.
+    fn queue_len(&self, time: usize) -> usize {
+        let arrived = self.queue.len() + self.pending.partition_point(|(_, t)| *t <= time);
+        self.capacity.map_or(arrived, |c| arrived.min(c))
+    }
+
+    fn dropped_count(&self) -> usize {
+        self.dropped.len()
+    }
+
+    fn close(&mut self, time: usize) {
+        self.closed = Some(time);
+        self.pending.retain(|(_, t)| *t <= time);
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.is_some()
+    }
+}
+
+/// A flow fed packet by packet while the scheduler runs, such as the
+/// packets of a closed-loop source.
+///
+/// The flow is not empty until it is closed, even while no packet is
+/// waiting, so the scheduler keeps running, and its port transmitting,
+/// until the next packet is injected.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OpenFlow {
+    packets: VariableLengthFlow,
+}
+
+impl OpenFlow {
+    pub fn new() -> OpenFlow {
+        OpenFlow::default()
+    }
+}
+
+impl Flow for OpenFlow {
+    fn packet_arrive(&mut self, packet: Packet, time: usize) {
+        self.packets.packet_arrive(packet, time);
+    }
+
+    fn pop_packet(&mut self) -> Packet {
+        self.packets.pop_packet()
+    }
+
+    fn peek_packet(&self, time: usize) -> Option<Packet> {
+        self.packets.peek_packet(time)
+    }
+
+    fn next_arrival(&self) -> Option<usize> {
+        self.packets.next_arrival()
+    }
+
+    /// Whether the flow was closed and has no packet left.
+    fn empty(&self) -> bool {
+        self.packets.is_closed() && self.packets.empty()
+    }
+
+    fn queue_len(&self, time: usize) -> usize {
+        self.packets.queue_len(time)
+    }
+
+    fn close(&mut self, time: usize) {
+        self.packets.close(time);
+    }
+
+    fn is_closed(&self) -> bool {
+        self.packets.is_closed()
     }
 }
 
@@ -143,4 +453,97 @@ mod test {
         assert!(!flow.empty());
         assert!(flow.peek_packet(0).is_some());
     }
+
+    #[test]
+    fn flow_close_test() {
+        let mut flow = VariableLengthFlow::new();
+        for (name, time) in [("p1", 0), ("p2", 1), ("p3", 2), ("p4", 3)] {
+            flow.packet_arrive(Packet::new(name, 1), time);
+        }
+
+        // The packets arriving after the flow was closed are discarded,
+        // and so are the ones added afterwards.
+        flow.close(1);
+        flow.packet_arrive(Packet::new("p5", 1), 5);
+        assert!(flow.is_closed());
+        assert_eq!(flow.packet_states.len(), 2);
+
+        flow.pop_packet();
+        assert!(!flow.retired());
+        flow.pop_packet();
+        assert!(flow.retired());
+    }
+
+    #[test]
+    fn fixed_flow_dynamic_name_test() {
+        let mut flow = FixedLengthFlow::new(2);
+        for i in 0..3 {
+            flow.add_packet(format!("f1_p{}", i), i);
+        }
+        assert_eq!(flow.peek_packet(0), Some(Packet::new("f1_p0", 2)));
+        flow.pop_packet();
+        assert_eq!(flow.peek_packet(1), Some(Packet::new("f1_p1", 2)));
+    }
+
+    #[test]
+    fn flow_arrival_order_test() {
+        let mut flow = FixedLengthFlow::new(1);
+        for (name, time) in [("a", 2), ("b", 0), ("c", 2), ("d", 1), ("e", 3)] {
+            flow.add_packet(name, time);
+        }
+
+        // Packets arriving at the same time keep the order they were added in.
+        let names: Vec<String> = (0..5).map(|_| flow.pop_packet().name).collect();
+        assert_eq!(names, ["b", "d", "a", "c", "e"]);
+    }
+
+    #[test]
+    fn bounded_flow_test() {
+        let mut flow = VariableLengthFlow::new();
+        for (name, time) in [("p1", 0), ("p2", 0), ("p3", 0), ("p4", 1), ("p5", 2)] {
+            flow.packet_arrive(Packet::new(name, 1), time);
+        }
+        let mut flow = BoundedFlow::new(flow, Some(2), None);
+
+        // p3 finds p1 and p2 queued.
+        assert_eq!(flow.peek_packet(0), Some(Packet::new("p1", 1)));
+        assert_eq!(flow.pop_packet(), Packet::new("p1", 1));
+        assert_eq!(flow.dropped(), &[Packet::new("p3", 1)]);
+
+        // p4 takes the room left by p1, and p5 finds p2 and p4 queued.
+        flow.peek_packet(3);
+        assert_eq!(flow.pop_packet(), Packet::new("p2", 1));
+        assert_eq!(flow.pop_packet(), Packet::new("p4", 1));
+        assert_eq!(
+            flow.dropped(),
+            &[Packet::new("p3", 1), Packet::new("p5", 1)]
+        );
+        assert!(flow.empty());
+        assert_eq!(flow.dropped_count(), 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn bounded_flow_serde_test() {
+        let mut flow = VariableLengthFlow::new();
+        for idx in 0..40 {
+            flow.packet_arrive(Packet::new(format!("p{}", idx), 1), idx / 4);
+        }
+        let red = Red::new(1f64, 3f64, 0.5f64).with_weight(0.5).with_seed(7);
+        let mut flow = BoundedFlow::new(flow, Some(4), None).with_red(red);
+        flow.peek_packet(2);
+
+        // The decoded flow keeps its queue and the state of its RED,
+        // and drops the same packets from then on.
+        let json = serde_json::to_string(&flow).unwrap();
+        let mut decoded: BoundedFlow = serde_json::from_str(&json).unwrap();
+        for time in 2..20 {
+            assert_eq!(decoded.peek_packet(time), flow.peek_packet(time));
+            if !flow.empty() {
+                assert_eq!(decoded.pop_packet(), flow.pop_packet());
+            }
+        }
+        assert!(flow.dropped_count() > 0);
+        assert_eq!(decoded.dropped(), flow.dropped());
+    }
 }
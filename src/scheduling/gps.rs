@@ -0,0 +1,379 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::scheduling::{flow::Flow, stats::SchedulerStats, Packet, Port};
+
+/// Tolerance on the remaining length of a packet in the fluid model.
+const EPSILON: f64 = 1e-9;
+
+/// Generalized Processor Sharing (GPS) reference model.
+///
+/// Every backlogged flow is served simultaneously, at a share of the link
+/// rate proportional to its weight among the backlogged flows. The ideal
+/// departure times it computes are what packetized fair schedulers approximate.
+#[derive(Debug, Clone)]
+pub struct GPSReference {
+    rate: f64,
+    weights: Vec<f64>,
+    /// Packets of each flow with their arrival times, in arrival order.
+    flows: Vec<Vec<(Packet, usize)>>,
+}
+
+/// How far the departure times of a flow are from the GPS reference.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FlowDeviation {
+    /// Largest absolute difference between actual and ideal departure time.
+    pub max: f64,
+    /// Mean absolute difference between actual and ideal departure time.
+    pub mean: f64,
+}
+
+/// How much a flow was served over time, in bytes: a piecewise-linear
+/// function through its breakpoints, starting from 0.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ServiceCurve {
+    /// Times and bytes served by then, in time order.
+    pub points: Vec<(f64, f64)>,
+}
+
+impl ServiceCurve {
+    /// The bytes served by `time`.
+    pub fn at(&self, time: f64) -> f64 {
+        let after = self.points.partition_point(|&(t, _)| t <= time);
+        match (
+            after.checked_sub(1).map(|i| self.points[i]),
+            self.points.get(after),
+        ) {
+            (None, _) => 0f64,
+            (Some((_, served)), None) => served,
+            (Some((t0, s0)), Some(&(t1, s1))) => s0 + (s1 - s0) * (time - t0) / (t1 - t0),
+        }
+    }
+
+    fn push(&mut self, time: f64, served: f64) {
+        if self.points.last() != Some(&(time, served)) {
+            self.points.push((time, served));
+        }
+    }
+}
+
+/// The worst case of a flow against the GPS reference,
+/// see [`GPSReference::worst_case`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WorstCaseDeviation {
+    /// Largest time a packet left after its GPS departure.
+    pub delay: f64,
+    /// Largest number of bytes the flow was served behind GPS.
+    pub service_lag: f64,
+    /// Largest number of bytes the flow was served ahead of GPS.
+    pub service_lead: f64,
+}
+
+impl GPSReference {
+    pub fn new(rate: usize) -> GPSReference {
+        GPSReference {
+            rate: rate as f64,
+            weights: Vec::new(),
+            flows: Vec::new(),
+        }
+    }
+
+    /// Add a flow with a weight.
+    /// The flow is only read, so the same flow can be given to a scheduler.
+    pub fn add_flow(&mut self, flow: &dyn Flow, weight: f64) {
+        assert!(weight > 0f64, "GPS weights must be positive");
+        let mut flow = flow.clone_box();
+        let mut packets = Vec::new();
+        while let Some(arrive_time) = flow.next_arrival() {
+            packets.push((flow.pop_packet(), arrive_time));
+        }
+        self.flows.push(packets);
+        self.weights.push(weight);
+    }
+
+    /// Compute the ideal departure time of every packet,
+    /// per flow and in the order of the flow's packets.
+    pub fn departures(&self) -> Vec<Vec<f64>> {
+        self.simulate().0
+    }
+
+    /// Compute the fluid service of every flow over time.
+    pub fn service_curves(&self) -> Vec<ServiceCurve> {
+        self.simulate().1
+    }
+
+    /// Serve the flows as a fluid, returning the departures
+    /// and the service curve of every flow.
+    fn simulate(&self) -> (Vec<Vec<f64>>, Vec<ServiceCurve>) {
+        let mut departures: Vec<Vec<f64>> = self.flows.iter().map(|_| Vec::new()).collect();
+        let mut curves = vec![ServiceCurve::default(); self.flows.len()];
+        let mut served = vec![0f64; self.flows.len()];
+
+        // Arrivals of all flows, in time order.
+        let mut arrivals: Vec<(usize, usize)> = self
+            .flows
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, packets)| packets.iter().map(move |(_, time)| (*time, idx)))
+            .collect();
+        arrivals.sort();
+        let mut arrivals = arrivals.into_iter().peekable();
+        let mut next_packet = vec![0usize; self.flows.len()];
+
+        // Remaining length of the backlogged packets of each flow.
+        let mut queues: Vec<VecDeque<f64>> = self.flows.iter().map(|_| VecDeque::new()).collect();
+        let mut time = 0f64;
+
+        loop {
+            while let Some(&(arrive_time, idx)) = arrivals.peek() {
+                if arrive_time as f64 > time + EPSILON {
+                    break;
+                }
+                queues[idx].push_back(self.flows[idx][next_packet[idx]].0.len as f64);
+                next_packet[idx] += 1;
+                arrivals.next();
+            }
+
+            let backlogged: Vec<usize> = (0..queues.len())
+                .filter(|&idx| !queues[idx].is_empty())
+                .collect();
+            if backlogged.is_empty() {
+                match arrivals.peek() {
+                    Some(&(arrive_time, _)) => {
+                        time = arrive_time as f64;
+                        continue;
+                    }
+                    None => break,
+                }
+            }
+
+            let total_weight: f64 = backlogged.iter().map(|&idx| self.weights[idx]).sum();
+            let share = |idx: usize| self.rate * self.weights[idx] / total_weight;
+
+            // Advance to the next completion or arrival.
+            let mut step = backlogged
+                .iter()
+                .map(|&idx| queues[idx][0] / share(idx))
+                .fold(f64::INFINITY, f64::min);
+            if let Some(&(arrive_time, _)) = arrivals.peek() {
+                step = step.min(arrive_time as f64 - time);
+            }
+            for &idx in &backlogged {
+                curves[idx].push(time, served[idx]);
+            }
+            time += step;
+
+            for &idx in &backlogged {
+                queues[idx][0] -= share(idx) * step;
+                served[idx] += share(idx) * step;
+                if queues[idx][0] <= EPSILON {
+                    queues[idx].pop_front();
+                    departures[idx].push(time);
+                }
+                curves[idx].push(time, served[idx]);
+            }
+        }
+        (departures, curves)
+    }
+
+    /// Compare the departures recorded by a port with the GPS reference.
+    ///
+    /// Packets are matched by name, so names must be unique across flows.
+    /// Packets that have not left the port are ignored.
+    pub fn deviation(&self, port: &Port) -> Vec<FlowDeviation> {
+        let ideal = self.departures();
+        let reference: HashMap<&str, (usize, f64)> = self
+            .flows
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, packets)| {
+                let ideal = &ideal[idx];
+                packets
+                    .iter()
+                    .zip(ideal.iter())
+                    .map(move |((packet, _), time)| (packet.name.as_str(), (idx, *time)))
+            })
+            .collect();
+
+        let mut deviations = vec![FlowDeviation::default(); self.flows.len()];
+        let mut counts = vec![0usize; self.flows.len()];
+        for (packet, departure) in port.get_output().iter().zip(port.get_departure_times()) {
+            if let Some(&(idx, ideal)) = reference.get(packet.name.as_str()) {
+                let deviation = (*departure as f64 - ideal).abs();
+                deviations[idx].max = deviations[idx].max.max(deviation);
+                deviations[idx].mean += deviation;
+                counts[idx] += 1;
+            }
+        }
+        for (deviation, count) in deviations.iter_mut().zip(counts) {
+            if count > 0 {
+                deviation.mean /= count as f64;
+            }
+        }
+        deviations
+    }
+
+    /// The worst case of every flow of a packetized run against the
+    /// GPS reference, in delay and in service, such as WF2Q+ keeping
+    /// within one maximum packet length of it.
+    ///
+    /// A packet is served at the link rate, ending at its departure.
+    /// Packets are matched by name, so names must be unique across flows.
+    /// Packets that have not left the scheduler are ignored.
+    pub fn worst_case(&self, stats: &SchedulerStats) -> Vec<WorstCaseDeviation> {
+        let (ideal, curves) = self.simulate();
+        let reference: HashMap<&str, f64> = self
+            .flows
+            .iter()
+            .zip(&ideal)
+            .flat_map(|(packets, ideal)| {
+                packets
+                    .iter()
+                    .zip(ideal)
+                    .map(|((packet, _), time)| (packet.name.as_str(), *time))
+            })
+            .collect();
+
+        let mut worst = vec![WorstCaseDeviation::default(); self.flows.len()];
+        let mut packetized = vec![ServiceCurve::default(); self.flows.len()];
+        let mut served = vec![0f64; self.flows.len()];
+        for record in &stats.packets {
+            let idx = record.flow.index();
+            let Some(&ideal) = reference.get(record.name.as_str()) else {
+                continue;
+            };
+            worst[idx].delay = worst[idx].delay.max(record.departure as f64 - ideal);
+
+            let end = record.departure as f64;
+            let last = packetized[idx].points.last().map_or(0f64, |&(t, _)| t);
+            let start = (end - record.len as f64 / self.rate).max(last);
+            packetized[idx].push(start, served[idx]);
+            served[idx] += record.len as f64;
+            packetized[idx].push(end, served[idx]);
+        }
+
+        // Both curves are piecewise linear, so their distance
+        // is largest at a breakpoint of either.
+        for ((worst, gps), packetized) in worst.iter_mut().zip(&curves).zip(&packetized) {
+            for &(time, _) in gps.points.iter().chain(&packetized.points) {
+                let distance = gps.at(time) - packetized.at(time);
+                worst.service_lag = worst.service_lag.max(distance);
+                worst.service_lead = worst.service_lead.max(-distance);
+            }
+        }
+        worst
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        schedulers::{fifo::FIFOScheduler, wf2q::WF2QPlusScheduler, wfq::WFQScheduler},
+        Packet, Scheduler,
+    };
+
+    use super::GPSReference;
+
+    #[test]
+    fn gps_departures_test() {
+        let mut gps = GPSReference::new(1);
+
+        let mut flow1 = VariableLengthFlow::new();
+        flow1.packet_arrive(Packet::new("a1", 2), 0);
+        flow1.packet_arrive(Packet::new("a2", 2), 0);
+        gps.add_flow(&flow1, 1f64);
+
+        let mut flow2 = VariableLengthFlow::new();
+        flow2.packet_arrive(Packet::new("b1", 1), 1);
+        gps.add_flow(&flow2, 1f64);
+
+        // a1 is served alone for one tick, then shares the link with b1.
+        let departures = gps.departures();
+        assert_eq!(departures[0], vec![3f64, 5f64]);
+        assert_eq!(departures[1], vec![3f64]);
+    }
+
+    #[test]
+    fn gps_wfq_deviation_test() {
+        let mut wfq = WFQScheduler::new(1);
+        let mut gps = GPSReference::new(1);
+
+        let arrivals = [
+            (vec![("p1", 0), ("p4", 2), ("p6", 5)], 0.5f64),
+            (vec![("p2", 0), ("p5", 3), ("p9", 7)], 0.25f64),
+            (vec![("p3", 0), ("p7", 5), ("p8", 6)], 0.25f64),
+        ];
+        for (packets, weight) in arrivals {
+            let mut flow = VariableLengthFlow::new();
+            for (name, time) in packets {
+                flow.packet_arrive(Packet::new(name, 1), time);
+            }
+            gps.add_flow(&flow, weight);
+            wfq.add_flow(flow, weight);
+        }
+
+        wfq.run();
+
+        let deviations = gps.deviation(wfq.get_output_port());
+        assert_eq!(deviations.len(), 3);
+        for deviation in deviations {
+            assert!(deviation.mean <= deviation.max);
+            assert!(deviation.max <= 2f64);
+        }
+    }
+
+    #[test]
+    fn gps_service_curves_test() {
+        let mut gps = GPSReference::new(1);
+        let mut flow1 = VariableLengthFlow::new();
+        flow1.packet_arrive(Packet::new("a1", 2), 0);
+        flow1.packet_arrive(Packet::new("a2", 2), 0);
+        gps.add_flow(&flow1, 1f64);
+        let mut flow2 = VariableLengthFlow::new();
+        flow2.packet_arrive(Packet::new("b1", 1), 1);
+        gps.add_flow(&flow2, 1f64);
+
+        // a is served alone until 1, at half the rate until 3, then alone.
+        let curves = gps.service_curves();
+        assert_eq!(curves[0].at(1f64), 1f64);
+        assert_eq!(curves[0].at(2f64), 1.5f64);
+        assert_eq!(curves[0].at(5f64), 4f64);
+        assert_eq!(curves[0].at(9f64), 4f64);
+        assert_eq!(curves[1].at(0.5f64), 0f64);
+        assert_eq!(curves[1].at(3f64), 1f64);
+    }
+
+    #[test]
+    fn gps_worst_case_test() {
+        let mut gps = GPSReference::new(1);
+        let mut wf2q = WF2QPlusScheduler::new(1);
+        let mut fifo = FIFOScheduler::new(1);
+        let max_len = 3;
+        for (idx, weight) in [4f64, 1f64, 1f64].into_iter().enumerate() {
+            let mut flow = VariableLengthFlow::new();
+            for p in 0..8 {
+                let len = 1 + (idx + p) % max_len;
+                flow.packet_arrive(Packet::new(format!("f{}_{}", idx, p), len), p * idx);
+            }
+            gps.add_flow(&flow, weight);
+            wf2q.add_flow(flow.clone(), weight);
+            fifo.add_flow(flow);
+        }
+        wf2q.run();
+        fifo.run();
+
+        // WF2Q+ stays within a maximum packet length of GPS,
+        // in delay and in service, behind it or ahead of it.
+        let wf2q = gps.worst_case(&wf2q.scheduler_stats());
+        for worst in &wf2q {
+            assert!(worst.delay <= max_len as f64);
+            assert!(worst.service_lag <= max_len as f64);
+            assert!(worst.service_lead <= max_len as f64);
+        }
+
+        // FIFO makes the light flows wait behind the heavy one.
+        let fifo = gps.worst_case(&fifo.scheduler_stats());
+        assert!(fifo[2].delay > wf2q[2].delay);
+        assert!(fifo[2].service_lag > wf2q[2].service_lag);
+    }
+}
@@ -0,0 +1,105 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+/// Seed of the loss decisions used by the [`LossModel`] constructors.
+pub const DEFAULT_LOSS_SEED: u64 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum Loss {
+    /// Every packet is lost with this probability.
+    Random(f64),
+    /// Every bit is flipped with this probability, and a packet with a
+    /// flipped bit is lost.
+    BitError(f64),
+}
+
+/// Random loss of the packets crossing a link.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LossModel {
+    loss: Loss,
+    seed: u64,
+    rng: ChaCha12Rng,
+}
+
+impl LossModel {
+    /// Lose every packet with probability `p`, whatever its length.
+    pub fn random(p: f64) -> LossModel {
+        assert!(
+            (0f64..=1f64).contains(&p),
+            "a loss probability is within [0, 1]"
+        );
+        LossModel::with_loss(Loss::Random(p))
+    }
+
+    /// Lose every packet holding a bit flipped at the bit error rate `ber`,
+    /// so that longer packets are lost more often.
+    pub fn bit_error(ber: f64) -> LossModel {
+        assert!(
+            (0f64..=1f64).contains(&ber),
+            "a bit error rate is within [0, 1]"
+        );
+        LossModel::with_loss(Loss::BitError(ber))
+    }
+
+    fn with_loss(loss: Loss) -> LossModel {
+        LossModel {
+            loss,
+            seed: DEFAULT_LOSS_SEED,
+            rng: ChaCha12Rng::seed_from_u64(DEFAULT_LOSS_SEED),
+        }
+    }
+
+    /// Make the loss decisions reproducible from `seed`.
+    pub fn with_seed(mut self, seed: u64) -> LossModel {
+        self.seed = seed;
+        self.rng = ChaCha12Rng::seed_from_u64(seed);
+        self
+    }
+
+    /// The probability of losing a packet of `len` bytes.
+    pub fn loss_probability(&self, len: usize) -> f64 {
+        match self.loss {
+            Loss::Random(p) => p,
+            Loss::BitError(ber) => 1f64 - (1f64 - ber).powf(len as f64 * 8f64),
+        }
+    }
+
+    /// Decide whether a packet of `len` bytes is lost.
+    pub fn is_lost(&mut self, len: usize) -> bool {
+        let p = self.loss_probability(len);
+        p > 0f64 && self.rng.gen_bool(p)
+    }
+
+    /// Restart the loss decisions from the seed.
+    pub fn reset(&mut self) {
+        self.rng = ChaCha12Rng::seed_from_u64(self.seed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LossModel;
+
+    #[test]
+    fn loss_model_test() {
+        let mut none = LossModel::random(0f64);
+        assert!((0..100).all(|_| !none.is_lost(1500)));
+        let mut all = LossModel::bit_error(1f64);
+        assert!(all.is_lost(1));
+
+        // A BER of 1e-4 loses about 70% of 1500-byte packets,
+        // and 11% of 150-byte ones.
+        let ber = LossModel::bit_error(1e-4);
+        assert!((ber.loss_probability(1500) - 0.6988).abs() < 1e-3);
+        assert!((ber.loss_probability(150) - 0.1131).abs() < 1e-3);
+
+        let mut random = LossModel::random(0.5).with_seed(3);
+        let first: Vec<bool> = (0..20).map(|_| random.is_lost(1)).collect();
+        assert!(first.iter().any(|&lost| lost) && first.iter().any(|&lost| !lost));
+        random.reset();
+        let again: Vec<bool> = (0..20).map(|_| random.is_lost(1)).collect();
+        assert_eq!(first, again);
+    }
+}
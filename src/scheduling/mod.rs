@@ -1,10 +1,52 @@
+pub mod analysis;
+pub mod aqm;
+pub mod buffer;
+pub mod classifier;
+pub mod compare;
+#[cfg(feature = "config")]
+pub mod config;
+pub mod engine;
+pub mod evaluation;
+#[cfg(feature = "parallel")]
+pub mod experiments;
+pub mod fabric;
 pub mod flow;
+pub mod gps;
+pub mod loss;
+pub mod network;
+#[cfg(feature = "pcap")]
+pub mod pcap;
+pub mod policing;
 pub mod schedulers;
+pub mod shaping;
+pub mod source;
+pub mod stats;
+pub mod switch;
+pub mod traffic;
+pub mod units;
+pub mod viz;
+pub mod workload;
+
+pub use schedulers::{FlowId, Scheduler};
+
+use std::collections::{BTreeMap, VecDeque};
+
+use aqm::{pie::PIE_MAX_MARK_PROBABILITY, Pie, Red, Wred};
+use buffer::SharedBuffer;
+use loss::LossModel;
+use units::Rate;
+
+/// Bytes short of a packet length that still complete it,
+/// absorbing the rounding of fractional rates.
+const BYTE_EPSILON: f64 = 1e-9;
 
 /// A trait for objects that can be ticked.
 trait Tickable {
-    /// Tick the object.
-    /// Returns false if the object is done.
+    /// Advance the object by one tick.
+    /// What the returned flag reports depends on the object and is
+    /// documented with each implementation: the schedulers and the shaper
+    /// return false once they are done, while the port returns whether
+    /// a packet finished transmitting on this tick.
     fn tick(&mut self) -> bool;
 }
 
@@ -12,73 +54,1543 @@ trait Schedulable<T>: Tickable {
     fn schedule(&mut self) -> T;
 }
 
-#[derive(Debug)]
+/// The output of a scheduler run, detached from the scheduler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SchedulerOutput {
+    pub output: Vec<Packet>,
+    pub timer: usize,
+    /// The flow each packet of `output` came from.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub flows: Vec<FlowId>,
+    /// When each packet of `output` finished transmitting.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub departure_times: Vec<usize>,
+}
+
+impl SchedulerOutput {
+    /// The packets of `output` with their flow and departure time.
+    pub fn departures(&self) -> impl Iterator<Item = Departure> + '_ {
+        self.output
+            .iter()
+            .zip(&self.flows)
+            .zip(&self.departure_times)
+            .map(|((packet, &flow), &time)| Departure {
+                flow,
+                packet: packet.clone(),
+                time,
+            })
+    }
+}
+
+/// A packet that left a scheduler, attributed to the flow it came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Departure {
+    pub flow: FlowId,
+    pub packet: Packet,
+    /// When the packet finished transmitting.
+    pub time: usize,
+}
+
+#[derive(Debug, Clone)]
 pub struct Port {
     pub id: usize,
-    rate: usize,
-    in_queue: Vec<Packet>,
+    /// Bytes transmitted per tick, possibly less than one.
+    rate: f64,
+    /// The rate given at construction, restored by `reset`.
+    initial_rate: f64,
+    /// `(tick, rate)` pairs: from `tick` on, the port transmits at `rate`.
+    rate_profile: Vec<(usize, f64)>,
+    /// Index of the first entry of `rate_profile` not applied yet.
+    next_rate_change: usize,
+    timer: usize,
+    /// The packets waiting or being transmitted, each with its index
+    /// among the packets accepted by the port.
+    in_queue: VecDeque<(Packet, usize)>,
     out_queue: Vec<Packet>,
+    /// Index among the accepted packets of each packet in `out_queue`.
+    accepted_indices: Vec<usize>,
+    /// The number of packets accepted so far.
+    accepted: usize,
+    /// Departure time of each packet in `out_queue`.
+    departures: Vec<usize>,
+    /// Whether each packet in `out_queue` was lost on the link.
+    lost: Vec<bool>,
+    /// Ticks from the end of a transmission to the delivery at the far end.
+    propagation_delay: usize,
+    /// Random loss of the transmitted packets.
+    loss: Option<LossModel>,
+    /// Maximum number of packets in `in_queue`, unbounded if None.
+    capacity: Option<usize>,
+    /// Maximum number of bytes in `in_queue`, unbounded if None.
+    byte_capacity: Option<usize>,
+    /// Early drop policy applied before the capacity check.
+    red: Option<Red>,
+    /// Early drop policy with thresholds per DSCP, applied after RED.
+    wred: Option<Wred>,
+    /// Early drop policy on the queueing delay, applied after RED.
+    pie: Option<Pie>,
+    /// Queue lengths from which yellow and red packets are dropped.
+    color_limits: Option<(usize, usize)>,
+    dropped: usize,
+    /// Packets marked instead of dropped early.
+    marked: usize,
 
-    current_processed: usize,
+    /// Bytes of the head packet transmitted so far.
+    current_processed: f64,
+    /// Whether a tick left over after a packet completes goes to the
+    /// next packet, rather than being lost to tick granularity.
+    byte_accurate: bool,
+    /// When the head packet started transmitting, and a copy of it
+    /// to check that it is not preempted. Only kept byte-accurately.
+    in_flight: Option<(f64, Packet)>,
+    /// Start and end of the transmission of each packet in `out_queue`,
+    /// only kept byte-accurately.
+    transmissions: Vec<(f64, f64)>,
+    /// Whether the head packet has started transmitting.
+    head_started: bool,
+    /// Frame preemption, where express frames suspend preemptable ones.
+    preemption: Option<FramePreemption>,
+    /// The bytes sent and the start of the preemptable frame suspended by
+    /// an express frame. It is the first preemptable frame of the queue.
+    suspended: Option<(f64, Option<(f64, Packet)>)>,
+    /// The value of `current_processed` when the current fragment of the
+    /// head packet started.
+    fragment_start: f64,
+    preempted: usize,
+    /// Traffic class queues feeding `in_queue`, which then only holds the
+    /// packet being transmitted. Empty while the port has a single queue.
+    class_queues: Vec<VecDeque<(Packet, usize)>>,
+    queue_service: QueueService,
+    /// The queue whose turn it is under WRR, and the packets it sent.
+    wrr_turn: (usize, usize),
+    /// The queue of the packet being transmitted.
+    head_queue: usize,
+    /// Byte pool the queues draw from.
+    shared_buffer: Option<SharedBuffer>,
+    /// Bytes the port takes from its scheduler ahead of transmission.
+    tx_credit: usize,
 }
 
 impl Port {
     pub fn new(id: usize, rate: usize) -> Port {
+        Port::with_rate(id, Rate::from(rate))
+    }
+
+    /// Create a port with a fractional rate,
+    /// such as a slow link sending less than a byte per tick.
+    pub fn with_rate(id: usize, rate: Rate) -> Port {
+        let rate = rate.as_bytes_per_tick();
         Port {
             id,
             rate,
-            current_processed: 0,
-            in_queue: Vec::new(),
+            initial_rate: rate,
+            rate_profile: Vec::new(),
+            next_rate_change: 0,
+            timer: 0,
+            current_processed: 0f64,
+            in_queue: VecDeque::new(),
             out_queue: Vec::new(),
+            accepted_indices: Vec::new(),
+            accepted: 0,
+            departures: Vec::new(),
+            lost: Vec::new(),
+            propagation_delay: 0,
+            loss: None,
+            capacity: None,
+            byte_capacity: None,
+            red: None,
+            wred: None,
+            pie: None,
+            color_limits: None,
+            dropped: 0,
+            marked: 0,
+            byte_accurate: false,
+            in_flight: None,
+            transmissions: Vec::new(),
+            head_started: false,
+            preemption: None,
+            suspended: None,
+            fragment_start: 0f64,
+            preempted: 0,
+            class_queues: Vec::new(),
+            queue_service: QueueService::Strict,
+            wrr_turn: (0, 0),
+            head_queue: 0,
+            shared_buffer: None,
+            tx_credit: 0,
+        }
+    }
+
+    /// Create a port whose queue holds at most `capacity` packets,
+    /// including the one being transmitted.
+    pub fn with_capacity(id: usize, rate: usize, capacity: usize) -> Port {
+        Port {
+            capacity: Some(capacity),
+            ..Port::new(id, rate)
+        }
+    }
+
+    pub fn set_capacity(&mut self, capacity: Option<usize>) {
+        self.capacity = capacity;
+    }
+
+    /// Limit the queue to `capacity` bytes, including the packet
+    /// being transmitted. Applies along with the limit in packets.
+    pub fn set_byte_capacity(&mut self, capacity: Option<usize>) {
+        self.byte_capacity = capacity;
+    }
+
+    /// Create a port that drops packets with Random Early Detection.
+    pub fn with_red(id: usize, rate: usize, min_th: f64, max_th: f64, max_p: f64) -> Port {
+        Port {
+            red: Some(Red::new(min_th, max_th, max_p)),
+            ..Port::new(id, rate)
+        }
+    }
+
+    pub fn set_red(&mut self, red: Option<Red>) {
+        self.red = red;
+    }
+
+    pub fn get_red(&self) -> Option<&Red> {
+        self.red.as_ref()
+    }
+
+    pub fn set_wred(&mut self, wred: Option<Wred>) {
+        self.wred = wred;
+    }
+
+    pub fn get_wred(&self) -> Option<&Wred> {
+        self.wred.as_ref()
+    }
+
+    pub fn set_pie(&mut self, pie: Option<Pie>) {
+        self.pie = pie;
+    }
+
+    pub fn get_pie(&self) -> Option<&Pie> {
+        self.pie.as_ref()
+    }
+
+    /// Track transmissions to the byte: the part of a tick left over after
+    /// a packet completes is used by the next packet, so that several
+    /// packets can complete on one tick, and the exact start and end of
+    /// every transmission are recorded, see [`Port::get_transmission_times`].
+    /// Transmissions are checked to never be preempted.
+    /// Otherwise, a packet completing uses its whole last tick.
+    pub fn set_byte_accurate(&mut self, byte_accurate: bool) {
+        self.byte_accurate = byte_accurate;
+    }
+
+    pub fn is_byte_accurate(&self) -> bool {
+        self.byte_accurate
+    }
+
+    /// Let express frames preempt preemptable ones, as in IEEE 802.1Qbu:
+    /// express frames are sent before preemptable ones, and one arriving
+    /// while a preemptable frame is transmitted suspends it if both its
+    /// fragments are long enough. The frame resumes after the express
+    /// frames, paying the overhead of a new fragment.
+    /// Without preemption, frames are sent in arrival order.
+    /// Only available on a port with a single queue.
+    pub fn set_frame_preemption(&mut self, preemption: Option<FramePreemption>) {
+        assert!(
+            preemption.is_none() || self.class_queues.is_empty(),
+            "frame preemption needs a port with a single queue"
+        );
+        self.preemption = preemption;
+    }
+
+    pub fn get_frame_preemption(&self) -> Option<FramePreemption> {
+        self.preemption
+    }
+
+    /// Split the port into `count` queues, like the traffic classes of a
+    /// NIC or switch port, with `service` choosing the queue to send from
+    /// whenever a transmission completes. The packet being transmitted is
+    /// never preempted. Packets go to the queue of their priority, capped
+    /// at the last queue, or to the queue given to [`Port::submit_to`].
+    /// Drop policies and capacities apply to the port as a whole.
+    /// A single queue, the default, sends packets in arrival order.
+    pub fn set_queues(&mut self, count: usize, service: QueueService) {
+        assert!(count > 0, "a port needs at least one queue");
+        assert!(self.empty(), "queues are set on an empty port");
+        assert!(
+            count == 1 || self.preemption.is_none(),
+            "frame preemption needs a port with a single queue"
+        );
+        if let QueueService::WeightedRoundRobin(weights) = &service {
+            assert_eq!(weights.len(), count, "every queue needs a weight");
+            assert!(weights.iter().all(|&w| w > 0), "weights must be positive");
         }
+        self.class_queues = if count > 1 {
+            vec![VecDeque::new(); count]
+        } else {
+            Vec::new()
+        };
+        self.queue_service = service;
+        self.wrr_turn = (0, 0);
+    }
+
+    /// Let the queues draw from a shared byte pool, each up to its
+    /// threshold. The pool is checked after the capacities of the port,
+    /// and packets it refuses count as dropped by the port.
+    pub fn set_shared_buffer(&mut self, buffer: Option<SharedBuffer>) {
+        self.shared_buffer = buffer;
+    }
+
+    pub fn get_shared_buffer(&self) -> Option<&SharedBuffer> {
+        self.shared_buffer.as_ref()
+    }
+
+    pub fn queue_count(&self) -> usize {
+        self.class_queues.len().max(1)
+    }
+
+    pub fn get_queue_service(&self) -> &QueueService {
+        &self.queue_service
+    }
+
+    /// The number of packets waiting in a queue,
+    /// not counting the packet being transmitted.
+    pub fn class_queue_len(&self, queue: usize) -> usize {
+        match self.class_queues.get(queue) {
+            Some(packets) => packets.len(),
+            None => {
+                assert!(queue < self.queue_count(), "no queue {}", queue);
+                self.in_queue.len().saturating_sub(1)
+            }
+        }
+    }
+
+    /// The number of preemptable frames suspended by express frames.
+    pub fn preempted_count(&self) -> usize {
+        self.preempted
+    }
+
+    /// Deliver packets `delay` ticks after they finish transmitting.
+    pub fn set_propagation_delay(&mut self, delay: usize) {
+        self.propagation_delay = delay;
+    }
+
+    pub fn get_propagation_delay(&self) -> usize {
+        self.propagation_delay
+    }
+
+    /// Lose transmitted packets on the link. Lost packets still count as
+    /// output of the port, but are not delivered.
+    pub fn set_loss(&mut self, loss: Option<LossModel>) {
+        self.loss = loss;
+    }
+
+    pub fn get_loss(&self) -> Option<&LossModel> {
+        self.loss.as_ref()
+    }
+
+    /// Drop yellow packets arriving when `yellow` packets are queued
+    /// and red packets arriving when `red` packets are queued,
+    /// so that green packets keep the rest of the queue.
+    pub fn set_color_limits(&mut self, limits: Option<(usize, usize)>) {
+        self.color_limits = limits;
+    }
+
+    /// Let the port take packets from its scheduler ahead of transmission,
+    /// up to `bytes` queued. With the default of 0, a scheduler waits for
+    /// the port to be idle before every decision, deciding as late as it
+    /// can. Drop policies and capacities still apply to what is submitted.
+    pub fn set_tx_credit(&mut self, bytes: usize) {
+        self.tx_credit = bytes;
+    }
+
+    pub fn get_tx_credit(&self) -> usize {
+        self.tx_credit
+    }
+
+    /// The bytes the port still takes from its scheduler
+    /// before its transmit credit is used up.
+    pub fn free_bytes(&self) -> usize {
+        self.tx_credit.saturating_sub(self.queue_bytes())
+    }
+
+    /// Whether the port takes a packet of `len` bytes from its scheduler
+    /// now: an idle port takes any packet, a busy one what fits its credit.
+    pub fn can_accept(&self, len: usize) -> bool {
+        self.empty() || len <= self.free_bytes()
+    }
+
+    /// Whether the port takes a packet from its scheduler now, for a
+    /// scheduler that only knows the packet once it has decided. As with
+    /// byte queue limits, the packet may then overdraw the credit.
+    pub fn is_accepting(&self) -> bool {
+        self.empty() || self.free_bytes() > 0
+    }
+
+    /// The number of packets waiting or being transmitted.
+    pub fn queue_len(&self) -> usize {
+        self.in_queue.len() + self.class_queues.iter().map(|q| q.len()).sum::<usize>()
+    }
+
+    /// The number of bytes waiting or being transmitted.
+    pub fn queue_bytes(&self) -> usize {
+        self.queued_packets().map(|p| p.len).sum()
+    }
+
+    /// The packets waiting or being transmitted, the latter first.
+    fn queued_packets(&self) -> impl Iterator<Item = &Packet> {
+        self.in_queue
+            .iter()
+            .chain(self.class_queues.iter().flatten())
+            .map(|(packet, _)| packet)
+    }
+
+    /// The number of ticks to transmit the queue at the current rate,
+    /// counted at one byte per tick while the port is stopped.
+    pub fn queue_delay(&self) -> usize {
+        let rate = self.get_rate().as_bytes_per_tick();
+        let rate = if rate > 0f64 { rate } else { 1f64 };
+        let total: usize = self
+            .queued_packets()
+            .map(|p| ticks_for(p.len as f64, rate))
+            .sum();
+        total - ((self.current_processed / rate) as usize).min(total)
     }
 
     pub fn empty(&self) -> bool {
-        self.in_queue.is_empty()
+        self.in_queue.is_empty() && self.class_queues.iter().all(|q| q.is_empty())
     }
 
-    pub fn submit(&mut self, packet: Packet) {
-        self.in_queue.push(packet);
+    /// Enqueue a packet for transmission, in the queue of its priority.
+    /// If the packet is dropped early, by its color or because the queue
+    /// is full, the packet is given back. ECN-capable packets are marked
+    /// instead of dropped early.
+    pub fn submit(&mut self, packet: Packet) -> Result<(), Packet> {
+        let queue = (packet.priority as usize).min(self.queue_count() - 1);
+        self.submit_to(queue, packet)
     }
 
-    pub fn get_output(&mut self) -> &Vec<Packet> {
+    /// Enqueue a packet for transmission in the given queue,
+    /// see [`Port::submit`] and [`Port::set_queues`].
+    pub fn submit_to(&mut self, queue: usize, mut packet: Packet) -> Result<(), Packet> {
+        assert!(queue < self.queue_count(), "no queue {}", queue);
+        let queue_len = self.queue_len();
+        let mut early = self
+            .red
+            .as_mut()
+            .is_some_and(|red| red.should_drop(queue_len));
+        if !early {
+            early = self
+                .wred
+                .as_mut()
+                .is_some_and(|wred| wred.should_drop(queue_len, packet.dscp));
+        }
+        if !early {
+            if let Some(pie) = &mut self.pie {
+                // PIE only marks while its drop probability is low.
+                if pie.should_drop(queue_len) {
+                    early = true;
+                    if pie.probability() > PIE_MAX_MARK_PROBABILITY {
+                        self.dropped += 1;
+                        return Err(packet);
+                    }
+                }
+            }
+        }
+        if early {
+            if !packet.mark_ce() {
+                self.dropped += 1;
+                return Err(packet);
+            }
+            self.marked += 1;
+        }
+        if let Some((yellow, red)) = self.color_limits {
+            let limit = match packet.color {
+                Color::Green => None,
+                Color::Yellow => Some(yellow),
+                Color::Red => Some(red),
+            };
+            if limit.is_some_and(|limit| queue_len >= limit) {
+                self.dropped += 1;
+                return Err(packet);
+            }
+        }
+        let full = self.capacity.is_some_and(|c| queue_len >= c)
+            || self
+                .byte_capacity
+                .is_some_and(|c| self.queue_bytes() + packet.len > c);
+        if full {
+            self.dropped += 1;
+            return Err(packet);
+        }
+        if let Some(buffer) = &mut self.shared_buffer {
+            if buffer.admit(queue, packet.len).is_err() {
+                self.dropped += 1;
+                return Err(packet);
+            }
+        }
+        let packet = (packet, self.accepted);
+        self.accepted += 1;
+        if let Some(packets) = self.class_queues.get_mut(queue) {
+            packets.push_back(packet);
+            self.refill();
+        } else {
+            self.enqueue(packet);
+        }
+        Ok(())
+    }
+
+    /// Move the next packet of the queues to the transmitter once it is
+    /// free, taking the turn of its queue.
+    fn refill(&mut self) {
+        if !self.in_queue.is_empty() {
+            return;
+        }
+        let nonempty = |q: usize| !self.class_queues[q].is_empty();
+        let count = self.class_queues.len();
+        let queue = match &self.queue_service {
+            QueueService::Strict => (0..count).rev().find(|&q| nonempty(q)),
+            QueueService::WeightedRoundRobin(weights) => {
+                let (turn, sent) = self.wrr_turn;
+                if sent < weights[turn] && nonempty(turn) {
+                    Some(turn)
+                } else {
+                    (1..=count)
+                        .map(|k| (turn + k) % count)
+                        .find(|&q| nonempty(q))
+                }
+            }
+        };
+        let Some(queue) = queue else {
+            return;
+        };
+        self.wrr_turn = match self.wrr_turn {
+            (turn, sent) if turn == queue => (turn, sent + 1),
+            _ => (queue, 1),
+        };
+        let packet = self.class_queues[queue].pop_front().unwrap();
+        self.in_queue.push_back(packet);
+        self.head_queue = queue;
+    }
+
+    /// Queue an accepted packet. With frame preemption, an express frame
+    /// goes behind the express frames only, preempting the preemptable
+    /// frame being transmitted if possible.
+    fn enqueue(&mut self, packet: (Packet, usize)) {
+        let Some(preemption) = self.preemption else {
+            self.in_queue.push_back(packet);
+            return;
+        };
+        if packet.0.frame_class == FrameClass::Preemptable {
+            self.in_queue.push_back(packet);
+            return;
+        }
+        let busy = self.head_started
+            && self
+                .head()
+                .is_some_and(|p| p.frame_class == FrameClass::Preemptable);
+        if busy {
+            let head = self.head().unwrap();
+            let sent = self.current_processed - self.fragment_start;
+            let remaining = head.len as f64 - self.current_processed;
+            let min_fragment = preemption.min_fragment as f64;
+            if sent + BYTE_EPSILON >= min_fragment && remaining + BYTE_EPSILON >= min_fragment {
+                self.suspended = Some((self.current_processed, self.in_flight.take()));
+                self.current_processed = 0f64;
+                self.fragment_start = 0f64;
+                self.head_started = false;
+                self.preempted += 1;
+                self.in_queue.push_front(packet);
+                return;
+            }
+        }
+        // Behind the frame being transmitted and the express frames.
+        let skip = usize::from(busy);
+        let pos = skip
+            + self
+                .in_queue
+                .iter()
+                .skip(skip)
+                .take_while(|(p, _)| p.frame_class == FrameClass::Express)
+                .count();
+        if pos == 0 {
+            // Ahead of a head that has not sent a byte yet, which starts
+            // again once it is back at the head.
+            self.in_flight = None;
+        }
+        self.in_queue.insert(pos, packet);
+    }
+
+    /// Empty the port and restart its clock and rate profile,
+    /// keeping its configuration.
+    pub fn reset(&mut self) {
+        self.timer = 0;
+        self.rate = self.initial_rate;
+        self.next_rate_change = 0;
+        self.in_queue.clear();
+        self.out_queue.clear();
+        self.accepted_indices.clear();
+        self.accepted = 0;
+        self.departures.clear();
+        self.lost.clear();
+        self.dropped = 0;
+        self.marked = 0;
+        self.current_processed = 0f64;
+        self.in_flight = None;
+        self.transmissions.clear();
+        self.head_started = false;
+        self.suspended = None;
+        self.fragment_start = 0f64;
+        self.preempted = 0;
+        self.class_queues.iter_mut().for_each(VecDeque::clear);
+        self.wrr_turn = (0, 0);
+        self.head_queue = 0;
+        if let Some(buffer) = &mut self.shared_buffer {
+            buffer.reset();
+        }
+        if let Some(red) = &mut self.red {
+            red.reset();
+        }
+        if let Some(wred) = &mut self.wred {
+            wred.reset();
+        }
+        if let Some(pie) = &mut self.pie {
+            pie.reset();
+        }
+        if let Some(loss) = &mut self.loss {
+            loss.reset();
+        }
+    }
+
+    /// The number of packets dropped, early or because the queue was full.
+    pub fn dropped_count(&self) -> usize {
+        self.dropped
+    }
+
+    /// The number of packets marked instead of dropped early.
+    pub fn marked_count(&self) -> usize {
+        self.marked
+    }
+
+    /// The number of transmitted packets lost on the link.
+    pub fn lost_count(&self) -> usize {
+        self.lost.iter().filter(|&&lost| lost).count()
+    }
+
+    /// The output packets that reached the far end of the link,
+    /// with the tick they arrived at.
+    pub fn get_delivered(&self) -> Vec<(&Packet, usize)> {
+        self.out_queue
+            .iter()
+            .zip(&self.departures)
+            .zip(&self.lost)
+            .filter(|(_, &lost)| !lost)
+            .map(|((packet, &departure), _)| (packet, departure + self.propagation_delay))
+            .collect()
+    }
+
+    pub fn get_output(&self) -> &Vec<Packet> {
         &self.out_queue
     }
 
+    /// The index of each output packet among the packets accepted by the
+    /// port, counted in the order they were submitted. Packets may leave
+    /// in another order, overtaken by a higher class or an express frame.
+    pub fn get_accepted_indices(&self) -> &[usize] {
+        &self.accepted_indices
+    }
+
+    /// The tick at which each output packet finished transmitting.
+    pub fn get_departure_times(&self) -> &Vec<usize> {
+        &self.departures
+    }
+
+    /// The exact start and end of the transmission of each output packet,
+    /// in ticks. Only recorded while the port is byte-accurate.
+    pub fn get_transmission_times(&self) -> &[(f64, f64)] {
+        &self.transmissions
+    }
+
+    /// Keep transmitting until the queue is empty.
     pub fn proceed_rest(&mut self) {
-        while let Some(packet) = self.in_queue.first() {
-            self.current_processed = 0;
-            self.out_queue.push(self.in_queue.remove(0));
+        while let Some(ticks) = self.ticks_to_completion() {
+            self.advance(ticks);
+        }
+    }
+
+    /// The number of ticks until the packet being transmitted completes,
+    /// including the tick on which it completes.
+    /// Returns None if the queue is empty or the port cannot make progress.
+    pub fn ticks_to_completion(&self) -> Option<usize> {
+        let packet = self.head()?;
+        let mut remaining = (packet.len as f64 - self.current_processed).max(0f64);
+        let mut rate = self.rate;
+        let mut change = self.next_rate_change;
+        let mut time = self.timer;
+        let mut elapsed = 0;
+        loop {
+            while let Some(&(tick, new_rate)) = self.rate_profile.get(change) {
+                if tick > time {
+                    break;
+                }
+                rate = new_rate;
+                change += 1;
+            }
+            let segment = self.rate_profile.get(change).map(|&(tick, _)| tick - time);
+            if rate > 0f64 {
+                let needed = ticks_for(remaining, rate).max(1);
+                if segment.is_none_or(|segment| needed <= segment) {
+                    return Some(elapsed + needed);
+                }
+            }
+            let segment = segment?;
+            remaining -= rate * segment as f64;
+            elapsed += segment;
+            time += segment;
         }
-        self.current_processed = 0;
     }
 
+    /// Advance the port by `ticks` ticks at once.
+    /// This is equivalent to calling `tick` that many times.
+    pub fn advance(&mut self, mut ticks: usize) {
+        if self.pie.is_some() {
+            // PIE samples the queueing delay on every tick.
+            for _ in 0..ticks {
+                self.tick();
+            }
+            return;
+        }
+        while ticks > 0 {
+            self.apply_rate_changes();
+            // The rate is constant until the next change.
+            let segment = match self.rate_profile.get(self.next_rate_change) {
+                Some(&(tick, _)) => (tick - self.timer).min(ticks),
+                None => ticks,
+            };
+            self.advance_at_current_rate(segment);
+            ticks -= segment;
+        }
+    }
+
+    fn advance_at_current_rate(&mut self, mut ticks: usize) {
+        while ticks > 0 {
+            let needed = match self.head() {
+                Some(packet) if self.rate > 0f64 => {
+                    let remaining = packet.len as f64 - self.current_processed;
+                    Some(ticks_for(remaining, self.rate).max(1))
+                }
+                _ => None,
+            };
+            match needed {
+                Some(needed) if needed <= ticks => {
+                    self.start_transmission(self.timer as f64);
+                    self.head_started = true;
+                    self.timer += needed - 1;
+                    self.current_processed += (needed - 1) as f64 * self.rate;
+                    self.tick();
+                    ticks -= needed;
+                }
+                Some(_) => {
+                    self.start_transmission(self.timer as f64);
+                    self.head_started = true;
+                    self.timer += ticks;
+                    self.current_processed += ticks as f64 * self.rate;
+                    ticks = 0;
+                }
+                None => {
+                    self.timer += ticks;
+                    ticks = 0;
+                }
+            }
+        }
+    }
+
+    /// Change the transmission rate from now on.
+    /// A rate of 0 stalls the port.
+    pub fn set_rate(&mut self, rate: usize) {
+        self.set_fractional_rate(Rate::from(rate));
+    }
+
+    /// Change the transmission rate from now on,
+    /// allowing less than a byte per tick.
+    pub fn set_fractional_rate(&mut self, rate: Rate) {
+        self.rate = rate.as_bytes_per_tick();
+    }
+
+    /// Drive the rate from a list of `(tick, rate)` changes:
+    /// from `tick` on, the port transmits at `rate`.
+    pub fn set_rate_profile(&mut self, profile: Vec<(usize, usize)>) {
+        self.set_fractional_rate_profile(
+            profile
+                .into_iter()
+                .map(|(tick, rate)| (tick, Rate::from(rate)))
+                .collect(),
+        );
+    }
+
+    /// Like `set_rate_profile`, with fractional rates.
+    pub fn set_fractional_rate_profile(&mut self, profile: Vec<(usize, Rate)>) {
+        let mut profile: Vec<(usize, f64)> = profile
+            .into_iter()
+            .map(|(tick, rate)| (tick, rate.as_bytes_per_tick()))
+            .collect();
+        profile.sort_by_key(|&(tick, _)| tick);
+        self.rate_profile = profile;
+        self.next_rate_change = 0;
+    }
+
+    fn apply_rate_changes(&mut self) {
+        while let Some(&(tick, rate)) = self.rate_profile.get(self.next_rate_change) {
+            if tick > self.timer {
+                break;
+            }
+            self.rate = rate;
+            self.next_rate_change += 1;
+        }
+    }
+
+    /// The current transmission rate, in whole bytes per tick.
     pub fn get_bandwidth(&self) -> usize {
-        self.rate
+        self.get_rate().as_bytes_per_tick() as usize
+    }
+
+    /// The current transmission rate.
+    pub fn get_rate(&self) -> Rate {
+        let rate = self.rate_profile[self.next_rate_change..]
+            .iter()
+            .take_while(|&&(tick, _)| tick <= self.timer)
+            .last()
+            .map_or(self.rate, |&(_, rate)| rate);
+        Rate::bytes_per_tick(rate)
     }
+
+    /// The packet being transmitted, or next to be.
+    fn head(&self) -> Option<&Packet> {
+        self.in_queue.front().map(|(packet, _)| packet)
+    }
+
+    /// Record that the head packet starts transmitting at `time`,
+    /// unless it already started.
+    fn start_transmission(&mut self, time: f64) {
+        if !self.byte_accurate || self.in_flight.is_some() {
+            return;
+        }
+        if let Some(packet) = self.head() {
+            self.in_flight = Some((time, packet.clone()));
+        }
+    }
+
+    /// Move the head packet, which finished transmitting at `end`,
+    /// to the output.
+    fn complete_transmission(&mut self, end: f64) {
+        let (packet, index) = self.in_queue.pop_front().unwrap();
+        if self.byte_accurate {
+            let (start, started) = self
+                .in_flight
+                .take()
+                .expect("a packet completed without starting");
+            assert_eq!(
+                started, packet,
+                "the transmission of a packet was preempted"
+            );
+            self.transmissions.push((start, end));
+        }
+        if let Some(buffer) = &mut self.shared_buffer {
+            buffer.release(self.head_queue, packet.len);
+        }
+        let lost = self.loss.as_mut().is_some_and(|l| l.is_lost(packet.len));
+        self.lost.push(lost);
+        self.out_queue.push(packet);
+        self.accepted_indices.push(index);
+        self.departures.push(self.timer);
+        self.head_started = false;
+        self.fragment_start = 0f64;
+        self.refill();
+    }
+
+    /// Resume the suspended frame once it is back at the head of the queue,
+    /// with the overhead of a new fragment to send first.
+    fn resume_transmission(&mut self) {
+        let Some(preemption) = self.preemption else {
+            return;
+        };
+        let resumes = self
+            .head()
+            .is_some_and(|p| p.frame_class == FrameClass::Preemptable);
+        if !resumes {
+            return;
+        }
+        if let Some((sent, in_flight)) = self.suspended.take() {
+            self.fragment_start = sent - preemption.overhead as f64;
+            self.current_processed += self.fragment_start;
+            self.in_flight = in_flight;
+            self.head_started = true;
+        }
+    }
+}
+
+/// The number of whole ticks to transmit `bytes` at `rate`.
+fn ticks_for(bytes: f64, rate: f64) -> usize {
+    ((bytes - BYTE_EPSILON) / rate).ceil().max(0f64) as usize
 }
 
 impl Tickable for Port {
+    /// Transmit the packet at the head of the queue for one tick.
+    /// Returns true if the packet finished transmitting on this tick.
     fn tick(&mut self) -> bool {
-        if let Some(packet) = self.in_queue.first() {
-            self.current_processed += self.rate;
-            if self.current_processed >= packet.len {
-                self.current_processed = 0;
-                self.out_queue.push(self.in_queue.remove(0));
+        self.apply_rate_changes();
+        if let Some(mut pie) = self.pie.take() {
+            pie.tick(self.queue_delay() as f64);
+            self.pie = Some(pie);
+        }
+        self.start_transmission(self.timer as f64);
+        self.timer += 1;
+        if self.in_queue.is_empty() {
+            return false;
+        }
+        self.current_processed += self.rate;
+        self.head_started = true;
+        let mut completed = false;
+        while let Some(packet) = self.head() {
+            let excess = self.current_processed - packet.len as f64;
+            if excess + BYTE_EPSILON < 0f64 {
+                break;
             }
+            completed = true;
+            if !self.byte_accurate {
+                self.current_processed = 0f64;
+                self.complete_transmission(self.timer as f64);
+                self.resume_transmission();
+                break;
+            }
+            // The bytes sent past the end of the packet belong to the next.
+            let excess = excess.max(0f64);
+            let end = if self.rate > 0f64 {
+                self.timer as f64 - excess / self.rate
+            } else {
+                self.timer as f64
+            };
+            self.complete_transmission(end);
+            if !self.in_queue.is_empty() {
+                self.current_processed = excess;
+                self.head_started = excess > 0f64;
+            } else {
+                self.current_processed = 0f64;
+            }
+            self.resume_transmission();
+            self.start_transmission(end);
         }
-        false
+        completed
     }
 }
 
+/// Drop precedence of a packet, as set by a meter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Color {
+    #[default]
+    Green,
+    Yellow,
+    Red,
+}
+
+/// Explicit Congestion Notification (ECN) codepoint of a packet,
+/// as in RFC 3168.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Ecn {
+    /// Not ECN-capable, congestion is signalled by dropping the packet.
+    #[default]
+    NotEct,
+    /// ECN-capable.
+    Ect,
+    /// Congestion experienced, marked by a queue on the way.
+    Ce,
+}
+
+/// Whether a frame can be preempted, as in IEEE 802.1Qbu.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FrameClass {
+    /// Time-critical, sent before preemptable frames, which it suspends.
+    #[default]
+    Express,
+    /// Can be suspended by express frames and resumed in a new fragment.
+    Preemptable,
+}
+
+/// Smallest fragment of a preemptable frame, in bytes, as in IEEE 802.3br.
+pub const DEFAULT_MIN_FRAGMENT: usize = 64;
+
+/// Bytes added by every fragment after the first: preamble, fragment
+/// header, checksum and inter-frame gap.
+pub const DEFAULT_FRAGMENT_OVERHEAD: usize = 24;
+
+/// Frame preemption settings of a [`Port`].
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FramePreemption {
+    /// A frame is only preempted once it has sent this many bytes of its
+    /// current fragment and while this many remain.
+    pub min_fragment: usize,
+    /// Bytes to send again when a suspended frame resumes.
+    pub overhead: usize,
+}
+
+impl Default for FramePreemption {
+    fn default() -> FramePreemption {
+        FramePreemption {
+            min_fragment: DEFAULT_MIN_FRAGMENT,
+            overhead: DEFAULT_FRAGMENT_OVERHEAD,
+        }
+    }
+}
+
+/// How a [`Port`] with several queues picks the queue to send from next.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum QueueService {
+    /// The last queue with a packet, so higher queues starve lower ones.
+    #[default]
+    Strict,
+    /// The queues take turns, each sending up to its weight in packets.
+    WeightedRoundRobin(Vec<usize>),
+}
+
+/// Time to live of a new packet, in hops.
+pub const DEFAULT_TTL: u8 = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Packet {
-    pub name: &'static str,
+    pub name: String,
     pub len: usize,
+    /// Green unless the packet went through a meter.
+    pub color: Color,
+    pub ecn: Ecn,
+    /// Differentiated Services codepoint, the class of the packet.
+    pub dscp: u8,
+    /// Priority of the packet, higher is more urgent, as in 802.1p.
+    pub priority: u8,
+    /// Identifier of the flow the packet belongs to, if it was given one.
+    pub flow_id: Option<usize>,
+    /// Hops left before the packet is discarded.
+    pub ttl: u8,
+    /// Free-form metadata, for classifiers and experiments.
+    pub tags: BTreeMap<String, String>,
+    /// Express unless the packet may be preempted on the link.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub frame_class: FrameClass,
 }
 
 impl Packet {
-    pub fn new(name: &'static str, len: usize) -> Packet {
-        Packet { name, len }
+    pub fn new(name: impl Into<String>, len: usize) -> Packet {
+        Packet {
+            name: name.into(),
+            len,
+            color: Color::Green,
+            ecn: Ecn::NotEct,
+            dscp: 0,
+            priority: 0,
+            flow_id: None,
+            ttl: DEFAULT_TTL,
+            tags: BTreeMap::new(),
+            frame_class: FrameClass::Express,
+        }
+    }
+
+    pub fn with_color(self, color: Color) -> Packet {
+        Packet { color, ..self }
+    }
+
+    pub fn with_ecn(self, ecn: Ecn) -> Packet {
+        Packet { ecn, ..self }
+    }
+
+    pub fn with_dscp(self, dscp: u8) -> Packet {
+        Packet { dscp, ..self }
+    }
+
+    pub fn with_priority(self, priority: u8) -> Packet {
+        Packet { priority, ..self }
+    }
+
+    pub fn with_flow_id(self, flow_id: usize) -> Packet {
+        Packet {
+            flow_id: Some(flow_id),
+            ..self
+        }
+    }
+
+    pub fn with_ttl(self, ttl: u8) -> Packet {
+        Packet { ttl, ..self }
+    }
+
+    pub fn with_frame_class(self, frame_class: FrameClass) -> Packet {
+        Packet {
+            frame_class,
+            ..self
+        }
+    }
+
+    /// Attach a tag, replacing any previous value of `key`.
+    pub fn with_tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Packet {
+        self.tags.insert(key.into(), value.into());
+        self
+    }
+
+    /// The value of the tag `key`, if the packet has it.
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags.get(key).map(String::as_str)
+    }
+
+    /// Take one hop off the time to live.
+    /// Returns false if the packet expired and has to be discarded.
+    pub fn decrement_ttl(&mut self) -> bool {
+        if self.ttl == 0 {
+            return false;
+        }
+        self.ttl -= 1;
+        self.ttl > 0
+    }
+
+    /// Signal congestion by marking the packet, if it is ECN-capable.
+    /// Returns false if the packet has to be dropped instead.
+    pub fn mark_ce(&mut self) -> bool {
+        if self.ecn == Ecn::NotEct {
+            return false;
+        }
+        self.ecn = Ecn::Ce;
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        aqm::Red, loss::LossModel, units::Rate, FrameClass, FramePreemption, Packet, Port,
+        QueueService, Tickable, DEFAULT_TTL,
+    };
+
+    #[test]
+    fn packet_metadata_test() {
+        let mut packet = Packet::new("p", 1)
+            .with_priority(5)
+            .with_flow_id(3)
+            .with_ttl(2)
+            .with_tag("app", "video")
+            .with_tag("app", "voice");
+        assert_eq!(packet.priority, 5);
+        assert_eq!(packet.flow_id, Some(3));
+        assert_eq!(packet.tag("app"), Some("voice"));
+        assert_eq!(packet.tag("user"), None);
+
+        assert!(packet.decrement_ttl());
+        assert!(!packet.decrement_ttl());
+        assert_eq!(packet.ttl, 0);
+        assert!(!packet.decrement_ttl());
+        assert_eq!(Packet::new("q", 1).ttl, DEFAULT_TTL);
+    }
+
+    #[test]
+    fn port_tick_test() {
+        let mut port = Port::new(0, 2);
+        assert!(!port.tick());
+
+        // Completes within a single tick.
+        port.submit(Packet::new("p1", 2)).unwrap();
+        assert!(port.tick());
+        assert_eq!(port.get_output(), &vec![Packet::new("p1", 2)]);
+
+        port.submit(Packet::new("p2", 3)).unwrap();
+        assert!(!port.tick());
+        assert!(port.tick());
+        assert!(!port.tick());
+        assert_eq!(port.get_output().len(), 2);
+        assert_eq!(port.get_departure_times(), &vec![2, 4]);
+    }
+
+    #[test]
+    fn port_advance_test() {
+        let mut ticked = Port::new(0, 2);
+        let mut advanced = Port::new(0, 2);
+        for port in [&mut ticked, &mut advanced] {
+            port.submit(Packet::new("p1", 5)).unwrap();
+            port.submit(Packet::new("p2", 1)).unwrap();
+            port.submit(Packet::new("p3", 4)).unwrap();
+        }
+
+        for _ in 0..5 {
+            ticked.tick();
+        }
+        advanced.advance(5);
+        assert_eq!(ticked.get_output(), advanced.get_output());
+        assert_eq!(ticked.get_departure_times(), advanced.get_departure_times());
+        assert_eq!(ticked.ticks_to_completion(), Some(1));
+
+        ticked.proceed_rest();
+        advanced.proceed_rest();
+        assert_eq!(ticked.get_departure_times(), &vec![3, 4, 6]);
+        assert_eq!(ticked.get_departure_times(), advanced.get_departure_times());
+    }
+
+    #[test]
+    fn port_capacity_test() {
+        let mut port = Port::with_capacity(0, 1, 2);
+        assert!(port.submit(Packet::new("p1", 1)).is_ok());
+        assert!(port.submit(Packet::new("p2", 1)).is_ok());
+        assert_eq!(port.submit(Packet::new("p3", 1)), Err(Packet::new("p3", 1)));
+        assert!(port.submit(Packet::new("p4", 1)).is_err());
+        assert_eq!(port.dropped_count(), 2);
+
+        // Room is made once a packet has been transmitted.
+        port.tick();
+        assert!(port.submit(Packet::new("p5", 1)).is_ok());
+        assert_eq!(port.dropped_count(), 2);
+    }
+
+    #[test]
+    fn port_byte_capacity_test() {
+        let mut port = Port::new(0, 1);
+        port.set_byte_capacity(Some(4));
+        assert!(port.submit(Packet::new("p1", 3)).is_ok());
+        assert!(port.submit(Packet::new("p2", 2)).is_err());
+        // A shorter packet still fits behind it.
+        assert!(port.submit(Packet::new("p3", 1)).is_ok());
+        assert_eq!(port.queue_bytes(), 4);
+        assert_eq!(port.dropped_count(), 1);
+    }
+
+    #[test]
+    fn port_red_test() {
+        let overload = |seed| {
+            let mut port = Port::new(0, 1);
+            port.set_red(Some(
+                Red::new(5f64, 15f64, 0.1).with_weight(0.2).with_seed(seed),
+            ));
+
+            let mut first_drop = None;
+            for t in 0..200 {
+                for i in 0..2 {
+                    let _ = port.submit(Packet::new(format!("p{}_{}", t, i), 1));
+                    if first_drop.is_none() && port.dropped_count() > 0 {
+                        first_drop = Some((port.queue_len(), port.get_red().unwrap().average()));
+                    }
+                }
+                port.tick();
+            }
+            (port.dropped_count(), first_drop.unwrap())
+        };
+
+        let (dropped, (queue_len, average)) = overload(7);
+        assert!(dropped > 0);
+        // Nothing is dropped before the average reaches min_th,
+        // and the average lags behind the queue.
+        assert!((5f64..15f64).contains(&average));
+        assert!(queue_len as f64 >= average);
+        assert_eq!(overload(7).0, dropped);
+    }
+
+    #[test]
+    fn port_rate_profile_test() {
+        let build = || {
+            let mut port = Port::new(0, 2);
+            port.submit(Packet::new("p1", 10)).unwrap();
+            port
+        };
+
+        // 10 bytes at rate 2 complete on the fifth tick.
+        let mut port = build();
+        port.proceed_rest();
+        assert_eq!(port.get_departure_times(), &vec![5]);
+
+        // Halving the rate after two ticks leaves 6 bytes at rate 1.
+        let mut port = build();
+        port.set_rate_profile(vec![(2, 1)]);
+        assert_eq!(port.ticks_to_completion(), Some(8));
+        assert_eq!(port.get_bandwidth(), 2);
+        port.tick();
+        port.tick();
+        assert_eq!(port.get_bandwidth(), 1);
+        port.proceed_rest();
+        assert_eq!(port.get_departure_times(), &vec![8]);
+
+        let mut advanced = build();
+        advanced.set_rate_profile(vec![(2, 1)]);
+        advanced.advance(8);
+        assert_eq!(advanced.get_departure_times(), &vec![8]);
+
+        // A zero rate stalls the port until the rate comes back.
+        let mut port = build();
+        port.set_rate_profile(vec![(1, 0), (4, 2)]);
+        assert_eq!(port.ticks_to_completion(), Some(8));
+        for _ in 0..4 {
+            assert!(!port.tick());
+        }
+        port.set_rate(0);
+        port.set_rate_profile(vec![]);
+        assert_eq!(port.ticks_to_completion(), None);
+        assert!(!port.tick());
+        port.set_rate(4);
+        assert_eq!(port.get_bandwidth(), 4);
+        assert!(!port.tick());
+        assert!(port.tick());
+        assert_eq!(port.get_departure_times(), &vec![7]);
+    }
+
+    #[test]
+    fn port_fractional_rate_test() {
+        // A tenth of a byte per tick takes 30 ticks for 3 bytes,
+        // whatever the rounding of the tenths.
+        let mut port = Port::with_rate(0, Rate::bytes_per_tick(0.1));
+        port.submit(Packet::new("p1", 3)).unwrap();
+        port.submit(Packet::new("p2", 1)).unwrap();
+        assert_eq!(port.ticks_to_completion(), Some(30));
+        assert_eq!(port.queue_delay(), 40);
+        assert_eq!(port.get_bandwidth(), 0);
+        let mut ticked = port.clone();
+        while !ticked.tick() {}
+        assert_eq!(ticked.get_departure_times(), &vec![30]);
+        port.proceed_rest();
+        assert_eq!(port.get_departure_times(), &vec![30, 40]);
+
+        // 2.5 bytes per tick alternate between 2 and 3 bytes.
+        let mut port = Port::new(0, 1);
+        port.set_fractional_rate_profile(vec![(0, Rate::bytes_per_tick(2.5))]);
+        for p in 0..4 {
+            port.submit(Packet::new(format!("p{}", p), 5)).unwrap();
+        }
+        port.advance(8);
+        assert_eq!(port.get_departure_times(), &vec![2, 4, 6, 8]);
+        assert_eq!(port.get_rate(), Rate(2.5));
+    }
+
+    #[test]
+    fn port_byte_accurate_test() {
+        let submit_all = |port: &mut Port| {
+            for p in 0..3 {
+                port.submit(Packet::new(format!("p{}", p), 2)).unwrap();
+            }
+        };
+
+        // At 3 bytes per tick, a packet of 2 bytes takes a whole tick,
+        // unless the rest of the tick goes to the next packet.
+        let mut coarse = Port::new(0, 3);
+        submit_all(&mut coarse);
+        coarse.proceed_rest();
+        assert_eq!(coarse.get_departure_times(), &vec![1, 2, 3]);
+        assert!(coarse.get_transmission_times().is_empty());
+
+        let mut exact = Port::new(0, 3);
+        exact.set_byte_accurate(true);
+        submit_all(&mut exact);
+        assert!(exact.tick());
+        assert_eq!(exact.ticks_to_completion(), Some(1));
+        assert!(exact.tick());
+        assert!(exact.empty());
+        assert_eq!(exact.get_departure_times(), &vec![1, 2, 2]);
+        let expected = [
+            (0f64, 2f64 / 3f64),
+            (2f64 / 3f64, 4f64 / 3f64),
+            (4f64 / 3f64, 2f64),
+        ];
+        for (&(start, end), (expected_start, expected_end)) in
+            exact.get_transmission_times().iter().zip(expected)
+        {
+            assert!((start - expected_start).abs() < 1e-9);
+            assert!((end - expected_end).abs() < 1e-9);
+        }
+
+        // The link idles once the queue is empty, and advancing
+        // gives the same transmissions as ticking.
+        let mut advanced = Port::new(0, 3);
+        advanced.set_byte_accurate(true);
+        advanced.submit(Packet::new("p0", 2)).unwrap();
+        advanced.advance(3);
+        advanced.submit(Packet::new("p1", 7)).unwrap();
+        advanced.proceed_rest();
+        assert_eq!(advanced.get_departure_times(), &vec![1, 6]);
+        assert_eq!(advanced.get_transmission_times()[1].0, 3f64);
+        assert!((advanced.get_transmission_times()[1].1 - 3f64 - 7f64 / 3f64).abs() < 1e-9);
+
+        advanced.reset();
+        assert!(advanced.get_transmission_times().is_empty());
+        assert!(advanced.is_byte_accurate());
+    }
+
+    #[test]
+    fn port_frame_preemption_test() {
+        let bulk = |name: &str| Packet::new(name, 200).with_frame_class(FrameClass::Preemptable);
+        let build = || {
+            let mut port = Port::new(0, 10);
+            port.set_byte_accurate(true);
+            port.set_frame_preemption(Some(FramePreemption::default()));
+            port.submit(bulk("b1")).unwrap();
+            port.submit(bulk("b2")).unwrap();
+            port
+        };
+
+        // After 80 bytes of b1, the express frame suspends it, and b1
+        // resumes with the 24 bytes of a new fragment, ahead of b2.
+        let mut port = build();
+        port.advance(8);
+        port.submit(Packet::new("e1", 100)).unwrap();
+        port.proceed_rest();
+        let names: Vec<&str> = port.get_output().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["e1", "b1", "b2"]);
+        assert_eq!(port.get_departure_times(), &vec![18, 33, 53]);
+        assert_eq!(port.get_accepted_indices(), [2, 0, 1]);
+        assert_eq!(port.get_transmission_times()[0], (8f64, 18f64));
+        let (start, end) = port.get_transmission_times()[1];
+        assert_eq!(start, 0f64);
+        assert!((end - 32.4f64).abs() < 1e-9);
+        assert_eq!(port.preempted_count(), 1);
+
+        // After 30 bytes, the first fragment would be too short,
+        // so the express frame waits for b1 but still goes before b2.
+        let mut port = build();
+        port.advance(3);
+        port.submit(Packet::new("e1", 100)).unwrap();
+        port.proceed_rest();
+        let names: Vec<&str> = port.get_output().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["b1", "e1", "b2"]);
+        assert_eq!(port.preempted_count(), 0);
+
+        // Without preemption, frames are sent in arrival order.
+        let mut port = build();
+        port.set_frame_preemption(None);
+        port.advance(8);
+        port.submit(Packet::new("e1", 100)).unwrap();
+        port.proceed_rest();
+        assert_eq!(port.get_departure_times(), &vec![20, 40, 50]);
+        assert_eq!(port.get_output()[2].name, "e1");
+    }
+
+    #[test]
+    fn port_frame_preemption_unstarted_head_test() {
+        // e1 comes too late to preempt b1 and goes before b2. e2 arrives
+        // on the tick b2 completes, when b3 is next but has not sent a
+        // byte yet: e2 goes first, without preempting anything.
+        let mut port = Port::new(0, 1);
+        port.set_byte_accurate(true);
+        port.set_frame_preemption(Some(FramePreemption {
+            min_fragment: 2,
+            overhead: 1,
+        }));
+        let bulk =
+            |name: &str, len| Packet::new(name, len).with_frame_class(FrameClass::Preemptable);
+        let arrivals = [
+            (0, Packet::new("e0", 5)),
+            (30, bulk("b1", 6)),
+            (35, Packet::new("e1", 5)),
+            (36, bulk("b2", 2)),
+            (41, bulk("b3", 5)),
+            (43, Packet::new("e2", 7)),
+        ];
+        for (time, packet) in arrivals {
+            port.advance(time - port.timer);
+            port.submit(packet).unwrap();
+        }
+        port.proceed_rest();
+        let names: Vec<&str> = port.get_output().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["e0", "b1", "e1", "b2", "e2", "b3"]);
+        assert_eq!(port.get_departure_times(), &vec![5, 36, 41, 43, 50, 55]);
+        assert_eq!(
+            port.get_transmission_times()[4..],
+            [(43f64, 50f64), (50f64, 55f64)]
+        );
+        assert_eq!(port.preempted_count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "preempted")]
+    fn port_preemption_test() {
+        let mut port = Port::new(0, 1);
+        port.set_byte_accurate(true);
+        port.submit(Packet::new("p1", 3)).unwrap();
+        port.tick();
+        port.in_queue.push_front((Packet::new("urgent", 1), 1));
+        port.proceed_rest();
+    }
+
+    #[test]
+    fn port_queues_test() {
+        // The urgent packet waits for the packet being transmitted only.
+        let mut port = Port::new(0, 1);
+        port.set_queues(8, QueueService::Strict);
+        port.submit(Packet::new("p1", 2)).unwrap();
+        port.submit(Packet::new("p2", 2)).unwrap();
+        port.tick();
+        port.submit(Packet::new("urgent", 1).with_priority(7))
+            .unwrap();
+        assert_eq!((port.queue_len(), port.class_queue_len(7)), (3, 1));
+        port.proceed_rest();
+        let names: Vec<&str> = port.get_output().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["p1", "urgent", "p2"]);
+        assert_eq!(port.get_departure_times(), &vec![2, 3, 5]);
+        assert_eq!(port.get_accepted_indices(), [0, 2, 1]);
+
+        // Under WRR, the first queue sends two packets per turn.
+        port.reset();
+        port.set_queues(2, QueueService::WeightedRoundRobin(vec![2, 1]));
+        for p in 0..6 {
+            port.submit_to(0, Packet::new(format!("a{}", p), 1))
+                .unwrap();
+        }
+        for p in 0..3 {
+            port.submit_to(1, Packet::new(format!("b{}", p), 1))
+                .unwrap();
+        }
+        port.proceed_rest();
+        let names: Vec<&str> = port.get_output().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["a0", "a1", "b0", "a2", "a3", "b1", "a4", "a5", "b2"]
+        );
+    }
+
+    #[test]
+    fn port_tx_credit_test() {
+        // An idle port takes any packet, a busy one only what fits its credit.
+        let mut port = Port::new(0, 1);
+        assert!(port.can_accept(10) && port.is_accepting());
+        port.submit(Packet::new("p1", 2)).unwrap();
+        assert!(!port.can_accept(1) && !port.is_accepting());
+
+        port.set_tx_credit(4);
+        assert_eq!(port.free_bytes(), 2);
+        assert!(port.can_accept(2) && !port.can_accept(3));
+        port.submit(Packet::new("p2", 3)).unwrap();
+        assert_eq!(port.free_bytes(), 0);
+        assert!(!port.is_accepting());
+        port.proceed_rest();
+        assert_eq!(port.free_bytes(), 4);
+    }
+
+    #[test]
+    fn port_link_test() {
+        let mut port = Port::new(0, 1);
+        port.set_propagation_delay(10);
+        port.set_loss(Some(LossModel::random(0.5).with_seed(1)));
+        for p in 0..20 {
+            port.submit(Packet::new(format!("p{}", p), 1)).unwrap();
+        }
+        port.proceed_rest();
+        // Lost packets still leave the port.
+        assert_eq!(port.get_output().len(), 20);
+        let delivered = port.get_delivered();
+        assert_eq!(delivered.len() + port.lost_count(), 20);
+        assert!(port.lost_count() > 0 && !delivered.is_empty());
+        let (packet, time) = delivered[0];
+        let departure = port.get_output().iter().position(|p| p == packet).unwrap() + 1;
+        assert_eq!(time, departure + 10);
+
+        let lost = port.lost_count();
+        port.reset();
+        for p in 0..20 {
+            port.submit(Packet::new(format!("p{}", p), 1)).unwrap();
+        }
+        port.proceed_rest();
+        assert_eq!(port.lost_count(), lost);
     }
 }
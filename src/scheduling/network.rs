@@ -0,0 +1,620 @@
+//! Networks of nodes joined by links, each link fed by its own scheduler.
+//!
+//! Flows follow static routes from node to node. A packet waits in the
+//! scheduler of every link on its route, is transmitted at the rate of the
+//! output port of that scheduler, and reaches the next node after the
+//! propagation delay of the link.
+//!
+//! Receivers acknowledge the packets of closed-loop sources, and the
+//! acknowledgements go back on a reverse path with its own delay, loss and
+//! possibly congestion.
+
+use std::collections::{BTreeSet, VecDeque};
+
+use crate::scheduling::{
+    flow::{OpenFlow, VariableLengthFlow},
+    loss::LossModel,
+    stats::{FlowStats, PacketRecord},
+    Ecn, FlowId, Packet, Scheduler,
+};
+
+/// A one-way link between two nodes of a [`Network`].
+pub struct Link {
+    pub from: usize,
+    pub to: usize,
+    /// Ticks from the end of a transmission to the arrival at `to`.
+    pub delay: usize,
+    /// Random loss of the packets transmitted on the link.
+    pub loss: Option<LossModel>,
+    scheduler: Box<dyn Scheduler>,
+}
+
+impl Link {
+    pub fn scheduler(&self) -> &dyn Scheduler {
+        self.scheduler.as_ref()
+    }
+}
+
+/// A flow of a [`Network`] and the links it goes through.
+struct Route {
+    links: Vec<usize>,
+    weight: f64,
+    flow: VariableLengthFlow,
+}
+
+/// A packet on its way, with its arrival time at its next link.
+struct InFlight {
+    packet: Packet,
+    time: usize,
+    /// Arrival time at the first link.
+    sent: usize,
+    /// Ticks waited in the schedulers so far.
+    queueing: usize,
+}
+
+/// Nodes, links and routed flows, simulated link by link.
+///
+/// Packet names must be unique within a flow, since they are used to
+/// follow packets from hop to hop.
+#[derive(Default)]
+pub struct Network {
+    nodes: Vec<String>,
+    links: Vec<Link>,
+    routes: Vec<Route>,
+    /// End-to-end records of the delivered packets, in delivery order.
+    records: Vec<PacketRecord>,
+}
+
+impl Network {
+    pub fn new() -> Network {
+        Network::default()
+    }
+
+    /// Add a node, returning its index.
+    pub fn add_node(&mut self, name: impl Into<String>) -> usize {
+        self.nodes.push(name.into());
+        self.nodes.len() - 1
+    }
+
+    /// Add a link from node `from` to node `to`, returning its index.
+    /// The link transmits at the rate of the output port of `scheduler`,
+    /// which should have no flows yet.
+    pub fn add_link(
+        &mut self,
+        from: usize,
+        to: usize,
+        delay: usize,
+        scheduler: Box<dyn Scheduler>,
+    ) -> usize {
+        assert!(
+            from < self.nodes.len() && to < self.nodes.len(),
+            "links join existing nodes"
+        );
+        self.links.push(Link {
+            from,
+            to,
+            delay,
+            loss: None,
+            scheduler,
+        });
+        self.links.len() - 1
+    }
+
+    /// Add a flow going through the given nodes, in order, returning its
+    /// identifier. Consecutive nodes must be joined by a link, and the
+    /// weight is given to the flow at every link.
+    pub fn add_flow(&mut self, path: &[usize], flow: VariableLengthFlow, weight: f64) -> FlowId {
+        assert!(path.len() > 1, "a route goes through at least two nodes");
+        let links = path
+            .windows(2)
+            .map(|hop| {
+                self.links
+                    .iter()
+                    .position(|l| l.from == hop[0] && l.to == hop[1])
+                    .unwrap_or_else(|| panic!("no link from node {} to {}", hop[0], hop[1]))
+            })
+            .collect();
+        self.routes.push(Route {
+            links,
+            weight,
+            flow,
+        });
+        FlowId(self.routes.len() - 1)
+    }
+
+    pub fn node(&self, idx: usize) -> &str {
+        &self.nodes[idx]
+    }
+
+    pub fn link(&self, idx: usize) -> &Link {
+        &self.links[idx]
+    }
+
+    /// Lose packets transmitted on a link.
+    pub fn set_loss(&mut self, link: usize, loss: Option<LossModel>) {
+        self.links[link].loss = loss;
+    }
+
+    /// Simulate every link, upstream links first. Run the network once,
+    /// as the packets reaching a link are added to its scheduler as new
+    /// flows. Panics if the routes make links wait on each other in a
+    /// loop.
+    pub fn run(&mut self) {
+        let mut in_flight: Vec<VecDeque<InFlight>> = self
+            .routes
+            .iter()
+            .map(|route| {
+                route
+                    .flow
+                    .packet_states
+                    .iter()
+                    .map(|(packet, time)| InFlight {
+                        packet: packet.clone(),
+                        time: *time,
+                        sent: *time,
+                        queueing: 0,
+                    })
+                    .collect()
+            })
+            .collect();
+        // Next hop of each flow, as an index into its route.
+        let mut hops = vec![0; self.routes.len()];
+
+        for link in self.link_order() {
+            let crossing: Vec<usize> = (0..self.routes.len())
+                .filter(|&f| self.routes[f].links.get(hops[f]) == Some(&link))
+                .collect();
+            let Link {
+                delay,
+                loss,
+                scheduler,
+                ..
+            } = &mut self.links[link];
+            for &f in &crossing {
+                let mut flow = VariableLengthFlow::new();
+                flow.packet_states = in_flight[f]
+                    .iter()
+                    .map(|p| (p.packet.clone(), p.time))
+                    .collect();
+                scheduler.add_flow(Box::new(flow), self.routes[f].weight);
+            }
+            scheduler.run();
+
+            // Follow each departed packet, in order within its flow,
+            // skipping the packets dropped before it.
+            let mut waiting: Vec<VecDeque<InFlight>> = crossing
+                .iter()
+                .map(|&f| std::mem::take(&mut in_flight[f]))
+                .collect();
+            let stats = scheduler.scheduler_stats();
+            for (packet, record) in scheduler.output().iter().zip(&stats.packets) {
+                let queue = &mut waiting[record.flow.index()];
+                while queue.front().is_some_and(|p| p.packet.name != record.name) {
+                    queue.pop_front();
+                }
+                let Some(mut p) = queue.pop_front() else {
+                    continue;
+                };
+                if loss.as_mut().is_some_and(|l| l.is_lost(packet.len)) {
+                    continue;
+                }
+                p.packet = packet.clone();
+                p.time = record.departure + *delay;
+                p.queueing += record.dequeue - record.arrival;
+                in_flight[crossing[record.flow.index()]].push_back(p);
+            }
+            for &f in &crossing {
+                hops[f] += 1;
+            }
+        }
+
+        for (f, packets) in in_flight.into_iter().enumerate() {
+            for p in packets {
+                self.records.push(PacketRecord {
+                    flow: FlowId(f),
+                    name: p.packet.name,
+                    len: p.packet.len,
+                    marked: p.packet.ecn == Ecn::Ce,
+                    arrival: p.sent,
+                    dequeue: p.sent + p.queueing,
+                    departure: p.time,
+                });
+            }
+        }
+        self.records.sort_by_key(|r| r.departure);
+    }
+
+    /// The links in an order where every link comes after the links
+    /// feeding it on some route.
+    fn link_order(&self) -> Vec<usize> {
+        let mut feeding = vec![0; self.links.len()];
+        for route in &self.routes {
+            for hop in route.links.windows(2) {
+                feeding[hop[1]] += 1;
+            }
+        }
+        let mut ready: VecDeque<usize> =
+            (0..self.links.len()).filter(|&l| feeding[l] == 0).collect();
+        let mut order = Vec::new();
+        while let Some(link) = ready.pop_front() {
+            order.push(link);
+            for route in &self.routes {
+                for hop in route.links.windows(2).filter(|hop| hop[0] == link) {
+                    feeding[hop[1]] -= 1;
+                    if feeding[hop[1]] == 0 {
+                        ready.push_back(hop[1]);
+                    }
+                }
+            }
+        }
+        assert_eq!(
+            order.len(),
+            self.links.len(),
+            "the routes loop through the links"
+        );
+        order
+    }
+
+    /// The delivered packets, with their arrival time at the first link,
+    /// their total queueing delay and their arrival time at the last node.
+    pub fn records(&self) -> &[PacketRecord] {
+        &self.records
+    }
+
+    /// End-to-end statistics of each flow. Packets dropped or lost on any
+    /// link count as dropped.
+    pub fn stats(&self) -> Vec<FlowStats> {
+        (0..self.routes.len())
+            .map(|f| {
+                let records = self.records.iter().filter(|r| r.flow.index() == f);
+                let stats = FlowStats::from_records(records);
+                FlowStats {
+                    dropped: self.routes[f].flow.packet_states.len() - stats.packets,
+                    ..stats
+                }
+            })
+            .collect()
+    }
+}
+
+/// Length of an acknowledgement packet, in bytes: the IP and TCP headers.
+pub const DEFAULT_ACK_LEN: usize = 40;
+
+/// The feedback of a receiver on a packet of its flow: which packet
+/// arrived, telling its source of the delivery, and the next one expected
+/// in order, whose repetition tells of a loss.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ack {
+    pub flow: FlowId,
+    /// The packet acknowledged.
+    pub seq: usize,
+    /// The next packet the receiver expects: every packet before arrived.
+    pub next: usize,
+    /// Whether the packet arrived with a congestion mark, echoed back.
+    pub ece: bool,
+}
+
+impl Ack {
+    /// The acknowledgement as a packet of `len` bytes, for a reverse path.
+    pub fn to_packet(&self, len: usize) -> Packet {
+        let packet = Packet::new(format!("ack{}", self.seq), len)
+            .with_flow_id(self.flow.index())
+            .with_tag("seq", self.seq.to_string())
+            .with_tag("ack", self.next.to_string());
+        if self.ece {
+            packet.with_tag("ece", "1")
+        } else {
+            packet
+        }
+    }
+
+    /// Read back an acknowledgement made by [`Ack::to_packet`].
+    pub fn from_packet(packet: &Packet) -> Option<Ack> {
+        Some(Ack {
+            flow: FlowId(packet.flow_id?),
+            seq: packet.tag("seq")?.parse().ok()?,
+            next: packet.tag("ack")?.parse().ok()?,
+            ece: packet.tag("ece").is_some(),
+        })
+    }
+}
+
+/// The receiving end of a flow, acknowledging every packet it gets with
+/// the next one it expects in order.
+#[derive(Debug, Clone, Default)]
+pub struct Receiver {
+    expected: usize,
+    /// The packets received past a hole.
+    out_of_order: BTreeSet<usize>,
+}
+
+impl Receiver {
+    pub fn new() -> Receiver {
+        Receiver::default()
+    }
+
+    /// The next packet expected in order.
+    pub fn expected(&self) -> usize {
+        self.expected
+    }
+
+    /// Take a packet of `flow` numbered by its "seq" tag, returning its
+    /// acknowledgement, or nothing if the packet has no number.
+    pub fn receive(&mut self, flow: FlowId, packet: &Packet) -> Option<Ack> {
+        let seq = packet.tag("seq")?.parse().ok()?;
+        if seq == self.expected {
+            self.expected += 1;
+            while self.out_of_order.remove(&self.expected) {
+                self.expected += 1;
+            }
+        } else if seq > self.expected {
+            self.out_of_order.insert(seq);
+        }
+        Some(Ack {
+            flow,
+            seq,
+            next: self.expected,
+            ece: packet.ecn == Ecn::Ce,
+        })
+    }
+}
+
+/// The way acknowledgements take back to their sources: a propagation
+/// delay, with optional random loss and an optional link where they queue
+/// as packets, so that the return path may itself be congested.
+pub struct ReversePath {
+    delay: usize,
+    loss: Option<LossModel>,
+    ack_len: usize,
+    /// The scheduler of the link and the flow of the acknowledgements.
+    link: Option<(Box<dyn Scheduler>, FlowId)>,
+    /// The acknowledgements of the output of the link handled so far.
+    departed: usize,
+    in_flight: VecDeque<(usize, Ack)>,
+}
+
+impl ReversePath {
+    /// An uncongested path delivering every acknowledgement `delay` ticks
+    /// after it was sent.
+    pub fn new(delay: usize) -> ReversePath {
+        ReversePath {
+            delay,
+            loss: None,
+            ack_len: DEFAULT_ACK_LEN,
+            link: None,
+            departed: 0,
+            in_flight: VecDeque::new(),
+        }
+    }
+
+    /// Lose acknowledgements on the path, after the link if there is one.
+    pub fn with_loss(mut self, loss: LossModel) -> ReversePath {
+        self.loss = Some(loss);
+        self
+    }
+
+    /// Make the acknowledgement packets `len` bytes long.
+    pub fn with_ack_len(mut self, len: usize) -> ReversePath {
+        self.ack_len = len;
+        self
+    }
+
+    /// Queue the acknowledgements in `scheduler` before the delay, as a
+    /// flow of their own next to the flows it already has. The scheduler
+    /// should not have been run yet.
+    pub fn with_link(mut self, mut scheduler: Box<dyn Scheduler>) -> ReversePath {
+        let flow = scheduler.add_flow(Box::new(OpenFlow::new()), 1f64);
+        self.link = Some((scheduler, flow));
+        self.departed = 0;
+        self
+    }
+
+    pub fn delay(&self) -> usize {
+        self.delay
+    }
+
+    pub fn link(&self) -> Option<&dyn Scheduler> {
+        self.link.as_ref().map(|(scheduler, _)| scheduler.as_ref())
+    }
+
+    /// Send an acknowledgement at `time`, no earlier than the last call
+    /// to `receive`.
+    pub fn send(&mut self, ack: Ack, time: usize) {
+        match &mut self.link {
+            Some((scheduler, flow)) => scheduler
+                .inject(*flow, ack.to_packet(self.ack_len), time)
+                .expect("the flow of the acknowledgements takes injected packets"),
+            None => self.propagate(ack, time),
+        }
+    }
+
+    /// Advance the path to `time`, returning the acknowledgements that
+    /// reached their sources by then, in arrival order.
+    pub fn receive(&mut self, time: usize) -> Vec<Ack> {
+        if let Some((scheduler, _)) = &mut self.link {
+            let mut departed = Vec::new();
+            while scheduler.timer() < time {
+                scheduler.step();
+                let now = scheduler.timer();
+                departed.extend(
+                    scheduler.output()[self.departed..]
+                        .iter()
+                        .filter_map(Ack::from_packet)
+                        .map(|ack| (ack, now)),
+                );
+                self.departed = scheduler.output().len();
+            }
+            for (ack, now) in departed {
+                self.propagate(ack, now);
+            }
+        }
+        let mut arrived = Vec::new();
+        while self.in_flight.front().is_some_and(|(at, _)| *at <= time) {
+            arrived.push(self.in_flight.pop_front().unwrap().1);
+        }
+        arrived
+    }
+
+    /// Put an acknowledgement that left at `time` on its way, unless lost.
+    fn propagate(&mut self, ack: Ack, time: usize) {
+        if self.loss.as_mut().is_some_and(|l| l.is_lost(self.ack_len)) {
+            return;
+        }
+        self.in_flight.push_back((time + self.delay, ack));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        loss::LossModel,
+        schedulers::{fifo::FIFOScheduler, Scheduler},
+        traffic::{ClosedLoop, CongestionControl, TcpSource},
+        Ecn, FlowId, Packet, Port,
+    };
+
+    use super::{Ack, Network, Receiver, ReversePath};
+
+    #[test]
+    fn network_test() {
+        // a -> b -> c, with a second flow joining at b.
+        let mut network = Network::new();
+        let a = network.add_node("a");
+        let b = network.add_node("b");
+        let c = network.add_node("c");
+        let ab = network.add_link(a, b, 3, Box::new(FIFOScheduler::new(2)));
+        network.add_link(b, c, 1, Box::new(FIFOScheduler::new(1)));
+
+        let mut long = VariableLengthFlow::new();
+        long.packet_arrive(Packet::new("l0", 2), 0);
+        long.packet_arrive(Packet::new("l1", 2), 0);
+        let mut short = VariableLengthFlow::new();
+        short.packet_arrive(Packet::new("s0", 1), 3);
+        let mut cramped = FIFOScheduler::new(1);
+        *cramped.get_output_port() = Port::with_capacity(0, 1, 1);
+        let d = network.add_node("d");
+        network.add_link(b, d, 0, Box::new(cramped));
+        let mut burst = VariableLengthFlow::new();
+        burst.packet_arrive(Packet::new("x0", 1), 0);
+        burst.packet_arrive(Packet::new("x1", 1), 0);
+
+        network.add_flow(&[a, b, c], long, 1f64);
+        network.add_flow(&[b, c], short, 1f64);
+        network.add_flow(&[b, d], burst, 1f64);
+        network.run();
+
+        // l0 leaves a at 1 and reaches b at 4, where s0 arrived at 3.
+        // s0 leaves b at 4, l0 at 6 and l1 at 8, arriving at c a tick later.
+        let delivered: Vec<(&str, usize, usize)> = network
+            .records()
+            .iter()
+            .map(|r| (r.name.as_str(), r.arrival, r.departure))
+            .collect();
+        assert_eq!(
+            delivered,
+            vec![("x0", 0, 1), ("s0", 3, 5), ("l0", 0, 7), ("l1", 0, 9)]
+        );
+        assert_eq!(network.link(ab).scheduler().output().len(), 2);
+
+        let stats = network.stats();
+        assert_eq!(stats[0].packets, 2);
+        assert_eq!(stats[0].max_delay, 9);
+        assert_eq!(stats[0].mean_delay, 8f64);
+        assert_eq!(stats[1].mean_delay, 2f64);
+        assert_eq!(stats[2].packets, 1);
+        assert_eq!(stats[2].dropped, 1);
+
+        // Packets lost on a link count as dropped.
+        let mut network = Network::new();
+        let a = network.add_node("a");
+        let b = network.add_node("b");
+        let ab = network.add_link(a, b, 0, Box::new(FIFOScheduler::new(1)));
+        network.set_loss(ab, Some(LossModel::random(1f64)));
+        let mut flow = VariableLengthFlow::new();
+        flow.packet_arrive(Packet::new("p0", 1), 0);
+        network.add_flow(&[a, b], flow, 1f64);
+        network.run();
+        assert!(network.records().is_empty());
+        assert_eq!(network.stats()[0].dropped, 1);
+        assert_eq!(network.link(ab).scheduler().output().len(), 1);
+    }
+
+    #[test]
+    fn feedback_test() {
+        // Packets 0, 2 and 1 arrive: 2 is acknowledged with a repeat of
+        // the next expected, and its mark is echoed.
+        let mut receiver = Receiver::new();
+        let flow = FlowId(3);
+        let packet =
+            |seq: usize| Packet::new(format!("p{}", seq), 1).with_tag("seq", seq.to_string());
+        let mut marked = packet(2);
+        marked.ecn = Ecn::Ce;
+        let acks: Vec<Ack> = [packet(0), marked, packet(1)]
+            .iter()
+            .filter_map(|p| receiver.receive(flow, p))
+            .collect();
+        let next: Vec<(usize, usize, bool)> = acks.iter().map(|a| (a.seq, a.next, a.ece)).collect();
+        assert_eq!(next, vec![(0, 1, false), (2, 1, true), (1, 3, false)]);
+        assert_eq!(receiver.receive(flow, &Packet::new("x", 1)), None);
+        assert_eq!(
+            Ack::from_packet(&acks[1].to_packet(40)),
+            Some(acks[1].clone())
+        );
+
+        // An uncongested path only delays.
+        let mut reverse = ReversePath::new(5);
+        reverse.send(acks[0].clone(), 0);
+        assert!(reverse.receive(4).is_empty());
+        assert_eq!(reverse.receive(5), vec![acks[0].clone()]);
+
+        // Acks queue on a link, two ticks each, before the delay.
+        let mut reverse = ReversePath::new(1)
+            .with_ack_len(2)
+            .with_link(Box::new(FIFOScheduler::new(1)));
+        for ack in &acks {
+            reverse.send(ack.clone(), 0);
+        }
+        let arrivals: Vec<usize> = (0..10)
+            .filter(|&t| !reverse.receive(t).is_empty())
+            .collect();
+        assert_eq!(arrivals, vec![3, 5, 7]);
+        assert_eq!(reverse.link().unwrap().output().len(), 3);
+
+        // A lossy path loses acks.
+        let mut reverse = ReversePath::new(0).with_loss(LossModel::random(1f64));
+        reverse.send(acks[0].clone(), 0);
+        assert!(reverse.receive(10).is_empty());
+    }
+
+    #[test]
+    fn congested_reverse_path_test() {
+        let run = |reverse: Option<ReversePath>| {
+            let mut fifo = FIFOScheduler::new(1);
+            *fifo.get_output_port() = Port::with_capacity(0, 1, 10);
+            let mut path = ClosedLoop::new(Box::new(fifo), 20);
+            if let Some(reverse) = reverse {
+                path.set_reverse_path(reverse);
+            }
+            let flow = path.add_source(TcpSource::new(CongestionControl::Reno, 1), 1f64);
+            assert!(path.run_until(2_000));
+            let source = path.source(flow);
+            (source.acked(), path.scheduler().stats()[0].dropped)
+        };
+
+        // Over an uncongested return path the source fills the link.
+        let (acked, dropped) = run(None);
+        assert!(acked > 1_700, "{} acked", acked);
+        assert!(dropped > 0);
+
+        // Acks twice the length of the packets, on a return link as fast
+        // as the forward one, clock the source out at half of the link,
+        // and the forward queue never overflows.
+        let reverse = ReversePath::new(20)
+            .with_ack_len(2)
+            .with_link(Box::new(FIFOScheduler::new(1)));
+        let (acked, dropped) = run(Some(reverse));
+        assert!((950..1_010).contains(&acked), "{} acked", acked);
+        assert_eq!(dropped, 0);
+    }
+}
@@ -0,0 +1,509 @@
+//! Replay of captured traffic from pcap and pcapng files.
+//!
+//! Only what the simulation needs is decoded: the capture time and the
+//! length on the wire of every packet, and its 5-tuple when it is an
+//! IPv4 or IPv6 packet over Ethernet, Linux cooked capture or raw IP.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, Read},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::Path,
+};
+
+use crate::scheduling::{classifier::FIVE_TUPLE, flow::VariableLengthFlow, Packet};
+
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+
+const LINKTYPE_ETHERNET: u16 = 1;
+const LINKTYPE_RAW: u16 = 101;
+const LINKTYPE_LINUX_SLL: u16 = 113;
+const LINKTYPE_IPV4: u16 = 228;
+const LINKTYPE_IPV6: u16 = 229;
+
+/// The addresses, ports and protocol identifying the connection
+/// a packet belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FiveTuple {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    /// Zero for protocols without ports and for non-first fragments.
+    pub sport: u16,
+    pub dport: u16,
+    pub proto: u8,
+}
+
+/// A packet read from a capture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedPacket {
+    /// Capture time, in nanoseconds since the Unix epoch.
+    pub time_ns: u64,
+    /// Length of the packet on the wire, in bytes, which is more than was
+    /// captured when the capture was truncated.
+    pub len: usize,
+    /// None for packets other than IPv4 and IPv6.
+    pub five_tuple: Option<FiveTuple>,
+}
+
+/// The packets of a pcap or pcapng file, in file order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capture {
+    pub packets: Vec<CapturedPacket>,
+}
+
+impl Capture {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Capture> {
+        Capture::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Read a capture, telling pcap from pcapng by its first bytes.
+    pub fn read(mut reader: impl Read) -> io::Result<Capture> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let magic = Bytes::new(&data, false).u32(0)?;
+        let packets = if magic == PCAPNG_SECTION_HEADER {
+            read_pcapng(&data)?
+        } else {
+            read_pcap(&data)?
+        };
+        Ok(Capture { packets })
+    }
+
+    /// The packets and their arrival ticks, counted in ticks of `tick_ns`
+    /// nanoseconds from the first packet of the capture.
+    ///
+    /// Packets are tagged with their [`FIVE_TUPLE`], so that a
+    /// [`HashClassifier`](super::classifier::HashClassifier) can sort
+    /// them, and named by their position in the capture.
+    pub fn arrivals(&self, tick_ns: u64) -> Vec<(Packet, usize)> {
+        let start = self.start();
+        self.in_time_order()
+            .map(|idx| self.arrival(idx, start, tick_ns))
+            .collect()
+    }
+
+    /// Group the packets into one flow per 5-tuple, in the order the
+    /// connections first appear, see [`Capture::arrivals`].
+    /// Packets without a 5-tuple share a last flow.
+    ///
+    /// Every packet gets the index of its flow as flow id.
+    pub fn flows(&self, tick_ns: u64) -> Vec<VariableLengthFlow> {
+        let mut indices: HashMap<FiveTuple, usize> = HashMap::new();
+        for five_tuple in self.packets.iter().filter_map(|p| p.five_tuple) {
+            let next = indices.len();
+            indices.entry(five_tuple).or_insert(next);
+        }
+        let other = indices.len();
+        let start = self.start();
+        let mut flows = Vec::new();
+        for idx in self.in_time_order() {
+            let flow_idx = self.packets[idx]
+                .five_tuple
+                .map_or(other, |five_tuple| indices[&five_tuple]);
+            if flows.len() <= flow_idx {
+                flows.resize_with(flow_idx + 1, VariableLengthFlow::new);
+            }
+            let (packet, time) = self.arrival(idx, start, tick_ns);
+            flows[flow_idx]
+                .packet_states
+                .push_back((packet.with_flow_id(flow_idx), time));
+        }
+        flows
+    }
+
+    /// The indices of the packets sorted by capture time.
+    fn in_time_order(&self) -> impl Iterator<Item = usize> {
+        let mut order: Vec<usize> = (0..self.packets.len()).collect();
+        order.sort_by_key(|&idx| self.packets[idx].time_ns);
+        order.into_iter()
+    }
+
+    /// The capture time of the first packet.
+    fn start(&self) -> u64 {
+        self.packets.iter().map(|p| p.time_ns).min().unwrap_or(0)
+    }
+
+    fn arrival(&self, idx: usize, start: u64, tick_ns: u64) -> (Packet, usize) {
+        assert!(tick_ns > 0, "a tick must last at least one nanosecond");
+        let captured = &self.packets[idx];
+        let mut packet = Packet::new(format!("pcap{}", idx), captured.len);
+        if let Some(t) = &captured.five_tuple {
+            let values = [
+                t.src.to_string(),
+                t.dst.to_string(),
+                t.sport.to_string(),
+                t.dport.to_string(),
+                t.proto.to_string(),
+            ];
+            for (key, value) in FIVE_TUPLE.iter().zip(values) {
+                packet = packet.with_tag(*key, value);
+            }
+        }
+        let time = (captured.time_ns - start) / tick_ns;
+        (packet, time as usize)
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Integers in a capture, in the byte order of the file.
+struct Bytes<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Bytes<'a> {
+    fn new(data: &'a [u8], big_endian: bool) -> Bytes<'a> {
+        Bytes { data, big_endian }
+    }
+
+    fn slice(&self, offset: usize, len: usize) -> io::Result<&'a [u8]> {
+        self.data
+            .get(offset..offset + len)
+            .ok_or_else(|| invalid("truncated capture"))
+    }
+
+    fn u16(&self, offset: usize) -> io::Result<u16> {
+        let bytes = self.slice(offset, 2)?.try_into().unwrap();
+        Ok(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(&self, offset: usize) -> io::Result<u32> {
+        let bytes = self.slice(offset, 4)?.try_into().unwrap();
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+}
+
+fn read_pcap(data: &[u8]) -> io::Result<Vec<CapturedPacket>> {
+    let magic = Bytes::new(data, false).u32(0)?;
+    let (big_endian, nanos) = match magic {
+        PCAP_MAGIC_MICROS => (false, false),
+        PCAP_MAGIC_NANOS => (false, true),
+        _ if magic.swap_bytes() == PCAP_MAGIC_MICROS => (true, false),
+        _ if magic.swap_bytes() == PCAP_MAGIC_NANOS => (true, true),
+        _ => return Err(invalid("not a pcap or pcapng file")),
+    };
+    let bytes = Bytes::new(data, big_endian);
+    let linktype = bytes.u32(20)? as u16;
+
+    let mut packets = Vec::new();
+    let mut offset = 24;
+    while offset < data.len() {
+        let seconds = bytes.u32(offset)? as u64;
+        let fraction = bytes.u32(offset + 4)? as u64;
+        let captured_len = bytes.u32(offset + 8)? as usize;
+        let len = bytes.u32(offset + 12)? as usize;
+        let frame = bytes.slice(offset + 16, captured_len)?;
+        let fraction_ns = if nanos { fraction } else { fraction * 1_000 };
+        packets.push(CapturedPacket {
+            time_ns: seconds * 1_000_000_000 + fraction_ns,
+            len,
+            five_tuple: five_tuple(linktype, frame),
+        });
+        offset += 16 + captured_len;
+    }
+    Ok(packets)
+}
+
+/// Read the enhanced packet blocks of a pcapng file. Simple packet blocks
+/// carry no timestamp and are skipped, like every other block.
+fn read_pcapng(data: &[u8]) -> io::Result<Vec<CapturedPacket>> {
+    let mut packets = Vec::new();
+    // Link type and timestamp unit in nanoseconds of each interface
+    // of the current section.
+    let mut interfaces: Vec<(u16, f64)> = Vec::new();
+    let mut big_endian = false;
+    let mut offset = 0;
+    while offset < data.len() {
+        if Bytes::new(data, false).u32(offset)? == PCAPNG_SECTION_HEADER {
+            let order = Bytes::new(data, false).u32(offset + 8)?;
+            big_endian = match order {
+                PCAPNG_BYTE_ORDER_MAGIC => false,
+                _ if order.swap_bytes() == PCAPNG_BYTE_ORDER_MAGIC => true,
+                _ => return Err(invalid("invalid pcapng byte order")),
+            };
+            interfaces.clear();
+        }
+        let bytes = Bytes::new(data, big_endian);
+        let block_type = bytes.u32(offset)?;
+        let block_len = bytes.u32(offset + 4)? as usize;
+        if block_len < 12 || !block_len.is_multiple_of(4) {
+            return Err(invalid("invalid pcapng block length"));
+        }
+        let body = Bytes::new(bytes.slice(offset + 8, block_len - 12)?, big_endian);
+
+        match block_type {
+            PCAPNG_INTERFACE_DESCRIPTION => {
+                let linktype = body.u16(0)?;
+                interfaces.push((linktype, timestamp_unit_ns(&body, 8)?));
+            }
+            PCAPNG_ENHANCED_PACKET => {
+                let interface = body.u32(0)? as usize;
+                let &(linktype, unit_ns) = interfaces
+                    .get(interface)
+                    .ok_or_else(|| invalid("packet of an undescribed interface"))?;
+                let timestamp = ((body.u32(4)? as u64) << 32) | body.u32(8)? as u64;
+                let captured_len = body.u32(12)? as usize;
+                let len = body.u32(16)? as usize;
+                let frame = body.slice(20, captured_len)?;
+                packets.push(CapturedPacket {
+                    time_ns: (timestamp as f64 * unit_ns) as u64,
+                    len,
+                    five_tuple: five_tuple(linktype, frame),
+                });
+            }
+            _ => {}
+        }
+        offset += block_len;
+    }
+    Ok(packets)
+}
+
+/// The timestamp unit of an interface, from the `if_tsresol` option
+/// among the options starting at `offset`, microseconds by default.
+fn timestamp_unit_ns(body: &Bytes, mut offset: usize) -> io::Result<f64> {
+    const OPTION_END: u16 = 0;
+    const OPTION_TSRESOL: u16 = 9;
+    while offset + 4 <= body.data.len() {
+        let code = body.u16(offset)?;
+        let len = body.u16(offset + 2)? as usize;
+        if code == OPTION_END {
+            break;
+        }
+        if code == OPTION_TSRESOL {
+            let resolution = body.slice(offset + 4, 1)?[0];
+            let exponent = (resolution & 0x7f) as i32;
+            // The high bit tells a power of two from a power of ten.
+            let unit_s = if resolution & 0x80 == 0 {
+                10f64.powi(-exponent)
+            } else {
+                2f64.powi(-exponent)
+            };
+            return Ok(unit_s * 1e9);
+        }
+        offset += 4 + len.div_ceil(4) * 4;
+    }
+    Ok(1_000f64)
+}
+
+/// Decode the 5-tuple of a frame of the given link type, if it is
+/// an IPv4 or IPv6 packet.
+fn five_tuple(linktype: u16, frame: &[u8]) -> Option<FiveTuple> {
+    const ETHERTYPE_IPV4: u16 = 0x0800;
+    const ETHERTYPE_IPV6: u16 = 0x86dd;
+    const ETHERTYPE_VLAN: u16 = 0x8100;
+    let network = Bytes::new(frame, true);
+    let (ethertype, payload) = match linktype {
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            while network.u16(offset).ok()? == ETHERTYPE_VLAN {
+                offset += 4;
+            }
+            (network.u16(offset).ok()?, offset + 2)
+        }
+        LINKTYPE_LINUX_SLL => (network.u16(14).ok()?, 16),
+        LINKTYPE_RAW => match frame.first()? >> 4 {
+            4 => (ETHERTYPE_IPV4, 0),
+            6 => (ETHERTYPE_IPV6, 0),
+            _ => return None,
+        },
+        LINKTYPE_IPV4 => (ETHERTYPE_IPV4, 0),
+        LINKTYPE_IPV6 => (ETHERTYPE_IPV6, 0),
+        _ => return None,
+    };
+    let ip = Bytes::new(frame.get(payload..)?, true);
+    let (src, dst, proto, transport, first_fragment) = match ethertype {
+        ETHERTYPE_IPV4 => {
+            let header_len = (ip.slice(0, 1).ok()?[0] & 0x0f) as usize * 4;
+            let src: [u8; 4] = ip.slice(12, 4).ok()?.try_into().unwrap();
+            let dst: [u8; 4] = ip.slice(16, 4).ok()?.try_into().unwrap();
+            (
+                IpAddr::V4(Ipv4Addr::from(src)),
+                IpAddr::V4(Ipv4Addr::from(dst)),
+                ip.slice(9, 1).ok()?[0],
+                header_len,
+                ip.u16(6).ok()? & 0x1fff == 0,
+            )
+        }
+        ETHERTYPE_IPV6 => {
+            let src: [u8; 16] = ip.slice(8, 16).ok()?.try_into().unwrap();
+            let dst: [u8; 16] = ip.slice(24, 16).ok()?.try_into().unwrap();
+            (
+                IpAddr::V6(Ipv6Addr::from(src)),
+                IpAddr::V6(Ipv6Addr::from(dst)),
+                ip.slice(6, 1).ok()?[0],
+                40,
+                true,
+            )
+        }
+        _ => return None,
+    };
+    // TCP, UDP and SCTP all start with the ports.
+    let (sport, dport) = if first_fragment && [6, 17, 132].contains(&proto) {
+        (
+            ip.u16(transport).unwrap_or(0),
+            ip.u16(transport + 2).unwrap_or(0),
+        )
+    } else {
+        (0, 0)
+    };
+    Some(FiveTuple {
+        src,
+        dst,
+        sport,
+        dport,
+        proto,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use crate::scheduling::{
+        classifier::{Classifier, HashClassifier},
+        schedulers::{drr::DRRScheduler, Scheduler},
+    };
+
+    use super::{Capture, FiveTuple};
+
+    /// An Ethernet frame of an IPv4 UDP packet of 100 bytes.
+    fn udp_frame(src: u8, sport: u16) -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        frame.extend([0x08, 0x00]);
+        let mut ip = vec![0x45, 0, 0, 100, 0, 0, 0, 0, 64, 17, 0, 0];
+        ip.extend([10, 0, 0, src, 10, 0, 0, 254]);
+        ip.extend(sport.to_be_bytes());
+        ip.extend(53u16.to_be_bytes());
+        frame.extend(ip);
+        frame
+    }
+
+    fn pcap(packets: &[(u32, u32, Vec<u8>)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(0xa1b2c3d4u32.to_le_bytes());
+        data.extend(2u16.to_le_bytes());
+        data.extend(4u16.to_le_bytes());
+        data.extend([0u8; 8]);
+        data.extend(65535u32.to_le_bytes());
+        data.extend(1u32.to_le_bytes());
+        for (seconds, micros, frame) in packets {
+            data.extend(seconds.to_le_bytes());
+            data.extend(micros.to_le_bytes());
+            data.extend((frame.len() as u32).to_le_bytes());
+            data.extend(114u32.to_le_bytes());
+            data.extend(frame);
+        }
+        data
+    }
+
+    fn pcapng_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let len = 12 + body.len().div_ceil(4) * 4;
+        let mut block = Vec::new();
+        block.extend(block_type.to_be_bytes());
+        block.extend((len as u32).to_be_bytes());
+        block.extend(body);
+        block.resize(len - 4, 0);
+        block.extend((len as u32).to_be_bytes());
+        block
+    }
+
+    #[test]
+    fn pcap_test() {
+        let data = pcap(&[
+            (1, 0, udp_frame(1, 1000)),
+            (1, 1_500, udp_frame(2, 2000)),
+            (1, 2_000, udp_frame(1, 1000)),
+            (1, 3_000, vec![0u8; 14]),
+        ]);
+        let capture = Capture::read(data.as_slice()).unwrap();
+        assert_eq!(capture.packets.len(), 4);
+        assert_eq!(capture.packets[1].time_ns, 1_001_500_000);
+        assert_eq!(capture.packets[1].len, 114);
+        assert_eq!(
+            capture.packets[0].five_tuple,
+            Some(FiveTuple {
+                src: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                dst: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 254)),
+                sport: 1000,
+                dport: 53,
+                proto: 17,
+            })
+        );
+        assert_eq!(capture.packets[3].five_tuple, None);
+
+        // One tick per millisecond.
+        let flows = capture.flows(1_000_000);
+        let times: Vec<Vec<usize>> = flows
+            .iter()
+            .map(|f| f.packet_states.iter().map(|(_, t)| *t).collect())
+            .collect();
+        assert_eq!(times, vec![vec![0, 2], vec![1], vec![3]]);
+        assert_eq!(flows[1].packet_states[0].0.flow_id, Some(1));
+
+        // Tagged arrivals classify the same way.
+        let arrivals = capture.arrivals(1_000_000);
+        assert_eq!(arrivals[1].0.tag("sport"), Some("2000"));
+        let hash = HashClassifier::new(64);
+        assert_eq!(hash.classify(&arrivals[0].0), hash.classify(&arrivals[2].0));
+
+        let mut drr = DRRScheduler::new(114);
+        for flow in flows {
+            drr.add_flow(flow, 1);
+        }
+        drr.run();
+        assert_eq!(drr.output().len(), 4);
+    }
+
+    #[test]
+    fn pcapng_test() {
+        let mut section = Vec::new();
+        section.extend(0x1a2b3c4du32.to_be_bytes());
+        section.extend([0, 1, 0, 0]);
+        section.extend(u64::MAX.to_be_bytes());
+        let mut interface = Vec::new();
+        interface.extend(1u16.to_be_bytes());
+        interface.extend([0u8; 6]);
+        // Nanosecond timestamps.
+        interface.extend([0, 9, 0, 1, 9, 0, 0, 0, 0, 0, 0, 0]);
+        let mut data = pcapng_block(0x0a0d0d0a, &section);
+        data.extend(pcapng_block(1, &interface));
+        for (time, src) in [(5_000u64, 1u8), (7_500, 2)] {
+            let frame = udp_frame(src, 4000);
+            let mut packet = Vec::new();
+            packet.extend(0u32.to_be_bytes());
+            packet.extend(((time >> 32) as u32).to_be_bytes());
+            packet.extend((time as u32).to_be_bytes());
+            packet.extend((frame.len() as u32).to_be_bytes());
+            packet.extend(200u32.to_be_bytes());
+            packet.extend(frame);
+            data.extend(pcapng_block(6, &packet));
+        }
+
+        let capture = Capture::read(data.as_slice()).unwrap();
+        assert_eq!(capture.packets.len(), 2);
+        assert_eq!(capture.packets[1].time_ns, 7_500);
+        assert_eq!(capture.packets[1].len, 200);
+        let flows = capture.flows(1_000);
+        assert_eq!(flows.len(), 2);
+        assert_eq!(flows[1].packet_states[0].1, 2);
+
+        assert!(Capture::read([0u8; 24].as_slice()).is_err());
+    }
+}
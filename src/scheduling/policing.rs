@@ -0,0 +1,332 @@
+use crate::scheduling::{
+    flow::{Flow, VariableLengthFlow},
+    Color, Packet,
+};
+
+/// What a policer does with a packet that exceeds its profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PolicerAction {
+    Drop,
+    /// Keep the packet, but apart from the conforming ones.
+    Mark,
+}
+
+/// The traffic of a flow split by a policer.
+#[derive(Debug, Clone)]
+pub struct PolicedFlow {
+    /// Packets within the profile, at their original arrival times.
+    pub conforming: VariableLengthFlow,
+    /// Packets exceeding the profile, when they are marked.
+    pub marked: VariableLengthFlow,
+    /// Packets exceeding the profile, when they are dropped.
+    pub dropped: Vec<Packet>,
+}
+
+/// Leaky bucket policer.
+///
+/// The bucket drains at `rate` per tick and a packet conforms if it fits
+/// in the `depth` left, in which case it fills the bucket by its length.
+/// Unlike a shaper, nonconforming packets are never delayed:
+/// they are dropped or marked on arrival.
+#[derive(Debug, Clone)]
+pub struct LeakyBucketPolicer {
+    rate: usize,
+    depth: usize,
+    action: PolicerAction,
+    level: usize,
+    /// The tick at which `level` was measured.
+    last_update: usize,
+}
+
+impl LeakyBucketPolicer {
+    /// Create a policer with an empty bucket.
+    pub fn new(rate: usize, depth: usize, action: PolicerAction) -> LeakyBucketPolicer {
+        LeakyBucketPolicer {
+            rate,
+            depth,
+            action,
+            level: 0,
+            last_update: 0,
+        }
+    }
+
+    /// Check a packet arriving at `time` and account for it if it conforms.
+    /// Arrivals must be checked in time order.
+    pub fn conforms(&mut self, packet: &Packet, time: usize) -> bool {
+        let drained = self.rate * (time - self.last_update);
+        self.level = self.level.saturating_sub(drained);
+        self.last_update = time;
+        if self.level + packet.len > self.depth {
+            return false;
+        }
+        self.level += packet.len;
+        true
+    }
+
+    /// Police every packet of a flow. The flow is only read.
+    pub fn police(&mut self, flow: &dyn Flow) -> PolicedFlow {
+        let mut flow = flow.clone_box();
+        let mut policed = PolicedFlow {
+            conforming: VariableLengthFlow::new(),
+            marked: VariableLengthFlow::new(),
+            dropped: Vec::new(),
+        };
+        while let Some(time) = flow.next_arrival() {
+            let packet = flow.pop_packet();
+            if self.conforms(&packet, time) {
+                policed.conforming.packet_arrive(packet, time);
+            } else {
+                match self.action {
+                    PolicerAction::Drop => policed.dropped.push(packet),
+                    PolicerAction::Mark => policed.marked.packet_arrive(packet, time),
+                }
+            }
+        }
+        policed
+    }
+}
+
+/// A meter coloring packets by how far their flow exceeds its profile.
+pub trait Meter {
+    /// Color a packet of `len` bytes arriving at `time`.
+    /// Arrivals must be metered in time order.
+    fn meter(&mut self, len: usize, time: usize) -> Color;
+
+    /// Color every packet of a flow. The flow is only read.
+    fn mark(&mut self, flow: &dyn Flow) -> VariableLengthFlow {
+        let mut flow = flow.clone_box();
+        let mut marked = VariableLengthFlow::new();
+        while let Some(time) = flow.next_arrival() {
+            let packet = flow.pop_packet();
+            let color = self.meter(packet.len, time);
+            marked.packet_arrive(packet.with_color(color), time);
+        }
+        marked
+    }
+}
+
+/// Single Rate Three Color Marker (srTCM), as in RFC 2697, color-blind.
+///
+/// Tokens arrive at the committed rate `cir` into the committed bucket of
+/// size `cbs`, and overflow into the excess bucket of size `ebs`. A packet
+/// is green if it fits in the committed bucket, yellow if it fits in the
+/// excess bucket and red otherwise. Both buckets start full.
+#[derive(Debug, Clone)]
+pub struct SrTCM {
+    cir: usize,
+    cbs: usize,
+    ebs: usize,
+    committed: usize,
+    excess: usize,
+    /// The tick at which the buckets were last filled.
+    last_update: usize,
+}
+
+impl SrTCM {
+    pub fn new(cir: usize, cbs: usize, ebs: usize) -> SrTCM {
+        SrTCM {
+            cir,
+            cbs,
+            ebs,
+            committed: cbs,
+            excess: ebs,
+            last_update: 0,
+        }
+    }
+}
+
+impl Meter for SrTCM {
+    fn meter(&mut self, len: usize, time: usize) -> Color {
+        let tokens = self.cir * (time - self.last_update);
+        self.last_update = time;
+        let to_committed = tokens.min(self.cbs - self.committed);
+        self.committed += to_committed;
+        self.excess = self.ebs.min(self.excess + tokens - to_committed);
+
+        if self.committed >= len {
+            self.committed -= len;
+            Color::Green
+        } else if self.excess >= len {
+            self.excess -= len;
+            Color::Yellow
+        } else {
+            Color::Red
+        }
+    }
+}
+
+/// Two Rate Three Color Marker (trTCM), as in RFC 2698, color-blind.
+///
+/// The peak bucket of size `pbs` fills at `pir` and the committed bucket
+/// of size `cbs` at `cir`. A packet exceeding the peak bucket is red,
+/// one exceeding only the committed bucket is yellow and the rest are
+/// green. Both buckets start full.
+#[derive(Debug, Clone)]
+pub struct TrTCM {
+    cir: usize,
+    cbs: usize,
+    pir: usize,
+    pbs: usize,
+    committed: usize,
+    peak: usize,
+    /// The tick at which the buckets were last filled.
+    last_update: usize,
+}
+
+impl TrTCM {
+    pub fn new(cir: usize, cbs: usize, pir: usize, pbs: usize) -> TrTCM {
+        assert!(
+            pir >= cir,
+            "the peak rate must be at least the committed rate"
+        );
+        TrTCM {
+            cir,
+            cbs,
+            pir,
+            pbs,
+            committed: cbs,
+            peak: pbs,
+            last_update: 0,
+        }
+    }
+}
+
+impl Meter for TrTCM {
+    fn meter(&mut self, len: usize, time: usize) -> Color {
+        let elapsed = time - self.last_update;
+        self.last_update = time;
+        self.committed = self.cbs.min(self.committed + self.cir * elapsed);
+        self.peak = self.pbs.min(self.peak + self.pir * elapsed);
+
+        if self.peak < len {
+            Color::Red
+        } else if self.committed < len {
+            self.peak -= len;
+            Color::Yellow
+        } else {
+            self.peak -= len;
+            self.committed -= len;
+            Color::Green
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        schedulers::{fifo::FIFOScheduler, sp::SPScheduler},
+        Color, FlowId, Packet, Scheduler,
+    };
+
+    use super::{LeakyBucketPolicer, Meter, PolicerAction, SrTCM, TrTCM};
+
+    fn flow() -> VariableLengthFlow {
+        let mut flow = VariableLengthFlow::new();
+        for (name, time) in [("p1", 0), ("p2", 0), ("p3", 0), ("p4", 1), ("p5", 5)] {
+            flow.packet_arrive(Packet::new(name, 1), time);
+        }
+        flow
+    }
+
+    #[test]
+    fn policer_drop_test() {
+        let mut policer = LeakyBucketPolicer::new(1, 2, PolicerAction::Drop);
+        let policed = policer.police(&flow());
+
+        // p3 overflows the bucket, which has drained by one for p4.
+        assert_eq!(policed.dropped, vec![Packet::new("p3", 1)]);
+        assert!(policed.marked.empty());
+        assert_eq!(
+            policed
+                .conforming
+                .packet_states
+                .iter()
+                .map(|(p, _)| p.name.as_str())
+                .collect::<Vec<_>>(),
+            ["p1", "p2", "p4", "p5"]
+        );
+    }
+
+    #[test]
+    fn policer_mark_test() {
+        let mut policer = LeakyBucketPolicer::new(1, 2, PolicerAction::Mark);
+        let policed = policer.police(&flow());
+        assert!(policed.dropped.is_empty());
+
+        // Marked traffic is only served once the conforming traffic is.
+        let mut sp = SPScheduler::new(1);
+        sp.add_flow(policed.conforming, 1);
+        sp.add_flow(policed.marked, 0);
+        sp.run();
+        assert_eq!(
+            sp.output(),
+            &[
+                Packet::new("p1", 1),
+                Packet::new("p2", 1),
+                Packet::new("p4", 1),
+                Packet::new("p3", 1),
+                Packet::new("p5", 1),
+            ]
+        );
+    }
+
+    fn colors(meter: &mut dyn Meter, arrivals: &[usize]) -> Vec<Color> {
+        arrivals.iter().map(|&time| meter.meter(1, time)).collect()
+    }
+
+    #[test]
+    fn srtcm_test() {
+        let mut meter = SrTCM::new(1, 2, 1);
+        // The burst drains the committed bucket, then the excess bucket.
+        // Three ticks later the committed bucket is full again
+        // and the extra token went to the excess bucket.
+        assert_eq!(
+            colors(&mut meter, &[0, 0, 0, 0, 3, 3, 3, 3]),
+            [
+                Color::Green,
+                Color::Green,
+                Color::Yellow,
+                Color::Red,
+                Color::Green,
+                Color::Green,
+                Color::Yellow,
+                Color::Red,
+            ]
+        );
+    }
+
+    #[test]
+    fn trtcm_test() {
+        let mut meter = TrTCM::new(1, 1, 2, 2);
+        assert_eq!(
+            colors(&mut meter, &[0, 0, 0, 1, 1, 1]),
+            [
+                Color::Green,
+                Color::Yellow,
+                Color::Red,
+                Color::Green,
+                Color::Yellow,
+                Color::Red,
+            ]
+        );
+    }
+
+    #[test]
+    fn color_drop_test() {
+        let mut flow = VariableLengthFlow::new();
+        for name in ["p1", "p2", "p3", "p4"] {
+            flow.packet_arrive(Packet::new(name, 1), 0);
+        }
+        let marked = SrTCM::new(1, 2, 1).mark(&flow);
+
+        // Yellow packets only get in while the queue is short,
+        // red packets never do.
+        let mut fifo = FIFOScheduler::new(1);
+        fifo.get_output_port().set_color_limits(Some((1, 0)));
+        fifo.add_flow(marked);
+        fifo.run();
+        assert_eq!(fifo.output(), &[Packet::new("p1", 1), Packet::new("p2", 1)]);
+        assert_eq!(fifo.dropped_count(FlowId(0)), 2);
+    }
+}
@@ -0,0 +1,402 @@
+use std::collections::BTreeMap;
+
+use crate::scheduling::{
+    flow::Flow,
+    schedulers::base::SchedulerBase,
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// The regulator of a flow of an [`ATSScheduler`], setting when each of
+/// its packets becomes eligible for transmission.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Regulator {
+    /// A token bucket of `burst` bytes filled at `rate` bytes per tick,
+    /// full at tick 0, as in the ATS scheduler state machine of
+    /// IEEE 802.1Qcr. A packet is eligible once the bucket holds
+    /// its length.
+    TokenBucket { rate: f64, burst: usize },
+    /// A length-rate quotient regulator: a packet is eligible the length
+    /// of the previous packet over `rate` ticks after the previous one
+    /// was eligible.
+    LengthRate { rate: f64 },
+}
+
+impl Regulator {
+    /// The initial state of the regulator: the time the bucket was empty,
+    /// or the earliest time the next packet can be eligible.
+    fn initial_state(self) -> f64 {
+        match self {
+            Regulator::TokenBucket { rate, burst } => -(burst as f64) / rate,
+            Regulator::LengthRate { .. } => 0f64,
+        }
+    }
+
+    /// The time a packet of `len` bytes would be eligible at, given the
+    /// regulator state, not earlier than `earliest`.
+    fn eligibility_time(self, state: f64, len: usize, earliest: f64) -> f64 {
+        match self {
+            Regulator::TokenBucket { rate, .. } => earliest.max(state + len as f64 / rate),
+            Regulator::LengthRate { .. } => earliest.max(state),
+        }
+    }
+
+    /// The state after a packet of `len` bytes became eligible at
+    /// `eligibility`.
+    fn next_state(self, state: f64, len: usize, eligibility: f64) -> f64 {
+        match self {
+            Regulator::TokenBucket { rate, burst } => {
+                let scheduler_eligibility = state + len as f64 / rate;
+                let bucket_full = state + burst as f64 / rate;
+                if eligibility < bucket_full {
+                    scheduler_eligibility
+                } else {
+                    // Tokens beyond the burst were lost while the bucket was full.
+                    scheduler_eligibility + eligibility - bucket_full
+                }
+            }
+            Regulator::LengthRate { rate } => eligibility + len as f64 / rate,
+        }
+    }
+}
+
+/// Asynchronous Traffic Shaping (ATS) scheduler, as in IEEE 802.1Qcr.
+///
+/// Every flow has a priority and, unless unregulated, a [`Regulator`].
+/// The flows of a priority share an interleaved regulator: their packets
+/// wait in one queue by arrival, and the head of the queue is released
+/// once the regulator of its flow makes it eligible, holding back the
+/// packets behind it. Packets are never released before those ahead of
+/// them in the queue. Released packets are then sent in strict priority
+/// order as in [`SPScheduler`](super::sp::SPScheduler), by release within
+/// a priority.
+#[derive(Clone)]
+pub struct ATSScheduler {
+    base: SchedulerBase,
+    /// Priority level of each flow, higher is served first.
+    priorities: Vec<usize>,
+    regulators: Vec<Option<Regulator>>,
+    /// State of the regulator of each flow, see `Regulator::initial_state`.
+    regulator_states: Vec<f64>,
+    /// Eligibility time of the last packet released at each priority.
+    group_eligibility: BTreeMap<usize, f64>,
+    /// Flow index, arrival time and packet of every released packet
+    /// waiting for the port, by release.
+    released: Vec<(usize, usize, Packet)>,
+}
+
+impl ATSScheduler {
+    pub fn new(bandwidth: usize) -> ATSScheduler {
+        ATSScheduler {
+            base: SchedulerBase::new(bandwidth),
+            priorities: Vec::new(),
+            regulators: Vec::new(),
+            regulator_states: Vec::new(),
+            group_eligibility: BTreeMap::new(),
+            released: Vec::new(),
+        }
+    }
+
+    /// Add a flow with a priority level, higher is served first,
+    /// regulated by `regulator` if given.
+    pub fn add_flow(
+        &mut self,
+        flow: impl Flow + 'static,
+        priority: usize,
+        regulator: Option<Regulator>,
+    ) -> FlowId {
+        self.push_flow(Box::new(flow), priority, regulator)
+    }
+
+    fn push_flow(
+        &mut self,
+        flow: Box<dyn Flow>,
+        priority: usize,
+        regulator: Option<Regulator>,
+    ) -> FlowId {
+        if let Some(Regulator::TokenBucket { rate, .. } | Regulator::LengthRate { rate }) =
+            regulator
+        {
+            assert!(rate > 0f64, "the rate of a regulator must be positive");
+        }
+        self.priorities.push(priority);
+        self.regulators.push(regulator);
+        self.regulator_states
+            .push(regulator.map_or(0f64, Regulator::initial_state));
+        self.base.add_flow(flow)
+    }
+
+    /// The flow whose packet heads the interleaved regulator of a
+    /// priority: the earliest arrived packet among the flows of the
+    /// priority.
+    fn regulator_head(&self, priority: usize) -> Option<usize> {
+        (0..self.base.flows.len())
+            .filter(|&idx| {
+                self.priorities[idx] == priority
+                    && self.base.flows[idx].peek_packet(self.base.timer).is_some()
+            })
+            .min_by_key(|&idx| self.base.flows[idx].next_arrival())
+    }
+
+    /// The time the packet heading a flow is eligible at.
+    fn eligibility_time(&self, idx: usize) -> f64 {
+        let arrival = self.base.flows[idx].next_arrival().unwrap() as f64;
+        let group = self
+            .group_eligibility
+            .get(&self.priorities[idx])
+            .copied()
+            .unwrap_or(0f64);
+        let earliest = arrival.max(group);
+        match self.regulators[idx] {
+            Some(regulator) => {
+                let len = self.base.flows[idx]
+                    .peek_packet(self.base.timer)
+                    .unwrap()
+                    .len;
+                regulator.eligibility_time(self.regulator_states[idx], len, earliest)
+            }
+            None => earliest,
+        }
+    }
+
+    /// Release the eligible packets heading the interleaved regulators.
+    fn release(&mut self) {
+        let mut priorities = self.priorities.clone();
+        priorities.sort_unstable();
+        priorities.dedup();
+        for priority in priorities {
+            while let Some(idx) = self.regulator_head(priority) {
+                let eligibility = self.eligibility_time(idx);
+                if eligibility > self.base.timer as f64 {
+                    break;
+                }
+                let arrive_time = self.base.flows[idx].next_arrival().unwrap();
+                let packet = self.base.flows[idx].pop_packet();
+                if let Some(regulator) = self.regulators[idx] {
+                    self.regulator_states[idx] =
+                        regulator.next_state(self.regulator_states[idx], packet.len, eligibility);
+                }
+                self.group_eligibility.insert(priority, eligibility);
+                self.released.push((idx, arrive_time, packet));
+            }
+        }
+    }
+
+    /// The number of packets of a flow released by its regulator
+    /// and waiting for the port.
+    pub fn released_count(&self, flow: FlowId) -> usize {
+        self.released
+            .iter()
+            .filter(|(idx, _, _)| *idx == flow.index())
+            .count()
+    }
+}
+
+impl Scheduler for ATSScheduler {
+    /// Add an unregulated flow with the weight used as its priority level.
+    fn add_flow(&mut self, flow: Box<dyn Flow>, weight: f64) -> FlowId {
+        self.push_flow(flow, weight.round() as usize, None)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        for (state, regulator) in self.regulator_states.iter_mut().zip(&self.regulators) {
+            *state = regulator.map_or(0f64, Regulator::initial_state);
+        }
+        self.group_eligibility.clear();
+        self.released.clear();
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
+    }
+}
+
+impl Tickable for ATSScheduler {
+    fn tick(&mut self) -> bool {
+        if self.released.is_empty() && self.base.all_empty() {
+            return false;
+        }
+
+        self.release();
+        if self.base.output_port.is_accepting() {
+            if let Some(position) = self.schedule() {
+                let (idx, arrive_time, packet) = self.released.remove(position);
+                self.base.submit(idx, packet, arrive_time);
+            }
+        }
+
+        let mut released = vec![0; self.base.flows.len()];
+        for (idx, _, _) in &self.released {
+            released[*idx] += 1;
+        }
+        self.base.queue_series.record(
+            self.base.timer,
+            self.base.output_port.queue_len(),
+            self.base
+                .flows
+                .iter()
+                .zip(released)
+                .map(|(f, released)| f.queue_len(self.base.timer) + released),
+        );
+
+        self.base.advance();
+
+        true
+    }
+}
+
+impl Schedulable<Option<usize>> for ATSScheduler {
+    /// Return the position among the released packets of the first one
+    /// of the highest priority.
+    fn schedule(&mut self) -> Option<usize> {
+        let mut best: Option<usize> = None;
+        for (position, (idx, _, _)) in self.released.iter().enumerate() {
+            if best.is_none_or(|b| self.priorities[*idx] > self.priorities[self.released[b].0]) {
+                best = Some(position);
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        FlowId, Packet, Scheduler,
+    };
+
+    use super::{ATSScheduler, Regulator};
+
+    fn flow(prefix: &str, count: usize, len: usize) -> VariableLengthFlow {
+        let mut flow = VariableLengthFlow::new();
+        for p in 0..count {
+            flow.packet_arrive(Packet::new(format!("{}{}", prefix, p), len), 0);
+        }
+        flow
+    }
+
+    #[test]
+    fn ats_token_bucket_test() {
+        let mut ats = ATSScheduler::new(4);
+        // A burst of two packets, then one packet every 4 ticks.
+        ats.add_flow(
+            flow("a", 5, 2),
+            1,
+            Some(Regulator::TokenBucket {
+                rate: 0.5,
+                burst: 4,
+            }),
+        );
+        ats.add_flow(flow("b", 10, 4), 0, None);
+
+        ats.run();
+
+        let stats = ats.scheduler_stats();
+        let departures: Vec<(&str, usize)> = stats
+            .packets
+            .iter()
+            .filter(|r| r.flow == FlowId(0))
+            .map(|r| (r.name.as_str(), r.departure))
+            .collect();
+        assert_eq!(
+            departures,
+            [("a0", 1), ("a1", 2), ("a2", 5), ("a3", 9), ("a4", 13)]
+        );
+        // Best-effort packets use the link between regulated ones.
+        assert_eq!(ats.output()[2].name, "b0");
+        assert_eq!(ats.output().len(), 15);
+    }
+
+    #[test]
+    fn ats_length_rate_test() {
+        let mut ats = ATSScheduler::new(4);
+        // One packet of 4 bytes every 2 ticks, without a burst.
+        ats.add_flow(
+            flow("a", 3, 4),
+            0,
+            Some(Regulator::LengthRate { rate: 2.0 }),
+        );
+        ats.run();
+        assert_eq!(ats.get_output_port().get_departure_times(), &[1, 3, 5]);
+    }
+
+    #[test]
+    fn ats_interleaved_test() {
+        let mut ats = ATSScheduler::new(4);
+        // A throttled flow heads the shared queue and holds back
+        // the unregulated flow of the same priority behind it.
+        let mut slow = VariableLengthFlow::new();
+        slow.packet_arrive(Packet::new("a0", 4), 0);
+        slow.packet_arrive(Packet::new("a1", 4), 0);
+        ats.add_flow(slow, 0, Some(Regulator::LengthRate { rate: 1.0 }));
+        let mut fast = VariableLengthFlow::new();
+        fast.packet_arrive(Packet::new("b0", 4), 1);
+        ats.add_flow(fast, 0, None);
+
+        ats.step();
+        ats.step();
+        assert_eq!(ats.released_count(FlowId(1)), 0);
+
+        ats.run();
+        let names: Vec<&str> = ats.output().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["a0", "a1", "b0"]);
+        assert_eq!(ats.get_output_port().get_departure_times(), &[1, 5, 6]);
+
+        ats.reset();
+        ats.run();
+        assert_eq!(ats.get_output_port().get_departure_times(), &[1, 5, 6]);
+    }
+}
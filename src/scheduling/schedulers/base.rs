@@ -0,0 +1,192 @@
+use crate::scheduling::{
+    flow::Flow,
+    source::SchedulableSource,
+    stats::{DropLog, EwmaThroughput, QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Tickable,
+};
+
+/// The queues of the flows of a scheduler, however they are organised.
+pub(crate) trait FlowQueues: Clone + Default {
+    /// Whether no flow has a packet left.
+    fn all_empty(&self) -> bool;
+
+    /// The number of packets of each flow arrived by `time`
+    /// and still waiting, in the order of the flows.
+    fn queue_lens(&self, time: usize) -> Vec<usize>;
+
+    /// The number of packets of a flow dropped in its own queue.
+    fn dropped_count(&self, flow_idx: usize) -> usize;
+}
+
+impl FlowQueues for Vec<Box<dyn Flow>> {
+    fn all_empty(&self) -> bool {
+        self.iter().all(|f| f.empty())
+    }
+
+    fn queue_lens(&self, time: usize) -> Vec<usize> {
+        self.iter().map(|f| f.queue_len(time)).collect()
+    }
+
+    fn dropped_count(&self, flow_idx: usize) -> usize {
+        self[flow_idx].dropped_count()
+    }
+}
+
+impl FlowQueues for Vec<Box<dyn SchedulableSource>> {
+    fn all_empty(&self) -> bool {
+        self.iter().all(|f| f.empty())
+    }
+
+    fn queue_lens(&self, time: usize) -> Vec<usize> {
+        self.iter().map(|f| f.queue_len(time)).collect()
+    }
+
+    fn dropped_count(&self, flow_idx: usize) -> usize {
+        self[flow_idx].dropped_count()
+    }
+}
+
+/// The state and bookkeeping every scheduler keeps besides its own
+/// scheduling state: the clock, the flows, the output port and the
+/// records of the packets going through it.
+#[derive(Debug, Clone)]
+pub(crate) struct SchedulerBase<F: FlowQueues = Vec<Box<dyn Flow>>> {
+    pub timer: usize,
+    pub flows: F,
+    /// The flows as they were added, restored by `reset`.
+    pub initial_flows: F,
+    pub output_port: Port,
+    pub throughput: EwmaThroughput,
+    pub queue_series: QueueSeries,
+    pub drops: DropLog,
+    /// Flow index, arrival time and dequeue time of every packet
+    /// accepted by the port, in the order it accepted them.
+    pub served: Vec<(usize, usize, usize)>,
+}
+
+impl<F: FlowQueues> SchedulerBase<F> {
+    pub fn new(bandwidth: usize) -> SchedulerBase<F> {
+        SchedulerBase {
+            timer: 0,
+            flows: F::default(),
+            initial_flows: F::default(),
+            output_port: Port::new(0, bandwidth),
+            throughput: EwmaThroughput::default(),
+            queue_series: QueueSeries::default(),
+            drops: DropLog::default(),
+            served: Vec::new(),
+        }
+    }
+
+    /// Start keeping the records of one more flow, added to the flows
+    /// by the caller, and return its identifier.
+    pub fn track_flow(&mut self) -> FlowId {
+        self.throughput.add_flow();
+        self.drops.add_flow();
+        FlowId(self.drops.flow_count() - 1)
+    }
+
+    /// Whether no flow has a packet left.
+    pub fn all_empty(&self) -> bool {
+        self.flows.all_empty()
+    }
+
+    /// The number of flows added so far.
+    pub fn flow_count(&self) -> usize {
+        self.drops.flow_count()
+    }
+
+    pub fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.throughput.set_alpha(alpha);
+    }
+
+    pub fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.throughput.estimate(flow.index())
+    }
+
+    /// The number of packets of a flow dropped at the output port
+    /// or in the queue of the flow.
+    pub fn dropped_count(&self, flow: FlowId) -> usize {
+        self.drops.count(flow.index()) + self.flows.dropped_count(flow.index())
+    }
+
+    /// Submit a packet of a flow to the output port, recording it as
+    /// served or dropped.
+    pub fn submit(&mut self, flow_idx: usize, packet: Packet, arrive_time: usize) {
+        self.throughput.record(flow_idx, packet.len);
+        match self.output_port.submit(packet) {
+            Ok(()) => self.served.push((flow_idx, arrive_time, self.timer)),
+            Err(packet) => self
+                .drops
+                .record(flow_idx, &packet, arrive_time, self.timer),
+        }
+    }
+
+    /// Sample the queues if a sample is due.
+    pub fn record_queues(&mut self) {
+        if self.queue_series.is_due(self.timer) {
+            let lens = self.flows.queue_lens(self.timer);
+            let port = self.output_port.queue_len();
+            self.queue_series.record(self.timer, port, lens);
+        }
+    }
+
+    /// Move the clock, the port and the throughput estimate to the next tick.
+    pub fn advance(&mut self) {
+        self.timer += 1;
+        self.output_port.tick();
+        self.throughput.tick();
+    }
+
+    /// Bring back the flows as they were added and forget what happened.
+    pub fn reset(&mut self) {
+        self.timer = 0;
+        self.flows = self.initial_flows.clone();
+        self.output_port.reset();
+        self.throughput.reset();
+        self.queue_series.clear();
+        self.drops.reset();
+        self.served.clear();
+    }
+
+    pub fn stats(&self) -> SchedulerStats {
+        // Packets may leave the port in another order than they were
+        // accepted, so each is matched with its own record.
+        let served: Vec<_> = self
+            .output_port
+            .get_accepted_indices()
+            .iter()
+            .map(|&idx| self.served[idx])
+            .collect();
+        SchedulerStats::collect(
+            self.flow_count(),
+            &served,
+            self.output_port.get_output(),
+            self.output_port.get_departure_times(),
+            self.drops.records(),
+            |flow| self.dropped_count(flow),
+        )
+    }
+}
+
+impl<T: ?Sized> SchedulerBase<Vec<Box<T>>>
+where
+    Vec<Box<T>>: FlowQueues,
+    Box<T>: Clone,
+{
+    /// Add a flow and return its identifier.
+    pub fn add_flow(&mut self, flow: Box<T>) -> FlowId {
+        self.initial_flows.push(flow.clone());
+        self.flows.push(flow);
+        self.track_flow()
+    }
+}
+
+impl SchedulerBase {
+    /// Pop the head packet of a flow and submit it to the output port.
+    pub fn serve(&mut self, flow_idx: usize) {
+        let arrive_time = self.flows[flow_idx].next_arrival().unwrap();
+        let packet = self.flows[flow_idx].pop_packet();
+        self.submit(flow_idx, packet, arrive_time);
+    }
+}
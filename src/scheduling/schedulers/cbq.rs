@@ -0,0 +1,488 @@
+use crate::scheduling::{
+    flow::Flow,
+    schedulers::base::SchedulerBase,
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// Weight of the last idle time in the average idle time of a class.
+const AVGIDLE_WEIGHT: f64 = 1f64 / 16f64;
+
+/// Handle of a class of a [`CBQScheduler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct CBQClass(usize);
+
+#[derive(Debug, Clone)]
+struct ClassState {
+    parent: Option<usize>,
+    /// Allocated rate, in bytes per tick.
+    rate: usize,
+    /// Priority level, higher is served first.
+    priority: usize,
+    /// Whether the class may not borrow from its parent.
+    bounded: bool,
+    /// Whether the class neither borrows nor lends its allocation.
+    isolated: bool,
+    /// Cap of the average idle time, in ticks.
+    max_idle: f64,
+    /// Average of the time between packets beyond what the allocated
+    /// rate needs, negative once the class sent faster than its rate.
+    avgidle: f64,
+    /// Start time and length of the last packet of the class.
+    last: Option<(usize, usize)>,
+    has_children: bool,
+    /// Indices of the flows of a leaf class.
+    flows: Vec<usize>,
+    /// Position in `flows` of the flow to visit first.
+    next_flow: usize,
+}
+
+/// Class-Based Queueing (CBQ) scheduler, after Floyd and Jacobson's
+/// link-sharing model.
+///
+/// Classes form a tree and flows are attached to the leaves. Every class
+/// has an allocated rate, and an estimator of the average time between
+/// its packets beyond what its rate needs: a class is underlimit while
+/// that average idle time is not negative. A leaf may send while it is
+/// underlimit, and otherwise borrow from the closest underlimit ancestor,
+/// unless it or a class on the way is bounded. An isolated class is
+/// bounded and does not lend either: its allocation is kept out of what
+/// its parent lends to the other children. Among the leaves that may send,
+/// the highest priority is served, then the leaf borrowing from the lowest
+/// level, then the leaf with the largest average idle time, so that leaves
+/// borrowing together share in proportion to their allocations. The link
+/// stays idle while every backlogged leaf is overlimit and cannot borrow.
+#[derive(Clone)]
+pub struct CBQScheduler {
+    base: SchedulerBase,
+    bandwidth: usize,
+    classes: Vec<ClassState>,
+    /// The classes as they were set up, restored by `reset`.
+    initial_classes: Vec<ClassState>,
+    /// The class of the link rate the flows added without a class
+    /// share, created with the first of them.
+    root: Option<usize>,
+    /// Leaf class of each flow.
+    flow_classes: Vec<usize>,
+    /// Class to visit first among equal leaves.
+    next_class: usize,
+}
+
+impl CBQScheduler {
+    pub fn new(bandwidth: usize) -> CBQScheduler {
+        CBQScheduler {
+            base: SchedulerBase::new(bandwidth),
+            bandwidth,
+            classes: Vec::new(),
+            initial_classes: Vec::new(),
+            root: None,
+            flow_classes: Vec::new(),
+            next_class: 0,
+        }
+    }
+
+    /// Add a class under `parent`, or at the top of the tree, with an
+    /// allocated rate in bytes per tick. The class has priority 0, may
+    /// borrow, and has a maximum idle time of 0 so that it cannot save
+    /// up for bursts, see [`CBQScheduler::set_max_idle`].
+    pub fn add_class(&mut self, parent: Option<CBQClass>, rate: usize) -> CBQClass {
+        if let Some(parent) = parent {
+            assert!(
+                self.classes[parent.0].flows.is_empty(),
+                "flows are only attached to leaf classes"
+            );
+            self.classes[parent.0].has_children = true;
+            self.initial_classes[parent.0].has_children = true;
+        }
+        let class = ClassState {
+            parent: parent.map(|p| p.0),
+            rate,
+            priority: 0,
+            bounded: false,
+            isolated: false,
+            max_idle: 0f64,
+            avgidle: 0f64,
+            last: None,
+            has_children: false,
+            flows: Vec::new(),
+            next_flow: 0,
+        };
+        self.initial_classes.push(class.clone());
+        self.classes.push(class);
+        CBQClass(self.classes.len() - 1)
+    }
+
+    /// Set up a class before and after `reset`.
+    fn configure(&mut self, class: CBQClass, set: impl Fn(&mut ClassState)) {
+        set(&mut self.classes[class.0]);
+        set(&mut self.initial_classes[class.0]);
+    }
+
+    /// Set the priority level of a class, higher is served first.
+    pub fn set_priority(&mut self, class: CBQClass, priority: usize) {
+        self.configure(class, |c| c.priority = priority);
+    }
+
+    /// Forbid or allow a class to borrow from its parent.
+    pub fn set_bounded(&mut self, class: CBQClass, bounded: bool) {
+        self.configure(class, |c| c.bounded = bounded);
+    }
+
+    /// Make a class keep its allocation to itself, neither borrowing
+    /// nor lending it to the other children of its parent.
+    pub fn set_isolated(&mut self, class: CBQClass, isolated: bool) {
+        self.configure(class, |c| c.isolated = isolated);
+    }
+
+    /// Set how far the average idle time of a class may grow, in ticks,
+    /// bounding the burst it may send after being idle.
+    pub fn set_max_idle(&mut self, class: CBQClass, max_idle: f64) {
+        self.configure(class, |c| c.max_idle = max_idle);
+    }
+
+    /// Attach a flow to a leaf class.
+    /// Flows of the same class are served in turn.
+    pub fn add_flow_to(&mut self, class: CBQClass, flow: impl Flow + 'static) -> FlowId {
+        self.push_flow(class, Box::new(flow))
+    }
+
+    fn push_flow(&mut self, class: CBQClass, flow: Box<dyn Flow>) -> FlowId {
+        assert!(
+            !self.classes[class.0].has_children,
+            "flows are only attached to leaf classes"
+        );
+        let flow_idx = self.base.flows.len();
+        self.classes[class.0].flows.push(flow_idx);
+        self.initial_classes[class.0].flows.push(flow_idx);
+        self.flow_classes.push(class.0);
+        self.base.add_flow(flow)
+    }
+
+    /// The rate a class measures the traffic it accounts for against:
+    /// its allocation minus that of its isolated children.
+    fn lending_rate(&self, class: usize) -> f64 {
+        let isolated: usize = self
+            .classes
+            .iter()
+            .filter(|c| c.parent == Some(class) && c.isolated)
+            .map(|c| c.rate)
+            .sum();
+        self.classes[class].rate.saturating_sub(isolated) as f64
+    }
+
+    /// The average idle time of a class if it sent a packet now.
+    fn projected_avgidle(&self, class: usize) -> f64 {
+        let state = &self.classes[class];
+        let idle = match state.last {
+            Some((start, len)) => {
+                let rate = self.lending_rate(class);
+                if rate <= 0f64 {
+                    return f64::NEG_INFINITY;
+                }
+                (self.base.timer - start) as f64 - len as f64 / rate
+            }
+            None => state.max_idle,
+        };
+        ((1f64 - AVGIDLE_WEIGHT) * state.avgidle + AVGIDLE_WEIGHT * idle).min(state.max_idle)
+    }
+
+    /// Whether a class may send now on its own allocation.
+    pub fn is_underlimit(&self, class: CBQClass) -> bool {
+        self.projected_avgidle(class.0) >= 0f64
+    }
+
+    /// How many levels above a leaf the class it sends on is: 0 if it is
+    /// underlimit, 1 if it borrows from its parent and so on.
+    /// None if the leaf cannot send at all.
+    fn borrow_depth(&self, leaf: usize) -> Option<usize> {
+        let mut class = leaf;
+        let mut depth = 0;
+        loop {
+            if self.is_underlimit(CBQClass(class)) {
+                return Some(depth);
+            }
+            let state = &self.classes[class];
+            if state.bounded || state.isolated {
+                return None;
+            }
+            class = state.parent?;
+            depth += 1;
+        }
+    }
+
+    /// Account a packet sent now by a leaf in the estimators of the leaf
+    /// and of its ancestors, except the parents of isolated classes,
+    /// which do not lend their allocation.
+    fn charge(&mut self, leaf: usize, len: usize) {
+        let mut class = Some(leaf);
+        let mut child_isolated = false;
+        while let Some(idx) = class {
+            if !child_isolated {
+                let avgidle = self.projected_avgidle(idx);
+                let state = &mut self.classes[idx];
+                if avgidle.is_finite() {
+                    state.avgidle = avgidle;
+                }
+                state.last = Some((self.base.timer, len));
+            }
+            child_isolated = self.classes[idx].isolated;
+            class = self.classes[idx].parent;
+        }
+    }
+
+    /// The flow of a leaf to serve next, in turn among its arrived flows.
+    fn leaf_flow(&self, class: usize) -> Option<usize> {
+        let class = &self.classes[class];
+        let n = class.flows.len();
+        (0..n)
+            .map(|offset| class.flows[(class.next_flow + offset) % n])
+            .find(|&idx| self.base.flows[idx].peek_packet(self.base.timer).is_some())
+    }
+}
+
+impl Scheduler for CBQScheduler {
+    /// Add a flow in a class of its own with the weight as allocated rate,
+    /// under a root class of the link rate shared by such flows.
+    fn add_flow(&mut self, flow: Box<dyn Flow>, weight: f64) -> FlowId {
+        let root = match self.root {
+            Some(root) => CBQClass(root),
+            None => {
+                let root = self.add_class(None, self.bandwidth);
+                self.root = Some(root.0);
+                root
+            }
+        };
+        let rate = (weight.round() as usize).min(self.bandwidth);
+        let class = self.add_class(Some(root), rate);
+        self.push_flow(class, flow)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.classes = self.initial_classes.clone();
+        self.next_class = 0;
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
+    }
+}
+
+impl Tickable for CBQScheduler {
+    fn tick(&mut self) -> bool {
+        if self.base.all_empty() {
+            return false;
+        }
+
+        if self.base.output_port.is_accepting() {
+            if let Some(idx) = self.schedule() {
+                let arrive_time = self.base.flows[idx].next_arrival().unwrap();
+                let packet = self.base.flows[idx].pop_packet();
+                self.charge(self.flow_classes[idx], packet.len);
+                self.base.submit(idx, packet, arrive_time);
+            }
+        }
+
+        self.base.record_queues();
+        self.base.advance();
+
+        true
+    }
+}
+
+impl Schedulable<Option<usize>> for CBQScheduler {
+    /// Pick the leaf of the highest priority that borrows from the lowest
+    /// level, then with the largest average idle time, in turn among equal
+    /// leaves, and return the index of its next flow.
+    fn schedule(&mut self) -> Option<usize> {
+        let n = self.classes.len();
+        let mut best: Option<(usize, usize, (usize, isize, f64))> = None;
+        for offset in 0..n {
+            let class = (self.next_class + offset) % n;
+            if self.classes[class].has_children {
+                continue;
+            }
+            let Some(flow_idx) = self.leaf_flow(class) else {
+                continue;
+            };
+            let Some(depth) = self.borrow_depth(class) else {
+                continue;
+            };
+            let key = (
+                self.classes[class].priority,
+                -(depth as isize),
+                self.projected_avgidle(class),
+            );
+            if best.is_none_or(|(_, _, best)| key > best) {
+                best = Some((class, flow_idx, key));
+            }
+        }
+
+        let (class, flow_idx, _) = best?;
+        self.next_class = (class + 1) % n;
+        let leaf = &mut self.classes[class];
+        let pos = leaf.flows.iter().position(|&f| f == flow_idx).unwrap();
+        leaf.next_flow = (pos + 1) % leaf.flows.len();
+        Some(flow_idx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        Packet, Scheduler,
+    };
+
+    use super::CBQScheduler;
+
+    fn flow(prefix: &str, count: usize) -> VariableLengthFlow {
+        let mut flow = VariableLengthFlow::new();
+        for p in 0..count {
+            flow.packet_arrive(Packet::new(format!("{}{}", prefix, p), 4), 0);
+        }
+        flow
+    }
+
+    fn count(cbq: &CBQScheduler, prefix: char, packets: usize) -> usize {
+        cbq.output()[..packets]
+            .iter()
+            .filter(|p| p.name.starts_with(prefix))
+            .count()
+    }
+
+    #[test]
+    fn cbq_share_test() {
+        let mut cbq = CBQScheduler::new(4);
+        let root = cbq.add_class(None, 4);
+        let a = cbq.add_class(Some(root), 3);
+        let b = cbq.add_class(Some(root), 1);
+        cbq.add_flow_to(a, flow("a", 40));
+        cbq.add_flow_to(b, flow("b", 40));
+        cbq.run();
+
+        // While both are backlogged, each gets its allocation.
+        let a_count = count(&cbq, 'a', 40);
+        assert!((29..=31).contains(&a_count), "a got {} of 40", a_count);
+        assert_eq!(cbq.output().len(), 80);
+    }
+
+    #[test]
+    fn cbq_borrow_test() {
+        // Alone, a class borrows the whole link from its parent ...
+        let mut cbq = CBQScheduler::new(4);
+        let root = cbq.add_class(None, 4);
+        let a = cbq.add_class(Some(root), 1);
+        cbq.add_class(Some(root), 3);
+        cbq.add_flow_to(a, flow("a", 10));
+        cbq.run();
+        assert_eq!(cbq.timer(), 10);
+
+        // ... unless it is bounded to its own allocation.
+        cbq.set_bounded(a, true);
+        cbq.reset();
+        cbq.run();
+        let departures = cbq.get_output_port().get_departure_times();
+        let gaps: Vec<usize> = departures.windows(2).map(|w| w[1] - w[0]).collect();
+        assert!(gaps.iter().all(|&gap| gap >= 4), "gaps {:?}", gaps);
+    }
+
+    #[test]
+    fn cbq_isolated_test() {
+        // The allocation of an idle isolated class is not lent to b.
+        let mut cbq = CBQScheduler::new(4);
+        let root = cbq.add_class(None, 4);
+        let a = cbq.add_class(Some(root), 2);
+        let b = cbq.add_class(Some(root), 1);
+        cbq.set_isolated(a, true);
+        cbq.add_flow_to(b, flow("b", 20));
+        cbq.run();
+        let departures = cbq.get_output_port().get_departure_times();
+        let span = departures[19] - departures[0];
+        assert!((36..=40).contains(&span), "20 packets in {} ticks", span);
+
+        // Isolated classes are also bounded, and classes of higher
+        // priority go first.
+        let mut cbq = CBQScheduler::new(4);
+        let root = cbq.add_class(None, 4);
+        let a = cbq.add_class(Some(root), 1);
+        let b = cbq.add_class(Some(root), 1);
+        cbq.set_isolated(a, true);
+        cbq.set_priority(a, 1);
+        cbq.add_flow_to(a, flow("a", 5));
+        cbq.add_flow_to(b, flow("b", 5));
+        cbq.run();
+        assert_eq!(cbq.output()[0].name, "a0");
+        assert_eq!(count(&cbq, 'a', 5), 2);
+    }
+
+    #[test]
+    fn cbq_trait_test() {
+        let mut cbq = CBQScheduler::new(2);
+        for (prefix, weight) in [("a", 1f64), ("b", 1f64)] {
+            Scheduler::add_flow(&mut cbq, Box::new(flow(prefix, 10)), weight);
+        }
+        cbq.run();
+
+        // Both flows share the root of the link rate in equal parts,
+        // keeping the link busy.
+        assert_eq!(count(&cbq, 'a', 10), 5);
+        let departures = cbq.get_output_port().get_departure_times();
+        assert_eq!(departures.last(), Some(&40));
+    }
+}
@@ -0,0 +1,255 @@
+use crate::scheduling::{
+    flow::Flow,
+    schedulers::base::SchedulerBase,
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// Credit-Based Shaper (CBS) scheduler, as in IEEE 802.1Qav.
+///
+/// Every flow is a queue with a priority, served in strict priority order
+/// like [`SPScheduler`](super::sp::SPScheduler). Queues of time-sensitive
+/// classes are also shaped: their credit grows at the idle slope while
+/// they wait and drops at the send slope, the idle slope minus the link
+/// rate, while they transmit. A shaped queue is only eligible with a
+/// non-negative credit, which bounds its share of the link to the idle
+/// slope and leaves the rest to lower priorities.
+#[derive(Clone)]
+pub struct CBSScheduler {
+    base: SchedulerBase,
+    /// Priority level of each flow, higher is served first.
+    priorities: Vec<usize>,
+    /// Idle slope of each shaped flow, in bytes per tick.
+    idle_slopes: Vec<Option<usize>>,
+    credits: Vec<isize>,
+    /// The flow whose packet is being transmitted.
+    transmitting: Option<usize>,
+}
+
+impl CBSScheduler {
+    pub fn new(bandwidth: usize) -> CBSScheduler {
+        CBSScheduler {
+            base: SchedulerBase::new(bandwidth),
+            priorities: Vec::new(),
+            idle_slopes: Vec::new(),
+            credits: Vec::new(),
+            transmitting: None,
+        }
+    }
+
+    /// Add a flow with a priority level, higher is served first,
+    /// shaped to `idle_slope` bytes per tick if given.
+    pub fn add_flow(
+        &mut self,
+        flow: impl Flow + 'static,
+        priority: usize,
+        idle_slope: Option<usize>,
+    ) -> FlowId {
+        self.push_flow(Box::new(flow), priority, idle_slope)
+    }
+
+    fn push_flow(
+        &mut self,
+        flow: Box<dyn Flow>,
+        priority: usize,
+        idle_slope: Option<usize>,
+    ) -> FlowId {
+        self.priorities.push(priority);
+        self.idle_slopes.push(idle_slope);
+        self.credits.push(0);
+        self.base.add_flow(flow)
+    }
+
+    /// The current credit of a flow, always 0 for unshaped flows.
+    pub fn credit(&self, flow: FlowId) -> isize {
+        self.credits[flow.index()]
+    }
+
+    /// Update the credits for a tick transmitted at `rate`.
+    fn update_credits(&mut self, rate: usize) {
+        for idx in 0..self.base.flows.len() {
+            let Some(idle_slope) = self.idle_slopes[idx] else {
+                continue;
+            };
+            let idle_slope = idle_slope as isize;
+            let credit = &mut self.credits[idx];
+            if self.transmitting == Some(idx) {
+                *credit += idle_slope - rate as isize;
+            } else if self.base.flows[idx].peek_packet(self.base.timer).is_some() {
+                *credit += idle_slope;
+            } else if *credit < 0 {
+                *credit = (*credit + idle_slope).min(0);
+            } else {
+                // An idle queue does not save credit.
+                *credit = 0;
+            }
+        }
+    }
+}
+
+impl Scheduler for CBSScheduler {
+    /// Add an unshaped flow with the weight used as its priority level.
+    fn add_flow(&mut self, flow: Box<dyn Flow>, weight: f64) -> FlowId {
+        self.push_flow(flow, weight.round() as usize, None)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.credits.fill(0);
+        self.transmitting = None;
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn flow_state(&self) -> Option<(&'static str, Vec<f64>)> {
+        Some(("credit", self.credits.iter().map(|&c| c as f64).collect()))
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
+    }
+}
+
+impl Tickable for CBSScheduler {
+    fn tick(&mut self) -> bool {
+        if self.base.all_empty() {
+            return false;
+        }
+
+        if self.base.output_port.is_accepting() {
+            if let Some(idx) = self.schedule() {
+                self.transmitting = Some(idx);
+                self.base.serve(idx);
+            }
+        }
+
+        self.base.record_queues();
+
+        let rate = self.base.output_port.get_bandwidth();
+        self.base.output_port.tick();
+        self.update_credits(rate);
+        if self.base.output_port.empty() {
+            self.transmitting = None;
+        }
+        self.base.timer += 1;
+        self.base.throughput.tick();
+
+        true
+    }
+}
+
+impl Schedulable<Option<usize>> for CBSScheduler {
+    /// Return the index of the highest-priority flow with an arrived packet
+    /// and, if shaped, a non-negative credit.
+    fn schedule(&mut self) -> Option<usize> {
+        let mut best: Option<usize> = None;
+        for (idx, flow) in self.base.flows.iter().enumerate() {
+            if flow.peek_packet(self.base.timer).is_none()
+                || (self.idle_slopes[idx].is_some() && self.credits[idx] < 0)
+            {
+                continue;
+            }
+            if best.is_none_or(|b| self.priorities[idx] > self.priorities[b]) {
+                best = Some(idx);
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        FlowId, Packet, Scheduler,
+    };
+
+    use super::CBSScheduler;
+
+    fn flow(prefix: &str, count: usize) -> VariableLengthFlow {
+        let mut flow = VariableLengthFlow::new();
+        for p in 0..count {
+            flow.packet_arrive(Packet::new(format!("{}{}", prefix, p), 4), 0);
+        }
+        flow
+    }
+
+    #[test]
+    fn cbs_test() {
+        let mut cbs = CBSScheduler::new(4);
+        // A stream class reserving a quarter of the link,
+        // above a best-effort class.
+        cbs.add_flow(flow("a", 10), 1, Some(1));
+        cbs.add_flow(flow("b", 10), 0, None);
+
+        cbs.run();
+
+        // Each stream packet costs 3 credits that take 3 ticks to recover,
+        // which best-effort traffic uses.
+        let names: Vec<&str> = cbs.output().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names[..8], ["a0", "b0", "b1", "b2", "a1", "b3", "b4", "b5"]);
+        assert_eq!(cbs.output().len(), 20);
+    }
+
+    #[test]
+    fn cbs_idle_credit_test() {
+        let mut cbs = CBSScheduler::new(4);
+        let mut stream = VariableLengthFlow::new();
+        stream.packet_arrive(Packet::new("a0", 4), 0);
+        stream.packet_arrive(Packet::new("a1", 4), 8);
+        cbs.add_flow(stream, 1, Some(1));
+        cbs.run();
+
+        // The credit recovered to 0 while idle but did not go beyond.
+        assert_eq!(cbs.credit(FlowId(0)), -3);
+        assert_eq!(cbs.get_output_port().get_departure_times(), &[1, 9]);
+    }
+}
@@ -1,67 +1,209 @@
 use crate::scheduling::{
-    flow::{Flow, VariableLengthFlow},
-    Port, Schedulable, Tickable,
+    engine::{Dequeue, EventScheduler},
+    flow::Flow,
+    schedulers::base::SchedulerBase,
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
 };
 
 /// Deficit Round Robin (DRR) scheduler.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DRRScheduler {
-    timer: usize,
-    flows: Vec<VariableLengthFlow>,
+    base: SchedulerBase,
     weights: Vec<usize>,
     deficit_counters: Vec<usize>,
-    output_port: Port,
+    /// The next flow to visit in the current round.
+    cursor: usize,
 }
 
 impl DRRScheduler {
     pub fn new(capacity: usize) -> DRRScheduler {
         DRRScheduler {
-            timer: 0,
-            flows: Vec::new(),
+            base: SchedulerBase::new(capacity),
             weights: Vec::new(),
             deficit_counters: Vec::new(),
-            output_port: Port::new(0, capacity),
+            cursor: 0,
         }
     }
 
-    pub fn add_flow(&mut self, flow: VariableLengthFlow, weight: usize) {
-        self.flows.push(flow);
+    pub fn add_flow(&mut self, flow: impl Flow + 'static, weight: usize) -> FlowId {
+        Scheduler::add_flow(self, Box::new(flow), weight as f64)
+    }
+
+    /// Run like [`Scheduler::run`], but jump over the ticks on which
+    /// the port is busy or no packet is eligible
+    /// instead of stepping through them one by one.
+    ///
+    /// Returns the number of ticks that were actually simulated.
+    pub fn run_event_driven(&mut self) -> usize {
+        let mut steps = 0;
+        while let Some(next_arrival) = self
+            .base
+            .flows
+            .iter()
+            .filter_map(|f| f.next_arrival())
+            .min()
+        {
+            let quiet = match self.base.output_port.ticks_to_completion() {
+                // Wait for the packet in transmission to complete.
+                Some(ticks) if !self.base.output_port.is_accepting() => ticks - 1,
+                // Wait for the next arrival, accumulating deficits.
+                _ => next_arrival.saturating_sub(self.base.timer + 1),
+            };
+            if quiet > 0 {
+                if self.base.output_port.is_accepting() {
+                    for i in 0..self.base.flows.len() {
+                        if self.base.flows[i].retired() {
+                            self.deficit_counters[i] = 0;
+                        } else if self.base.flows[i].empty() {
+                            self.deficit_counters[i] = self.weights[i];
+                        } else {
+                            self.deficit_counters[i] += quiet * self.weights[i];
+                        }
+                    }
+                }
+                self.base.timer += quiet;
+                self.base.output_port.advance(quiet);
+                self.base.throughput.advance(quiet);
+            }
+            self.tick();
+            steps += 1;
+        }
+        self.base.output_port.proceed_rest();
+        steps
+    }
+
+    /// Visit the flows of the round from the cursor on, up to the next
+    /// one whose head packet has arrived and fits in its deficit, which
+    /// pays for the packet.
+    fn next_in_round(&mut self) -> Option<usize> {
+        while self.cursor < self.base.flows.len() {
+            let i = self.cursor;
+            self.cursor += 1;
+            match self.base.flows[i].peek_packet(self.base.timer) {
+                Some(p) if self.deficit_counters[i] >= p.len => {
+                    self.deficit_counters[i] -= p.len;
+                    return Some(i);
+                }
+                Some(_) => {}
+                // Only an empty flow loses its deficit, not one whose
+                // next packet has not arrived yet.
+                None if self.base.flows[i].empty() => self.deficit_counters[i] = 0,
+                None => {}
+            }
+        }
+        None
+    }
+
+    /// Give every flow its quantum for the next round.
+    fn add_quanta(&mut self) {
+        for i in 0..self.base.flows.len() {
+            // A retired flow no longer earns its quantum.
+            if !self.base.flows[i].retired() {
+                self.deficit_counters[i] += self.weights[i];
+            }
+        }
+    }
+}
+
+impl Scheduler for DRRScheduler {
+    fn add_flow(&mut self, flow: Box<dyn Flow>, weight: f64) -> FlowId {
+        let weight = weight.round() as usize;
+        assert!(weight > 0, "a flow must get a positive quantum");
         self.weights.push(weight);
         self.deficit_counters.push(weight);
+        self.base.add_flow(flow)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
     }
 
-    pub fn run(&mut self) {
+    fn run(&mut self) {
         while self.tick() {}
-        self.output_port.proceed_rest();
+        self.base.output_port.proceed_rest();
     }
 
-    pub fn get_output_port(&mut self) -> &mut Port {
-        &mut self.output_port
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.deficit_counters = self.weights.clone();
+        self.cursor = 0;
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn flow_state(&self) -> Option<(&'static str, Vec<f64>)> {
+        Some((
+            "deficit",
+            self.deficit_counters.iter().map(|&d| d as f64).collect(),
+        ))
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
     }
 }
 
 impl Tickable for DRRScheduler {
     fn tick(&mut self) -> bool {
-        if self.flows.iter().all(|f| f.empty()) {
+        if self.base.all_empty() {
             return false;
         }
-        self.timer += 1;
-        self.output_port.tick();
-        if !self.output_port.empty() {
-            return true;
-        }
 
-        assert!(
-            self.flows.len() == self.weights.len()
-                && self.weights.len() == self.deficit_counters.len()
-        );
+        self.base.timer += 1;
+        self.base.output_port.tick();
+        if self.base.output_port.is_accepting() {
+            assert!(
+                self.base.flows.len() == self.weights.len()
+                    && self.weights.len() == self.deficit_counters.len()
+            );
 
-        // Add back if scheduled
-        if self.schedule() {
-            for i in 0..self.flows.len() {
-                self.deficit_counters[i] += self.weights[i];
+            // Add back if scheduled
+            if self.schedule() {
+                self.add_quanta();
             }
         }
+        self.base.throughput.tick();
+        self.base.record_queues();
 
         true
     }
@@ -69,34 +211,54 @@ impl Tickable for DRRScheduler {
 
 impl Schedulable<bool> for DRRScheduler {
     fn schedule(&mut self) -> bool {
-        if !self.output_port.empty() {
+        if !self.base.output_port.is_accepting() {
             return false;
         }
-        for i in 0..self.flows.len() {
-            if let Some(p) = self.flows[i].peek_packet(self.timer) {
-                if self.deficit_counters[i] >= p.len {
-                    self.deficit_counters[i] -= p.len;
-                    self.output_port.submit(p);
-                    self.flows[i].pop_packet();
-                }
-            } else {
-                self.deficit_counters[i] = 0;
-            }
+        self.cursor = 0;
+        while let Some(i) = self.next_in_round() {
+            let arrive_time = self.base.flows[i].next_arrival().unwrap();
+            let p = self.base.flows[i].pop_packet();
+            self.base.submit(i, p, arrive_time);
         }
         true
     }
 }
 
+impl EventScheduler for DRRScheduler {
+    fn enqueue(&mut self, flow_idx: usize, packet: Packet, time: f64) {
+        self.base.flows[flow_idx].packet_arrive(packet, time as usize);
+    }
+
+    /// Carry on with the round, the packets of a round being sent one at
+    /// a time as the link frees up, and start the next round at once when
+    /// the last one is over.
+    fn dequeue(&mut self, time: f64) -> Dequeue {
+        self.base.timer = time as usize;
+        loop {
+            if let Some(i) = self.next_in_round() {
+                return Dequeue::Packet(i, self.base.flows[i].pop_packet());
+            }
+            let waiting = (0..self.base.flows.len())
+                .any(|i| self.base.flows[i].peek_packet(self.base.timer).is_some());
+            if !waiting {
+                return Dequeue::Idle;
+            }
+            self.add_quanta();
+            self.cursor = 0;
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::scheduling::{
         flow::{self, Flow},
         schedulers::drr::DRRScheduler,
-        Packet,
+        FlowId, Packet, Scheduler, Tickable,
     };
 
     #[test]
-    fn ddr_test() {
+    fn drr_test() {
         let mut scheduler = DRRScheduler::new(1);
 
         let mut flow = flow::VariableLengthFlow::new();
@@ -116,9 +278,9 @@ mod test {
 
         scheduler.run();
 
-        assert_eq!(scheduler.timer, 15);
+        assert_eq!(scheduler.timer(), 13);
 
-        let output = scheduler.output_port.get_output();
+        let output = scheduler.output();
 
         assert_eq!(output.len(), 6);
         assert_eq!(
@@ -127,10 +289,140 @@ mod test {
                 Packet::new("1_1", 3),
                 Packet::new("2_1", 3),
                 Packet::new("3_1", 6),
+                Packet::new("1_2", 4),
                 Packet::new("2_2", 1),
-                Packet::new("3_2", 1),
-                Packet::new("1_2", 4)
+                Packet::new("3_2", 1)
             ]
         );
+
+        // The event-driven run replays the same schedule.
+        let result = scheduler.result();
+        let stats = scheduler.stats();
+        scheduler.reset();
+        scheduler.run_event_driven();
+        assert_eq!(scheduler.result(), result);
+        assert_eq!(scheduler.stats(), stats);
+    }
+
+    #[test]
+    fn drr_event_driven_sparse_test() {
+        let mut scheduler = DRRScheduler::new(1);
+
+        let mut flow = flow::VariableLengthFlow::new();
+        flow.packet_arrive(Packet::new("1_1", 10000), 0);
+        flow.packet_arrive(Packet::new("1_2", 500), 20000);
+        scheduler.add_flow(flow, 1000);
+
+        let mut flow = flow::VariableLengthFlow::new();
+        flow.packet_arrive(Packet::new("2_1", 2000), 5000);
+        scheduler.add_flow(flow, 1500);
+
+        scheduler.run();
+        let result = scheduler.result();
+        let stats = scheduler.stats();
+
+        scheduler.reset();
+        let steps = scheduler.run_event_driven();
+        assert_eq!(scheduler.result(), result);
+        assert_eq!(scheduler.stats(), stats);
+        assert!(steps * 100 < result.timer);
+    }
+
+    #[test]
+    fn drr_idle_deficit_test() {
+        let mut scheduler = DRRScheduler::new(1);
+
+        let mut flow = flow::VariableLengthFlow::new();
+        flow.packet_arrive(Packet::new("1_1", 2), 0);
+        flow.packet_arrive(Packet::new("1_2", 3), 6);
+        scheduler.add_flow(flow, 2);
+
+        // 1_1 is sent on the first tick and leaves the port on the third.
+        scheduler.tick();
+        assert_eq!(scheduler.deficit_counters[0], 2);
+        scheduler.tick();
+        scheduler.tick();
+
+        // 1_2 has not arrived yet, but the flow is backlogged,
+        // so its deficit keeps accumulating instead of being reset.
+        assert_eq!(scheduler.deficit_counters[0], 4);
+        scheduler.tick();
+        assert_eq!(scheduler.deficit_counters[0], 6);
+
+        scheduler.run();
+        assert_eq!(scheduler.timer(), 6);
+        assert_eq!(
+            scheduler.output(),
+            &[Packet::new("1_1", 2), Packet::new("1_2", 3)]
+        );
+    }
+
+    #[test]
+    fn drr_close_flow_test() {
+        let mut scheduler = DRRScheduler::new(1);
+
+        let mut flow = flow::VariableLengthFlow::new();
+        flow.packet_arrive(Packet::new("1_1", 1), 0);
+        flow.packet_arrive(Packet::new("1_2", 1), 5);
+        scheduler.add_flow(flow, 2);
+
+        let mut flow = flow::VariableLengthFlow::new();
+        for p in 0..6 {
+            flow.packet_arrive(Packet::new(format!("2_{}", p), 1), p);
+        }
+        scheduler.add_flow(flow, 1);
+
+        // 1_1 and 2_0 are sent on the first tick and leave the port
+        // on the third.
+        scheduler.tick();
+        scheduler.close_flow(FlowId(0));
+        scheduler.tick();
+        scheduler.tick();
+
+        // Once drained, the closed flow keeps no deficit,
+        // while the other flow goes on earning its quantum.
+        for _ in 0..3 {
+            scheduler.tick();
+            assert_eq!(scheduler.deficit_counters[0], 0);
+        }
+        assert!(scheduler.deficit_counters[1] > 0);
+
+        scheduler.run();
+        assert_eq!(scheduler.output().len(), 7);
+        assert!(scheduler.output().iter().all(|p| p.name != "1_2"));
+    }
+
+    #[test]
+    fn drr_mixed_flows_test() {
+        let mut scheduler = DRRScheduler::new(1);
+
+        let mut flow = flow::FixedLengthFlow::new(2);
+        flow.add_packet("1_1", 0);
+        flow.add_packet("1_2", 0);
+        scheduler.add_flow(flow, 2);
+
+        let mut flow = flow::VariableLengthFlow::new();
+        flow.packet_arrive(Packet::new("2_1", 1), 0);
+        flow.packet_arrive(Packet::new("2_2", 3), 0);
+        scheduler.add_flow(flow, 2);
+
+        scheduler.run();
+
+        assert_eq!(
+            scheduler.output(),
+            &[
+                Packet::new("1_1", 2),
+                Packet::new("2_1", 1),
+                Packet::new("1_2", 2),
+                Packet::new("2_2", 3),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "positive quantum")]
+    fn drr_zero_weight_test() {
+        let mut scheduler = DRRScheduler::new(1);
+        scheduler.add_flow(flow::VariableLengthFlow::new(), 0);
     }
 }
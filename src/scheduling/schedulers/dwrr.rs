@@ -0,0 +1,315 @@
+use std::collections::VecDeque;
+
+use crate::scheduling::{
+    flow::Flow,
+    schedulers::base::SchedulerBase,
+    source::SchedulableSource,
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// Quantum given to the flows added through [`Scheduler::add_flow`].
+pub const DEFAULT_QUANTUM: usize = 1;
+
+/// Deficit Weighted Round Robin (DWRR) scheduler,
+/// following Shreedhar and Varghese.
+///
+/// Flows with an arrived packet wait in an active list. The flow at the
+/// head of the list is visited: its deficit grows by `weight * quantum`,
+/// it sends head packets while they fit in the deficit, and it goes to the
+/// tail of the list, or leaves it with a zero deficit once it has nothing
+/// left to send. Only the visited flow is replenished.
+///
+/// The scheduler is also a [`SchedulableSource`], handing out the packets
+/// of its flows in DWRR order to a parent scheduler.
+#[derive(Debug, Clone)]
+pub struct DWRRScheduler {
+    base: SchedulerBase,
+    weights: Vec<usize>,
+    quanta: Vec<usize>,
+    deficit_counters: Vec<usize>,
+    /// Flows with an arrived packet, in visiting order.
+    active_list: VecDeque<usize>,
+    active: Vec<bool>,
+    /// The flow being visited.
+    visiting: Option<usize>,
+}
+
+impl DWRRScheduler {
+    pub fn new(bandwidth: usize) -> DWRRScheduler {
+        DWRRScheduler {
+            base: SchedulerBase::new(bandwidth),
+            weights: Vec::new(),
+            quanta: Vec::new(),
+            deficit_counters: Vec::new(),
+            active_list: VecDeque::new(),
+            active: Vec::new(),
+            visiting: None,
+        }
+    }
+
+    /// Add a flow whose deficit grows by `weight * quantum` bytes per visit.
+    pub fn add_flow(&mut self, flow: impl Flow + 'static, weight: usize, quantum: usize) -> FlowId {
+        self.push_flow(Box::new(flow), weight, quantum)
+    }
+
+    fn push_flow(&mut self, flow: Box<dyn Flow>, weight: usize, quantum: usize) -> FlowId {
+        assert!(weight * quantum > 0, "a flow must get a positive quantum");
+        self.weights.push(weight);
+        self.quanta.push(quantum);
+        self.deficit_counters.push(0);
+        self.active.push(false);
+        self.base.add_flow(flow)
+    }
+}
+
+impl Scheduler for DWRRScheduler {
+    /// Add a flow with the default quantum and a weight
+    /// rounded to the nearest integer.
+    fn add_flow(&mut self, flow: Box<dyn Flow>, weight: f64) -> FlowId {
+        self.push_flow(flow, weight.round() as usize, DEFAULT_QUANTUM)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.deficit_counters.fill(0);
+        self.active_list.clear();
+        self.active.fill(false);
+        self.visiting = None;
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn flow_state(&self) -> Option<(&'static str, Vec<f64>)> {
+        Some((
+            "deficit",
+            self.deficit_counters.iter().map(|&d| d as f64).collect(),
+        ))
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
+    }
+}
+
+impl Tickable for DWRRScheduler {
+    fn tick(&mut self) -> bool {
+        if self.base.all_empty() {
+            return false;
+        }
+
+        if self.base.output_port.is_accepting()
+            && SchedulableSource::peek_packet(self, self.base.timer).is_some()
+        {
+            let idx = self.visiting.unwrap();
+            let (packet, arrive_time) = self.dequeue();
+            self.base.submit(idx, packet, arrive_time);
+        }
+
+        self.base.record_queues();
+        self.base.advance();
+
+        true
+    }
+}
+
+impl Schedulable<Option<usize>> for DWRRScheduler {
+    /// Enroll the newly backlogged flows at the tail of the active list,
+    /// then take the flow at its head and replenish its deficit.
+    fn schedule(&mut self) -> Option<usize> {
+        for idx in 0..self.base.flows.len() {
+            if !self.active[idx] && self.base.flows[idx].peek_packet(self.base.timer).is_some() {
+                self.active[idx] = true;
+                self.active_list.push_back(idx);
+            }
+        }
+
+        let idx = self.active_list.pop_front()?;
+        self.deficit_counters[idx] += self.weights[idx] * self.quanta[idx];
+        Some(idx)
+    }
+}
+
+impl SchedulableSource for DWRRScheduler {
+    /// Continue the visit of the current flow while its head packet fits
+    /// in its deficit, otherwise move on to the next flow of the active list.
+    /// Visits take no time, so flows are visited until a packet is selected
+    /// or no flow is left in the active list.
+    fn peek_packet(&mut self, time: usize) -> Option<Packet> {
+        self.base.timer = time;
+        loop {
+            if let Some(idx) = self.visiting {
+                match self.base.flows[idx].peek_packet(time) {
+                    Some(packet) if packet.len <= self.deficit_counters[idx] => {
+                        return Some(packet);
+                    }
+                    Some(_) => self.active_list.push_back(idx),
+                    None => {
+                        self.deficit_counters[idx] = 0;
+                        self.active[idx] = false;
+                    }
+                }
+                self.visiting = None;
+            }
+            self.visiting = Some(self.schedule()?);
+        }
+    }
+
+    fn dequeue(&mut self) -> (Packet, usize) {
+        let idx = self.visiting.expect("no packet was selected");
+        let arrive_time = self.base.flows[idx].next_arrival().unwrap();
+        let packet = self.base.flows[idx].pop_packet();
+        self.deficit_counters[idx] -= packet.len;
+        // A flow with nothing left to send ends its visit at once
+        // and forgets its deficit.
+        if self.base.flows[idx].peek_packet(self.base.timer).is_none() {
+            self.deficit_counters[idx] = 0;
+            self.active[idx] = false;
+            self.visiting = None;
+        }
+        (packet, arrive_time)
+    }
+
+    fn next_arrival(&self) -> Option<usize> {
+        self.base
+            .flows
+            .iter()
+            .filter_map(|f| f.next_arrival())
+            .min()
+    }
+
+    fn queue_len(&self, time: usize) -> usize {
+        self.base.flows.iter().map(|f| f.queue_len(time)).sum()
+    }
+
+    fn empty(&self) -> bool {
+        self.base.all_empty()
+    }
+
+    /// The packets dropped in the queues of the flows.
+    fn dropped_count(&self) -> usize {
+        self.base.flows.iter().map(|f| f.dropped_count()).sum()
+    }
+
+    /// Close every flow of the child scheduler.
+    fn close(&mut self, time: usize) {
+        self.base.flows.iter_mut().for_each(|f| f.close(time));
+    }
+
+    fn retired(&self) -> bool {
+        self.base.flows.iter().all(|f| f.retired())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        Packet, Scheduler, Tickable,
+    };
+
+    use super::DWRRScheduler;
+
+    fn flows() -> (VariableLengthFlow, VariableLengthFlow) {
+        let mut a = VariableLengthFlow::new();
+        a.packet_arrive(Packet::new("a1", 3), 0);
+        a.packet_arrive(Packet::new("a2", 1), 0);
+
+        let mut b = VariableLengthFlow::new();
+        b.packet_arrive(Packet::new("b1", 1), 0);
+        b.packet_arrive(Packet::new("b2", 1), 0);
+        b.packet_arrive(Packet::new("b3", 1), 0);
+        (a, b)
+    }
+
+    #[test]
+    fn dwrr_test() {
+        let mut scheduler = DWRRScheduler::new(1);
+        let (a, b) = flows();
+        scheduler.add_flow(a, 1, 2);
+        scheduler.add_flow(b, 1, 2);
+
+        // a1 does not fit in the first quantum, so a is skipped
+        // while b is visited and sends b1.
+        scheduler.tick();
+        assert_eq!(scheduler.deficit_counters, vec![2, 1]);
+        assert_eq!(scheduler.active_list, [0]);
+        assert_eq!(scheduler.visiting, Some(1));
+
+        scheduler.run();
+        assert_eq!(
+            scheduler.output(),
+            &[
+                Packet::new("b1", 1),
+                Packet::new("b2", 1),
+                Packet::new("a1", 3),
+                Packet::new("a2", 1),
+                Packet::new("b3", 1),
+            ]
+        );
+        // Drained flows leave the active list with nothing saved.
+        assert!(scheduler.active_list.is_empty());
+        assert_eq!(scheduler.deficit_counters, vec![0, 0]);
+    }
+
+    #[test]
+    fn dwrr_weight_test() {
+        let mut scheduler = DWRRScheduler::new(1);
+        let (a, b) = flows();
+        // Doubling the weight of a lets a1 and a2 through on the first visit.
+        scheduler.add_flow(a, 2, 2);
+        scheduler.add_flow(b, 1, 2);
+
+        scheduler.run();
+        let names: Vec<&str> = scheduler.output().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["a1", "a2", "b1", "b2", "b3"]);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::scheduling::{
+    flow::Flow,
+    schedulers::{
+        base::SchedulerBase,
+        tie_break::{TieBreak, TieBreaker},
+    },
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// Earliest Deadline First (EDF) scheduler.
+///
+/// Every flow has a delay budget, and a packet is due by its arrival time
+/// plus the budget of its flow. Whenever the link is free, the arrived
+/// packet with the earliest deadline is served, ties going to the flow
+/// added first. Packets leaving the port after their deadline are
+/// counted as deadline misses in the statistics.
+#[derive(Clone)]
+pub struct EDFScheduler {
+    base: SchedulerBase,
+    /// Delay budget of each flow, in ticks.
+    budgets: Vec<usize>,
+    tie_break: TieBreaker,
+}
+
+impl EDFScheduler {
+    pub fn new(bandwidth: usize) -> EDFScheduler {
+        EDFScheduler {
+            base: SchedulerBase::new(bandwidth),
+            budgets: Vec::new(),
+            tie_break: TieBreaker::default(),
+        }
+    }
+
+    /// Add a flow whose packets are due `budget` ticks after they arrive.
+    pub fn add_flow(&mut self, flow: impl Flow + 'static, budget: usize) -> FlowId {
+        Scheduler::add_flow(self, Box::new(flow), budget as f64)
+    }
+
+    /// Choose how flows with equal deadlines are ordered.
+    pub fn set_tie_break(&mut self, tie_break: TieBreak) {
+        self.tie_break = TieBreaker::new(tie_break);
+    }
+}
+
+impl Scheduler for EDFScheduler {
+    /// Add a flow with the weight used as its delay budget.
+    fn add_flow(&mut self, flow: Box<dyn Flow>, weight: f64) -> FlowId {
+        self.budgets.push(weight.round() as usize);
+        self.base.add_flow(flow)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.tie_break.reset();
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        let mut stats = self.base.stats();
+        stats.count_deadline_misses(&self.budgets);
+        stats
+    }
+}
+
+impl Tickable for EDFScheduler {
+    fn tick(&mut self) -> bool {
+        if self.base.all_empty() {
+            return false;
+        }
+
+        // Decide only when the port takes a packet, so that an urgent
+        // packet arriving meanwhile overtakes the waiting packets.
+        if self.base.output_port.is_accepting() {
+            if let Some(idx) = self.schedule() {
+                self.base.serve(idx);
+            }
+        }
+
+        self.base.record_queues();
+        self.base.advance();
+
+        true
+    }
+}
+
+impl Schedulable<Option<usize>> for EDFScheduler {
+    /// Return the index of the flow whose arrived head packet
+    /// has the earliest deadline.
+    fn schedule(&mut self) -> Option<usize> {
+        let mut deadlines = Vec::new();
+        for (idx, flow) in self.base.flows.iter().enumerate() {
+            if flow.peek_packet(self.base.timer).is_none() {
+                continue;
+            }
+            let deadline = flow.next_arrival().unwrap() + self.budgets[idx];
+            deadlines.push((idx, deadline as f64));
+        }
+        let (flows, timer) = (&self.base.flows, self.base.timer);
+        self.tie_break
+            .pick(deadlines, |idx| flows[idx].queue_len(timer))
+            .map(|(idx, _)| idx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        Packet, Scheduler,
+    };
+
+    use super::EDFScheduler;
+
+    #[test]
+    fn edf_test() {
+        let mut edf = EDFScheduler::new(1);
+
+        let mut bulk = VariableLengthFlow::new();
+        bulk.packet_arrive(Packet::new("a1", 2), 0);
+        bulk.packet_arrive(Packet::new("a2", 2), 0);
+        edf.add_flow(bulk, 10);
+
+        let mut realtime = VariableLengthFlow::new();
+        realtime.packet_arrive(Packet::new("b1", 1), 1);
+        realtime.packet_arrive(Packet::new("b2", 1), 1);
+        edf.add_flow(realtime, 2);
+
+        edf.run();
+
+        // The real-time packets overtake a2 once a1 is sent,
+        // but b2 still leaves one tick after its deadline.
+        assert_eq!(
+            edf.output(),
+            &[
+                Packet::new("a1", 2),
+                Packet::new("b1", 1),
+                Packet::new("b2", 1),
+                Packet::new("a2", 2),
+            ]
+        );
+        let stats = edf.stats();
+        assert_eq!(stats[0].deadline_misses, 0);
+        assert_eq!(stats[1].deadline_misses, 1);
+        assert_eq!(stats[1].max_delay, 3);
+    }
+}
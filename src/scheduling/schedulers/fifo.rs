@@ -0,0 +1,265 @@
+use crate::scheduling::{
+    engine::{Dequeue, EventScheduler},
+    flow::Flow,
+    schedulers::base::SchedulerBase,
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// First-In First-Out (FIFO) scheduler.
+///
+/// Merges the packets of all flows and serves them in order of arrival,
+/// breaking ties by flow index.
+///
+/// FIFO has no decision to delay, so every arrived packet joins the port
+/// queue at once, whatever its transmit credit, and the drop policies of
+/// the port act as the drop policy of the queue.
+#[derive(Clone)]
+pub struct FIFOScheduler {
+    base: SchedulerBase,
+}
+
+impl FIFOScheduler {
+    pub fn new(bandwidth: usize) -> FIFOScheduler {
+        FIFOScheduler {
+            base: SchedulerBase::new(bandwidth),
+        }
+    }
+
+    pub fn add_flow(&mut self, flow: impl Flow + 'static) -> FlowId {
+        Scheduler::add_flow(self, Box::new(flow), 1f64)
+    }
+}
+
+impl Scheduler for FIFOScheduler {
+    fn add_flow(&mut self, flow: Box<dyn Flow>, _weight: f64) -> FlowId {
+        self.base.add_flow(flow)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
+    }
+}
+
+impl Tickable for FIFOScheduler {
+    fn tick(&mut self) -> bool {
+        if self.base.all_empty() {
+            return false;
+        }
+
+        // Everything that has arrived joins the port queue in arrival order.
+        while let Some(idx) = self.schedule() {
+            self.base.serve(idx);
+        }
+
+        self.base.record_queues();
+        self.base.advance();
+
+        true
+    }
+}
+
+impl EventScheduler for FIFOScheduler {
+    fn enqueue(&mut self, flow_idx: usize, packet: Packet, time: f64) {
+        self.base.flows[flow_idx].packet_arrive(packet, time as usize);
+    }
+
+    fn dequeue(&mut self, time: f64) -> Dequeue {
+        self.base.timer = time as usize;
+        match self.schedule() {
+            Some(idx) => Dequeue::Packet(idx, self.base.flows[idx].pop_packet()),
+            None => Dequeue::Idle,
+        }
+    }
+}
+
+impl Schedulable<Option<usize>> for FIFOScheduler {
+    /// Return the index of the flow whose head packet arrived first,
+    /// or None if no packet has arrived yet.
+    fn schedule(&mut self) -> Option<usize> {
+        let mut earliest: Option<(usize, usize)> = None;
+        for (idx, flow) in self.base.flows.iter().enumerate() {
+            if let Some(arrive_time) = flow.next_arrival() {
+                if arrive_time > self.base.timer {
+                    continue;
+                }
+                if earliest.is_none_or(|(_, time)| arrive_time < time) {
+                    earliest = Some((idx, arrive_time));
+                }
+            }
+        }
+        earliest.map(|(idx, _)| idx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        FrameClass, FramePreemption, Packet, QueueService, Scheduler,
+    };
+
+    use super::FIFOScheduler;
+
+    #[test]
+    fn fifo_test() {
+        let mut fifo = FIFOScheduler::new(1);
+
+        let mut flow1 = VariableLengthFlow::new();
+        flow1.packet_arrive(Packet::new("p1", 2), 0);
+        flow1.packet_arrive(Packet::new("p3", 1), 2);
+        flow1.packet_arrive(Packet::new("p6", 1), 7);
+        fifo.add_flow(flow1);
+
+        let mut flow2 = VariableLengthFlow::new();
+        flow2.packet_arrive(Packet::new("p2", 3), 1);
+        flow2.packet_arrive(Packet::new("p4", 1), 3);
+        flow2.packet_arrive(Packet::new("p5", 2), 4);
+        fifo.add_flow(flow2);
+
+        fifo.run();
+
+        assert_eq!(
+            fifo.output(),
+            &[
+                Packet::new("p1", 2),
+                Packet::new("p2", 3),
+                Packet::new("p3", 1),
+                Packet::new("p4", 1),
+                Packet::new("p5", 2),
+                Packet::new("p6", 1),
+            ]
+        );
+        assert_eq!(fifo.timer(), 8);
+    }
+
+    #[test]
+    fn fifo_port_queues_test() {
+        let mut fifo = FIFOScheduler::new(1);
+        fifo.get_output_port().set_queues(2, QueueService::Strict);
+        fifo.get_output_port().set_tx_credit(10);
+
+        let mut flow1 = VariableLengthFlow::new();
+        flow1.packet_arrive(Packet::new("a0", 5), 0);
+        flow1.packet_arrive(Packet::new("a1", 5), 0);
+        let a = fifo.add_flow(flow1);
+
+        let mut flow2 = VariableLengthFlow::new();
+        flow2.packet_arrive(Packet::new("b0", 5).with_priority(1), 1);
+        let b = fifo.add_flow(flow2);
+
+        fifo.run();
+
+        // The urgent packet overtakes a1 in the port, and every packet
+        // keeps its own flow and arrival time.
+        let records: Vec<_> = fifo
+            .scheduler_stats()
+            .packets
+            .into_iter()
+            .map(|r| (r.name, r.flow, r.arrival, r.departure))
+            .collect();
+        assert_eq!(
+            records,
+            vec![
+                ("a0".to_string(), a, 0, 5),
+                ("b0".to_string(), b, 1, 10),
+                ("a1".to_string(), a, 0, 15),
+            ]
+        );
+        let result = fifo.result();
+        assert_eq!(result.flows, vec![a, b, a]);
+        assert_eq!(result.departure_times, vec![5, 10, 15]);
+    }
+
+    #[test]
+    fn fifo_frame_preemption_test() {
+        let mut fifo = FIFOScheduler::new(1);
+        let port = fifo.get_output_port();
+        port.set_byte_accurate(true);
+        port.set_frame_preemption(Some(FramePreemption {
+            min_fragment: 1,
+            overhead: 0,
+        }));
+        port.set_tx_credit(20);
+
+        let mut flow1 = VariableLengthFlow::new();
+        flow1.packet_arrive(
+            Packet::new("a0", 10).with_frame_class(FrameClass::Preemptable),
+            0,
+        );
+        let a = fifo.add_flow(flow1);
+
+        let mut flow2 = VariableLengthFlow::new();
+        flow2.packet_arrive(Packet::new("b0", 1), 3);
+        let b = fifo.add_flow(flow2);
+
+        fifo.run();
+
+        // The express frame suspends a0, which still counts as a0.
+        let records: Vec<_> = fifo
+            .scheduler_stats()
+            .packets
+            .into_iter()
+            .map(|r| (r.name, r.flow, r.arrival, r.departure))
+            .collect();
+        assert_eq!(
+            records,
+            vec![("b0".to_string(), b, 3, 4), ("a0".to_string(), a, 0, 11),]
+        );
+        assert_eq!(fifo.get_output_port().preempted_count(), 1);
+    }
+}
@@ -0,0 +1,402 @@
+use std::{
+    collections::VecDeque,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use crate::scheduling::{
+    aqm::{codel::Stamped, CoDel},
+    flow::Flow,
+    schedulers::base::SchedulerBase,
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// Default number of queues flows are hashed into.
+pub const DEFAULT_FQ_CODEL_QUEUES: usize = 1024;
+
+/// Default number of bytes a queue may send per round.
+pub const DEFAULT_FQ_CODEL_QUANTUM: usize = 1;
+
+/// Default number of packets held across all queues.
+pub const DEFAULT_FQ_CODEL_LIMIT: usize = 10240;
+
+/// A packet waiting in a queue of the scheduler.
+#[derive(Debug, Clone)]
+struct Enqueued {
+    packet: Packet,
+    flow_idx: usize,
+    arrive_time: usize,
+    enqueue_time: usize,
+}
+
+impl Stamped for Enqueued {
+    fn packet(&self) -> &Packet {
+        &self.packet
+    }
+
+    fn packet_mut(&mut self) -> &mut Packet {
+        &mut self.packet
+    }
+
+    fn enqueue_time(&self) -> usize {
+        self.enqueue_time
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Queue {
+    packets: VecDeque<Enqueued>,
+    bytes: usize,
+    codel: CoDel,
+    deficit: isize,
+    /// Whether the queue is in the list of new or old queues.
+    listed: bool,
+}
+
+/// Flow Queue CoDel (FQ-CoDel) scheduler, as in RFC 8290.
+///
+/// Arriving packets are stamped and hashed by flow into one of many
+/// queues, each managed by its own [`CoDel`]. The queues are served by
+/// deficit round robin, with queues that just became active served before
+/// the ones that stayed backlogged. When the packets held across all
+/// queues exceed the limit, the longest queue drops its head packet.
+///
+/// With a single queue, this is a plain CoDel queue.
+pub struct FQCoDelScheduler {
+    base: SchedulerBase,
+    queues: Vec<Queue>,
+    /// The CoDel state every queue starts with.
+    codel: CoDel,
+    quantum: usize,
+    limit: usize,
+    /// Seed mixed into the flow hash.
+    perturbation: u64,
+    new_queues: VecDeque<usize>,
+    old_queues: VecDeque<usize>,
+    queued: usize,
+}
+
+impl FQCoDelScheduler {
+    pub fn new(bandwidth: usize) -> FQCoDelScheduler {
+        let mut scheduler = FQCoDelScheduler {
+            base: SchedulerBase::new(bandwidth),
+            queues: Vec::new(),
+            codel: CoDel::default(),
+            quantum: DEFAULT_FQ_CODEL_QUANTUM,
+            limit: DEFAULT_FQ_CODEL_LIMIT,
+            perturbation: 0,
+            new_queues: VecDeque::new(),
+            old_queues: VecDeque::new(),
+            queued: 0,
+        };
+        scheduler.set_queue_count(DEFAULT_FQ_CODEL_QUEUES);
+        scheduler
+    }
+
+    /// Hash the flows into `count` queues, dropping what is queued.
+    pub fn set_queue_count(&mut self, count: usize) {
+        assert!(count > 0, "FQ-CoDel needs at least one queue");
+        let queue = Queue {
+            packets: VecDeque::new(),
+            bytes: 0,
+            codel: self.codel.clone(),
+            deficit: 0,
+            listed: false,
+        };
+        self.queues = vec![queue; count];
+        self.new_queues.clear();
+        self.old_queues.clear();
+        self.queued = 0;
+    }
+
+    /// Set the CoDel parameters of every queue, dropping what is queued.
+    pub fn set_codel(&mut self, target: usize, interval: usize) {
+        self.codel = CoDel::new(target, interval);
+        self.set_queue_count(self.queues.len());
+    }
+
+    /// Set the number of bytes a queue may send per round.
+    pub fn set_quantum(&mut self, quantum: usize) {
+        assert!(quantum > 0, "the quantum must be positive");
+        self.quantum = quantum;
+    }
+
+    /// Set the number of packets held across all queues.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
+    /// Change the flow hash, which decides which flows share a queue.
+    pub fn set_perturbation(&mut self, perturbation: u64) {
+        self.perturbation = perturbation;
+    }
+
+    pub fn add_flow(&mut self, flow: impl Flow + 'static) -> FlowId {
+        Scheduler::add_flow(self, Box::new(flow), 1f64)
+    }
+
+    /// The queue the packets of a flow go to.
+    pub fn queue_of(&self, flow: FlowId) -> usize {
+        let mut hasher = DefaultHasher::new();
+        (self.perturbation, flow.index()).hash(&mut hasher);
+        (hasher.finish() % self.queues.len() as u64) as usize
+    }
+
+    /// Stamp an arrived packet and put it in the queue of its flow.
+    fn enqueue(&mut self, flow_idx: usize, packet: Packet, arrive_time: usize) {
+        let idx = self.queue_of(FlowId(flow_idx));
+        let queue = &mut self.queues[idx];
+        queue.bytes += packet.len;
+        queue.packets.push_back(Enqueued {
+            packet,
+            flow_idx,
+            arrive_time,
+            enqueue_time: self.base.timer,
+        });
+        if !queue.listed {
+            queue.listed = true;
+            queue.deficit = self.quantum as isize;
+            self.new_queues.push_back(idx);
+        }
+
+        self.queued += 1;
+        if self.queued > self.limit {
+            let fattest = (0..self.queues.len())
+                .max_by_key(|&i| self.queues[i].bytes)
+                .unwrap();
+            let queue = &mut self.queues[fattest];
+            let head = queue.packets.pop_front().unwrap();
+            queue.bytes -= head.packet.len;
+            self.queued -= 1;
+            self.base.drops.record(
+                head.flow_idx,
+                &head.packet,
+                head.arrive_time,
+                self.base.timer,
+            );
+        }
+    }
+}
+
+impl Scheduler for FQCoDelScheduler {
+    /// Add a flow. FQ-CoDel has no weights, so the weight is ignored.
+    fn add_flow(&mut self, flow: Box<dyn Flow>, _weight: f64) -> FlowId {
+        self.base.add_flow(flow)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.set_queue_count(self.queues.len());
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
+    }
+}
+
+impl Tickable for FQCoDelScheduler {
+    fn tick(&mut self) -> bool {
+        if self.queued == 0 && self.base.all_empty() {
+            return false;
+        }
+
+        for idx in 0..self.base.flows.len() {
+            while self.base.flows[idx].peek_packet(self.base.timer).is_some() {
+                let arrive_time = self.base.flows[idx].next_arrival().unwrap();
+                let packet = self.base.flows[idx].pop_packet();
+                self.enqueue(idx, packet, arrive_time);
+            }
+        }
+
+        if self.base.output_port.is_accepting() {
+            if let Some(entry) = self.schedule() {
+                let idx = entry.flow_idx;
+                self.base.submit(idx, entry.packet, entry.arrive_time);
+            }
+        }
+
+        // The packets of a flow wait in the queue it is hashed into.
+        if self.base.queue_series.is_due(self.base.timer) {
+            let mut lens = vec![0; self.base.flows.len()];
+            for entry in self.queues.iter().flat_map(|q| &q.packets) {
+                lens[entry.flow_idx] += 1;
+            }
+            let port = self.base.output_port.queue_len();
+            self.base.queue_series.record(self.base.timer, port, lens);
+        }
+
+        self.base.advance();
+
+        true
+    }
+}
+
+impl Schedulable<Option<Enqueued>> for FQCoDelScheduler {
+    /// Take the next packet in deficit round robin order, new queues first,
+    /// letting CoDel drop packets of the visited queue on the way.
+    fn schedule(&mut self) -> Option<Enqueued> {
+        loop {
+            let (idx, from_new) = match self.new_queues.front() {
+                Some(&idx) => (idx, true),
+                None => (*self.old_queues.front()?, false),
+            };
+            let list = if from_new {
+                &mut self.new_queues
+            } else {
+                &mut self.old_queues
+            };
+
+            let queue = &mut self.queues[idx];
+            if queue.deficit <= 0 {
+                queue.deficit += self.quantum as isize;
+                list.pop_front();
+                self.old_queues.push_back(idx);
+                continue;
+            }
+
+            let before = queue.packets.len();
+            let mut dropped = Vec::new();
+            let head = queue
+                .codel
+                .dequeue(&mut queue.packets, self.base.timer, &mut dropped);
+            self.queued -= before - queue.packets.len();
+            for entry in dropped {
+                queue.bytes -= entry.packet.len;
+                self.base.drops.record(
+                    entry.flow_idx,
+                    &entry.packet,
+                    entry.arrive_time,
+                    self.base.timer,
+                );
+            }
+
+            let Some(head) = head else {
+                list.pop_front();
+                // A new queue that empties goes through the old queues once,
+                // so that it cannot regain priority by emptying at once.
+                if from_new && !self.old_queues.is_empty() {
+                    self.old_queues.push_back(idx);
+                } else {
+                    queue.listed = false;
+                }
+                continue;
+            };
+            queue.bytes -= head.packet.len;
+            queue.deficit -= head.packet.len as isize;
+            return Some(head);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        schedulers::fifo::FIFOScheduler,
+        FlowId, Packet, Scheduler,
+    };
+
+    use super::FQCoDelScheduler;
+
+    fn flow(prefix: &str, times: impl Iterator<Item = usize>) -> VariableLengthFlow {
+        let mut flow = VariableLengthFlow::new();
+        for t in times {
+            flow.packet_arrive(Packet::new(format!("{}{}", prefix, t), 1), t);
+        }
+        flow
+    }
+
+    #[test]
+    fn fq_codel_isolation_test() {
+        // A bulk flow offering twice the link rate and a sparse flow.
+        let bulk = || flow("bulk", (0..400).flat_map(|t| [t, t]));
+        let sparse = || flow("sparse", (0..400).step_by(10));
+
+        let mut fq_codel = FQCoDelScheduler::new(1);
+        fq_codel.set_codel(5, 20);
+        fq_codel.add_flow(bulk());
+        fq_codel.add_flow(sparse());
+        assert_ne!(fq_codel.queue_of(FlowId(0)), fq_codel.queue_of(FlowId(1)));
+        fq_codel.run();
+
+        // The sparse flow is served within a round of its arrival
+        // and loses nothing, while CoDel drops from the bulk flow.
+        let stats = fq_codel.stats();
+        assert_eq!(fq_codel.dropped_count(FlowId(1)), 0);
+        assert!(stats[1].max_delay <= 2);
+        assert!(fq_codel.dropped_count(FlowId(0)) > 250);
+
+        // In a single FIFO queue, the sparse flow waits behind the backlog
+        // of the bulk flow, which grows without bound.
+        let mut fifo = FIFOScheduler::new(1);
+        fifo.add_flow(bulk());
+        fifo.add_flow(sparse());
+        fifo.run();
+        let fifo_stats = fifo.stats();
+        assert!(fifo_stats[1].max_delay > 300);
+        assert!(fifo_stats[0].max_delay > 2 * stats[0].max_delay);
+    }
+
+    #[test]
+    fn fq_codel_single_queue_test() {
+        // With one queue and no congestion it is FIFO.
+        let mut fq_codel = FQCoDelScheduler::new(1);
+        fq_codel.set_queue_count(1);
+        fq_codel.add_flow(flow("a", [0, 1, 4].into_iter()));
+        fq_codel.add_flow(flow("b", [0, 2].into_iter()));
+        fq_codel.run();
+
+        let names: Vec<&str> = fq_codel.output().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["a0", "b0", "a1", "b2", "a4"]);
+    }
+}
@@ -0,0 +1,350 @@
+use crate::scheduling::{
+    flow::Flow,
+    schedulers::base::SchedulerBase,
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// Handle of a class of an [`HTBScheduler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct HTBClass(usize);
+
+#[derive(Debug, Clone)]
+struct ClassState {
+    parent: Option<usize>,
+    /// Guaranteed rate, in bytes per tick.
+    rate: usize,
+    /// Rate the class may reach by borrowing, in bytes per tick.
+    ceil: usize,
+    /// Size of both token buckets.
+    burst: usize,
+    /// Tokens of the guaranteed rate, negative once overdrawn.
+    tokens: isize,
+    /// Tokens of the ceiling rate, negative once overdrawn.
+    ctokens: isize,
+    has_children: bool,
+    /// Indices of the flows of a leaf class.
+    flows: Vec<usize>,
+    /// Position in `flows` of the flow to visit first.
+    next_flow: usize,
+}
+
+/// Hierarchical Token Bucket (HTB) scheduler, in the spirit of Linux tc-htb.
+///
+/// Classes form a tree and flows are attached to the leaves. Every class
+/// has a guaranteed rate and a ceiling, each backed by a token bucket.
+/// A leaf sends on its own rate while it has tokens, and beyond that
+/// borrows the spare rate of the closest ancestor that has tokens, as long
+/// as no class on the way has reached its ceiling. Sending a packet charges
+/// the buckets of the leaf and of all its ancestors.
+pub struct HTBScheduler {
+    base: SchedulerBase,
+    bandwidth: usize,
+    classes: Vec<ClassState>,
+    /// The classes as they were set up, restored by `reset`.
+    initial_classes: Vec<ClassState>,
+    /// Leaf class of each flow.
+    flow_classes: Vec<usize>,
+    /// Class to visit first among leaves borrowing at the same depth.
+    next_class: usize,
+}
+
+impl HTBScheduler {
+    pub fn new(bandwidth: usize) -> HTBScheduler {
+        HTBScheduler {
+            base: SchedulerBase::new(bandwidth),
+            bandwidth,
+            classes: Vec::new(),
+            initial_classes: Vec::new(),
+            flow_classes: Vec::new(),
+            next_class: 0,
+        }
+    }
+
+    /// Add a class under `parent`, or at the top of the tree,
+    /// with a guaranteed rate and a ceiling in bytes per tick.
+    /// Both buckets hold one tick of the ceiling rate,
+    /// see [`HTBScheduler::set_burst`].
+    pub fn add_class(&mut self, parent: Option<HTBClass>, rate: usize, ceil: usize) -> HTBClass {
+        assert!(ceil >= rate, "the ceiling of a class is below its rate");
+        if let Some(parent) = parent {
+            assert!(
+                self.classes[parent.0].flows.is_empty(),
+                "flows are only attached to leaf classes"
+            );
+            self.classes[parent.0].has_children = true;
+            self.initial_classes[parent.0].has_children = true;
+        }
+        let class = ClassState {
+            parent: parent.map(|p| p.0),
+            rate,
+            ceil,
+            burst: ceil,
+            tokens: ceil as isize,
+            ctokens: ceil as isize,
+            has_children: false,
+            flows: Vec::new(),
+            next_flow: 0,
+        };
+        self.initial_classes.push(class.clone());
+        self.classes.push(class);
+        HTBClass(self.classes.len() - 1)
+    }
+
+    /// Set the size of both token buckets of a class, which start full.
+    pub fn set_burst(&mut self, class: HTBClass, burst: usize) {
+        for classes in [&mut self.classes, &mut self.initial_classes] {
+            let class = &mut classes[class.0];
+            class.burst = burst;
+            class.tokens = burst as isize;
+            class.ctokens = burst as isize;
+        }
+    }
+
+    /// Attach a flow to a leaf class.
+    /// Flows of the same class are served in turn.
+    pub fn add_flow_to(&mut self, class: HTBClass, flow: impl Flow + 'static) -> FlowId {
+        self.push_flow(class, Box::new(flow))
+    }
+
+    fn push_flow(&mut self, class: HTBClass, flow: Box<dyn Flow>) -> FlowId {
+        assert!(
+            !self.classes[class.0].has_children,
+            "flows are only attached to leaf classes"
+        );
+        let flow_idx = self.base.flows.len();
+        self.classes[class.0].flows.push(flow_idx);
+        self.initial_classes[class.0].flows.push(flow_idx);
+        self.flow_classes.push(class.0);
+        self.base.add_flow(flow)
+    }
+
+    /// The flow of a leaf to serve next, in turn among its arrived flows.
+    fn leaf_flow(&self, class: usize) -> Option<usize> {
+        let class = &self.classes[class];
+        let n = class.flows.len();
+        (0..n)
+            .map(|offset| class.flows[(class.next_flow + offset) % n])
+            .find(|&idx| self.base.flows[idx].peek_packet(self.base.timer).is_some())
+    }
+
+    /// How many levels above a leaf the rate it can send on comes from:
+    /// 0 for its own rate, 1 for its parent and so on.
+    /// None if the leaf cannot send at all.
+    fn borrow_depth(&self, leaf: usize) -> Option<usize> {
+        let mut class = Some(leaf);
+        let mut depth = 0;
+        while let Some(idx) = class {
+            let state = &self.classes[idx];
+            if state.ctokens < 0 {
+                return None;
+            }
+            if state.tokens >= 0 {
+                return Some(depth);
+            }
+            class = state.parent;
+            depth += 1;
+        }
+        None
+    }
+
+    /// Take `len` bytes from the buckets of a class and its ancestors.
+    fn charge(&mut self, leaf: usize, len: usize) {
+        let mut class = Some(leaf);
+        while let Some(idx) = class {
+            let state = &mut self.classes[idx];
+            state.tokens -= len as isize;
+            state.ctokens -= len as isize;
+            class = state.parent;
+        }
+    }
+}
+
+impl Scheduler for HTBScheduler {
+    /// Add a flow in a top-level class of its own,
+    /// with the weight as guaranteed rate and the link rate as ceiling.
+    fn add_flow(&mut self, flow: Box<dyn Flow>, weight: f64) -> FlowId {
+        let rate = (weight.round() as usize).min(self.bandwidth);
+        let class = self.add_class(None, rate, self.bandwidth);
+        self.push_flow(class, flow)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.classes = self.initial_classes.clone();
+        self.next_class = 0;
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
+    }
+}
+
+impl Tickable for HTBScheduler {
+    fn tick(&mut self) -> bool {
+        if self.base.all_empty() {
+            return false;
+        }
+
+        if self.base.output_port.is_accepting() {
+            if let Some(idx) = self.schedule() {
+                let arrive_time = self.base.flows[idx].next_arrival().unwrap();
+                let packet = self.base.flows[idx].pop_packet();
+                self.charge(self.flow_classes[idx], packet.len);
+                self.base.submit(idx, packet, arrive_time);
+            }
+        }
+
+        self.base.record_queues();
+        self.base.advance();
+        for class in &mut self.classes {
+            class.tokens = (class.tokens + class.rate as isize).min(class.burst as isize);
+            class.ctokens = (class.ctokens + class.ceil as isize).min(class.burst as isize);
+        }
+
+        true
+    }
+}
+
+impl Schedulable<Option<usize>> for HTBScheduler {
+    /// Pick the leaf that borrows from the lowest level, in turn among
+    /// leaves at the same level, and return the index of its next flow.
+    fn schedule(&mut self) -> Option<usize> {
+        let n = self.classes.len();
+        let mut best: Option<(usize, usize, usize)> = None;
+        for offset in 0..n {
+            let class = (self.next_class + offset) % n;
+            if self.classes[class].has_children {
+                continue;
+            }
+            let Some(flow_idx) = self.leaf_flow(class) else {
+                continue;
+            };
+            let Some(depth) = self.borrow_depth(class) else {
+                continue;
+            };
+            if best.is_none_or(|(_, _, min)| depth < min) {
+                best = Some((class, flow_idx, depth));
+            }
+        }
+
+        let (class, flow_idx, _) = best?;
+        self.next_class = (class + 1) % n;
+        let leaf = &mut self.classes[class];
+        let pos = leaf.flows.iter().position(|&f| f == flow_idx).unwrap();
+        leaf.next_flow = (pos + 1) % leaf.flows.len();
+        Some(flow_idx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        Packet, Scheduler,
+    };
+
+    use super::HTBScheduler;
+
+    fn flow(prefix: &str, count: usize) -> VariableLengthFlow {
+        let mut flow = VariableLengthFlow::new();
+        for p in 0..count {
+            flow.packet_arrive(Packet::new(format!("{}{}", prefix, p), 4), 0);
+        }
+        flow
+    }
+
+    #[test]
+    fn htb_share_test() {
+        let mut htb = HTBScheduler::new(4);
+        let root = htb.add_class(None, 4, 4);
+        let a = htb.add_class(Some(root), 3, 4);
+        let b = htb.add_class(Some(root), 1, 4);
+        htb.add_flow_to(a, flow("a", 40));
+        htb.add_flow_to(b, flow("b", 40));
+        htb.run();
+
+        // While both are backlogged, each gets its guaranteed rate.
+        let window = &htb.output()[..40];
+        let a_count = window.iter().filter(|p| p.name.starts_with('a')).count();
+        assert!((29..=31).contains(&a_count), "a got {} of 40", a_count);
+        assert_eq!(htb.output().len(), 80);
+    }
+
+    #[test]
+    fn htb_borrow_test() {
+        // Alone, a class borrows the whole link from its parent ...
+        let mut htb = HTBScheduler::new(4);
+        let root = htb.add_class(None, 4, 4);
+        let a = htb.add_class(Some(root), 1, 4);
+        htb.add_class(Some(root), 3, 4);
+        htb.add_flow_to(a, flow("a", 10));
+        htb.run();
+        assert_eq!(htb.timer(), 10);
+
+        // ... but never beyond its ceiling.
+        let mut htb = HTBScheduler::new(4);
+        let root = htb.add_class(None, 4, 4);
+        let a = htb.add_class(Some(root), 1, 2);
+        htb.add_flow_to(a, flow("a", 10));
+        htb.run();
+        // After a burst of two packets from the full bucket,
+        // one packet every two ticks.
+        assert_eq!(htb.timer(), 18);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::scheduling::{
+    flow::Flow,
+    schedulers::base::{FlowQueues, SchedulerBase},
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// Handle of a class of a [`HierarchicalWFQScheduler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct ClassHandle(usize);
+
+/// A class of flows sharing the bandwidth given to the class.
+#[derive(Debug, Clone, Default)]
+struct FlowClass {
+    weight: f64,
+    flows: Vec<Box<dyn Flow>>,
+    flow_weights: Vec<f64>,
+    total_flow_weight: f64,
+    /// Index of each flow of the class among all flows of the scheduler.
+    flow_indices: Vec<usize>,
+    /// Virtual finish time of the last packet served from each flow.
+    flow_finish: Vec<f64>,
+    /// Virtual finish time of the head packet of each flow,
+    /// stamped when the packet becomes eligible.
+    flow_tags: Vec<Option<f64>>,
+    /// Virtual time inside the class.
+    virtual_time: f64,
+    /// Virtual finish time of the last packet served from the class.
+    finish: f64,
+    /// Virtual finish time of the head of the class.
+    tag: Option<f64>,
+}
+
+impl FlowClass {
+    /// Stamp the newly eligible head packets and pick the flow to serve next,
+    /// returning its position in the class and its virtual finish time.
+    fn pick(&mut self, time: usize) -> Option<(usize, f64)> {
+        let mut best: Option<(usize, f64)> = None;
+        for (pos, flow) in self.flows.iter().enumerate() {
+            let Some(packet) = flow.peek_packet(time) else {
+                continue;
+            };
+            let tag = *self.flow_tags[pos].get_or_insert_with(|| {
+                self.flow_finish[pos].max(self.virtual_time)
+                    + estimate_time(packet.len, self.flow_weights[pos], self.total_flow_weight)
+            });
+            if best.is_none_or(|(_, min)| tag < min) {
+                best = Some((pos, tag));
+            }
+        }
+        best
+    }
+}
+
+impl FlowQueues for Vec<FlowClass> {
+    fn all_empty(&self) -> bool {
+        self.iter().all(|c| c.flows.iter().all(|f| f.empty()))
+    }
+
+    fn queue_lens(&self, time: usize) -> Vec<usize> {
+        let mut lens = vec![0; self.iter().map(|c| c.flows.len()).sum()];
+        for class in self {
+            for (&idx, flow) in class.flow_indices.iter().zip(&class.flows) {
+                lens[idx] = flow.queue_len(time);
+            }
+        }
+        lens
+    }
+
+    fn dropped_count(&self, flow_idx: usize) -> usize {
+        self.iter()
+            .flat_map(|c| c.flow_indices.iter().zip(&c.flows))
+            .find(|(&idx, _)| idx == flow_idx)
+            .map_or(0, |(_, flow)| flow.dropped_count())
+    }
+}
+
+/// Transmission time of a packet at the share of bandwidth given by a weight,
+/// as estimated by WFQ.
+fn estimate_time(len: usize, weight: f64, total_weight: f64) -> f64 {
+    let assumed_rate = weight / total_weight;
+    len as f64 / assumed_rate
+}
+
+/// Two-level Weighted Fair Queueing scheduler.
+///
+/// Classes share the bandwidth by class weight, and the flows of a class
+/// share the bandwidth of the class by flow weight. Each level orders
+/// packets by virtual finish time, with the virtual time of a level
+/// following the finish time of the last packet it served.
+pub struct HierarchicalWFQScheduler {
+    /// Keeps the classes, each holding its flows.
+    base: SchedulerBase<Vec<FlowClass>>,
+    total_class_weight: f64,
+    /// Virtual time across classes.
+    virtual_time: f64,
+}
+
+impl HierarchicalWFQScheduler {
+    pub fn new(bandwidth: usize) -> HierarchicalWFQScheduler {
+        HierarchicalWFQScheduler {
+            base: SchedulerBase::new(bandwidth),
+            total_class_weight: 0f64,
+            virtual_time: 0f64,
+        }
+    }
+
+    /// Add a class with a weight and return its handle.
+    pub fn add_class(&mut self, weight: f64) -> ClassHandle {
+        let class = FlowClass {
+            weight,
+            ..FlowClass::default()
+        };
+        self.base.initial_flows.push(class.clone());
+        self.base.flows.push(class);
+        self.total_class_weight += weight;
+        ClassHandle(self.base.flows.len() - 1)
+    }
+
+    /// Add a flow with a weight to a class.
+    pub fn add_flow_to(
+        &mut self,
+        class: ClassHandle,
+        flow: impl Flow + 'static,
+        weight: f64,
+    ) -> FlowId {
+        self.push_flow(class, Box::new(flow), weight)
+    }
+
+    fn push_flow(&mut self, class: ClassHandle, flow: Box<dyn Flow>, weight: f64) -> FlowId {
+        let flow_idx = self.base.flow_count();
+        for classes in [&mut self.base.flows, &mut self.base.initial_flows] {
+            let class = &mut classes[class.0];
+            class.flows.push(flow.clone());
+            class.flow_weights.push(weight);
+            class.total_flow_weight += weight;
+            class.flow_indices.push(flow_idx);
+            class.flow_finish.push(0f64);
+            class.flow_tags.push(None);
+        }
+        self.base.track_flow()
+    }
+
+    /// The class of a flow and its position in the class.
+    fn locate(&self, flow_idx: usize) -> (usize, usize) {
+        self.base
+            .flows
+            .iter()
+            .enumerate()
+            .find_map(|(c, class)| {
+                let pos = class.flow_indices.iter().position(|&idx| idx == flow_idx)?;
+                Some((c, pos))
+            })
+            .expect("no flow with this index")
+    }
+}
+
+impl Scheduler for HierarchicalWFQScheduler {
+    /// Add a flow in a class of its own with the given weight.
+    fn add_flow(&mut self, flow: Box<dyn Flow>, weight: f64) -> FlowId {
+        let class = self.add_class(weight);
+        self.push_flow(class, flow, 1f64)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        let (class, pos) = self.locate(flow.index());
+        self.base.flows[class].flows[pos].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        let (class, pos) = self.locate(flow.index());
+        self.base.flows[class].flows[pos].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.virtual_time = 0f64;
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
+    }
+}
+
+impl Tickable for HierarchicalWFQScheduler {
+    fn tick(&mut self) -> bool {
+        if self.base.all_empty() {
+            return false;
+        }
+
+        if self.base.output_port.is_accepting() {
+            if let Some((class_idx, pos)) = self.schedule() {
+                let class = &mut self.base.flows[class_idx];
+                let flow_idx = class.flow_indices[pos];
+                let arrive_time = class.flows[pos].next_arrival().unwrap();
+                let packet = class.flows[pos].pop_packet();
+                self.base.submit(flow_idx, packet, arrive_time);
+            }
+        }
+
+        self.base.record_queues();
+        self.base.advance();
+
+        true
+    }
+}
+
+impl Schedulable<Option<(usize, usize)>> for HierarchicalWFQScheduler {
+    /// Pick the class with the smallest virtual finish time,
+    /// then the flow of that class with the smallest virtual finish time.
+    /// Return the index of the class and the position of the flow in it.
+    fn schedule(&mut self) -> Option<(usize, usize)> {
+        let mut best: Option<(usize, usize, f64)> = None;
+        for (class_idx, class) in self.base.flows.iter_mut().enumerate() {
+            let Some((pos, _)) = class.pick(self.base.timer) else {
+                continue;
+            };
+            let len = class.flows[pos].peek_packet(self.base.timer).unwrap().len;
+            let tag = *class.tag.get_or_insert_with(|| {
+                class.finish.max(self.virtual_time)
+                    + estimate_time(len, class.weight, self.total_class_weight)
+            });
+            if best.is_none_or(|(_, _, min)| tag < min) {
+                best = Some((class_idx, pos, tag));
+            }
+        }
+
+        let (class_idx, pos, tag) = best?;
+        self.virtual_time = tag;
+        let class = &mut self.base.flows[class_idx];
+        class.finish = tag;
+        class.tag = None;
+        let flow_tag = class.flow_tags[pos].take().unwrap();
+        class.virtual_time = flow_tag;
+        class.flow_finish[pos] = flow_tag;
+        Some((class_idx, pos))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        Packet, Scheduler,
+    };
+
+    use super::HierarchicalWFQScheduler;
+
+    #[test]
+    fn hwfq_test() {
+        let mut scheduler = HierarchicalWFQScheduler::new(1);
+        let video = scheduler.add_class(3f64);
+        let bulk = scheduler.add_class(1f64);
+
+        for (class, prefix) in [(video, "v"), (bulk, "b")] {
+            for f in 0..2 {
+                let mut flow = VariableLengthFlow::new();
+                for p in 0..20 {
+                    flow.packet_arrive(Packet::new(format!("{}{}_{}", prefix, f, p), 2), 0);
+                }
+                scheduler.add_flow_to(class, flow, 1f64);
+            }
+        }
+
+        scheduler.run();
+        assert_eq!(scheduler.output().len(), 80);
+
+        // While both classes are backlogged, they share the link 3:1,
+        // and the flows of a class share the class bandwidth equally.
+        let window = &scheduler.output()[..40];
+        let bytes = |prefix: &str| -> usize {
+            window
+                .iter()
+                .filter(|p| p.name.starts_with(prefix))
+                .map(|p| p.len)
+                .sum()
+        };
+        assert_eq!(bytes("v"), 60);
+        assert_eq!(bytes("b"), 20);
+        assert_eq!(bytes("v0"), bytes("v1"));
+        assert_eq!(bytes("b0"), bytes("b1"));
+
+        let stats = scheduler.stats();
+        assert_eq!(stats.len(), 4);
+        assert!(stats.iter().all(|s| s.packets == 20));
+    }
+}
@@ -0,0 +1,274 @@
+use crate::scheduling::{
+    flow::Flow,
+    schedulers::{
+        base::SchedulerBase,
+        tie_break::{TieBreak, TieBreaker},
+    },
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// Least Slack Time First (LSTF) scheduler.
+///
+/// Every flow has a delay budget as in
+/// [`EDFScheduler`](super::edf::EDFScheduler), and may expect some delay
+/// past this port, such as on the next hops. The slack of a packet is how
+/// long it can still wait: its deadline minus the current time, its
+/// transmission time and the delay expected past the port. Whenever the
+/// link is free, the arrived packet with the least slack is served. Packets
+/// sent with a negative slack are counted as slack misses, and packets
+/// leaving the port after their deadline minus the expected delay as
+/// deadline misses.
+#[derive(Clone)]
+pub struct LSTFScheduler {
+    base: SchedulerBase,
+    /// Delay budget of each flow, in ticks.
+    budgets: Vec<usize>,
+    /// Delay each flow expects past the port, in ticks.
+    remaining_delays: Vec<usize>,
+    /// Packets of each flow sent with a negative slack.
+    slack_misses: Vec<usize>,
+    tie_break: TieBreaker,
+}
+
+impl LSTFScheduler {
+    pub fn new(bandwidth: usize) -> LSTFScheduler {
+        LSTFScheduler {
+            base: SchedulerBase::new(bandwidth),
+            budgets: Vec::new(),
+            remaining_delays: Vec::new(),
+            slack_misses: Vec::new(),
+            tie_break: TieBreaker::default(),
+        }
+    }
+
+    /// Add a flow whose packets are due `budget` ticks after they arrive.
+    pub fn add_flow(&mut self, flow: impl Flow + 'static, budget: usize) -> FlowId {
+        Scheduler::add_flow(self, Box::new(flow), budget as f64)
+    }
+
+    /// Set the delay the packets of a flow are expected to take
+    /// past the port, taken from their slack. 0 by default.
+    pub fn set_remaining_delay(&mut self, flow: FlowId, delay: usize) {
+        self.remaining_delays[flow.index()] = delay;
+    }
+
+    /// Choose how flows with equal slacks are ordered.
+    pub fn set_tie_break(&mut self, tie_break: TieBreak) {
+        self.tie_break = TieBreaker::new(tie_break);
+    }
+
+    /// The slack of the head packet of a flow, None if no packet arrived.
+    pub fn slack(&self, flow: FlowId) -> Option<isize> {
+        let idx = flow.index();
+        let packet = self.base.flows[idx].peek_packet(self.base.timer)?;
+        let deadline = self.base.flows[idx].next_arrival().unwrap() + self.budgets[idx];
+        let transmission = packet
+            .len
+            .div_ceil(self.base.output_port.get_bandwidth().max(1));
+        Some(
+            deadline as isize
+                - self.base.timer as isize
+                - transmission as isize
+                - self.remaining_delays[idx] as isize,
+        )
+    }
+}
+
+impl Scheduler for LSTFScheduler {
+    /// Add a flow with the weight used as its delay budget.
+    fn add_flow(&mut self, flow: Box<dyn Flow>, weight: f64) -> FlowId {
+        self.budgets.push(weight.round() as usize);
+        self.remaining_delays.push(0);
+        self.slack_misses.push(0);
+        self.base.add_flow(flow)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.slack_misses.fill(0);
+        self.tie_break.reset();
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        let mut stats = self.base.stats();
+        let budgets: Vec<usize> = self
+            .budgets
+            .iter()
+            .zip(&self.remaining_delays)
+            .map(|(budget, remaining)| budget.saturating_sub(*remaining))
+            .collect();
+        stats.count_deadline_misses(&budgets);
+        for (flow, &misses) in stats.flows.iter_mut().zip(&self.slack_misses) {
+            flow.slack_misses = misses;
+        }
+        stats.aggregate.slack_misses = self.slack_misses.iter().sum();
+        stats
+    }
+}
+
+impl Tickable for LSTFScheduler {
+    fn tick(&mut self) -> bool {
+        if self.base.all_empty() {
+            return false;
+        }
+
+        if self.base.output_port.is_accepting() {
+            if let Some(idx) = self.schedule() {
+                if self.slack(FlowId(idx)).unwrap() < 0 {
+                    self.slack_misses[idx] += 1;
+                }
+                self.base.serve(idx);
+            }
+        }
+
+        self.base.record_queues();
+        self.base.advance();
+
+        true
+    }
+}
+
+impl Schedulable<Option<usize>> for LSTFScheduler {
+    /// Return the index of the flow whose arrived head packet
+    /// has the least slack.
+    fn schedule(&mut self) -> Option<usize> {
+        let slacks: Vec<(usize, f64)> = (0..self.base.flows.len())
+            .filter_map(|idx| Some((idx, self.slack(FlowId(idx))? as f64)))
+            .collect();
+        let (flows, timer) = (&self.base.flows, self.base.timer);
+        self.tie_break
+            .pick(slacks, |idx| flows[idx].queue_len(timer))
+            .map(|(idx, _)| idx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        schedulers::edf::EDFScheduler,
+        FlowId, Packet, Scheduler,
+    };
+
+    use super::LSTFScheduler;
+
+    fn flows() -> (VariableLengthFlow, VariableLengthFlow) {
+        let mut long = VariableLengthFlow::new();
+        long.packet_arrive(Packet::new("a1", 4), 0);
+        long.packet_arrive(Packet::new("a2", 4), 0);
+        let mut short = VariableLengthFlow::new();
+        short.packet_arrive(Packet::new("b1", 1), 0);
+        (long, short)
+    }
+
+    #[test]
+    fn lstf_test() {
+        // The long packets are due first once their transmission is
+        // accounted for, and b1 waits for both.
+        let (long, short) = flows();
+        let mut lstf = LSTFScheduler::new(1);
+        lstf.add_flow(long, 9);
+        lstf.add_flow(short, 7);
+        assert_eq!(lstf.slack(FlowId(0)), Some(5));
+        assert_eq!(lstf.slack(FlowId(1)), Some(6));
+        lstf.run();
+        let names: Vec<&str> = lstf.output().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["a1", "a2", "b1"]);
+        let stats = lstf.scheduler_stats();
+        assert_eq!(stats.flows[0].slack_misses, 0);
+        assert_eq!(stats.flows[1].slack_misses, 1);
+        assert_eq!(stats.flows[1].deadline_misses, 1);
+
+        // The short packet still has two ticks to go past the port,
+        // leaving it less slack than the long ones.
+        let (long, short) = flows();
+        let mut lstf = LSTFScheduler::new(1);
+        lstf.add_flow(long, 9);
+        lstf.add_flow(short, 7);
+        lstf.set_remaining_delay(FlowId(1), 2);
+        assert_eq!(lstf.slack(FlowId(1)), Some(4));
+        lstf.run();
+        let names: Vec<&str> = lstf.output().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["b1", "a1", "a2"]);
+
+        // a2 is sent at tick 5 with a slack of 0, and every packet is on time.
+        let stats = lstf.scheduler_stats();
+        assert_eq!(stats.aggregate.slack_misses, 0);
+        assert_eq!(stats.aggregate.deadline_misses, 0);
+    }
+
+    #[test]
+    fn lstf_slack_miss_test() {
+        let (long, short) = flows();
+        let mut lstf = LSTFScheduler::new(1);
+        lstf.add_flow(long, 6);
+        lstf.add_flow(short, 2);
+        lstf.run();
+
+        // a2 has no slack left once a1 is sent, and leaves at 9, due by 6.
+        let stats = lstf.scheduler_stats();
+        assert_eq!(stats.flows[0].slack_misses, 1);
+        assert_eq!(stats.flows[0].deadline_misses, 1);
+        assert_eq!(stats.aggregate.slack_misses, 1);
+
+        // EDF sends the same packets without counting slack misses.
+        let (long, short) = flows();
+        let mut edf = EDFScheduler::new(1);
+        edf.add_flow(long, 6);
+        edf.add_flow(short, 2);
+        edf.run();
+        assert_eq!(edf.output(), lstf.output());
+        assert_eq!(edf.scheduler_stats().aggregate.slack_misses, 0);
+    }
+}
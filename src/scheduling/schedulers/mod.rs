@@ -1,3 +1,534 @@
+use std::fmt;
+
+use crate::scheduling::{
+    flow::Flow,
+    stats::{trace::Trace, FlowStats, QueueSeries, SchedulerStats},
+    Departure, Packet, Port, SchedulerOutput,
+};
+
+pub mod ats;
+mod base;
+pub mod cbq;
+pub mod cbs;
 pub mod drr;
+pub mod dwrr;
+pub mod edf;
+pub mod fifo;
+pub mod fq_codel;
+pub mod htb;
+pub mod hwfq;
+pub mod lstf;
+pub mod pfabric;
+pub mod qfq;
+pub mod rr;
+pub mod scfq;
+pub mod sfq;
+pub mod sp;
+pub mod stochastic_fq;
+pub mod tas;
+pub mod tie_break;
+pub mod vc;
+mod virtual_tags;
+pub mod wf2q;
 pub mod wfq;
 pub mod wrr;
+
+/// Identifier of a flow of a scheduler, returned by [`Scheduler::add_flow`].
+///
+/// Identifiers are handed out in the order the flows are added and are
+/// never reused, even once a flow is closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct FlowId(pub usize);
+
+impl FlowId {
+    /// The position of the flow among the flows of its scheduler,
+    /// as in [`SchedulerStats::flows`].
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl fmt::Display for FlowId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Whether a scheduler may send a packet before the packet is eligible.
+///
+/// Rate-based schedulers define when a packet is eligible, see
+/// [`VirtualClockScheduler`](vc::VirtualClockScheduler).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ServiceMode {
+    /// Never leave the link idle while a packet is waiting.
+    #[default]
+    WorkConserving,
+    /// Leave the link idle until a waiting packet is eligible, so that the
+    /// scheduler also shapes its flows and bounds their jitter.
+    NonWorkConserving,
+}
+
+/// The interface shared by all scheduling disciplines,
+/// so that harnesses can be written generically over them.
+pub trait Scheduler {
+    /// Add a flow with a weight, returning its identifier.
+    /// Disciplines without weights ignore it, and disciplines with
+    /// integer weights round it to the nearest integer.
+    fn add_flow(&mut self, flow: Box<dyn Flow>, weight: f64) -> FlowId;
+
+    /// Add a packet arriving at `time` to a flow, for instance between
+    /// calls to `step` to model open-ended arrivals. The time should not be
+    /// before the current time. The packet is given back if the flow takes
+    /// no packets from outside, such as a child scheduler.
+    /// `reset` forgets the injected packets.
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet>;
+
+    /// Close a flow now: the packets arriving later are discarded,
+    /// including injected ones, and the flow stops taking part once the
+    /// packets already queued are served. Flows can also be added mid-run.
+    fn close_flow(&mut self, flow: FlowId);
+
+    /// Run the scheduler until all flows are drained
+    /// and every packet has left the output port.
+    fn run(&mut self);
+
+    /// Advance the scheduler by one tick.
+    /// Returns false once all flows are drained, when only
+    /// the packets left in the output port remain to be sent.
+    fn step(&mut self) -> bool;
+
+    /// Step until the timer reaches at least `time`, leaving the packets
+    /// in flight in the output port. Returns false if all flows were
+    /// drained before, in which case the timer stops short of `time`.
+    fn run_until(&mut self, time: usize) -> bool {
+        while self.timer() < time {
+            if !self.step() {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Step for `ticks` ticks from now, see `run_until`.
+    fn step_by(&mut self, ticks: usize) -> bool {
+        self.run_until(self.timer() + ticks)
+    }
+
+    /// The packets that have left the output port, in departure order.
+    fn output(&self) -> &[Packet];
+
+    /// The current time of the scheduler, in ticks.
+    fn timer(&self) -> usize;
+
+    /// Set the smoothing factor of the per-flow EWMA throughput estimate.
+    fn set_ewma_alpha(&mut self, alpha: f64);
+
+    /// Get the smoothed recent throughput of a flow.
+    fn ewma_throughput(&self, flow: FlowId) -> f64;
+
+    /// The output port, for instance to give it a buffer or class queues
+    /// before a run.
+    fn get_output_port(&mut self) -> &mut Port;
+
+    /// The number of packets of a flow dropped at the output port,
+    /// by the discipline itself, such as on overflow or by an AQM,
+    /// or in the queue of the flow.
+    fn dropped_count(&self, flow: FlowId) -> usize;
+
+    /// The state the discipline keeps per flow, such as deficit counters
+    /// or virtual finish times, as a name and one value per flow.
+    /// None for disciplines without per-flow state.
+    fn flow_state(&self) -> Option<(&'static str, Vec<f64>)> {
+        None
+    }
+
+    /// Restore the scheduler to its state right after its flows were added,
+    /// so that the same workload can be run again.
+    fn reset(&mut self);
+
+    /// Sample the occupancy of the queues every `interval` ticks,
+    /// or stop sampling with None. The samples taken so far are dropped.
+    fn set_queue_sampling(&mut self, interval: Option<usize>);
+
+    /// The queue occupancy sampled so far.
+    fn queue_series(&self) -> &QueueSeries;
+
+    /// Statistics of the packets that have left the output port,
+    /// per packet, per flow and over all flows.
+    fn scheduler_stats(&self) -> SchedulerStats;
+
+    /// Per-flow statistics of the packets that have left the output port.
+    fn stats(&self) -> Vec<FlowStats> {
+        self.scheduler_stats().flows
+    }
+
+    /// Every event of the run, ready to be exported as CSV or JSON.
+    fn trace(&self) -> Trace {
+        Trace::new(&self.scheduler_stats())
+    }
+
+    /// The packets that have left the output port, in departure order,
+    /// with the flow each came from and its departure time.
+    fn departures(&self) -> Vec<Departure> {
+        self.scheduler_stats()
+            .packets
+            .into_iter()
+            .zip(self.output())
+            .map(|(record, packet)| Departure {
+                flow: record.flow,
+                packet: packet.clone(),
+                time: record.departure,
+            })
+            .collect()
+    }
+
+    /// Snapshot the output and the timer into a standalone result,
+    /// keeping the flow and departure time of every packet.
+    fn result(&self) -> SchedulerOutput {
+        let packets = self.scheduler_stats().packets;
+        SchedulerOutput {
+            output: self.output().to_vec(),
+            timer: self.timer(),
+            flows: packets.iter().map(|r| r.flow).collect(),
+            departure_times: packets.iter().map(|r| r.departure).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{FixedLengthFlow, Flow, OpenFlow, VariableLengthFlow},
+        schedulers::{
+            ats::ATSScheduler, cbq::CBQScheduler, cbs::CBSScheduler, drr::DRRScheduler,
+            dwrr::DWRRScheduler, edf::EDFScheduler, fifo::FIFOScheduler,
+            fq_codel::FQCoDelScheduler, htb::HTBScheduler, hwfq::HierarchicalWFQScheduler,
+            lstf::LSTFScheduler, pfabric::PFabricScheduler, qfq::QFQScheduler, rr::RRScheduler,
+            scfq::SCFQScheduler, sfq::SFQScheduler, sp::SPScheduler,
+            stochastic_fq::StochasticFQScheduler, tas::TASScheduler, vc::VirtualClockScheduler,
+            wf2q::WF2QPlusScheduler, wfq::WFQScheduler, wrr::WRRScheduler,
+        },
+        FlowId, Packet, Scheduler,
+    };
+
+    fn schedulers() -> Vec<Box<dyn Scheduler>> {
+        let mut wfq = WFQScheduler::new(1);
+        let mut flow = VariableLengthFlow::new();
+        flow.packet_arrive(Packet::new("p1", 2), 0);
+        flow.packet_arrive(Packet::new("p2", 2), 1);
+        wfq.add_flow(flow, 1f64);
+
+        let mut drr = DRRScheduler::new(1);
+        let mut flow = VariableLengthFlow::new();
+        flow.packet_arrive(Packet::new("p1", 2), 0);
+        flow.packet_arrive(Packet::new("p2", 2), 1);
+        drr.add_flow(flow, 2);
+
+        let mut wrr = WRRScheduler::new(1);
+        let mut flow = FixedLengthFlow::new(2);
+        flow.add_packet("p1", 0);
+        flow.add_packet("p2", 1);
+        wrr.add_flow(flow, 1);
+
+        vec![Box::new(wfq), Box::new(drr), Box::new(wrr)]
+    }
+
+    #[test]
+    fn scheduler_trait_object_test() {
+        for scheduler in schedulers().iter_mut() {
+            scheduler.run();
+            assert!(scheduler.timer() > 0);
+            assert_eq!(
+                scheduler.output(),
+                &[Packet::new("p1", 2), Packet::new("p2", 2)]
+            );
+        }
+    }
+
+    /// One scheduler of every discipline, without flows.
+    fn empty_schedulers() -> Vec<Box<dyn Scheduler>> {
+        vec![
+            Box::new(WFQScheduler::new(1)),
+            Box::new(DRRScheduler::new(1)),
+            Box::new(WRRScheduler::new(1)),
+            Box::new(FIFOScheduler::new(1)),
+            Box::new(RRScheduler::new(1)),
+            Box::new(HierarchicalWFQScheduler::new(1)),
+            Box::new(SPScheduler::new(1)),
+            Box::new(WF2QPlusScheduler::new(1)),
+            Box::new(SFQScheduler::new(1)),
+            Box::new(SCFQScheduler::new(1)),
+            Box::new(VirtualClockScheduler::new(1)),
+            Box::new(DWRRScheduler::new(1)),
+            Box::new(EDFScheduler::new(1)),
+            Box::new(HTBScheduler::new(1)),
+            Box::new(CBSScheduler::new(1)),
+            Box::new(TASScheduler::new(1)),
+            Box::new(ATSScheduler::new(1)),
+            Box::new(PFabricScheduler::new(1)),
+            Box::new(LSTFScheduler::new(1)),
+            Box::new(CBQScheduler::new(1)),
+            Box::new(FQCoDelScheduler::new(1)),
+            Box::new(StochasticFQScheduler::new(1)),
+            Box::new(QFQScheduler::new(1)),
+        ]
+    }
+
+    #[test]
+    fn scheduler_add_flow_test() {
+        for mut scheduler in empty_schedulers() {
+            for prefix in ["a", "b"] {
+                let mut flow = VariableLengthFlow::new();
+                flow.packet_arrive(Packet::new(format!("{}1", prefix), 1), 0);
+                flow.packet_arrive(Packet::new(format!("{}2", prefix), 1), 1);
+                scheduler.add_flow(Box::new(flow), 1f64);
+            }
+            scheduler.set_queue_sampling(Some(1));
+            scheduler.run();
+
+            assert_eq!(scheduler.output().len(), 4);
+            let stats = scheduler.stats();
+            assert_eq!(stats.len(), 2);
+            assert!(stats.iter().all(|s| s.packets == 2 && s.bytes == 2));
+            assert_eq!(scheduler.scheduler_stats().aggregate.packets, 4);
+
+            // Every tick is sampled, and at most the four packets are queued.
+            let series = scheduler.queue_series();
+            assert!(series.times().windows(2).all(|w| w[1] == w[0] + 1));
+            assert!((0..series.len()).all(|i| series.port()[i]
+                + series.flow(FlowId(0))[i]
+                + series.flow(FlowId(1))[i]
+                <= 4));
+            assert!(series.port().iter().any(|&len| len > 0));
+            scheduler.reset();
+            assert!(scheduler.queue_series().is_empty());
+        }
+    }
+
+    #[test]
+    fn scheduler_inject_test() {
+        for mut scheduler in empty_schedulers() {
+            let mut flow = VariableLengthFlow::new();
+            flow.packet_arrive(Packet::new("p1", 1), 0);
+            scheduler.add_flow(Box::new(flow), 1f64);
+
+            // The flow runs dry long before time 5.
+            assert!(!scheduler.run_until(5));
+            let paused = scheduler.timer();
+            assert!(paused < 5);
+
+            // Packets injected later are served when they arrive.
+            scheduler
+                .inject(FlowId(0), Packet::new("p2", 1), 10)
+                .unwrap();
+            assert!(scheduler.step_by(3));
+            assert_eq!(scheduler.timer(), paused + 3);
+            scheduler.run();
+            assert_eq!(
+                scheduler.output(),
+                &[Packet::new("p1", 1), Packet::new("p2", 1)]
+            );
+            let records = scheduler.scheduler_stats().packets;
+            assert_eq!(records[1].arrival, 10);
+            assert!(records[1].departure > 10);
+
+            scheduler.reset();
+            scheduler.run();
+            assert_eq!(scheduler.output().len(), 1);
+        }
+    }
+
+    #[test]
+    fn scheduler_open_flow_test() {
+        for mut scheduler in empty_schedulers() {
+            let flow = scheduler.add_flow(Box::new(OpenFlow::new()), 1f64);
+
+            // An open flow keeps the scheduler running while it waits,
+            // so each packet leaves before the next one is injected.
+            for p in 0..3 {
+                let time = scheduler.timer();
+                scheduler
+                    .inject(flow, Packet::new(format!("p{}", p), 1), time)
+                    .unwrap();
+                assert!(scheduler.step_by(5));
+                assert_eq!(scheduler.output().len(), p + 1);
+            }
+
+            // Once closed, it drains like any other flow.
+            scheduler.close_flow(flow);
+            scheduler.run();
+            assert_eq!(scheduler.output().len(), 3);
+        }
+    }
+
+    #[test]
+    fn scheduler_churn_test() {
+        for mut scheduler in empty_schedulers() {
+            let mut flow = VariableLengthFlow::new();
+            for p in 0..6 {
+                flow.packet_arrive(Packet::new(format!("a{}", p), 1), p);
+            }
+            let a = scheduler.add_flow(Box::new(flow), 1f64);
+            let mut flow = VariableLengthFlow::new();
+            for p in 0..3 {
+                flow.packet_arrive(Packet::new(format!("b{}", p), 1), 0);
+            }
+            scheduler.add_flow(Box::new(flow), 1f64);
+
+            // The first flow is closed at time 2, so a3 to a5 never arrive,
+            // and a third flow joins at time 3.
+            assert!(scheduler.run_until(2));
+            scheduler.close_flow(a);
+            scheduler.inject(a, Packet::new("a6", 1), 6).unwrap();
+            let mut flow = VariableLengthFlow::new();
+            flow.packet_arrive(Packet::new("c0", 1), 3);
+            let c = scheduler.add_flow(Box::new(flow), 1f64);
+            scheduler.run();
+
+            let mut names = scheduler
+                .output()
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>();
+            names.sort();
+            assert_eq!(names, ["a0", "a1", "a2", "b0", "b1", "b2", "c0"]);
+            let stats = scheduler.scheduler_stats();
+            assert_eq!(stats.flows.len(), 3);
+            assert_eq!(stats.flow(c).packets, 1);
+        }
+    }
+
+    #[test]
+    fn scheduler_flow_id_test() {
+        for mut scheduler in empty_schedulers() {
+            let ids: Vec<FlowId> = ["a", "b"]
+                .into_iter()
+                .map(|name| {
+                    let mut flow = VariableLengthFlow::new();
+                    flow.packet_arrive(Packet::new(name, 1), 0);
+                    scheduler.add_flow(Box::new(flow), 1f64)
+                })
+                .collect();
+            assert_eq!(ids, [FlowId(0), FlowId(1)]);
+            scheduler.run();
+
+            // Every packet and every trace event is attributed to its flow.
+            let stats = scheduler.scheduler_stats();
+            for record in &stats.packets {
+                let flow = if record.name == "a" { ids[0] } else { ids[1] };
+                assert_eq!(record.flow, flow);
+                assert_eq!(stats.flow(flow).packets, 1);
+            }
+            let trace = scheduler.trace();
+            assert!(trace
+                .events
+                .iter()
+                .all(|e| e.flow == if e.packet == "a" { ids[0] } else { ids[1] }));
+        }
+    }
+
+    #[test]
+    fn scheduler_departures_test() {
+        for mut scheduler in empty_schedulers() {
+            // Both flows name their packets alike,
+            // so only the departures tell them apart.
+            let ids: Vec<FlowId> = [0, 1]
+                .into_iter()
+                .map(|start| {
+                    let mut flow = VariableLengthFlow::new();
+                    flow.packet_arrive(Packet::new("p", 1), start);
+                    flow.packet_arrive(Packet::new("p", 1), start + 2);
+                    scheduler.add_flow(Box::new(flow), 1f64)
+                })
+                .collect();
+            scheduler.run();
+
+            let departures = scheduler.departures();
+            assert_eq!(departures.len(), 4);
+            for flow in ids {
+                let times: Vec<usize> = departures
+                    .iter()
+                    .filter(|d| d.flow == flow)
+                    .map(|d| d.time)
+                    .collect();
+                assert_eq!(times.len(), 2);
+                assert!(times[0] < times[1]);
+            }
+            assert!(departures.windows(2).all(|w| w[0].time <= w[1].time));
+            assert_eq!(
+                scheduler.result().departures().collect::<Vec<_>>(),
+                departures
+            );
+        }
+    }
+
+    #[test]
+    fn scheduler_reset_test() {
+        for scheduler in schedulers().iter_mut() {
+            scheduler.run();
+            let first = scheduler.result();
+
+            scheduler.reset();
+            assert_eq!(scheduler.timer(), 0);
+            assert!(scheduler.output().is_empty());
+
+            scheduler.run();
+            assert_eq!(scheduler.result(), first);
+        }
+    }
+
+    #[test]
+    fn scheduler_clone_test() {
+        let mut wfq = WFQScheduler::new(1);
+        for (name, weight) in [("a", 1f64), ("b", 2f64)] {
+            let mut flow = VariableLengthFlow::new();
+            for idx in 0..3 {
+                flow.packet_arrive(Packet::new(format!("{}{}", name, idx), 2), idx);
+            }
+            wfq.add_flow(flow, weight);
+        }
+
+        // A clone runs on its own copy of the flows.
+        let mut copy = wfq.clone();
+        wfq.run();
+        assert_eq!(copy.timer(), 0);
+        copy.run();
+        assert_eq!(copy.result(), wfq.result());
+
+        let mut drr = DRRScheduler::new(1);
+        let mut flow = VariableLengthFlow::new();
+        flow.packet_arrive(Packet::new("p1", 2), 0);
+        drr.add_flow(flow, 2);
+        drr.step();
+        let mut copy = drr.clone();
+        drr.run();
+        copy.run();
+        assert_eq!(copy.result(), drr.result());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn scheduler_output_serde_test() {
+        use crate::scheduling::SchedulerOutput;
+
+        let mut drr = DRRScheduler::new(1);
+        let mut flow = VariableLengthFlow::new();
+        flow.packet_arrive(Packet::new("p1", 2), 0);
+        flow.packet_arrive(Packet::new("p2", 1), 1);
+        drr.add_flow(flow, 2);
+        drr.run();
+
+        let result = drr.result();
+        let json = serde_json::to_string(&result).unwrap();
+        let decoded: SchedulerOutput = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, result);
+        assert_eq!(decoded.output.len(), 2);
+        assert_eq!(decoded.flows, [FlowId(0), FlowId(0)]);
+    }
+}
@@ -0,0 +1,238 @@
+use crate::scheduling::{
+    flow::Flow,
+    schedulers::base::SchedulerBase,
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// pFabric scheduler, shortest remaining flow size first.
+///
+/// Every flow has a size in bytes, as carried by pFabric packets, and
+/// whenever the link is free the flow with the fewest bytes left to send
+/// is served, approximating shortest-remaining-processing-time (SRPT)
+/// to minimize flow completion times. Flows with the same remaining size
+/// are served by the arrival of their head packet, then in the order
+/// they were added. Large flows are starved for as long as smaller ones
+/// are backlogged.
+#[derive(Clone)]
+pub struct PFabricScheduler {
+    base: SchedulerBase,
+    /// Size of each flow in bytes.
+    sizes: Vec<usize>,
+    /// Bytes of each flow handed to the output port.
+    sent: Vec<usize>,
+    /// The sizes as they were added, restored by `reset`.
+    initial_sizes: Vec<usize>,
+}
+
+impl PFabricScheduler {
+    pub fn new(bandwidth: usize) -> PFabricScheduler {
+        PFabricScheduler {
+            base: SchedulerBase::new(bandwidth),
+            sizes: Vec::new(),
+            sent: Vec::new(),
+            initial_sizes: Vec::new(),
+        }
+    }
+
+    /// Add a flow of `size` bytes, as declared by its sender.
+    pub fn add_flow(&mut self, flow: impl Flow + 'static, size: usize) -> FlowId {
+        self.push_flow(Box::new(flow), size)
+    }
+
+    fn push_flow(&mut self, flow: Box<dyn Flow>, size: usize) -> FlowId {
+        self.initial_sizes.push(size);
+        self.sizes.push(size);
+        self.sent.push(0);
+        self.base.add_flow(flow)
+    }
+
+    /// The bytes a flow has left to send, by its size.
+    pub fn remaining(&self, flow: FlowId) -> usize {
+        self.sizes[flow.index()].saturating_sub(self.sent[flow.index()])
+    }
+}
+
+impl Scheduler for PFabricScheduler {
+    /// Add a flow whose size is the total length of the packets it holds,
+    /// ignoring the weight.
+    fn add_flow(&mut self, flow: Box<dyn Flow>, _weight: f64) -> FlowId {
+        let mut packets = flow.clone();
+        let mut size = 0;
+        while packets.next_arrival().is_some() {
+            size += packets.pop_packet().len;
+        }
+        self.push_flow(flow, size)
+    }
+
+    /// Injected packets extend the size of their flow.
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.sizes[flow.index()] += packet.len;
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.sizes = self.initial_sizes.clone();
+        self.sent.fill(0);
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn flow_state(&self) -> Option<(&'static str, Vec<f64>)> {
+        Some((
+            "remaining",
+            (0..self.base.flows.len())
+                .map(|idx| self.remaining(FlowId(idx)) as f64)
+                .collect(),
+        ))
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
+    }
+}
+
+impl Tickable for PFabricScheduler {
+    fn tick(&mut self) -> bool {
+        if self.base.all_empty() {
+            return false;
+        }
+
+        if self.base.output_port.is_accepting() {
+            if let Some(idx) = self.schedule() {
+                let arrive_time = self.base.flows[idx].next_arrival().unwrap();
+                let packet = self.base.flows[idx].pop_packet();
+                self.sent[idx] += packet.len;
+                self.base.submit(idx, packet, arrive_time);
+            }
+        }
+
+        self.base.record_queues();
+        self.base.advance();
+
+        true
+    }
+}
+
+impl Schedulable<Option<usize>> for PFabricScheduler {
+    /// Return the index of the flow with an arrived packet
+    /// and the fewest remaining bytes.
+    fn schedule(&mut self) -> Option<usize> {
+        (0..self.base.flows.len())
+            .filter(|&idx| self.base.flows[idx].peek_packet(self.base.timer).is_some())
+            .min_by_key(|&idx| {
+                (
+                    self.remaining(FlowId(idx)),
+                    self.base.flows[idx].next_arrival(),
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        FlowId, Packet, Scheduler,
+    };
+
+    use super::PFabricScheduler;
+
+    fn flow(prefix: &str, count: usize, arrival: usize) -> VariableLengthFlow {
+        let mut flow = VariableLengthFlow::new();
+        for p in 0..count {
+            flow.packet_arrive(Packet::new(format!("{}{}", prefix, p), 1), arrival);
+        }
+        flow
+    }
+
+    #[test]
+    fn pfabric_test() {
+        let mut pfabric = PFabricScheduler::new(1);
+        pfabric.add_flow(flow("a", 6, 0), 6);
+        pfabric.add_flow(flow("b", 2, 2), 2);
+        pfabric.add_flow(flow("c", 3, 0), 3);
+
+        pfabric.run();
+
+        // c is served first, then b, which arrived in the meantime
+        // with less left than a.
+        let names: Vec<&str> = pfabric.output().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["c0", "c1", "c2", "b0", "b1", "a0", "a1", "a2", "a3", "a4", "a5"]
+        );
+        assert_eq!(pfabric.remaining(FlowId(0)), 0);
+
+        // Small flows complete quickly, the largest one last.
+        let stats = pfabric.scheduler_stats();
+        let fct: Vec<usize> = stats.flows.iter().map(|f| f.completion_time).collect();
+        assert_eq!(fct, [11, 3, 3]);
+        assert_eq!(stats.mean_completion_time(), 17f64 / 3f64);
+    }
+
+    #[test]
+    fn pfabric_remaining_test() {
+        let mut pfabric = PFabricScheduler::new(1);
+        // The size given to the trait is taken from the packets.
+        Scheduler::add_flow(&mut pfabric, Box::new(flow("a", 3, 0)), 1f64);
+        pfabric.add_flow(flow("b", 2, 1), 4);
+
+        pfabric.step();
+        assert_eq!(pfabric.flow_state(), Some(("remaining", vec![2f64, 4f64])));
+        pfabric.inject(FlowId(0), Packet::new("a3", 1), 1).unwrap();
+        pfabric.run();
+
+        // b never has less left than a.
+        let names: Vec<&str> = pfabric.output().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["a0", "a1", "a2", "a3", "b0", "b1"]);
+
+        pfabric.reset();
+        assert_eq!(pfabric.remaining(FlowId(0)), 3);
+    }
+}
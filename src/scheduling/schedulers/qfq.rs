@@ -0,0 +1,397 @@
+use std::collections::VecDeque;
+
+use crate::scheduling::{
+    flow::Flow,
+    schedulers::base::SchedulerBase,
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// Tolerance when comparing virtual times.
+const EPSILON: f64 = 1e-9;
+
+/// Number of slots in the bucket list of a group.
+const QFQ_MAX_SLOTS: usize = 32;
+
+/// Number of groups, one per power of two of the slot size.
+const QFQ_MAX_GROUPS: usize = 64;
+
+/// Default length of the largest packet a flow may send, in bytes.
+pub const DEFAULT_QFQ_MAX_PACKET_LEN: usize = 1500;
+
+/// The flows of a group, by their rounded virtual start times.
+#[derive(Debug, Clone)]
+struct Group {
+    /// Virtual start time of the front slot, a multiple of the slot size.
+    start: f64,
+    /// Bucket list of flows as a ring of slots, each a slot size apart.
+    slots: Vec<VecDeque<usize>>,
+    front: usize,
+    /// Bit k is set if the k-th slot from the front holds flows.
+    full: u32,
+}
+
+impl Group {
+    fn new() -> Group {
+        Group {
+            start: 0f64,
+            slots: vec![VecDeque::new(); QFQ_MAX_SLOTS],
+            front: 0,
+            full: 0,
+        }
+    }
+}
+
+/// Quick Fair Queueing (QFQ) scheduler.
+///
+/// QFQ approximates WF2Q+ in constant time with respect to the number of
+/// flows. Every flow has a virtual start and finish time as in WF2Q+, and
+/// is placed in the group of its slot size, the largest packet length
+/// over its share of the link rounded up to a power of two. Within a
+/// group, flows are kept in a bucket list by their start time rounded
+/// down to the slot size, and the group takes the start time of its front
+/// slot and a finish time two slots later. Whenever the link is free, the
+/// eligible group with the smallest finish time is served, a group being
+/// eligible once the system virtual time reaches its start, and the first
+/// flow of its front slot sends a packet. Groups are few and tracked in a
+/// bitmap, so neither step looks at the flows one by one.
+///
+/// Packets longer than the largest packet length still get through,
+/// but weaken the fairness guarantees.
+#[derive(Clone)]
+pub struct QFQScheduler {
+    base: SchedulerBase,
+    weights: Vec<f64>,
+    total_weight: f64,
+    max_packet_len: usize,
+    /// System virtual time.
+    virtual_time: f64,
+    /// Virtual finish time of the last packet served from each flow.
+    finish: Vec<f64>,
+    /// Virtual start and finish time of the head packet of each flow.
+    tags: Vec<Option<(f64, f64)>>,
+    groups: Vec<Group>,
+    /// Bit g is set if group g holds flows.
+    backlogged: u64,
+}
+
+impl QFQScheduler {
+    pub fn new(bandwidth: usize) -> QFQScheduler {
+        QFQScheduler {
+            base: SchedulerBase::new(bandwidth),
+            weights: Vec::new(),
+            total_weight: 0f64,
+            max_packet_len: DEFAULT_QFQ_MAX_PACKET_LEN,
+            virtual_time: 0f64,
+            finish: Vec::new(),
+            tags: Vec::new(),
+            groups: vec![Group::new(); QFQ_MAX_GROUPS],
+            backlogged: 0,
+        }
+    }
+
+    /// Add a flow to the scheduler with a weight.
+    pub fn add_flow(&mut self, flow: impl Flow + 'static, weight: f64) -> FlowId {
+        Scheduler::add_flow(self, Box::new(flow), weight)
+    }
+
+    /// Set the length of the largest packet the flows may send,
+    /// which decides the groups of the flows.
+    pub fn set_max_packet_len(&mut self, len: usize) {
+        assert!(len > 0, "the largest packet needs a positive length");
+        self.max_packet_len = len;
+    }
+
+    /// The group of a flow, as the log2 of its slot size.
+    pub fn group_of(&self, flow: FlowId) -> usize {
+        let share = self.weights[flow.index()] / self.total_weight;
+        let slot = self.max_packet_len as f64 / share;
+        ((slot.log2() - EPSILON).ceil().max(0f64) as usize).min(QFQ_MAX_GROUPS - 1)
+    }
+
+    /// Give the head packet of a flow its virtual start and finish time
+    /// and put the flow in its group, if the packet has arrived and the
+    /// flow is not in a group yet.
+    fn stamp(&mut self, idx: usize, start: f64) {
+        if self.tags[idx].is_some() {
+            return;
+        }
+        if let Some(packet) = self.base.flows[idx].peek_packet(self.base.timer) {
+            let share = self.weights[idx] / self.total_weight;
+            self.tags[idx] = Some((start, start + packet.len as f64 / share));
+            self.insert(self.group_of(FlowId(idx)), idx, start);
+        }
+    }
+
+    /// Put a flow in the slot of its rounded start time, moving the front
+    /// of the group back if the flow starts before it.
+    fn insert(&mut self, g: usize, idx: usize, start: f64) {
+        let size = (1u64 << g) as f64;
+        let rounded = (start / size).floor() * size;
+        let group = &mut self.groups[g];
+        if self.backlogged & (1 << g) == 0 {
+            group.start = rounded;
+            group.full = 0;
+        } else if rounded < group.start {
+            // Never shift a full slot out of the ring.
+            let shift =
+                (((group.start - rounded) / size).round() as u32).min(group.full.leading_zeros());
+            group.full <<= shift;
+            group.front = (group.front + QFQ_MAX_SLOTS - shift as usize) % QFQ_MAX_SLOTS;
+            group.start -= shift as f64 * size;
+        }
+        let k =
+            (((rounded - group.start) / size).max(0f64).round() as usize).min(QFQ_MAX_SLOTS - 1);
+        group.slots[(group.front + k) % QFQ_MAX_SLOTS].push_back(idx);
+        group.full |= 1 << k;
+        self.backlogged |= 1 << g;
+    }
+
+    /// Take the first flow of the front slot of a group, moving the front
+    /// to the next full slot.
+    fn pop(&mut self, g: usize) -> usize {
+        let size = (1u64 << g) as f64;
+        let group = &mut self.groups[g];
+        let slot = &mut group.slots[group.front];
+        let idx = slot.pop_front().unwrap();
+        if slot.is_empty() {
+            group.full &= !1;
+            if group.full == 0 {
+                self.backlogged &= !(1 << g);
+            } else {
+                let skip = group.full.trailing_zeros();
+                group.full >>= skip;
+                group.front = (group.front + skip as usize) % QFQ_MAX_SLOTS;
+                group.start += skip as f64 * size;
+            }
+        }
+        idx
+    }
+
+    /// The virtual finish time of a group, two slots past its start.
+    fn group_finish(&self, g: usize) -> f64 {
+        self.groups[g].start + (2u64 << g) as f64
+    }
+
+    /// The backlogged groups, from the smallest slot size.
+    fn backlogged_groups(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..QFQ_MAX_GROUPS).filter(|g| self.backlogged & (1 << g) != 0)
+    }
+}
+
+impl Scheduler for QFQScheduler {
+    fn add_flow(&mut self, flow: Box<dyn Flow>, weight: f64) -> FlowId {
+        self.weights.push(weight);
+        self.total_weight += weight;
+        self.finish.push(0f64);
+        self.tags.push(None);
+        self.base.add_flow(flow)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.virtual_time = 0f64;
+        self.finish.fill(0f64);
+        self.tags.fill(None);
+        self.groups = vec![Group::new(); QFQ_MAX_GROUPS];
+        self.backlogged = 0;
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn flow_state(&self) -> Option<(&'static str, Vec<f64>)> {
+        Some(("finish", self.finish.clone()))
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
+    }
+}
+
+impl Tickable for QFQScheduler {
+    fn tick(&mut self) -> bool {
+        if self.base.all_empty() {
+            return false;
+        }
+
+        if self.base.output_port.is_accepting() {
+            if let Some(idx) = self.schedule() {
+                let arrive_time = self.base.flows[idx].next_arrival().unwrap();
+                let packet = self.base.flows[idx].pop_packet();
+                // A flow that stays backlogged starts its next packet
+                // where the previous one finished.
+                self.stamp(idx, self.finish[idx]);
+                self.base.submit(idx, packet, arrive_time);
+            }
+        }
+
+        self.base.record_queues();
+        self.base.advance();
+
+        true
+    }
+}
+
+impl Schedulable<Option<usize>> for QFQScheduler {
+    /// Return the index of the first flow of the eligible group with the
+    /// smallest virtual finish time, advancing the virtual time.
+    fn schedule(&mut self) -> Option<usize> {
+        // A newly backlogged flow starts no earlier than the virtual time.
+        for idx in 0..self.base.flows.len() {
+            self.stamp(idx, self.finish[idx].max(self.virtual_time));
+        }
+        if self.backlogged == 0 {
+            return None;
+        }
+
+        // With no eligible group, the virtual time jumps to the first start.
+        let eligible = |s: &Self, g: usize| s.groups[g].start <= s.virtual_time + EPSILON;
+        if !self.backlogged_groups().any(|g| eligible(self, g)) {
+            self.virtual_time = self
+                .backlogged_groups()
+                .map(|g| self.groups[g].start)
+                .fold(f64::INFINITY, f64::min);
+        }
+        let group = self
+            .backlogged_groups()
+            .filter(|&g| eligible(self, g))
+            .min_by(|&a, &b| self.group_finish(a).total_cmp(&self.group_finish(b)))?;
+
+        let idx = self.pop(group);
+        let (_, finish) = self.tags[idx].take().unwrap();
+        self.finish[idx] = finish;
+        self.virtual_time += self.base.flows[idx]
+            .peek_packet(self.base.timer)
+            .unwrap()
+            .len as f64;
+        Some(idx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        schedulers::wfq::WFQScheduler,
+        FlowId, Packet, Scheduler,
+    };
+
+    use super::QFQScheduler;
+
+    /// One flow with half of the link and ten flows sharing the other half,
+    /// all backlogged at time 0.
+    fn load(scheduler: &mut dyn Scheduler) {
+        let mut heavy = VariableLengthFlow::new();
+        for p in 0..11 {
+            heavy.packet_arrive(Packet::new(format!("a{}", p), 1), 0);
+        }
+        scheduler.add_flow(Box::new(heavy), 0.5);
+
+        for f in 0..10 {
+            let mut light = VariableLengthFlow::new();
+            light.packet_arrive(Packet::new(format!("b{}", f), 1), 0);
+            scheduler.add_flow(Box::new(light), 0.05);
+        }
+    }
+
+    #[test]
+    fn qfq_test() {
+        let mut qfq = QFQScheduler::new(1);
+        qfq.set_max_packet_len(1);
+        load(&mut qfq);
+        assert_eq!(qfq.group_of(FlowId(0)), 1);
+        assert_eq!(qfq.group_of(FlowId(1)), 5);
+        qfq.run();
+
+        let mut wfq = WFQScheduler::new(1);
+        load(&mut wfq);
+        wfq.run();
+
+        let heavy = |output: &[Packet]| -> Vec<bool> {
+            output[..10]
+                .iter()
+                .map(|p| p.name.starts_with('a'))
+                .collect()
+        };
+
+        // WFQ sends the heavy flow as one burst, QFQ interleaves it
+        // with the light flows as WF2Q+ does.
+        assert!(heavy(wfq.output()).iter().all(|&h| h));
+        assert_eq!(heavy(qfq.output()), [true, false].repeat(5));
+        assert_eq!(qfq.output().len(), 21);
+    }
+
+    #[test]
+    fn qfq_share_test() {
+        let mut qfq = QFQScheduler::new(1);
+        for (prefix, weight) in [("a", 1f64), ("b", 3f64)] {
+            let mut flow = VariableLengthFlow::new();
+            for p in 0..100 {
+                flow.packet_arrive(Packet::new(format!("{}{}", prefix, p), 1), 0);
+            }
+            qfq.add_flow(flow, weight);
+        }
+        qfq.set_max_packet_len(1);
+        qfq.run();
+
+        // While both flows are backlogged, b gets three times the service of a.
+        let b = qfq.output()[..80]
+            .iter()
+            .filter(|p| p.name.starts_with('b'))
+            .count();
+        assert!((58..=62).contains(&b), "b sent {}", b);
+
+        let output = qfq.output().to_vec();
+        qfq.reset();
+        qfq.run();
+        assert_eq!(qfq.output(), output);
+    }
+}
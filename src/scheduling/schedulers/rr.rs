@@ -0,0 +1,173 @@
+use crate::scheduling::{
+    flow::Flow,
+    schedulers::base::SchedulerBase,
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// Round Robin (RR) scheduler.
+///
+/// Cycles over the flows and serves one packet per flow per round,
+/// skipping the flows without an arrived packet.
+#[derive(Clone)]
+pub struct RRScheduler {
+    base: SchedulerBase,
+    /// Index of the flow to visit first in the next decision.
+    next_flow: usize,
+}
+
+impl RRScheduler {
+    pub fn new(bandwidth: usize) -> RRScheduler {
+        RRScheduler {
+            base: SchedulerBase::new(bandwidth),
+            next_flow: 0,
+        }
+    }
+
+    pub fn add_flow(&mut self, flow: impl Flow + 'static) -> FlowId {
+        Scheduler::add_flow(self, Box::new(flow), 1f64)
+    }
+}
+
+impl Scheduler for RRScheduler {
+    fn add_flow(&mut self, flow: Box<dyn Flow>, _weight: f64) -> FlowId {
+        self.base.add_flow(flow)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.next_flow = 0;
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
+    }
+}
+
+impl Tickable for RRScheduler {
+    fn tick(&mut self) -> bool {
+        if self.base.all_empty() {
+            return false;
+        }
+
+        // Decide only when the port takes a packet, so that late arrivals
+        // still get their turn in the current round.
+        if self.base.output_port.is_accepting() {
+            if let Some(idx) = self.schedule() {
+                self.base.serve(idx);
+            }
+        }
+
+        self.base.record_queues();
+        self.base.advance();
+
+        true
+    }
+}
+
+impl Schedulable<Option<usize>> for RRScheduler {
+    /// Return the index of the next flow in the round with an arrived packet.
+    fn schedule(&mut self) -> Option<usize> {
+        let n = self.base.flows.len();
+        for offset in 0..n {
+            let idx = (self.next_flow + offset) % n;
+            if self.base.flows[idx].peek_packet(self.base.timer).is_some() {
+                self.next_flow = (idx + 1) % n;
+                return Some(idx);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        Packet, Scheduler,
+    };
+
+    use super::RRScheduler;
+
+    #[test]
+    fn rr_test() {
+        let mut rr = RRScheduler::new(1);
+
+        let mut flow1 = VariableLengthFlow::new();
+        flow1.packet_arrive(Packet::new("a1", 1), 0);
+        flow1.packet_arrive(Packet::new("a2", 2), 0);
+        flow1.packet_arrive(Packet::new("a3", 1), 0);
+        rr.add_flow(flow1);
+
+        let mut flow2 = VariableLengthFlow::new();
+        flow2.packet_arrive(Packet::new("b1", 2), 0);
+        flow2.packet_arrive(Packet::new("b2", 1), 0);
+        flow2.packet_arrive(Packet::new("b3", 1), 0);
+        rr.add_flow(flow2);
+
+        rr.run();
+
+        assert_eq!(
+            rr.output(),
+            &[
+                Packet::new("a1", 1),
+                Packet::new("b1", 2),
+                Packet::new("a2", 2),
+                Packet::new("b2", 1),
+                Packet::new("a3", 1),
+                Packet::new("b3", 1),
+            ]
+        );
+        assert_eq!(rr.timer(), 8);
+    }
+}
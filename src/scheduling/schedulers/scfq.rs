@@ -0,0 +1,228 @@
+use crate::scheduling::{
+    engine::{Dequeue, EventScheduler},
+    flow::Flow,
+    schedulers::{
+        base::SchedulerBase,
+        tie_break::{TieBreak, TieBreaker},
+        virtual_tags::VirtualTags,
+    },
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// Self-Clocked Fair Queueing (SCFQ) scheduler.
+///
+/// Instead of simulating GPS, the virtual time is the virtual finish time
+/// of the packet in service. Whenever the link is free, the packet with
+/// the smallest virtual finish time is served.
+#[derive(Clone)]
+pub struct SCFQScheduler {
+    base: SchedulerBase,
+    weights: Vec<f64>,
+    virtual_tags: VirtualTags,
+    tie_break: TieBreaker,
+}
+
+impl SCFQScheduler {
+    pub fn new(bandwidth: usize) -> SCFQScheduler {
+        SCFQScheduler {
+            base: SchedulerBase::new(bandwidth),
+            weights: Vec::new(),
+            virtual_tags: VirtualTags::default(),
+            tie_break: TieBreaker::default(),
+        }
+    }
+
+    /// Add a flow to the scheduler with a weight.
+    pub fn add_flow(&mut self, flow: impl Flow + 'static, weight: f64) -> FlowId {
+        Scheduler::add_flow(self, Box::new(flow), weight)
+    }
+
+    /// Choose how flows with equal virtual finish times are ordered.
+    pub fn set_tie_break(&mut self, tie_break: TieBreak) {
+        self.tie_break = TieBreaker::new(tie_break);
+    }
+
+    /// Take the head packet of the flow to serve next,
+    /// with the index of the flow and the arrival time of the packet.
+    fn pop_next(&mut self) -> Option<(usize, Packet, usize)> {
+        let idx = self.schedule()?;
+        let weight = self.weights[idx];
+        let (packet, arrive_time) =
+            self.virtual_tags
+                .pop(&mut self.base.flows, idx, self.base.timer, weight);
+        Some((idx, packet, arrive_time))
+    }
+}
+
+impl Scheduler for SCFQScheduler {
+    fn add_flow(&mut self, flow: Box<dyn Flow>, weight: f64) -> FlowId {
+        self.weights.push(weight);
+        self.virtual_tags.add_flow();
+        self.base.add_flow(flow)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.virtual_tags.reset();
+        self.tie_break.reset();
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn flow_state(&self) -> Option<(&'static str, Vec<f64>)> {
+        Some(("finish", self.virtual_tags.finish.clone()))
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
+    }
+}
+
+impl Tickable for SCFQScheduler {
+    fn tick(&mut self) -> bool {
+        if self.base.all_empty() {
+            return false;
+        }
+
+        if self.base.output_port.is_accepting() {
+            if let Some((idx, packet, arrive_time)) = self.pop_next() {
+                self.base.submit(idx, packet, arrive_time);
+            }
+        }
+
+        self.base.record_queues();
+        self.base.advance();
+
+        true
+    }
+}
+
+impl EventScheduler for SCFQScheduler {
+    fn enqueue(&mut self, flow_idx: usize, packet: Packet, time: f64) {
+        self.base.flows[flow_idx].packet_arrive(packet, time as usize);
+    }
+
+    fn dequeue(&mut self, time: f64) -> Dequeue {
+        self.base.timer = time as usize;
+        match self.pop_next() {
+            Some((idx, packet, _)) => Dequeue::Packet(idx, packet),
+            None => Dequeue::Idle,
+        }
+    }
+}
+
+impl Schedulable<Option<usize>> for SCFQScheduler {
+    /// Return the index of the flow with the smallest virtual finish time,
+    /// advancing the virtual time to it.
+    fn schedule(&mut self) -> Option<usize> {
+        let tags = &mut self.virtual_tags;
+        tags.stamp_all(&self.base.flows, self.base.timer, |idx| self.weights[idx]);
+
+        let tagged = tags
+            .tags
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, tag)| tag.map(|(_, finish)| (idx, finish)));
+        let (flows, timer) = (&self.base.flows, self.base.timer);
+        let (idx, _) = self
+            .tie_break
+            .pick(tagged, |idx| flows[idx].queue_len(timer))?;
+        let (_, finish) = tags.take(idx);
+        tags.virtual_time = finish;
+        Some(idx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        Packet, Scheduler,
+    };
+
+    use super::SCFQScheduler;
+
+    #[test]
+    fn scfq_test() {
+        let mut scfq = SCFQScheduler::new(1);
+
+        // Same fixture as `wfq_test`.
+        let mut flow1 = VariableLengthFlow::new();
+        flow1.packet_arrive(Packet::new("p1", 1), 0);
+        flow1.packet_arrive(Packet::new("p4", 1), 2);
+        flow1.packet_arrive(Packet::new("p6", 1), 5);
+        scfq.add_flow(flow1, 0.5f64);
+
+        let mut flow2 = VariableLengthFlow::new();
+        flow2.packet_arrive(Packet::new("p2", 1), 0);
+        flow2.packet_arrive(Packet::new("p5", 1), 3);
+        flow2.packet_arrive(Packet::new("p9", 1), 7);
+        scfq.add_flow(flow2, 0.25f64);
+
+        let mut flow3 = VariableLengthFlow::new();
+        flow3.packet_arrive(Packet::new("p3", 1), 0);
+        flow3.packet_arrive(Packet::new("p7", 1), 5);
+        flow3.packet_arrive(Packet::new("p8", 1), 6);
+        scfq.add_flow(flow3, 0.25f64);
+
+        scfq.run();
+
+        assert_eq!(scfq.timer(), 9);
+        // p8 and p9 finish at the same virtual time,
+        // and ties are broken by flow order.
+        let names: Vec<&str> = scfq.output().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["p1", "p2", "p3", "p4", "p5", "p6", "p7", "p9", "p8"]
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use crate::scheduling::{
+    flow::Flow,
+    schedulers::{
+        base::SchedulerBase,
+        tie_break::{TieBreak, TieBreaker},
+        virtual_tags::VirtualTags,
+    },
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// Start-time Fair Queueing (SFQ) scheduler.
+///
+/// Every head packet carries a virtual start and finish time, and whenever
+/// the link is free the packet with the smallest virtual start time is served.
+/// The virtual time is the start time of the packet in service, so the tags
+/// never depend on the link rate and fairness holds on ports whose rate varies.
+#[derive(Clone)]
+pub struct SFQScheduler {
+    base: SchedulerBase,
+    weights: Vec<f64>,
+    virtual_tags: VirtualTags,
+    tie_break: TieBreaker,
+}
+
+impl SFQScheduler {
+    pub fn new(bandwidth: usize) -> SFQScheduler {
+        SFQScheduler {
+            base: SchedulerBase::new(bandwidth),
+            weights: Vec::new(),
+            virtual_tags: VirtualTags::default(),
+            tie_break: TieBreaker::default(),
+        }
+    }
+
+    /// Add a flow to the scheduler with a weight.
+    pub fn add_flow(&mut self, flow: impl Flow + 'static, weight: f64) -> FlowId {
+        Scheduler::add_flow(self, Box::new(flow), weight)
+    }
+
+    /// Choose how flows with equal virtual start times are ordered.
+    pub fn set_tie_break(&mut self, tie_break: TieBreak) {
+        self.tie_break = TieBreaker::new(tie_break);
+    }
+
+    /// Take the head packet of the flow to serve next,
+    /// with the index of the flow and the arrival time of the packet.
+    fn pop_next(&mut self) -> Option<(usize, Packet, usize)> {
+        let idx = self.schedule()?;
+        let weight = self.weights[idx];
+        let (packet, arrive_time) =
+            self.virtual_tags
+                .pop(&mut self.base.flows, idx, self.base.timer, weight);
+        Some((idx, packet, arrive_time))
+    }
+}
+
+impl Scheduler for SFQScheduler {
+    fn add_flow(&mut self, flow: Box<dyn Flow>, weight: f64) -> FlowId {
+        self.weights.push(weight);
+        self.virtual_tags.add_flow();
+        self.base.add_flow(flow)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.virtual_tags.reset();
+        self.tie_break.reset();
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn flow_state(&self) -> Option<(&'static str, Vec<f64>)> {
+        Some(("finish", self.virtual_tags.finish.clone()))
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
+    }
+}
+
+impl Tickable for SFQScheduler {
+    fn tick(&mut self) -> bool {
+        if self.base.all_empty() {
+            return false;
+        }
+
+        if self.base.output_port.is_accepting() {
+            if let Some((idx, packet, arrive_time)) = self.pop_next() {
+                self.base.submit(idx, packet, arrive_time);
+            }
+        }
+
+        self.base.record_queues();
+        self.base.advance();
+
+        true
+    }
+}
+
+impl Schedulable<Option<usize>> for SFQScheduler {
+    /// Return the index of the flow with the smallest virtual start time,
+    /// advancing the virtual time to it.
+    fn schedule(&mut self) -> Option<usize> {
+        // Decisions are made once the previous packet is sent, so without
+        // a tagged head the system has been idle: the virtual time jumps
+        // to the largest finish time served so far.
+        let tags = &mut self.virtual_tags;
+        if tags.tags.iter().all(|tag| tag.is_none()) {
+            tags.virtual_time = tags.finish.iter().copied().fold(0f64, f64::max);
+        }
+        tags.stamp_all(&self.base.flows, self.base.timer, |idx| self.weights[idx]);
+
+        let tagged = tags
+            .tags
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, tag)| tag.map(|(start, _)| (idx, start)));
+        let (flows, timer) = (&self.base.flows, self.base.timer);
+        let (idx, start) = self
+            .tie_break
+            .pick(tagged, |idx| flows[idx].queue_len(timer))?;
+        tags.take(idx);
+        tags.virtual_time = start;
+        Some(idx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        Packet, Scheduler,
+    };
+
+    use super::SFQScheduler;
+
+    fn scheduler() -> SFQScheduler {
+        let mut sfq = SFQScheduler::new(1);
+        for (prefix, weight) in [("a", 2f64), ("b", 1f64)] {
+            let mut flow = VariableLengthFlow::new();
+            for p in 0..12 {
+                flow.packet_arrive(Packet::new(format!("{}{}", prefix, p), 2), 0);
+            }
+            sfq.add_flow(flow, weight);
+        }
+        sfq
+    }
+
+    #[test]
+    fn sfq_test() {
+        let mut sfq = scheduler();
+        sfq.run();
+
+        // While both flows are backlogged, they share the link 2:1.
+        let window = &sfq.output()[..12];
+        let count = |prefix: char| window.iter().filter(|p| p.name.starts_with(prefix)).count();
+        assert_eq!(count('a'), 8);
+        assert_eq!(count('b'), 4);
+        assert_eq!(sfq.output().len(), 24);
+    }
+
+    #[test]
+    fn sfq_variable_rate_test() {
+        let mut constant = scheduler();
+        constant.run();
+
+        let mut variable = scheduler();
+        variable
+            .get_output_port()
+            .set_rate_profile(vec![(4, 4), (10, 1), (20, 3)]);
+        variable.run();
+
+        // The order of service does not depend on the link rate.
+        assert_eq!(variable.output(), constant.output());
+        assert!(variable.timer() < constant.timer());
+    }
+}
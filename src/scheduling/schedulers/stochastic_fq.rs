@@ -0,0 +1,418 @@
+use std::{
+    collections::VecDeque,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use crate::scheduling::{
+    flow::Flow,
+    schedulers::base::SchedulerBase,
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// Default number of buckets flow keys are hashed into.
+pub const DEFAULT_SFQ_DIVISOR: usize = 1024;
+
+/// Default number of bytes a bucket may send per round.
+pub const DEFAULT_SFQ_QUANTUM: usize = 1;
+
+/// Default number of packets held across all buckets.
+pub const DEFAULT_SFQ_LIMIT: usize = 127;
+
+/// A packet waiting in a bucket of the scheduler.
+#[derive(Debug, Clone)]
+struct Enqueued {
+    packet: Packet,
+    flow_idx: usize,
+    arrive_time: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Bucket {
+    packets: VecDeque<Enqueued>,
+    deficit: isize,
+    /// Whether the bucket is in the round robin.
+    active: bool,
+}
+
+/// Stochastic Fairness Queueing scheduler, as in the Linux sfq qdisc.
+///
+/// Every arriving packet is hashed by its flow key, its
+/// [`flow_id`](Packet::flow_id) or else the flow it came from, into one of
+/// a fixed number of buckets, and the backlogged buckets are served by
+/// deficit round robin. Flows whose keys collide share a bucket and its
+/// share of the link, so the hash can be perturbed periodically to spread
+/// the unfairness over all flows, moving the queued packets to their new
+/// buckets. When the packets held across all buckets exceed the limit,
+/// the longest bucket drops its newest packet.
+///
+/// Not to be confused with Start-time Fair Queueing,
+/// see [`SFQScheduler`](super::sfq::SFQScheduler).
+#[derive(Clone)]
+pub struct StochasticFQScheduler {
+    base: SchedulerBase,
+    buckets: Vec<Bucket>,
+    quantum: usize,
+    limit: usize,
+    /// Seed mixed into the hash, as set and as perturbed since.
+    initial_perturbation: u64,
+    perturbation: u64,
+    /// Ticks between two perturbations of the hash, never if None.
+    perturb_period: Option<usize>,
+    /// Buckets in round robin order.
+    active: VecDeque<usize>,
+    queued: usize,
+}
+
+impl StochasticFQScheduler {
+    pub fn new(bandwidth: usize) -> StochasticFQScheduler {
+        StochasticFQScheduler {
+            base: SchedulerBase::new(bandwidth),
+            buckets: vec![Bucket::default(); DEFAULT_SFQ_DIVISOR],
+            quantum: DEFAULT_SFQ_QUANTUM,
+            limit: DEFAULT_SFQ_LIMIT,
+            initial_perturbation: 0,
+            perturbation: 0,
+            perturb_period: None,
+            active: VecDeque::new(),
+            queued: 0,
+        }
+    }
+
+    /// Hash the flow keys into `divisor` buckets, dropping what is queued.
+    pub fn set_divisor(&mut self, divisor: usize) {
+        assert!(divisor > 0, "SFQ needs at least one bucket");
+        self.buckets = vec![Bucket::default(); divisor];
+        self.active.clear();
+        self.queued = 0;
+    }
+
+    /// Set the number of bytes a bucket may send per round.
+    pub fn set_quantum(&mut self, quantum: usize) {
+        assert!(quantum > 0, "the quantum must be positive");
+        self.quantum = quantum;
+    }
+
+    /// Set the number of packets held across all buckets.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
+    /// Set the seed of the hash, which decides which flows collide.
+    pub fn set_perturbation(&mut self, perturbation: u64) {
+        self.initial_perturbation = perturbation;
+        self.perturbation = perturbation;
+    }
+
+    /// Perturb the hash every `period` ticks, or never.
+    pub fn set_perturb_period(&mut self, period: Option<usize>) {
+        assert!(
+            period != Some(0),
+            "the perturbation period must be positive"
+        );
+        self.perturb_period = period;
+    }
+
+    pub fn add_flow(&mut self, flow: impl Flow + 'static) -> FlowId {
+        Scheduler::add_flow(self, Box::new(flow), 1f64)
+    }
+
+    /// The bucket a flow key is hashed into under the current perturbation.
+    pub fn bucket_of(&self, key: usize) -> usize {
+        let mut hasher = DefaultHasher::new();
+        (self.perturbation, key).hash(&mut hasher);
+        (hasher.finish() % self.buckets.len() as u64) as usize
+    }
+
+    /// The flow key of a packet from a flow.
+    fn key(packet: &Packet, flow_idx: usize) -> usize {
+        packet.flow_id.unwrap_or(flow_idx)
+    }
+
+    /// Put a packet in the bucket of its key, without dropping.
+    fn push(&mut self, entry: Enqueued) {
+        let idx = self.bucket_of(Self::key(&entry.packet, entry.flow_idx));
+        let bucket = &mut self.buckets[idx];
+        bucket.packets.push_back(entry);
+        if !bucket.active {
+            bucket.active = true;
+            bucket.deficit = self.quantum as isize;
+            self.active.push_back(idx);
+        }
+    }
+
+    /// Put an arrived packet in the bucket of its key.
+    fn enqueue(&mut self, flow_idx: usize, packet: Packet, arrive_time: usize) {
+        self.push(Enqueued {
+            packet,
+            flow_idx,
+            arrive_time,
+        });
+
+        self.queued += 1;
+        if self.queued > self.limit {
+            let longest = (0..self.buckets.len())
+                .max_by_key(|&i| self.buckets[i].packets.len())
+                .unwrap();
+            let tail = self.buckets[longest].packets.pop_back().unwrap();
+            self.queued -= 1;
+            self.base.drops.record(
+                tail.flow_idx,
+                &tail.packet,
+                tail.arrive_time,
+                self.base.timer,
+            );
+        }
+    }
+
+    /// Change the seed of the hash and move the queued packets
+    /// to their new buckets, keeping the order of every flow.
+    fn perturb(&mut self) {
+        let mut hasher = DefaultHasher::new();
+        (self.perturbation, self.base.timer).hash(&mut hasher);
+        self.perturbation = hasher.finish();
+
+        let order: Vec<usize> = self.active.drain(..).collect();
+        let mut entries = Vec::new();
+        for idx in order {
+            let bucket = &mut self.buckets[idx];
+            entries.extend(bucket.packets.drain(..));
+            bucket.active = false;
+        }
+        for entry in entries {
+            self.push(entry);
+        }
+    }
+}
+
+impl Scheduler for StochasticFQScheduler {
+    /// Add a flow. SFQ has no weights, so the weight is ignored.
+    fn add_flow(&mut self, flow: Box<dyn Flow>, _weight: f64) -> FlowId {
+        self.base.add_flow(flow)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.set_divisor(self.buckets.len());
+        self.perturbation = self.initial_perturbation;
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
+    }
+}
+
+impl Tickable for StochasticFQScheduler {
+    fn tick(&mut self) -> bool {
+        if self.queued == 0 && self.base.all_empty() {
+            return false;
+        }
+
+        if self
+            .perturb_period
+            .is_some_and(|period| self.base.timer > 0 && self.base.timer.is_multiple_of(period))
+        {
+            self.perturb();
+        }
+
+        for idx in 0..self.base.flows.len() {
+            while self.base.flows[idx].peek_packet(self.base.timer).is_some() {
+                let arrive_time = self.base.flows[idx].next_arrival().unwrap();
+                let packet = self.base.flows[idx].pop_packet();
+                self.enqueue(idx, packet, arrive_time);
+            }
+        }
+
+        if self.base.output_port.is_accepting() {
+            if let Some(entry) = self.schedule() {
+                let idx = entry.flow_idx;
+                self.base.submit(idx, entry.packet, entry.arrive_time);
+            }
+        }
+
+        // The packets of a flow wait in the bucket its key is hashed into.
+        if self.base.queue_series.is_due(self.base.timer) {
+            let mut lens = vec![0; self.base.flows.len()];
+            for entry in self.buckets.iter().flat_map(|b| &b.packets) {
+                lens[entry.flow_idx] += 1;
+            }
+            let port = self.base.output_port.queue_len();
+            self.base.queue_series.record(self.base.timer, port, lens);
+        }
+
+        self.base.advance();
+
+        true
+    }
+}
+
+impl Schedulable<Option<Enqueued>> for StochasticFQScheduler {
+    /// Take the next packet in deficit round robin order.
+    fn schedule(&mut self) -> Option<Enqueued> {
+        loop {
+            let idx = *self.active.front()?;
+            let bucket = &mut self.buckets[idx];
+            if bucket.deficit <= 0 {
+                bucket.deficit += self.quantum as isize;
+                self.active.rotate_left(1);
+                continue;
+            }
+            let Some(head) = bucket.packets.pop_front() else {
+                bucket.active = false;
+                self.active.pop_front();
+                continue;
+            };
+            bucket.deficit -= head.packet.len as isize;
+            self.queued -= 1;
+            return Some(head);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        stats::fairness::Fairness,
+        FlowId, Packet, Scheduler,
+    };
+
+    use super::StochasticFQScheduler;
+
+    /// Three flows sending a packet every tick on a link of one
+    /// packet per tick, the first two with keys hashed together.
+    fn colliding(perturb_period: Option<usize>) -> StochasticFQScheduler {
+        let mut sfq = StochasticFQScheduler::new(1);
+        sfq.set_divisor(2);
+        let b = (1..)
+            .find(|&key| sfq.bucket_of(key) == sfq.bucket_of(0))
+            .unwrap();
+        let c = (1..)
+            .find(|&key| sfq.bucket_of(key) != sfq.bucket_of(0))
+            .unwrap();
+        for (name, key) in [("a", 0), ("b", b), ("c", c)] {
+            let mut flow = VariableLengthFlow::new();
+            for t in 0..200 {
+                let packet = Packet::new(format!("{}{}", name, t), 1).with_flow_id(key);
+                flow.packet_arrive(packet, t);
+            }
+            sfq.add_flow(flow);
+        }
+        sfq.set_limit(1000);
+        sfq.set_perturb_period(perturb_period);
+        sfq
+    }
+
+    fn fairness(sfq: &StochasticFQScheduler) -> Fairness {
+        Fairness::compute(&sfq.scheduler_stats(), &[1f64; 3], 0, 200)
+    }
+
+    #[test]
+    fn sfq_collision_test() {
+        let mut sfq = colliding(None);
+        sfq.run();
+
+        // The colliding flows share half of the link, the other flow
+        // gets the other half to itself.
+        let served = fairness(&sfq).served;
+        assert!((45..=55).contains(&served[0]), "a got {}", served[0]);
+        assert!((45..=55).contains(&served[1]), "b got {}", served[1]);
+        assert!((95..=105).contains(&served[2]), "c got {}", served[2]);
+        assert!(fairness(&sfq).jain_index < 0.9);
+        assert_eq!(sfq.output().len(), 600);
+    }
+
+    #[test]
+    fn sfq_perturbation_test() {
+        let mut sfq = colliding(None);
+        sfq.run();
+        let fixed = fairness(&sfq).jain_index;
+
+        // Perturbing the hash spreads the collisions over the flows,
+        // and keeps the packets of every flow in order.
+        let mut sfq = colliding(Some(10));
+        sfq.run();
+        assert!(fairness(&sfq).jain_index > fixed);
+        for prefix in ['a', 'b', 'c'] {
+            let order: Vec<usize> = sfq
+                .output()
+                .iter()
+                .filter(|p| p.name.starts_with(prefix))
+                .map(|p| p.name[1..].parse().unwrap())
+                .collect();
+            assert!(order.windows(2).all(|w| w[0] < w[1]));
+        }
+
+        let output = sfq.output().to_vec();
+        sfq.reset();
+        sfq.run();
+        assert_eq!(sfq.output(), output);
+    }
+
+    #[test]
+    fn sfq_limit_test() {
+        let mut sfq = StochasticFQScheduler::new(1);
+        sfq.set_limit(4);
+        let mut flow = VariableLengthFlow::new();
+        for p in 0..8 {
+            flow.packet_arrive(Packet::new(format!("p{}", p), 1), 0);
+        }
+        sfq.add_flow(flow);
+        sfq.run();
+
+        // The bucket keeps the oldest packets, one of them already sent.
+        let names: Vec<&str> = sfq.output().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["p0", "p1", "p2", "p3"]);
+        assert_eq!(sfq.dropped_count(FlowId(0)), 4);
+    }
+}
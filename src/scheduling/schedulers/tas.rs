@@ -0,0 +1,259 @@
+use crate::scheduling::{
+    flow::Flow,
+    schedulers::base::SchedulerBase,
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// An entry of a gate control list: the gates open for `duration` ticks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GateControlEntry {
+    pub duration: usize,
+    /// The flows whose gate is open, the others are closed.
+    pub open: Vec<FlowId>,
+}
+
+impl GateControlEntry {
+    pub fn new(duration: usize, open: impl Into<Vec<FlowId>>) -> GateControlEntry {
+        GateControlEntry {
+            duration,
+            open: open.into(),
+        }
+    }
+}
+
+/// Time-Aware Shaper (TAS) scheduler, as in IEEE 802.1Qbv.
+///
+/// Every flow is a queue behind a transmission gate. A gate control list
+/// repeats in cycles and sets which gates are open at each tick. Among
+/// the flows with an open gate, the highest priority is served as in
+/// [`SPScheduler`](super::sp::SPScheduler). As a guard band, a packet is
+/// only started if its transmission ends before its gate closes.
+/// Without a gate control list, all gates are always open.
+pub struct TASScheduler {
+    base: SchedulerBase,
+    /// Priority level of each flow, higher is served first.
+    priorities: Vec<usize>,
+    gate_control_list: Vec<GateControlEntry>,
+}
+
+impl TASScheduler {
+    pub fn new(bandwidth: usize) -> TASScheduler {
+        TASScheduler {
+            base: SchedulerBase::new(bandwidth),
+            priorities: Vec::new(),
+            gate_control_list: Vec::new(),
+        }
+    }
+
+    /// Add a flow with a priority level, higher is served first.
+    pub fn add_flow(&mut self, flow: impl Flow + 'static, priority: usize) -> FlowId {
+        Scheduler::add_flow(self, Box::new(flow), priority as f64)
+    }
+
+    /// Set the gate control list, repeated from tick 0.
+    pub fn set_gate_control_list(&mut self, entries: Vec<GateControlEntry>) {
+        assert!(
+            entries.iter().all(|e| e.duration > 0),
+            "gate control entries must last at least one tick"
+        );
+        self.gate_control_list = entries;
+    }
+
+    /// For how many ticks from `time` on the gate of a flow stays open,
+    /// `usize::MAX` if it never closes.
+    fn open_ticks(&self, flow: FlowId, time: usize) -> usize {
+        let entries = &self.gate_control_list;
+        let cycle: usize = entries.iter().map(|e| e.duration).sum();
+        if cycle == 0 {
+            return usize::MAX;
+        }
+
+        let mut offset = time % cycle;
+        let mut current = 0;
+        while offset >= entries[current].duration {
+            offset -= entries[current].duration;
+            current += 1;
+        }
+
+        let mut ticks = 0;
+        let mut remaining = entries[current].duration - offset;
+        for step in 0..entries.len() {
+            let entry = &entries[(current + step) % entries.len()];
+            if !entry.open.contains(&flow) {
+                return ticks;
+            }
+            ticks += remaining;
+            remaining = entries[(current + step + 1) % entries.len()].duration;
+        }
+        usize::MAX
+    }
+}
+
+impl Scheduler for TASScheduler {
+    /// Add a flow with the weight used as its priority level.
+    fn add_flow(&mut self, flow: Box<dyn Flow>, weight: f64) -> FlowId {
+        self.priorities.push(weight.round() as usize);
+        self.base.add_flow(flow)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
+    }
+}
+
+impl Tickable for TASScheduler {
+    fn tick(&mut self) -> bool {
+        if self.base.all_empty() {
+            return false;
+        }
+
+        // Decide only when the port takes a packet, so that a higher
+        // priority arriving meanwhile overtakes the waiting packets.
+        if self.base.output_port.is_accepting() {
+            if let Some(idx) = self.schedule() {
+                self.base.serve(idx);
+            }
+        }
+
+        self.base.record_queues();
+        self.base.advance();
+
+        true
+    }
+}
+
+impl Schedulable<Option<usize>> for TASScheduler {
+    /// Return the index of the highest-priority flow with an arrived packet
+    /// that can be sent before its gate closes.
+    fn schedule(&mut self) -> Option<usize> {
+        let rate = self.base.output_port.get_bandwidth();
+        let mut best: Option<usize> = None;
+        for (idx, flow) in self.base.flows.iter().enumerate() {
+            let Some(packet) = flow.peek_packet(self.base.timer) else {
+                continue;
+            };
+            if self.open_ticks(FlowId(idx), self.base.timer) < packet.len.div_ceil(rate) {
+                continue;
+            }
+            if best.is_none_or(|b| self.priorities[idx] > self.priorities[b]) {
+                best = Some(idx);
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        Packet, Scheduler,
+    };
+
+    use super::{GateControlEntry, TASScheduler};
+
+    #[test]
+    fn tas_test() {
+        let mut a = VariableLengthFlow::new();
+        for name in ["a0", "a1", "a2"] {
+            a.packet_arrive(Packet::new(name, 3), 0);
+        }
+        let mut b = VariableLengthFlow::new();
+        for name in ["b0", "b1"] {
+            b.packet_arrive(Packet::new(name, 2), 0);
+        }
+
+        let mut tas = TASScheduler::new(1);
+        let a = tas.add_flow(a, 1);
+        let b = tas.add_flow(b, 0);
+        tas.set_gate_control_list(vec![
+            GateControlEntry::new(4, [a]),
+            GateControlEntry::new(6, [b]),
+        ]);
+        tas.run();
+
+        // a1 does not fit in what is left of the first window of a,
+        // and b waits for its own window despite the idle link.
+        let names: Vec<&str> = tas.output().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["a0", "b0", "b1", "a1", "a2"]);
+        assert_eq!(
+            tas.get_output_port().get_departure_times(),
+            &[3, 6, 8, 13, 23]
+        );
+    }
+
+    #[test]
+    fn tas_open_ticks_test() {
+        let mut tas = TASScheduler::new(1);
+        let a = tas.add_flow(VariableLengthFlow::new(), 0);
+        let b = tas.add_flow(VariableLengthFlow::new(), 0);
+        assert_eq!(tas.open_ticks(a, 7), usize::MAX);
+
+        // The gate of a stays open across the end of the cycle.
+        tas.set_gate_control_list(vec![
+            GateControlEntry::new(2, [a]),
+            GateControlEntry::new(3, [b]),
+            GateControlEntry::new(1, [a, b]),
+        ]);
+        assert_eq!(tas.open_ticks(a, 5), 3);
+        assert_eq!(tas.open_ticks(a, 1), 1);
+        assert_eq!(tas.open_ticks(a, 2), 0);
+        assert_eq!(tas.open_ticks(b, 8), 4);
+    }
+}
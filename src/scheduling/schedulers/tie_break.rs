@@ -0,0 +1,127 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Keys closer than this are taken as equal.
+pub const EPSILON: f64 = 1e-9;
+
+/// How a scheduler chooses between flows it ranks equal,
+/// such as flows with the same virtual finish time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TieBreak {
+    /// The flow added first.
+    #[default]
+    LowestIndex,
+    /// The first tied flow after the flow served last, wrapping around.
+    RoundRobin,
+    /// Any tied flow, uniformly, reproducible from the seed.
+    Random { seed: u64 },
+    /// The flow with the most packets waiting, then the flow added first.
+    LongestQueue,
+}
+
+/// Applies a [`TieBreak`] to the decisions of a scheduler.
+#[derive(Debug, Clone)]
+pub struct TieBreaker {
+    policy: TieBreak,
+    rng: StdRng,
+    last: Option<usize>,
+}
+
+impl TieBreaker {
+    pub fn new(policy: TieBreak) -> TieBreaker {
+        let seed = match policy {
+            TieBreak::Random { seed } => seed,
+            _ => 0,
+        };
+        TieBreaker {
+            policy,
+            rng: StdRng::seed_from_u64(seed),
+            last: None,
+        }
+    }
+
+    pub fn policy(&self) -> TieBreak {
+        self.policy
+    }
+
+    /// Choose among flows and their keys one with the smallest key.
+    /// Returns None if there is no candidate.
+    pub fn pick(
+        &mut self,
+        candidates: impl IntoIterator<Item = (usize, f64)>,
+        queue_len: impl Fn(usize) -> usize,
+    ) -> Option<(usize, f64)> {
+        let candidates: Vec<(usize, f64)> = candidates.into_iter().collect();
+        let min = candidates
+            .iter()
+            .map(|&(_, key)| key)
+            .fold(f64::INFINITY, f64::min);
+        let tied: Vec<(usize, f64)> = candidates
+            .into_iter()
+            .filter(|&(_, key)| key <= min + EPSILON)
+            .collect();
+        let chosen = match self.policy {
+            TieBreak::LowestIndex => tied.first().copied(),
+            TieBreak::RoundRobin => tied
+                .iter()
+                .find(|&&(idx, _)| self.last.is_none_or(|last| idx > last))
+                .or(tied.first())
+                .copied(),
+            TieBreak::Random { .. } if !tied.is_empty() => {
+                Some(tied[self.rng.gen_range(0..tied.len())])
+            }
+            TieBreak::Random { .. } => None,
+            TieBreak::LongestQueue => tied
+                .iter()
+                .copied()
+                .rev()
+                .max_by_key(|&(idx, _)| queue_len(idx)),
+        };
+        if let Some((idx, _)) = chosen {
+            self.last = Some(idx);
+        }
+        chosen
+    }
+
+    /// Forget past decisions and restart the random choices.
+    pub fn reset(&mut self) {
+        *self = TieBreaker::new(self.policy);
+    }
+}
+
+impl Default for TieBreaker {
+    fn default() -> TieBreaker {
+        TieBreaker::new(TieBreak::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TieBreak, TieBreaker};
+
+    #[test]
+    fn tie_breaker_test() {
+        let candidates = [(0, 2f64), (1, 1f64), (3, 1f64), (4, 1f64 + 1e-12)];
+        let queues = |idx: usize| [0, 1, 0, 5, 5][idx];
+        let picks = |policy| {
+            let mut tie_breaker = TieBreaker::new(policy);
+            (0..4)
+                .map(|_| tie_breaker.pick(candidates, queues).unwrap().0)
+                .collect::<Vec<usize>>()
+        };
+
+        assert_eq!(picks(TieBreak::LowestIndex), vec![1, 1, 1, 1]);
+        assert_eq!(picks(TieBreak::RoundRobin), vec![1, 3, 4, 1]);
+        // Both longest queues tie, the lower index wins.
+        assert_eq!(picks(TieBreak::LongestQueue), vec![3, 3, 3, 3]);
+        let random = picks(TieBreak::Random { seed: 1 });
+        assert_eq!(random, picks(TieBreak::Random { seed: 1 }));
+        assert!(random.iter().all(|idx| [1, 3, 4].contains(idx)));
+
+        let mut tie_breaker = TieBreaker::new(TieBreak::RoundRobin);
+        assert_eq!(tie_breaker.pick([], queues), None);
+        tie_breaker.pick(candidates, queues);
+        tie_breaker.reset();
+        assert_eq!(tie_breaker.pick(candidates, queues), Some((1, 1f64)));
+    }
+}
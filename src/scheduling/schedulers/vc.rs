@@ -0,0 +1,243 @@
+use crate::scheduling::{
+    flow::Flow,
+    schedulers::{
+        base::SchedulerBase,
+        tie_break::{TieBreak, TieBreaker},
+        ServiceMode,
+    },
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// Virtual Clock scheduler.
+///
+/// Every flow reserves a rate, and each packet is stamped with the time it
+/// would finish if its flow were sent at exactly that rate since the packet
+/// arrived. Whenever the link is free, the smallest stamp is served.
+/// The stamps follow real time rather than the other flows, so a flow that
+/// used idle bandwidth beyond its reservation is held back later on.
+///
+/// Run [`ServiceMode::NonWorkConserving`], a packet is only eligible from
+/// the time it would start if its flow were sent at its reserved rate, so
+/// no flow ever exceeds its reservation.
+#[derive(Clone)]
+pub struct VirtualClockScheduler {
+    base: SchedulerBase,
+    /// Reserved rate of each flow, in bytes per tick.
+    rates: Vec<f64>,
+    /// Virtual clock of each flow: the stamp of its last served packet.
+    clocks: Vec<f64>,
+    tie_break: TieBreaker,
+    mode: ServiceMode,
+}
+
+impl VirtualClockScheduler {
+    pub fn new(bandwidth: usize) -> VirtualClockScheduler {
+        VirtualClockScheduler {
+            base: SchedulerBase::new(bandwidth),
+            rates: Vec::new(),
+            clocks: Vec::new(),
+            tie_break: TieBreaker::default(),
+            mode: ServiceMode::default(),
+        }
+    }
+
+    /// Add a flow with its reserved rate, in bytes per tick.
+    pub fn add_flow(&mut self, flow: impl Flow + 'static, rate: f64) -> FlowId {
+        Scheduler::add_flow(self, Box::new(flow), rate)
+    }
+
+    /// Choose how flows with equal stamps are ordered.
+    pub fn set_tie_break(&mut self, tie_break: TieBreak) {
+        self.tie_break = TieBreaker::new(tie_break);
+    }
+
+    /// Choose whether the link may run ahead of the reserved rates.
+    pub fn set_service_mode(&mut self, mode: ServiceMode) {
+        self.mode = mode;
+    }
+}
+
+impl Scheduler for VirtualClockScheduler {
+    /// Add a flow with the weight used as its reserved rate.
+    fn add_flow(&mut self, flow: Box<dyn Flow>, weight: f64) -> FlowId {
+        assert!(weight > 0f64, "reserved rates must be positive");
+        self.rates.push(weight);
+        self.clocks.push(0f64);
+        self.base.add_flow(flow)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.clocks.fill(0f64);
+        self.tie_break.reset();
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn flow_state(&self) -> Option<(&'static str, Vec<f64>)> {
+        Some(("clock", self.clocks.clone()))
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
+    }
+}
+
+impl Tickable for VirtualClockScheduler {
+    fn tick(&mut self) -> bool {
+        if self.base.all_empty() {
+            return false;
+        }
+
+        if self.base.output_port.is_accepting() {
+            if let Some(idx) = self.schedule() {
+                self.base.serve(idx);
+            }
+        }
+
+        self.base.record_queues();
+        self.base.advance();
+
+        true
+    }
+}
+
+impl Schedulable<Option<usize>> for VirtualClockScheduler {
+    /// Return the index of the flow whose eligible head packet has the
+    /// smallest stamp, advancing the virtual clock of that flow.
+    fn schedule(&mut self) -> Option<usize> {
+        let mut stamps = Vec::new();
+        for (idx, flow) in self.base.flows.iter().enumerate() {
+            let Some(packet) = flow.peek_packet(self.base.timer) else {
+                continue;
+            };
+            let arrive_time = flow.next_arrival().unwrap() as f64;
+            let start = self.clocks[idx].max(arrive_time);
+            if self.mode == ServiceMode::NonWorkConserving && start > self.base.timer as f64 {
+                continue;
+            }
+            stamps.push((idx, start + packet.len as f64 / self.rates[idx]));
+        }
+
+        let (flows, timer) = (&self.base.flows, self.base.timer);
+        let (idx, stamp) = self
+            .tie_break
+            .pick(stamps, |idx| flows[idx].queue_len(timer))?;
+        self.clocks[idx] = stamp;
+        Some(idx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        schedulers::{scfq::SCFQScheduler, ServiceMode},
+        Packet, Scheduler,
+    };
+
+    use super::VirtualClockScheduler;
+
+    /// Two flows reserving half of the link each. The first one sends alone
+    /// for five ticks before the second one starts.
+    fn load(scheduler: &mut dyn Scheduler) {
+        let mut early = VariableLengthFlow::new();
+        for p in 0..10 {
+            early.packet_arrive(Packet::new(format!("a{}", p), 1), 0);
+        }
+        scheduler.add_flow(Box::new(early), 0.5);
+
+        let mut late = VariableLengthFlow::new();
+        for p in 0..4 {
+            late.packet_arrive(Packet::new(format!("b{}", p), 1), 5 + p);
+        }
+        scheduler.add_flow(Box::new(late), 0.5);
+    }
+
+    fn names(scheduler: &dyn Scheduler) -> Vec<&str> {
+        scheduler.output().iter().map(|p| p.name.as_str()).collect()
+    }
+
+    #[test]
+    fn virtual_clock_test() {
+        let mut vc = VirtualClockScheduler::new(1);
+        load(&mut vc);
+        vc.run();
+
+        // The early flow ran ahead of its reservation while alone,
+        // so it is held back once the late flow shows up.
+        assert_eq!(
+            names(&vc),
+            ["a0", "a1", "a2", "a3", "a4", "b0", "b1", "b2", "a5", "b3", "a6", "a7", "a8", "a9"]
+        );
+
+        // A fair discipline forgets the past and alternates instead.
+        let mut scfq = SCFQScheduler::new(1);
+        load(&mut scfq);
+        scfq.run();
+        assert_eq!(names(&scfq)[5..9], ["a5", "b0", "a6", "b1"]);
+
+        // Held to its reservation, the early flow leaves every other tick
+        // and the late flow never waits for it.
+        let mut shaped = VirtualClockScheduler::new(1);
+        shaped.set_service_mode(ServiceMode::NonWorkConserving);
+        load(&mut shaped);
+        shaped.run();
+        assert_eq!(
+            names(&shaped)[..8],
+            ["a0", "a1", "a2", "b0", "a3", "b1", "a4", "b2"]
+        );
+        let departures = shaped.get_output_port().get_departure_times().clone();
+        assert_eq!(departures[..3], [1, 3, 5]);
+        assert_eq!(shaped.timer(), 19);
+    }
+}
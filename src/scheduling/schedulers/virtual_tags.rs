@@ -0,0 +1,71 @@
+use crate::scheduling::{flow::Flow, Packet};
+
+/// Virtual start and finish times of the head packets, for the schedulers
+/// serving packets in the order of their virtual times.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct VirtualTags {
+    /// System virtual time.
+    pub virtual_time: f64,
+    /// Virtual finish time of the last packet served from each flow.
+    pub finish: Vec<f64>,
+    /// Virtual start and finish time of the head packet of each flow.
+    pub tags: Vec<Option<(f64, f64)>>,
+}
+
+impl VirtualTags {
+    pub fn add_flow(&mut self) {
+        self.finish.push(0f64);
+        self.tags.push(None);
+    }
+
+    pub fn reset(&mut self) {
+        self.virtual_time = 0f64;
+        self.finish.fill(0f64);
+        self.tags.fill(None);
+    }
+
+    /// Give the head packet of a flow its virtual start and finish time
+    /// if it has arrived by `time` and is not tagged yet, the flow being
+    /// served at `rate` in virtual time.
+    fn stamp(&mut self, idx: usize, start: f64, flow: &dyn Flow, time: usize, rate: f64) {
+        if self.tags[idx].is_some() {
+            return;
+        }
+        if let Some(packet) = flow.peek_packet(time) {
+            self.tags[idx] = Some((start, start + packet.len as f64 / rate));
+        }
+    }
+
+    /// Tag the head packets arrived by `time`. A newly backlogged flow
+    /// starts no earlier than the virtual time.
+    pub fn stamp_all(&mut self, flows: &[Box<dyn Flow>], time: usize, rate: impl Fn(usize) -> f64) {
+        for (idx, flow) in flows.iter().enumerate() {
+            let start = self.finish[idx].max(self.virtual_time);
+            self.stamp(idx, start, flow.as_ref(), time, rate(idx));
+        }
+    }
+
+    /// Take the tag of the flow chosen for service, returning its
+    /// virtual start and finish time.
+    pub fn take(&mut self, idx: usize) -> (f64, f64) {
+        let (start, finish) = self.tags[idx].take().unwrap();
+        self.finish[idx] = finish;
+        (start, finish)
+    }
+
+    /// Pop the head packet of the flow taken for service with its arrival
+    /// time. A flow that stays backlogged starts its next packet where the
+    /// previous one finished.
+    pub fn pop(
+        &mut self,
+        flows: &mut [Box<dyn Flow>],
+        idx: usize,
+        time: usize,
+        rate: f64,
+    ) -> (Packet, usize) {
+        let arrive_time = flows[idx].next_arrival().unwrap();
+        let packet = flows[idx].pop_packet();
+        self.stamp(idx, self.finish[idx], flows[idx].as_ref(), time, rate);
+        (packet, arrive_time)
+    }
+}
@@ -0,0 +1,257 @@
+use crate::scheduling::{
+    flow::Flow,
+    schedulers::{
+        base::SchedulerBase,
+        tie_break::{TieBreak, TieBreaker},
+        virtual_tags::VirtualTags,
+    },
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
+};
+
+/// Tolerance when comparing virtual times.
+const EPSILON: f64 = 1e-9;
+
+/// Worst-case Fair Weighted Fair Queueing (WF2Q+) scheduler.
+///
+/// Every head packet carries a virtual start and finish time. Whenever
+/// the link is free, only the packets whose virtual start time has been
+/// reached are eligible, and the one with the smallest virtual finish time
+/// is served. Unlike WFQ, a heavy flow cannot run ahead of its share
+/// and send a burst.
+#[derive(Clone)]
+pub struct WF2QPlusScheduler {
+    base: SchedulerBase,
+    weights: Vec<f64>,
+    total_weight: f64,
+    virtual_tags: VirtualTags,
+    tie_break: TieBreaker,
+}
+
+impl WF2QPlusScheduler {
+    pub fn new(bandwidth: usize) -> WF2QPlusScheduler {
+        WF2QPlusScheduler {
+            base: SchedulerBase::new(bandwidth),
+            weights: Vec::new(),
+            total_weight: 0f64,
+            virtual_tags: VirtualTags::default(),
+            tie_break: TieBreaker::default(),
+        }
+    }
+
+    /// Add a flow to the scheduler with a weight.
+    pub fn add_flow(&mut self, flow: impl Flow + 'static, weight: f64) -> FlowId {
+        Scheduler::add_flow(self, Box::new(flow), weight)
+    }
+
+    /// Choose how flows with equal virtual finish times are ordered.
+    pub fn set_tie_break(&mut self, tie_break: TieBreak) {
+        self.tie_break = TieBreaker::new(tie_break);
+    }
+
+    /// The total weight of the flows still taking part,
+    /// leaving out the closed flows that have drained.
+    fn active_weight(&self) -> f64 {
+        self.weights
+            .iter()
+            .zip(&self.base.flows)
+            .filter(|(_, f)| !f.retired())
+            .map(|(w, _)| w)
+            .sum()
+    }
+
+    /// Take the head packet of the flow to serve next,
+    /// with the index of the flow and the arrival time of the packet.
+    fn pop_next(&mut self) -> Option<(usize, Packet, usize)> {
+        let idx = self.schedule()?;
+        let share = self.weights[idx] / self.total_weight;
+        let (packet, arrive_time) =
+            self.virtual_tags
+                .pop(&mut self.base.flows, idx, self.base.timer, share);
+        Some((idx, packet, arrive_time))
+    }
+}
+
+impl Scheduler for WF2QPlusScheduler {
+    fn add_flow(&mut self, flow: Box<dyn Flow>, weight: f64) -> FlowId {
+        self.weights.push(weight);
+        self.total_weight += weight;
+        self.virtual_tags.add_flow();
+        self.base.add_flow(flow)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.virtual_tags.reset();
+        self.tie_break.reset();
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn flow_state(&self) -> Option<(&'static str, Vec<f64>)> {
+        Some(("finish", self.virtual_tags.finish.clone()))
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
+    }
+}
+
+impl Tickable for WF2QPlusScheduler {
+    fn tick(&mut self) -> bool {
+        if self.base.all_empty() {
+            return false;
+        }
+
+        if self.base.output_port.is_accepting() {
+            if let Some((idx, packet, arrive_time)) = self.pop_next() {
+                self.base.submit(idx, packet, arrive_time);
+            }
+        }
+
+        self.base.record_queues();
+        self.base.advance();
+
+        true
+    }
+}
+
+impl Schedulable<Option<usize>> for WF2QPlusScheduler {
+    /// Return the index of the eligible flow with the smallest
+    /// virtual finish time, advancing the virtual time.
+    fn schedule(&mut self) -> Option<usize> {
+        self.total_weight = self.active_weight();
+        let total_weight = self.total_weight;
+        let tags = &mut self.virtual_tags;
+        tags.stamp_all(&self.base.flows, self.base.timer, |idx| {
+            self.weights[idx] / total_weight
+        });
+
+        let min_start = tags
+            .tags
+            .iter()
+            .flatten()
+            .map(|(start, _)| *start)
+            .fold(f64::INFINITY, f64::min);
+        if min_start == f64::INFINITY {
+            return None;
+        }
+        tags.virtual_time = tags.virtual_time.max(min_start);
+
+        let virtual_time = tags.virtual_time;
+        let eligible = tags.tags.iter().enumerate().filter_map(|(idx, tag)| {
+            tag.filter(|&(start, _)| start <= virtual_time + EPSILON)
+                .map(|(_, finish)| (idx, finish))
+        });
+        let (flows, timer) = (&self.base.flows, self.base.timer);
+        let (idx, _) = self
+            .tie_break
+            .pick(eligible, |idx| flows[idx].queue_len(timer))?;
+        tags.take(idx);
+        tags.virtual_time += self.base.flows[idx]
+            .peek_packet(self.base.timer)
+            .unwrap()
+            .len as f64;
+        Some(idx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        schedulers::wfq::WFQScheduler,
+        Packet, Scheduler,
+    };
+
+    use super::WF2QPlusScheduler;
+
+    /// One flow with half of the link and ten flows sharing the other half,
+    /// all backlogged at time 0.
+    fn load(scheduler: &mut dyn Scheduler) {
+        let mut heavy = VariableLengthFlow::new();
+        for p in 0..11 {
+            heavy.packet_arrive(Packet::new(format!("a{}", p), 1), 0);
+        }
+        scheduler.add_flow(Box::new(heavy), 0.5);
+
+        for f in 0..10 {
+            let mut light = VariableLengthFlow::new();
+            light.packet_arrive(Packet::new(format!("b{}", f), 1), 0);
+            scheduler.add_flow(Box::new(light), 0.05);
+        }
+    }
+
+    #[test]
+    fn wf2q_plus_test() {
+        let mut wf2q = WF2QPlusScheduler::new(1);
+        load(&mut wf2q);
+        wf2q.run();
+
+        let mut wfq = WFQScheduler::new(1);
+        load(&mut wfq);
+        wfq.run();
+
+        let heavy = |output: &[Packet]| -> Vec<bool> {
+            output[..10]
+                .iter()
+                .map(|p| p.name.starts_with('a'))
+                .collect()
+        };
+
+        // WFQ sends the heavy flow as one burst ...
+        assert!(heavy(wfq.output()).iter().all(|&h| h));
+        // ... while WF2Q+ interleaves it with the light flows.
+        assert_eq!(heavy(wf2q.output()), [true, false].repeat(5),);
+        assert_eq!(wf2q.output().len(), 21);
+        assert!(wf2q.stats().iter().all(|s| s.packets > 0));
+    }
+}
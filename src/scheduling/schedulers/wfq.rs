@@ -1,61 +1,372 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+};
+
 use crate::scheduling::{
-    flow::{Flow, VariableLengthFlow},
-    Packet, Port, Schedulable, Tickable,
+    flow::Flow,
+    schedulers::{
+        base::SchedulerBase,
+        tie_break::{TieBreak, TieBreaker, EPSILON},
+    },
+    source::SchedulableSource,
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
 };
 
+/// Seed of the tie-breaking RNG used by [`WFQScheduler::new`].
+pub const DEFAULT_SEED: u64 = 0;
+
+/// A ready flow in a [`Backlog`], with the length of its head packet.
+#[derive(Debug, Clone, Copy)]
+struct Ready {
+    /// Length of the head packet over the weight of the flow, which
+    /// orders the estimated finish times whatever the total weight.
+    key: f64,
+    len: usize,
+    flow_idx: usize,
+    version: u64,
+}
+
+impl PartialEq for Ready {
+    fn eq(&self, other: &Ready) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ready {}
+
+impl PartialOrd for Ready {
+    fn partial_cmp(&self, other: &Ready) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ready {
+    /// Reversed, so that the heap pops the smallest key,
+    /// then the flow added first.
+    fn cmp(&self, other: &Ready) -> Ordering {
+        other
+            .key
+            .total_cmp(&self.key)
+            .then(other.flow_idx.cmp(&self.flow_idx))
+            .then(other.version.cmp(&self.version))
+    }
+}
+
+/// The flows with packets left, kept so that a decision does not
+/// look at every flow.
+///
+/// A flow whose head packet has arrived is ready, in a heap ordered by
+/// the estimated finish time of that packet. Any other flow with packets
+/// left is pending, in a heap ordered by the tick it is looked at again.
+/// A flow has at most one entry that counts: the entries left behind when
+/// it is moved carry an older version and are skipped as they come up.
+#[derive(Debug, Clone, Default)]
+struct Backlog {
+    ready: BinaryHeap<Ready>,
+    /// Tick, flow index and version of the pending flows, earliest first.
+    pending: BinaryHeap<Reverse<(usize, usize, u64)>>,
+    /// Version of the entry of every flow, None if the flow has none.
+    current: Vec<Option<u64>>,
+    next_version: u64,
+    /// Number of flows with an entry.
+    len: usize,
+}
+
+impl Backlog {
+    fn add_flow(&mut self) {
+        self.current.push(None);
+    }
+
+    /// Give a flow a new entry, replacing its current one.
+    fn renew(&mut self, flow_idx: usize) -> u64 {
+        self.remove(flow_idx);
+        let version = self.next_version;
+        self.next_version += 1;
+        self.current[flow_idx] = Some(version);
+        self.len += 1;
+        version
+    }
+
+    fn push_ready(&mut self, flow_idx: usize, len: usize, weight: f64) {
+        let version = self.renew(flow_idx);
+        self.ready.push(Ready {
+            key: len as f64 / weight,
+            len,
+            flow_idx,
+            version,
+        });
+    }
+
+    /// Look at a flow again at tick `time`.
+    fn push_pending(&mut self, flow_idx: usize, time: usize) {
+        let version = self.renew(flow_idx);
+        self.pending.push(Reverse((time, flow_idx, version)));
+    }
+
+    fn remove(&mut self, flow_idx: usize) {
+        if self.current[flow_idx].take().is_some() {
+            self.len -= 1;
+        }
+    }
+
+    fn is_current(&self, flow_idx: usize, version: u64) -> bool {
+        self.current[flow_idx] == Some(version)
+    }
+
+    /// Take out a pending flow to be looked at by tick `time`.
+    fn pop_due(&mut self, time: usize) -> Option<usize> {
+        while let Some(&Reverse((due, flow_idx, version))) = self.pending.peek() {
+            if due > time {
+                return None;
+            }
+            self.pending.pop();
+            if self.is_current(flow_idx, version) {
+                self.remove(flow_idx);
+                return Some(flow_idx);
+            }
+        }
+        None
+    }
+
+    /// The ready flow with the smallest key, left in place.
+    fn peek_ready(&mut self) -> Option<Ready> {
+        while let Some(&ready) = self.ready.peek() {
+            if self.is_current(ready.flow_idx, ready.version) {
+                return Some(ready);
+            }
+            self.ready.pop();
+        }
+        None
+    }
+
+    /// Take out the ready flow with the smallest key.
+    fn pop_ready(&mut self) -> Option<Ready> {
+        let ready = self.peek_ready()?;
+        self.ready.pop();
+        self.remove(ready.flow_idx);
+        Some(ready)
+    }
+
+    /// Whether no flow has packets left.
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn clear(&mut self) {
+        self.ready.clear();
+        self.pending.clear();
+        self.current.fill(None);
+        self.len = 0;
+    }
+}
+
 /// Weighted Fair Queueing (WFQ) scheduler
+#[derive(Clone)]
 pub struct WFQScheduler {
-    timer: usize,
+    /// Keeps the flows, or child schedulers in a hierarchy.
+    base: SchedulerBase<Vec<Box<dyn SchedulableSource>>>,
+    /// Breaks ties between equal estimated finish times.
+    tie_break: TieBreaker,
     weights: Vec<f64>,
     total_weight: f64,
-    flows: Vec<VariableLengthFlow>,
-    output_port: Port,
+    /// The flows with packets left, by the finish time of their head packet.
+    backlog: Backlog,
 }
 
 impl WFQScheduler {
     pub fn new(bandwidth: usize) -> WFQScheduler {
+        WFQScheduler::with_seed(bandwidth, DEFAULT_SEED)
+    }
+
+    /// Create a scheduler breaking ties at random, reproducibly from `seed`.
+    pub fn with_seed(bandwidth: usize, seed: u64) -> WFQScheduler {
         WFQScheduler {
-            timer: 0,
+            base: SchedulerBase::new(bandwidth),
+            tie_break: TieBreaker::new(TieBreak::Random { seed }),
             weights: Vec::new(),
             total_weight: 0f64,
-            flows: Vec::new(),
-            output_port: Port::new(0, bandwidth),
+            backlog: Backlog::default(),
         }
     }
 
     /// Add a flow to the scheduler with a weight.
-    pub fn add_flow(&mut self, flow: VariableLengthFlow, weight: f64) {
-        self.flows.push(flow);
+    pub fn add_flow(&mut self, flow: impl Flow + 'static, weight: f64) -> FlowId {
+        Scheduler::add_flow(self, Box::new(flow), weight)
+    }
+
+    /// Add any source with a weight, such as a child scheduler
+    /// sharing the bandwidth given to it among its own flows.
+    pub fn add_source(&mut self, source: impl SchedulableSource + 'static, weight: f64) -> FlowId {
+        self.push_source(Box::new(source), weight)
+    }
+
+    fn push_source(&mut self, source: Box<dyn SchedulableSource>, weight: f64) -> FlowId {
+        let id = self.base.add_flow(source);
         self.weights.push(weight);
         self.total_weight += weight;
+        self.backlog.add_flow();
+        self.requeue(id.index(), self.base.timer);
+        id
     }
 
-    pub fn run(&mut self) {
-        while self.tick() {}
-        self.output_port.proceed_rest();
+    /// Update the entry of a flow in the backlog after its queue changed,
+    /// looking at it again at its next arrival, but not before `time`.
+    fn requeue(&mut self, flow_idx: usize, time: usize) {
+        match self.base.flows[flow_idx].next_arrival() {
+            Some(arrival) if !self.base.flows[flow_idx].empty() => {
+                self.backlog.push_pending(flow_idx, arrival.max(time))
+            }
+            // An open flow waiting for its next packet is looked at
+            // every tick, as the packet may be injected at any time.
+            None if !self.base.flows[flow_idx].empty() => self.backlog.push_pending(flow_idx, time),
+            _ => self.backlog.remove(flow_idx),
+        }
+        if self.base.flows[flow_idx].retired() {
+            self.total_weight = self.active_weight();
+        }
     }
 
-    fn estimate_time(&self, flow_idx: &usize, pakcet: &Packet) -> f64 {
+    /// Choose how flows with equal estimated finish times are ordered.
+    pub fn set_tie_break(&mut self, tie_break: TieBreak) {
+        self.tie_break = TieBreaker::new(tie_break);
+    }
+
+    /// Run like [`Scheduler::run`], but jump over the ticks on which
+    /// no packet is eligible instead of stepping through them one by one.
+    ///
+    /// Returns the number of ticks that were actually simulated.
+    pub fn run_event_driven(&mut self) -> usize {
+        let mut steps = 0;
+        while let Some(next_arrival) = self
+            .base
+            .flows
+            .iter()
+            .filter_map(|f| f.next_arrival())
+            .min()
+        {
+            if next_arrival > self.base.timer {
+                let quiet = next_arrival - self.base.timer;
+                self.base.timer += quiet;
+                self.base.output_port.advance(quiet);
+                self.base.throughput.advance(quiet);
+            }
+            self.tick();
+            steps += 1;
+        }
+        self.base.output_port.proceed_rest();
+        steps
+    }
+
+    /// The total weight of the flows still taking part,
+    /// leaving out the closed flows that have drained.
+    fn active_weight(&self) -> f64 {
+        self.weights
+            .iter()
+            .zip(&self.base.flows)
+            .filter(|(_, f)| !f.retired())
+            .map(|(w, _)| w)
+            .sum()
+    }
+
+    fn estimate_time(&self, flow_idx: &usize, len: usize) -> f64 {
         let assumed_rate = self.weights[*flow_idx] / self.total_weight;
-        pakcet.len as f64 / assumed_rate
+        len as f64 / assumed_rate
+    }
+}
+
+impl Scheduler for WFQScheduler {
+    fn add_flow(&mut self, flow: Box<dyn Flow>, weight: f64) -> FlowId {
+        self.push_source(Box::new(flow), weight)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].inject(packet, time)?;
+        self.requeue(flow.index(), self.base.timer);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
+        self.requeue(flow.index(), self.base.timer);
+    }
+
+    fn run(&mut self) {
+        while self.tick() {}
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.tie_break.reset();
+        self.total_weight = self.active_weight();
+        self.backlog.clear();
+        for idx in 0..self.base.flows.len() {
+            self.requeue(idx, 0);
+        }
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
     }
 }
 
 impl Tickable for WFQScheduler {
     fn tick(&mut self) -> bool {
-        if self.flows.iter().all(|f| f.empty()) {
+        if self.backlog.is_empty() {
             return false;
         }
 
         // Add back if scheduled
-        if let Some(idx) = self.schedule() {
-            self.output_port.submit(self.flows[idx].pop_packet());
+        if self.base.output_port.is_accepting() {
+            if let Some(idx) = self.schedule() {
+                let (packet, arrive_time) = self.base.flows[idx].dequeue();
+                self.requeue(idx, self.base.timer + 1);
+                self.base.submit(idx, packet, arrive_time);
+            }
         }
 
-        self.timer += 1;
-        self.output_port.tick();
+        self.base.record_queues();
+        self.base.advance();
 
-        assert!(self.flows.len() == self.weights.len());
+        assert!(self.base.flows.len() == self.weights.len());
 
         true
     }
@@ -66,31 +377,48 @@ impl Schedulable<Option<usize>> for WFQScheduler {
     /// Return the index of the flow to be served
     /// else None.
     fn schedule(&mut self) -> Option<usize> {
-        let mut min_time = f64::INFINITY;
-        let mut min_flow_idx = 0;
-        for (idx, flow) in self.flows.iter().enumerate() {
-            if flow.empty() {
-                continue;
-            }
-            if let Some(packet) = flow.peek_packet(self.timer) {
-                let time = self.estimate_time(&idx, &packet);
-                if time < min_time {
-                    min_time = time;
-                    min_flow_idx = idx;
-                } else if time == min_time {
-                    // randomly choose one
-                    if rand::random() {
-                        min_flow_idx = idx;
-                    }
-                }
+        // The flows whose head packet may have arrived by now become ready.
+        while let Some(idx) = self.backlog.pop_due(self.base.timer) {
+            match self.base.flows[idx].peek_packet(self.base.timer) {
+                Some(packet) => self.backlog.push_ready(idx, packet.len, self.weights[idx]),
+                None => self.backlog.push_pending(idx, self.base.timer + 1),
             }
         }
 
-        if min_time == f64::INFINITY {
-            return None;
+        // Only the flows tied with the earliest finish time are taken out,
+        // in the order they were added, as a scan over the flows would.
+        let first = self.backlog.peek_ready()?;
+        let limit = self.estimate_time(&first.flow_idx, first.len) + EPSILON;
+        let mut tied = Vec::new();
+        while let Some(ready) = self.backlog.peek_ready() {
+            if self.estimate_time(&ready.flow_idx, ready.len) > limit {
+                break;
+            }
+            tied.push(self.backlog.pop_ready().unwrap());
         }
+        tied.sort_by_key(|ready| ready.flow_idx);
 
-        Some(min_flow_idx)
+        let candidates: Vec<(usize, f64)> = tied
+            .iter()
+            .map(|ready| {
+                (
+                    ready.flow_idx,
+                    self.estimate_time(&ready.flow_idx, ready.len),
+                )
+            })
+            .collect();
+        let (flows, timer) = (&self.base.flows, self.base.timer);
+        let chosen = self
+            .tie_break
+            .pick(candidates, |idx| flows[idx].queue_len(timer))
+            .map(|(idx, _)| idx);
+        for ready in tied {
+            if Some(ready.flow_idx) != chosen {
+                self.backlog
+                    .push_ready(ready.flow_idx, ready.len, self.weights[ready.flow_idx]);
+            }
+        }
+        chosen
     }
 }
 
@@ -98,7 +426,7 @@ impl Schedulable<Option<usize>> for WFQScheduler {
 mod test {
     use crate::scheduling::{
         flow::{self, Flow},
-        Packet,
+        FlowId, Packet, Scheduler, Tickable,
     };
 
     #[test]
@@ -125,12 +453,170 @@ mod test {
 
         wfq.run();
 
-        assert_eq!(wfq.timer, 9);
+        assert_eq!(wfq.timer(), 9);
 
-        let output = wfq.output_port.get_output();
+        let output = wfq.output();
 
         assert_eq!(output.len(), 9);
-        // Sicne the we randomly choose one when there are too many flows
-        // with the same estimated time, the output may be different.
+
+        let stats = wfq.stats();
+        assert_eq!(stats.len(), 3);
+        for flow in &stats {
+            assert_eq!(flow.packets, 3);
+            assert_eq!(flow.bytes, 3);
+            assert!(flow.mean_delay >= 1f64);
+            assert!(flow.max_delay as f64 >= flow.mean_delay);
+        }
+
+        // The event-driven run replays the same schedule, ties included,
+        // since they are broken by a seeded RNG that reset rewinds.
+        let result = wfq.result();
+        wfq.reset();
+        wfq.run_event_driven();
+        assert_eq!(wfq.result(), result);
+        assert_eq!(wfq.stats(), stats);
+    }
+
+    #[test]
+    fn wfq_ewma_throughput_test() {
+        let mut wfq = super::WFQScheduler::new(1);
+        wfq.set_ewma_alpha(0.5);
+
+        let mut flow = flow::VariableLengthFlow::new();
+        flow.packet_arrive(Packet::new("p1", 1), 0);
+        flow.packet_arrive(Packet::new("p2", 1), 0);
+        flow.packet_arrive(Packet::new("p3", 1), 0);
+        flow.packet_arrive(Packet::new("p4", 1), 20);
+        wfq.add_flow(flow, 1f64);
+
+        // The burst is served one packet per tick.
+        let mut last = wfq.ewma_throughput(FlowId(0));
+        for _ in 0..3 {
+            wfq.tick();
+            assert!(wfq.ewma_throughput(FlowId(0)) > last);
+            last = wfq.ewma_throughput(FlowId(0));
+        }
+        assert_eq!(last, 0.875);
+
+        // Nothing arrives until tick 20, so the estimate decays.
+        for _ in 0..5 {
+            wfq.tick();
+            assert!(wfq.ewma_throughput(FlowId(0)) < last);
+            last = wfq.ewma_throughput(FlowId(0));
+        }
+    }
+
+    #[test]
+    fn wfq_churn_weight_test() {
+        let mut wfq = super::WFQScheduler::new(1);
+
+        let mut flow = flow::VariableLengthFlow::new();
+        for p in 0..8 {
+            flow.packet_arrive(Packet::new(format!("a{}", p), 1), p);
+        }
+        wfq.add_flow(flow, 1f64);
+        let mut flow = flow::VariableLengthFlow::new();
+        flow.packet_arrive(Packet::new("b0", 1), 0);
+        flow.packet_arrive(Packet::new("b1", 1), 4);
+        wfq.add_flow(flow, 3f64);
+
+        wfq.step();
+        assert_eq!(wfq.total_weight, 4f64);
+
+        // b1 arrives after the flow was closed, so the flow retires
+        // once b0 is served and gives its share back.
+        wfq.close_flow(FlowId(1));
+        wfq.step_by(2);
+        assert_eq!(wfq.total_weight, 1f64);
+
+        let mut flow = flow::VariableLengthFlow::new();
+        flow.packet_arrive(Packet::new("c0", 1), 3);
+        wfq.add_flow(flow, 2f64);
+        wfq.step();
+        assert_eq!(wfq.total_weight, 3f64);
+
+        wfq.run();
+        assert_eq!(wfq.output().len(), 10);
+        assert!(wfq.output().iter().all(|p| p.name != "b1"));
+    }
+
+    #[test]
+    fn wfq_many_flows_test() {
+        use crate::scheduling::schedulers::tie_break::TieBreak;
+
+        let mut wfq = super::WFQScheduler::new(1);
+        wfq.set_tie_break(TieBreak::LowestIndex);
+        for f in 0..1000 {
+            let mut flow = flow::VariableLengthFlow::new();
+            flow.packet_arrive(Packet::new(format!("f{}", f), 1), 0);
+            wfq.add_flow(flow, (f + 1) as f64);
+        }
+
+        // The heaviest flows finish first.
+        wfq.step_by(3);
+        let names: Vec<&str> = wfq.output().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["f999", "f998", "f997"]);
+
+        // A packet injected into a drained flow is seen at once.
+        wfq.inject(FlowId(999), Packet::new("late", 1), 3).unwrap();
+        wfq.step();
+        assert_eq!(wfq.output().last().unwrap().name, "late");
+
+        wfq.run();
+        assert_eq!(wfq.output().len(), 1001);
+        assert_eq!(wfq.output().last().unwrap().name, "f0");
+    }
+
+    #[test]
+    fn wfq_seed_test() {
+        let build = |seed| {
+            let mut wfq = super::WFQScheduler::with_seed(1, seed);
+            for f in 0..4 {
+                let mut flow = flow::VariableLengthFlow::new();
+                for p in 0..4 {
+                    flow.packet_arrive(Packet::new(format!("f{}_p{}", f, p), 1), p);
+                }
+                wfq.add_flow(flow, 1f64);
+            }
+            wfq.run();
+            wfq.output().to_vec()
+        };
+
+        // Every packet ties with the other flows, so the order only
+        // depends on the tie-breaking RNG.
+        assert_eq!(build(42), build(42));
+        assert_eq!(build(super::DEFAULT_SEED), build(super::DEFAULT_SEED));
+    }
+
+    #[test]
+    fn wfq_tie_break_test() {
+        use crate::scheduling::schedulers::tie_break::TieBreak;
+
+        // Equal weights and lengths, so every decision is a tie.
+        let order = |tie_break| {
+            let mut wfq = super::WFQScheduler::new(1);
+            wfq.set_tie_break(tie_break);
+            for (f, count) in [(0, 2), (1, 2), (2, 3)] {
+                let mut flow = flow::VariableLengthFlow::new();
+                for p in 0..count {
+                    flow.packet_arrive(Packet::new(format!("{}{}", f, p), 1), 0);
+                }
+                wfq.add_flow(flow, 1f64);
+            }
+            wfq.run();
+            wfq.output()
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        assert_eq!(order(TieBreak::LowestIndex), "00 01 10 11 20 21 22");
+        assert_eq!(order(TieBreak::RoundRobin), "00 10 20 01 11 21 22");
+        assert_eq!(order(TieBreak::LongestQueue), "20 00 10 21 01 11 22");
+        assert_eq!(
+            order(TieBreak::Random { seed: 3 }),
+            order(TieBreak::Random { seed: 3 })
+        );
     }
 }
@@ -1,56 +1,112 @@
 use crate::scheduling::{
-    flow::{FixedLengthFlow, Flow},
-    Port, Schedulable, Tickable,
+    flow::Flow,
+    schedulers::base::SchedulerBase,
+    stats::{QueueSeries, SchedulerStats},
+    FlowId, Packet, Port, Schedulable, Scheduler, Tickable,
 };
 
 /// Weighted Round Robin (WRR) Scheduler
+#[derive(Clone)]
 pub struct WRRScheduler {
-    timer: usize,
+    base: SchedulerBase,
     weights: Vec<usize>,
     current_weight: Vec<usize>,
-    flows: Vec<FixedLengthFlow>,
-    output_port: Port,
 }
 
 impl WRRScheduler {
     pub fn new(bandwidth: usize) -> WRRScheduler {
         WRRScheduler {
-            timer: 0,
+            base: SchedulerBase::new(bandwidth),
             weights: Vec::new(),
             current_weight: Vec::new(),
-            flows: Vec::new(),
-            output_port: Port::new(0, bandwidth),
         }
     }
 
-    pub fn add_flow(&mut self, flow: FixedLengthFlow, weight: usize) {
-        self.flows.push(flow);
+    pub fn add_flow(&mut self, flow: impl Flow + 'static, weight: usize) -> FlowId {
+        Scheduler::add_flow(self, Box::new(flow), weight as f64)
+    }
+}
+
+impl Scheduler for WRRScheduler {
+    fn add_flow(&mut self, flow: Box<dyn Flow>, weight: f64) -> FlowId {
+        let weight = weight.round() as usize;
         self.weights.push(weight);
         self.current_weight.push(weight);
+        self.base.add_flow(flow)
+    }
+
+    fn inject(&mut self, flow: FlowId, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.base.flows[flow.index()].packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close_flow(&mut self, flow: FlowId) {
+        self.base.flows[flow.index()].close(self.base.timer);
     }
 
-    pub fn run(&mut self) {
+    fn run(&mut self) {
         while self.tick() {}
-        self.output_port.proceed_rest()
+        self.base.output_port.proceed_rest();
+    }
+
+    fn step(&mut self) -> bool {
+        self.tick()
+    }
+
+    fn output(&self) -> &[Packet] {
+        self.base.output_port.get_output()
+    }
+
+    fn timer(&self) -> usize {
+        self.base.timer
+    }
+
+    fn set_ewma_alpha(&mut self, alpha: f64) {
+        self.base.set_ewma_alpha(alpha);
+    }
+
+    fn ewma_throughput(&self, flow: FlowId) -> f64 {
+        self.base.ewma_throughput(flow)
+    }
+
+    fn get_output_port(&mut self) -> &mut Port {
+        &mut self.base.output_port
+    }
+
+    fn dropped_count(&self, flow: FlowId) -> usize {
+        self.base.dropped_count(flow)
+    }
+
+    fn reset(&mut self) {
+        self.base.reset();
+        self.current_weight = self.weights.clone();
+    }
+
+    fn set_queue_sampling(&mut self, interval: Option<usize>) {
+        self.base.queue_series.set_interval(interval);
+    }
+
+    fn queue_series(&self) -> &QueueSeries {
+        &self.base.queue_series
+    }
+
+    fn scheduler_stats(&self) -> SchedulerStats {
+        self.base.stats()
     }
 }
 
 impl Tickable for WRRScheduler {
     fn tick(&mut self) -> bool {
-        if self.flows.iter().all(|f| f.empty()) {
+        if self.base.all_empty() {
             return false;
         }
 
-        if self.schedule() {
+        if self.base.output_port.is_accepting() && self.schedule() {
             self.current_weight = self.weights.clone();
         }
 
-        self.timer += 1;
-        self.output_port.tick();
-
-        if self.timer > 100 {
-            panic!("WRRScheduler::tick() is stuck in an infinite loop");
-        }
+        self.base.record_queues();
+        self.base.advance();
 
         true
     }
@@ -58,14 +114,14 @@ impl Tickable for WRRScheduler {
 
 impl Schedulable<bool> for WRRScheduler {
     fn schedule(&mut self) -> bool {
-        for i in 0..self.flows.len() {
-            if self.flows[i].empty() {
+        for i in 0..self.base.flows.len() {
+            if self.base.flows[i].empty() {
                 continue;
             }
             if self.current_weight[i] > 0 {
-                if let Some(_packet) = self.flows[i].peek_packet(self.timer) {
+                if let Some(_packet) = self.base.flows[i].peek_packet(self.base.timer) {
                     self.current_weight[i] -= 1;
-                    self.output_port.submit(self.flows[i].pop_packet());
+                    self.base.serve(i);
                 }
                 return false;
             }
@@ -76,7 +132,7 @@ impl Schedulable<bool> for WRRScheduler {
 
 #[cfg(test)]
 mod test {
-    use crate::scheduling::{flow::FixedLengthFlow, Packet};
+    use crate::scheduling::{flow::FixedLengthFlow, Packet, Scheduler};
 
     use super::WRRScheduler;
 
@@ -109,9 +165,9 @@ mod test {
 
         wrr.run();
 
-        assert_eq!(wrr.timer, 16);
+        assert_eq!(wrr.timer(), 16);
 
-        let output = wrr.output_port.get_output();
+        let output = wrr.output();
 
         assert_eq!(output.len(), 13);
         assert_eq!(
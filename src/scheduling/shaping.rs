@@ -0,0 +1,364 @@
+use std::collections::VecDeque;
+
+use crate::scheduling::{
+    engine::{Dequeue, EventScheduler},
+    flow::{Flow, VariableLengthFlow},
+    Packet, Tickable,
+};
+
+/// Token bucket traffic shaper.
+///
+/// Tokens accumulate at `rate` per tick up to `depth`, and a packet is
+/// released once it has arrived and enough tokens are available for its length.
+/// A packet longer than `depth` never conforms and is dropped on arrival.
+///
+/// The shaper can be run on its own and its output turned into a flow,
+/// or placed in front of a scheduler through [`ShapedFlow`].
+#[derive(Debug)]
+pub struct TokenBucket {
+    timer: usize,
+    rate: usize,
+    depth: usize,
+    tokens: usize,
+    packet_states: VecDeque<(Packet, usize)>,
+    released: Vec<(Packet, usize)>,
+    /// Packets longer than the bucket depth.
+    dropped: Vec<Packet>,
+}
+
+impl TokenBucket {
+    /// Create a shaper with a full bucket.
+    pub fn new(rate: usize, depth: usize) -> TokenBucket {
+        assert!(rate > 0, "an empty bucket must refill");
+        TokenBucket {
+            timer: 0,
+            rate,
+            depth,
+            tokens: depth,
+            packet_states: VecDeque::new(),
+            released: Vec::new(),
+            dropped: Vec::new(),
+        }
+    }
+
+    pub fn packet_arrive(&mut self, packet: Packet, time: usize) {
+        if packet.len > self.depth {
+            self.dropped.push(packet);
+            return;
+        }
+        let pos = self.packet_states.partition_point(|(_, t)| *t <= time);
+        self.packet_states.insert(pos, (packet, time));
+    }
+
+    pub fn run(&mut self) {
+        while self.tick() {}
+    }
+
+    /// The released packets with the times at which they were released.
+    pub fn get_output(&self) -> &Vec<(Packet, usize)> {
+        &self.released
+    }
+
+    /// The packets dropped for being longer than the bucket depth.
+    pub fn dropped(&self) -> &[Packet] {
+        &self.dropped
+    }
+
+    /// Turn the reshaped packet stream into a flow for a scheduler.
+    pub fn to_flow(&self) -> VariableLengthFlow {
+        let mut flow = VariableLengthFlow::new();
+        for (packet, time) in &self.released {
+            flow.packet_arrive(packet.clone(), *time);
+        }
+        flow
+    }
+
+    pub fn empty(&self) -> bool {
+        self.packet_states.is_empty()
+    }
+
+    /// Release the head packet if it has arrived and conforms.
+    fn release_head(&mut self) -> Option<Packet> {
+        let (packet, arrive_time) = self.packet_states.front()?;
+        if *arrive_time > self.timer || packet.len > self.tokens {
+            return None;
+        }
+        self.tokens -= packet.len;
+        self.packet_states.pop_front().map(|(packet, _)| packet)
+    }
+
+    /// Accumulate the tokens of the ticks up to `time`.
+    fn refill_until(&mut self, time: usize) {
+        let ticks = time.saturating_sub(self.timer);
+        self.tokens = self
+            .tokens
+            .saturating_add(self.rate.saturating_mul(ticks))
+            .min(self.depth);
+        self.timer = self.timer.max(time);
+    }
+}
+
+impl Tickable for TokenBucket {
+    /// Release every conforming packet, then accumulate tokens.
+    /// Returns false once every packet has been released.
+    fn tick(&mut self) -> bool {
+        if self.empty() {
+            return false;
+        }
+
+        while let Some(packet) = self.release_head() {
+            self.released.push((packet, self.timer));
+        }
+        self.refill_until(self.timer + 1);
+
+        true
+    }
+}
+
+/// The shaper in front of the link of an [`Engine`](crate::scheduling::engine::Engine),
+/// holding the packets of every flow in one queue. The flow of a packet
+/// is kept in its `flow_id`.
+impl EventScheduler for TokenBucket {
+    fn enqueue(&mut self, flow_idx: usize, packet: Packet, time: f64) {
+        self.packet_arrive(packet.with_flow_id(flow_idx), time as usize);
+    }
+
+    /// Release the head packet, or wait for the tick the bucket holds
+    /// enough tokens for it.
+    fn dequeue(&mut self, time: f64) -> Dequeue {
+        self.refill_until(time as usize);
+        if let Some(packet) = self.release_head() {
+            let flow_idx = packet.flow_id.expect("enqueued packets keep their flow");
+            return Dequeue::Packet(flow_idx, packet);
+        }
+        match self.packet_states.front() {
+            Some((packet, arrive_time)) if self.rate > 0 => {
+                let wait = packet.len.saturating_sub(self.tokens).div_ceil(self.rate);
+                Dequeue::WaitUntil((*arrive_time).max(self.timer + wait) as f64)
+            }
+            _ => Dequeue::Idle,
+        }
+    }
+}
+
+/// A flow seen through a token bucket, handed to a scheduler in place of
+/// the flow itself.
+///
+/// A packet arrives at the scheduler when the bucket releases it,
+/// which does not depend on when the scheduler serves it. A packet longer
+/// than the bucket depth is dropped once it reaches the head of the flow.
+#[derive(Debug, Clone)]
+pub struct ShapedFlow {
+    flow: Box<dyn Flow>,
+    rate: usize,
+    depth: usize,
+    /// Tokens in the bucket at tick `last_release`.
+    tokens: usize,
+    last_release: usize,
+    /// Packets longer than the bucket depth.
+    dropped: Vec<Packet>,
+}
+
+impl ShapedFlow {
+    /// Shape a flow with a bucket that starts full.
+    pub fn new(flow: impl Flow + 'static, rate: usize, depth: usize) -> ShapedFlow {
+        assert!(rate > 0, "an empty bucket must refill");
+        let mut shaped = ShapedFlow {
+            flow: flow.clone_box(),
+            rate,
+            depth,
+            tokens: depth,
+            last_release: 0,
+            dropped: Vec::new(),
+        };
+        shaped.drop_oversized();
+        shaped
+    }
+
+    /// The packets dropped for being longer than the bucket depth.
+    pub fn dropped(&self) -> &[Packet] {
+        &self.dropped
+    }
+
+    /// Drop the head packets longer than the bucket depth, so that the
+    /// head can always be released.
+    fn drop_oversized(&mut self) {
+        while let Some(arrive_time) = self.flow.next_arrival() {
+            match self.flow.peek_packet(arrive_time) {
+                Some(packet) if packet.len > self.depth => {
+                    self.dropped.push(self.flow.pop_packet());
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// The tick at which the head packet is released,
+    /// with the tokens left in the bucket at that tick before it is released.
+    fn release(&self) -> Option<(usize, usize)> {
+        let arrive_time = self.flow.next_arrival()?;
+        let len = self.flow.peek_packet(arrive_time).unwrap().len;
+        let mut time = arrive_time.max(self.last_release);
+        let mut tokens = self
+            .tokens
+            .saturating_add(self.rate.saturating_mul(time - self.last_release))
+            .min(self.depth);
+        if tokens < len {
+            let wait = (len - tokens).div_ceil(self.rate);
+            time += wait;
+            tokens = tokens
+                .saturating_add(self.rate.saturating_mul(wait))
+                .min(self.depth);
+        }
+        Some((time, tokens))
+    }
+}
+
+impl Flow for ShapedFlow {
+    fn packet_arrive(&mut self, packet: Packet, time: usize) {
+        self.flow.packet_arrive(packet, time);
+        self.drop_oversized();
+    }
+
+    fn pop_packet(&mut self) -> Packet {
+        let (time, tokens) = self.release().unwrap();
+        let packet = self.flow.pop_packet();
+        self.tokens = tokens - packet.len;
+        self.last_release = time;
+        self.drop_oversized();
+        packet
+    }
+
+    fn peek_packet(&self, time: usize) -> Option<Packet> {
+        let (release, _) = self.release()?;
+        if release > time {
+            return None;
+        }
+        self.flow.peek_packet(time)
+    }
+
+    fn next_arrival(&self) -> Option<usize> {
+        self.release().map(|(time, _)| time)
+    }
+
+    fn empty(&self) -> bool {
+        self.flow.empty()
+    }
+
+    /// Counts the packets held back by the bucket as waiting.
+    fn queue_len(&self, time: usize) -> usize {
+        self.flow.queue_len(time)
+    }
+
+    /// Counts the packets dropped by the flow and those longer than the bucket depth.
+    fn dropped_count(&self) -> usize {
+        self.flow.dropped_count() + self.dropped.len()
+    }
+
+    /// Closes the shaped flow on the arrivals, before the bucket.
+    fn close(&mut self, time: usize) {
+        self.flow.close(time);
+    }
+
+    fn is_closed(&self) -> bool {
+        self.flow.is_closed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        schedulers::fifo::FIFOScheduler,
+        Packet, Scheduler,
+    };
+
+    use super::{ShapedFlow, TokenBucket};
+
+    #[test]
+    fn token_bucket_test() {
+        let mut shaper = TokenBucket::new(1, 2);
+        for name in ["p1", "p2", "p3", "p4"] {
+            shaper.packet_arrive(Packet::new(name, 2), 0);
+        }
+        shaper.run();
+
+        // The burst is smoothed out to one packet every two ticks.
+        assert_eq!(
+            shaper.get_output(),
+            &vec![
+                (Packet::new("p1", 2), 0),
+                (Packet::new("p2", 2), 2),
+                (Packet::new("p3", 2), 4),
+                (Packet::new("p4", 2), 6),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "refill")]
+    fn token_bucket_zero_rate_test() {
+        TokenBucket::new(0, 2);
+    }
+
+    #[test]
+    fn shaped_flow_test() {
+        let mut flow = VariableLengthFlow::new();
+        let mut shaper = TokenBucket::new(1, 2);
+        for (name, time) in [("p1", 0), ("p2", 0), ("p3", 1), ("p4", 9)] {
+            flow.packet_arrive(Packet::new(name, 2), time);
+            shaper.packet_arrive(Packet::new(name, 2), time);
+        }
+        shaper.run();
+
+        // The shaped flow releases packets when the shaper does.
+        let mut shaped = ShapedFlow::new(flow, 1, 2);
+        for (packet, time) in shaper.get_output() {
+            assert_eq!(shaped.next_arrival(), Some(*time));
+            if *time > 0 {
+                assert_eq!(shaped.peek_packet(*time - 1), None);
+            }
+            assert_eq!(shaped.peek_packet(*time).as_ref(), Some(packet));
+            assert_eq!(&shaped.pop_packet(), packet);
+        }
+        assert!(shaped.empty());
+    }
+
+    #[test]
+    fn shaped_flow_scheduler_test() {
+        let mut flow = VariableLengthFlow::new();
+        for name in ["p1", "p2", "p3"] {
+            flow.packet_arrive(Packet::new(name, 2), 0);
+        }
+
+        // A burst on a fast link is spread out to one packet every two ticks.
+        let mut fifo = FIFOScheduler::new(4);
+        fifo.add_flow(ShapedFlow::new(flow, 1, 2));
+        fifo.run();
+        assert_eq!(fifo.output().len(), 3);
+        assert_eq!(fifo.get_output_port().get_departure_times(), &[1, 3, 5]);
+    }
+
+    #[test]
+    fn oversized_packet_test() {
+        // A packet longer than the bucket can never conform: it is dropped
+        // and the packets behind it go on.
+        let mut shaper = TokenBucket::new(1, 2);
+        shaper.packet_arrive(Packet::new("p1", 2), 0);
+        shaper.packet_arrive(Packet::new("big", 3), 0);
+        shaper.packet_arrive(Packet::new("p2", 2), 1);
+        shaper.run();
+        assert_eq!(shaper.dropped(), &[Packet::new("big", 3)]);
+        assert_eq!(shaper.get_output().len(), 2);
+
+        let mut flow = VariableLengthFlow::new();
+        for (name, len, time) in [("big0", 5, 0), ("p1", 2, 0), ("big1", 3, 1), ("p2", 2, 1)] {
+            flow.packet_arrive(Packet::new(name, len), time);
+        }
+        let mut fifo = FIFOScheduler::new(4);
+        let id = fifo.add_flow(ShapedFlow::new(flow, 1, 2));
+        fifo.run();
+        assert_eq!(fifo.output().len(), 2);
+        assert_eq!(fifo.dropped_count(id), 2);
+    }
+}
@@ -0,0 +1,197 @@
+use std::fmt::Debug;
+
+use crate::scheduling::{flow::Flow, Packet};
+
+/// Something a scheduler can dequeue packets from.
+///
+/// Every [`Flow`] is a source, and so are the schedulers that can act as
+/// a child of another scheduler, which lets schedulers be stacked
+/// into a hierarchy.
+pub trait SchedulableSource: Debug + SourceClone {
+    /// Select the packet to hand out next, if one is available at `time`.
+    /// The selection sticks until the packet is dequeued.
+    fn peek_packet(&mut self, time: usize) -> Option<Packet>;
+
+    /// Hand out the selected packet together with its arrival time.
+    fn dequeue(&mut self) -> (Packet, usize);
+
+    /// Get the earliest arrival time among the remaining packets,
+    /// whether or not they have arrived yet.
+    fn next_arrival(&self) -> Option<usize>;
+
+    /// Check if the source has no packet left.
+    fn empty(&self) -> bool;
+
+    /// The number of packets that have arrived by `time`
+    /// and are still waiting to be handed out.
+    fn queue_len(&self, time: usize) -> usize;
+
+    /// The number of packets dropped by the source itself.
+    fn dropped_count(&self) -> usize {
+        0
+    }
+
+    /// Add a packet arriving at `time`.
+    /// The packet is given back if the source takes no packets from outside.
+    fn inject(&mut self, packet: Packet, _time: usize) -> Result<(), Packet> {
+        Err(packet)
+    }
+
+    /// Stop taking packets arriving after `time`, see [`Flow::close`].
+    fn close(&mut self, time: usize);
+
+    /// Whether the source was closed and has drained.
+    fn retired(&self) -> bool;
+}
+
+/// Clone a source behind a trait object.
+///
+/// Implemented for every source that is `Clone`.
+pub trait SourceClone {
+    fn clone_source(&self) -> Box<dyn SchedulableSource>;
+}
+
+impl<T: SchedulableSource + Clone + 'static> SourceClone for T {
+    fn clone_source(&self) -> Box<dyn SchedulableSource> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn SchedulableSource> {
+    fn clone(&self) -> Self {
+        self.clone_source()
+    }
+}
+
+impl<T: Flow + Clone + 'static> SchedulableSource for T {
+    fn peek_packet(&mut self, time: usize) -> Option<Packet> {
+        Flow::peek_packet(self, time)
+    }
+
+    fn dequeue(&mut self) -> (Packet, usize) {
+        let arrive_time = Flow::next_arrival(self).unwrap();
+        (self.pop_packet(), arrive_time)
+    }
+
+    fn next_arrival(&self) -> Option<usize> {
+        Flow::next_arrival(self)
+    }
+
+    fn empty(&self) -> bool {
+        Flow::empty(self)
+    }
+
+    fn queue_len(&self, time: usize) -> usize {
+        Flow::queue_len(self, time)
+    }
+
+    fn dropped_count(&self) -> usize {
+        Flow::dropped_count(self)
+    }
+
+    fn inject(&mut self, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close(&mut self, time: usize) {
+        Flow::close(self, time)
+    }
+
+    fn retired(&self) -> bool {
+        Flow::retired(self)
+    }
+}
+
+impl SchedulableSource for Box<dyn Flow> {
+    fn peek_packet(&mut self, time: usize) -> Option<Packet> {
+        self.as_ref().peek_packet(time)
+    }
+
+    fn dequeue(&mut self) -> (Packet, usize) {
+        let arrive_time = self.as_ref().next_arrival().unwrap();
+        (self.pop_packet(), arrive_time)
+    }
+
+    fn next_arrival(&self) -> Option<usize> {
+        self.as_ref().next_arrival()
+    }
+
+    fn empty(&self) -> bool {
+        self.as_ref().empty()
+    }
+
+    fn queue_len(&self, time: usize) -> usize {
+        self.as_ref().queue_len(time)
+    }
+
+    fn dropped_count(&self) -> usize {
+        self.as_ref().dropped_count()
+    }
+
+    fn inject(&mut self, packet: Packet, time: usize) -> Result<(), Packet> {
+        self.as_mut().packet_arrive(packet, time);
+        Ok(())
+    }
+
+    fn close(&mut self, time: usize) {
+        self.as_mut().close(time)
+    }
+
+    fn retired(&self) -> bool {
+        self.as_ref().retired()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        schedulers::{dwrr::DWRRScheduler, wfq::WFQScheduler},
+        FlowId, Packet, Scheduler,
+    };
+
+    fn flow(names: &[&str]) -> VariableLengthFlow {
+        let mut flow = VariableLengthFlow::new();
+        for name in names {
+            flow.packet_arrive(Packet::new(*name, 1), 0);
+        }
+        flow
+    }
+
+    #[test]
+    fn scheduler_as_source_test() {
+        // A customer sharing its bandwidth among its own flows with DWRR.
+        let mut customer = DWRRScheduler::new(1);
+        customer.add_flow(flow(&["x1", "x2"]), 1, 1);
+        customer.add_flow(flow(&["y1", "y2"]), 1, 1);
+
+        // WFQ between the customer and a plain flow.
+        let mut wfq = WFQScheduler::new(1);
+        wfq.add_source(customer, 3f64);
+        wfq.add_flow(flow(&["z1", "z2"]), 1f64);
+        wfq.run();
+
+        let names: Vec<&str> = wfq.output().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["x1", "y1", "x2", "y2", "z1", "z2"]);
+
+        let stats = wfq.stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].packets, 4);
+        assert_eq!(stats[1].packets, 2);
+
+        // The child is restored along with the parent.
+        let result = wfq.result();
+        wfq.reset();
+        wfq.run();
+        assert_eq!(wfq.result(), result);
+
+        // Packets can only be injected into the plain flow.
+        let packet = Packet::new("late", 1);
+        assert_eq!(
+            wfq.inject(FlowId(0), packet.clone(), 20),
+            Err(packet.clone())
+        );
+        assert_eq!(wfq.inject(FlowId(1), packet, 20), Ok(()));
+    }
+}
@@ -0,0 +1,219 @@
+use super::SchedulerStats;
+
+/// Fairness of the service received by the flows over some time span.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fairness {
+    /// First tick of the span.
+    pub start: usize,
+    /// Tick right after the span.
+    pub end: usize,
+    /// Bytes each flow sent during the span.
+    pub served: Vec<usize>,
+    /// Weighted max-min fair share of each flow, in bytes,
+    /// given what the flows had to send during the span.
+    pub fair_share: Vec<f64>,
+    /// Bytes sent over the fair share of each flow,
+    /// None for flows with nothing to send.
+    pub normalized_throughput: Vec<Option<f64>>,
+    /// Jain's index of the normalized throughputs, from `1 / n` when a
+    /// single flow gets the whole link to 1 when every flow gets its share.
+    pub jain_index: f64,
+    /// Largest distance between the bytes sent and the fair share of
+    /// a flow, as a fraction of all bytes sent during the span.
+    pub max_min_deviation: f64,
+}
+
+/// Fairness of a scheduler run, as a whole and per window of time.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FairnessReport {
+    pub overall: Fairness,
+    pub windows: Vec<Fairness>,
+}
+
+impl FairnessReport {
+    /// Analyze the fairness of a run between flows of the given weights,
+    /// over consecutive windows of `window` ticks.
+    ///
+    /// Packets count towards the window they departed in. A flow has to
+    /// send the packets that were waiting or in transmission during a
+    /// window, and flows with nothing to send are left out of the indices.
+    pub fn compute(stats: &SchedulerStats, weights: &[f64], window: usize) -> FairnessReport {
+        assert!(window > 0, "fairness windows must be at least one tick");
+        assert_eq!(
+            weights.len(),
+            stats.flows.len(),
+            "every flow needs a weight"
+        );
+        let end = stats
+            .packets
+            .iter()
+            .map(|r| r.departure + 1)
+            .max()
+            .unwrap_or(0);
+        FairnessReport {
+            overall: Fairness::compute(stats, weights, 0, end),
+            windows: (0..end)
+                .step_by(window)
+                .map(|start| Fairness::compute(stats, weights, start, (start + window).min(end)))
+                .collect(),
+        }
+    }
+}
+
+impl Fairness {
+    /// Fairness over the ticks from `start` to right before `end`.
+    pub fn compute(stats: &SchedulerStats, weights: &[f64], start: usize, end: usize) -> Fairness {
+        let flow_count = weights.len();
+        let mut served = vec![0usize; flow_count];
+        let mut demands = vec![0usize; flow_count];
+        for record in &stats.packets {
+            if record.arrival < end && record.departure >= start {
+                demands[record.flow.index()] += record.len;
+                if record.departure < end {
+                    served[record.flow.index()] += record.len;
+                }
+            }
+        }
+
+        let capacity: usize = served.iter().sum();
+        let fair_share = max_min_shares(&demands, weights, capacity as f64);
+        let normalized_throughput: Vec<Option<f64>> = served
+            .iter()
+            .zip(&fair_share)
+            .map(|(&bytes, &share)| (share > 0f64).then(|| bytes as f64 / share))
+            .collect();
+        let active: Vec<f64> = normalized_throughput.iter().flatten().copied().collect();
+        let max_min_deviation = if capacity == 0 {
+            0f64
+        } else {
+            served
+                .iter()
+                .zip(&fair_share)
+                .map(|(&bytes, share)| (bytes as f64 - share).abs())
+                .fold(0f64, f64::max)
+                / capacity as f64
+        };
+
+        Fairness {
+            start,
+            end,
+            served,
+            fair_share,
+            normalized_throughput,
+            jain_index: jain_index(&active),
+            max_min_deviation,
+        }
+    }
+}
+
+impl SchedulerStats {
+    /// Analyze the fairness of the run, see [`FairnessReport::compute`].
+    pub fn fairness(&self, weights: &[f64], window: usize) -> FairnessReport {
+        FairnessReport::compute(self, weights, window)
+    }
+}
+
+/// Jain's fairness index: `(sum x)^2 / (n * sum x^2)`.
+pub fn jain_index(values: &[f64]) -> f64 {
+    let sum: f64 = values.iter().sum();
+    let sum_sq: f64 = values.iter().map(|x| x * x).sum();
+    if sum_sq == 0f64 {
+        return 1f64;
+    }
+    sum * sum / (values.len() as f64 * sum_sq)
+}
+
+/// Share `capacity` between flows by weighted max-min fairness: no flow
+/// gets more than its demand, and what a flow leaves is shared by the
+/// others in proportion to their weights.
+pub fn max_min_shares(demands: &[usize], weights: &[f64], capacity: f64) -> Vec<f64> {
+    let mut shares = vec![0f64; demands.len()];
+    let mut unsatisfied: Vec<usize> = (0..demands.len())
+        .filter(|&idx| demands[idx] > 0 && weights[idx] > 0f64)
+        .collect();
+    let mut remaining = capacity;
+    while !unsatisfied.is_empty() && remaining > 0f64 {
+        let total_weight: f64 = unsatisfied.iter().map(|&idx| weights[idx]).sum();
+        let per_weight = remaining / total_weight;
+        let (satisfied, rest): (Vec<usize>, Vec<usize>) = unsatisfied
+            .iter()
+            .partition(|&&idx| demands[idx] as f64 <= per_weight * weights[idx]);
+        if satisfied.is_empty() {
+            for idx in rest {
+                shares[idx] = per_weight * weights[idx];
+            }
+            break;
+        }
+        for idx in satisfied {
+            shares[idx] = demands[idx] as f64;
+            remaining -= demands[idx] as f64;
+        }
+        unsatisfied = rest;
+    }
+    shares
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        schedulers::{fifo::FIFOScheduler, wf2q::WF2QPlusScheduler},
+        Packet, Scheduler,
+    };
+
+    use super::{jain_index, max_min_shares};
+
+    #[test]
+    fn max_min_shares_test() {
+        // The small demand is met and the rest is split 2:1.
+        let shares = max_min_shares(&[2, 100, 100, 0], &[1f64, 2f64, 1f64, 1f64], 14f64);
+        assert_eq!(shares, vec![2f64, 8f64, 4f64, 0f64]);
+
+        assert_eq!(jain_index(&[1f64, 1f64, 1f64]), 1f64);
+        assert_eq!(jain_index(&[1f64, 0f64, 0f64, 0f64]), 0.25);
+    }
+
+    #[test]
+    fn fairness_report_test() {
+        let flow = |prefix: &str| {
+            let mut flow = VariableLengthFlow::new();
+            for p in 0..20 {
+                flow.packet_arrive(Packet::new(format!("{}{}", prefix, p), 1), 0);
+            }
+            flow
+        };
+
+        // WF2Q+ gives each backlogged flow its weighted share in every window ...
+        let mut wf2q = WF2QPlusScheduler::new(1);
+        wf2q.add_flow(flow("a"), 3f64);
+        wf2q.add_flow(flow("b"), 1f64);
+        wf2q.run();
+        let weights = [3f64, 1f64];
+        let report = wf2q.scheduler_stats().fairness(&weights, 8);
+        // The first packet departs at tick 1.
+        assert_eq!(report.windows.len(), 6);
+        assert_eq!(report.windows[1].served, vec![6, 2]);
+        assert_eq!(report.windows[1].jain_index, 1f64);
+        assert_eq!(report.windows[1].max_min_deviation, 0f64);
+        assert!(report.windows[0].jain_index > 0.99);
+        assert_eq!(report.overall.jain_index, 1f64);
+
+        // ... while FIFO sends one flow after the other.
+        let mut fifo = FIFOScheduler::new(1);
+        fifo.add_flow(flow("a"));
+        fifo.add_flow(flow("b"));
+        fifo.run();
+        let report = fifo.scheduler_stats().fairness(&weights, 8);
+        assert_eq!(report.windows[1].served, vec![8, 0]);
+        assert_eq!(report.windows[1].max_min_deviation, 0.25);
+        assert_eq!(report.windows[1].jain_index, 0.5);
+        // Once the first flow is done, the second one has the link alone.
+        assert_eq!(
+            report.windows[4].normalized_throughput,
+            vec![None, Some(1f64)]
+        );
+        assert_eq!(report.overall.jain_index, 1f64);
+    }
+}
@@ -0,0 +1,162 @@
+use super::{percentile, SchedulerStats};
+use crate::scheduling::FlowId;
+
+/// The completion of one flow of a run.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FlowCompletion {
+    pub flow: FlowId,
+    /// Bytes the flow sent.
+    pub bytes: usize,
+    /// Ticks between the first arrival and the last departure of the flow.
+    pub completion_time: usize,
+    /// The completion time the flow would have had alone on the link.
+    pub isolated_time: usize,
+    /// Completion time over isolated completion time, 1 for a flow
+    /// that was never slowed down by the others.
+    pub slowdown: f64,
+}
+
+/// Flow completion times (FCT) of a run, over the flows that
+/// sent all their packets.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FctReport {
+    /// The completed flows, in the order they were added.
+    pub flows: Vec<FlowCompletion>,
+    pub mean: f64,
+    pub median: usize,
+    pub p95: usize,
+    pub p99: usize,
+    pub max: usize,
+    pub mean_slowdown: f64,
+    pub p99_slowdown: f64,
+}
+
+impl FctReport {
+    /// Analyze the completion of the flows of a run on a link of
+    /// `bandwidth` bytes per tick.
+    ///
+    /// A flow is completed if it sent at least one packet and dropped none.
+    /// Alone on the link, every packet of a flow would have been sent as
+    /// soon as it arrived and the previous one departed.
+    pub fn compute(stats: &SchedulerStats, bandwidth: usize) -> FctReport {
+        assert!(bandwidth > 0, "the link needs a positive bandwidth");
+        let mut flows: Vec<FlowCompletion> = stats
+            .flows
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| f.packets > 0 && f.dropped == 0)
+            .map(|(idx, f)| FlowCompletion {
+                flow: FlowId(idx),
+                bytes: f.bytes,
+                completion_time: f.completion_time,
+                isolated_time: 0,
+                slowdown: 0f64,
+            })
+            .collect();
+        for completion in &mut flows {
+            let mut records: Vec<_> = stats
+                .packets
+                .iter()
+                .filter(|r| r.flow == completion.flow)
+                .collect();
+            records.sort_by_key(|r| r.arrival);
+            let first_arrival = records[0].arrival;
+            let mut departure = first_arrival;
+            for record in records {
+                departure = departure.max(record.arrival) + record.len.div_ceil(bandwidth);
+            }
+            completion.isolated_time = departure - first_arrival;
+            completion.slowdown =
+                completion.completion_time as f64 / completion.isolated_time.max(1) as f64;
+        }
+        if flows.is_empty() {
+            return FctReport::default();
+        }
+
+        let count = flows.len() as f64;
+        let mut times: Vec<usize> = flows.iter().map(|f| f.completion_time).collect();
+        times.sort_unstable();
+        let mut slowdowns: Vec<f64> = flows.iter().map(|f| f.slowdown).collect();
+        slowdowns.sort_by(f64::total_cmp);
+        let rank = ((0.99 * count).ceil() as usize).max(1) - 1;
+        FctReport {
+            mean: times.iter().sum::<usize>() as f64 / count,
+            median: percentile(&times, 0.5),
+            p95: percentile(&times, 0.95),
+            p99: percentile(&times, 0.99),
+            max: *times.last().unwrap(),
+            mean_slowdown: slowdowns.iter().sum::<f64>() / count,
+            p99_slowdown: slowdowns[rank],
+            flows,
+        }
+    }
+
+    /// The completion of a flow, None if it did not complete.
+    pub fn flow(&self, flow: FlowId) -> Option<&FlowCompletion> {
+        self.flows.iter().find(|f| f.flow == flow)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        schedulers::{fifo::FIFOScheduler, pfabric::PFabricScheduler},
+        FlowId, Packet, Scheduler,
+    };
+
+    use super::FctReport;
+
+    fn flows() -> Vec<VariableLengthFlow> {
+        [(6, 0), (2, 2), (3, 0)]
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (count, arrival))| {
+                let mut flow = VariableLengthFlow::new();
+                for p in 0..count {
+                    flow.packet_arrive(Packet::new(format!("f{}_{}", idx, p), 2), arrival);
+                }
+                flow
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fct_test() {
+        let mut pfabric = PFabricScheduler::new(2);
+        for flow in flows() {
+            Scheduler::add_flow(&mut pfabric, Box::new(flow), 1f64);
+        }
+        pfabric.run();
+        let report = FctReport::compute(&pfabric.scheduler_stats(), 2);
+
+        // The smaller flows go first and are never slowed down.
+        let slowdowns: Vec<f64> = report.flows.iter().map(|f| f.slowdown).collect();
+        assert_eq!(slowdowns, [11f64 / 6f64, 1.5, 1f64]);
+        let a = report.flow(FlowId(0)).unwrap();
+        assert_eq!((a.bytes, a.completion_time, a.isolated_time), (12, 11, 6));
+        assert_eq!(report.mean, 17f64 / 3f64);
+        assert_eq!((report.median, report.p99, report.max), (3, 11, 11));
+        assert_eq!(report.p99_slowdown, 11f64 / 6f64);
+
+        // FIFO serves the large flow first, slowing the small ones down more.
+        let mut fifo = FIFOScheduler::new(2);
+        for flow in flows() {
+            Scheduler::add_flow(&mut fifo, Box::new(flow), 1f64);
+        }
+        fifo.run();
+        let fifo = FctReport::compute(&fifo.scheduler_stats(), 2);
+        assert!(fifo.mean_slowdown > report.mean_slowdown);
+        assert!(fifo.mean > report.mean);
+    }
+
+    #[test]
+    fn fct_empty_test() {
+        let fifo = FIFOScheduler::new(1);
+        let report = FctReport::compute(&fifo.scheduler_stats(), 1);
+        assert!(report.flows.is_empty());
+        assert_eq!(report.mean, 0f64);
+    }
+}
@@ -0,0 +1,535 @@
+use crate::scheduling::{Ecn, FlowId, Packet, Tickable};
+
+pub mod fairness;
+pub mod fct;
+pub mod trace;
+
+/// Default smoothing factor of the EWMA throughput estimate.
+pub const DEFAULT_EWMA_ALPHA: f64 = 0.125;
+
+/// Exponentially-weighted moving average of the throughput of each flow.
+///
+/// Schedulers record the bytes they serve from a flow during a tick,
+/// and the estimate is folded once per tick:
+/// `estimate = alpha * served + (1 - alpha) * estimate`.
+#[derive(Debug, Clone)]
+pub struct EwmaThroughput {
+    alpha: f64,
+    estimates: Vec<f64>,
+    served: Vec<usize>,
+}
+
+impl EwmaThroughput {
+    pub fn new(alpha: f64) -> EwmaThroughput {
+        assert!(
+            (0f64..=1f64).contains(&alpha),
+            "EWMA alpha must be within [0, 1]"
+        );
+        EwmaThroughput {
+            alpha,
+            estimates: Vec::new(),
+            served: Vec::new(),
+        }
+    }
+
+    /// Start tracking one more flow.
+    pub fn add_flow(&mut self) {
+        self.estimates.push(0f64);
+        self.served.push(0);
+    }
+
+    pub fn set_alpha(&mut self, alpha: f64) {
+        assert!(
+            (0f64..=1f64).contains(&alpha),
+            "EWMA alpha must be within [0, 1]"
+        );
+        self.alpha = alpha;
+    }
+
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    /// Record that `bytes` of the flow were served during the current tick.
+    pub fn record(&mut self, flow_idx: usize, bytes: usize) {
+        self.served[flow_idx] += bytes;
+    }
+
+    /// Forget the history of every flow.
+    pub fn reset(&mut self) {
+        self.estimates.fill(0f64);
+        self.served.fill(0);
+    }
+
+    /// Fold `ticks` ticks during which nothing was served.
+    pub fn advance(&mut self, ticks: usize) {
+        if ticks == 0 {
+            return;
+        }
+        self.tick();
+        let decay = (1f64 - self.alpha).powi(ticks as i32 - 1);
+        for estimate in self.estimates.iter_mut() {
+            *estimate *= decay;
+        }
+    }
+
+    /// Get the smoothed throughput of a flow, in length units per tick.
+    pub fn estimate(&self, flow_idx: usize) -> f64 {
+        self.estimates[flow_idx]
+    }
+}
+
+impl Default for EwmaThroughput {
+    fn default() -> Self {
+        EwmaThroughput::new(DEFAULT_EWMA_ALPHA)
+    }
+}
+
+impl Tickable for EwmaThroughput {
+    /// Fold the bytes served during this tick into the estimates.
+    fn tick(&mut self) -> bool {
+        for (estimate, served) in self.estimates.iter_mut().zip(self.served.iter_mut()) {
+            *estimate = self.alpha * *served as f64 + (1f64 - self.alpha) * *estimate;
+            *served = 0;
+        }
+        true
+    }
+}
+
+/// Queue occupancy of a scheduler sampled over time, in packets.
+///
+/// Nothing is recorded until a sampling interval is set. Schedulers then
+/// take a sample at the end of every tick that is a multiple of the
+/// interval, once the packet of the tick is served, of the packets in
+/// their output port, waiting or being transmitted, and of the packets
+/// arrived but not yet served in the queue of each flow. Sampling stops
+/// once the flows are drained.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QueueSeries {
+    interval: Option<usize>,
+    times: Vec<usize>,
+    port: Vec<usize>,
+    flows: Vec<Vec<usize>>,
+}
+
+impl QueueSeries {
+    /// Sample every `interval` ticks, or never if None.
+    pub fn new(interval: Option<usize>) -> QueueSeries {
+        assert!(
+            interval != Some(0),
+            "the sampling interval must be positive"
+        );
+        QueueSeries {
+            interval,
+            ..QueueSeries::default()
+        }
+    }
+
+    /// Change the sampling interval, forgetting the samples taken so far.
+    pub fn set_interval(&mut self, interval: Option<usize>) {
+        *self = QueueSeries::new(interval);
+    }
+
+    pub fn interval(&self) -> Option<usize> {
+        self.interval
+    }
+
+    /// Whether a sample is due at `time`.
+    pub fn is_due(&self, time: usize) -> bool {
+        self.interval.is_some_and(|i| time.is_multiple_of(i))
+    }
+
+    /// Take a sample at `time` if one is due. The flow queue lengths
+    /// are only evaluated when a sample is taken.
+    pub fn record(&mut self, time: usize, port: usize, flows: impl IntoIterator<Item = usize>) {
+        if !self.is_due(time) {
+            return;
+        }
+        self.times.push(time);
+        self.port.push(port);
+        for (idx, len) in flows.into_iter().enumerate() {
+            if idx == self.flows.len() {
+                self.flows.push(vec![0; self.times.len() - 1]);
+            }
+            self.flows[idx].push(len);
+        }
+    }
+
+    /// Forget the samples taken so far, keeping the interval.
+    pub fn clear(&mut self) {
+        self.set_interval(self.interval);
+    }
+
+    /// The number of samples taken.
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    /// The tick of every sample.
+    pub fn times(&self) -> &[usize] {
+        &self.times
+    }
+
+    /// The length of the output port queue at every sample.
+    pub fn port(&self) -> &[usize] {
+        &self.port
+    }
+
+    /// The length of the queue of a flow at every sample,
+    /// empty if nothing was recorded for the flow.
+    pub fn flow(&self, flow: FlowId) -> &[usize] {
+        self.flows.get(flow.index()).map_or(&[], |f| f)
+    }
+}
+
+/// A packet dropped by a scheduler, at its output port
+/// or in a queue of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DropRecord {
+    pub flow: FlowId,
+    pub name: String,
+    pub len: usize,
+    /// When the packet arrived at the scheduler.
+    pub arrival: usize,
+    /// When the packet was dropped.
+    pub time: usize,
+}
+
+/// The packets dropped by a scheduler, counted per flow.
+///
+/// Packets dropped inside the queue of a flow are counted by the flow.
+#[derive(Debug, Clone, Default)]
+pub struct DropLog {
+    counts: Vec<usize>,
+    records: Vec<DropRecord>,
+}
+
+impl DropLog {
+    pub fn add_flow(&mut self) {
+        self.counts.push(0);
+    }
+
+    pub fn record(&mut self, flow_idx: usize, packet: &Packet, arrival: usize, time: usize) {
+        self.counts[flow_idx] += 1;
+        self.records.push(DropRecord {
+            flow: FlowId(flow_idx),
+            name: packet.name.clone(),
+            len: packet.len,
+            arrival,
+            time,
+        });
+    }
+
+    /// The number of packets dropped from a flow.
+    pub fn count(&self, flow_idx: usize) -> usize {
+        self.counts[flow_idx]
+    }
+
+    pub fn flow_count(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Every drop, in the order they happened.
+    pub fn records(&self) -> &[DropRecord] {
+        &self.records
+    }
+
+    /// Forget the drops, keeping the flows.
+    pub fn reset(&mut self) {
+        self.counts.fill(0);
+        self.records.clear();
+    }
+}
+
+/// The life of a packet that went through a scheduler, in ticks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PacketRecord {
+    pub flow: FlowId,
+    pub name: String,
+    pub len: usize,
+    /// Whether the packet departed with a congestion mark.
+    pub marked: bool,
+    /// When the packet arrived at the scheduler.
+    pub arrival: usize,
+    /// When the scheduler handed the packet to the output port.
+    pub dequeue: usize,
+    /// When the packet finished transmitting.
+    pub departure: usize,
+}
+
+impl PacketRecord {
+    /// Departure time minus arrival time.
+    pub fn delay(&self) -> usize {
+        self.departure - self.arrival
+    }
+}
+
+/// Statistics of the packets served from one flow, or from all of them.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FlowStats {
+    pub packets: usize,
+    pub bytes: usize,
+    /// Mean of departure time minus arrival time.
+    pub mean_delay: f64,
+    pub median_delay: usize,
+    pub p95_delay: usize,
+    pub p99_delay: usize,
+    pub max_delay: usize,
+    /// Mean of dequeue time minus arrival time.
+    pub mean_queueing_delay: f64,
+    /// Mean difference between the delays of consecutive packets.
+    pub jitter: f64,
+    /// Bytes per tick between the first arrival and the last departure.
+    pub throughput: f64,
+    /// Ticks between the first arrival and the last departure, the flow
+    /// completion time (FCT) once every packet of the flow was sent.
+    pub completion_time: usize,
+    /// Packets that departed after their deadline.
+    /// Only counted by deadline-aware schedulers.
+    pub deadline_misses: usize,
+    /// Packets sent with a negative slack, bound to miss their deadline.
+    /// Only counted by slack-aware schedulers.
+    pub slack_misses: usize,
+    /// Packets that departed with a congestion mark.
+    pub marked: usize,
+    /// Packets dropped anywhere in the scheduler.
+    pub dropped: usize,
+}
+
+impl FlowStats {
+    /// Summarize packet records, in departure order.
+    pub(crate) fn from_records<'a>(records: impl Iterator<Item = &'a PacketRecord>) -> FlowStats {
+        let mut stats = FlowStats::default();
+        let mut delays = Vec::new();
+        let mut queueing_delay = 0;
+        let mut delay_changes = 0;
+        let mut first_arrival = usize::MAX;
+        let mut last_departure = 0;
+        for record in records {
+            stats.packets += 1;
+            stats.bytes += record.len;
+            if record.marked {
+                stats.marked += 1;
+            }
+            if let Some(&last) = delays.last() {
+                delay_changes += record.delay().abs_diff(last);
+            }
+            delays.push(record.delay());
+            queueing_delay += record.dequeue - record.arrival;
+            first_arrival = first_arrival.min(record.arrival);
+            last_departure = last_departure.max(record.departure);
+        }
+        if stats.packets == 0 {
+            return stats;
+        }
+
+        let count = stats.packets as f64;
+        stats.mean_delay = delays.iter().sum::<usize>() as f64 / count;
+        stats.mean_queueing_delay = queueing_delay as f64 / count;
+        if stats.packets > 1 {
+            stats.jitter = delay_changes as f64 / (count - 1f64);
+        }
+        delays.sort_unstable();
+        stats.median_delay = percentile(&delays, 0.5);
+        stats.p95_delay = percentile(&delays, 0.95);
+        stats.p99_delay = percentile(&delays, 0.99);
+        stats.max_delay = *delays.last().unwrap();
+        stats.completion_time = last_departure - first_arrival;
+        stats.throughput = stats.bytes as f64 / stats.completion_time.max(1) as f64;
+        stats
+    }
+}
+
+/// The nearest-rank percentile of sorted values.
+fn percentile(sorted: &[usize], p: f64) -> usize {
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.max(1) - 1]
+}
+
+/// Everything measured during a scheduler run.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SchedulerStats {
+    /// Every packet that left the output port, in departure order.
+    pub packets: Vec<PacketRecord>,
+    /// Every packet dropped by the scheduler, in drop order.
+    pub drops: Vec<DropRecord>,
+    pub flows: Vec<FlowStats>,
+    /// Statistics over the packets of all flows.
+    pub aggregate: FlowStats,
+}
+
+impl SchedulerStats {
+    /// Compute the statistics of a run from the output of a port.
+    ///
+    /// `served` holds the flow index, arrival time and dequeue time of
+    /// every packet of `output`, in the same order,
+    /// `departures` the departure time of every packet of `output`,
+    /// `drops` the packets the scheduler dropped, and `dropped` gives
+    /// the number of packets dropped from a flow, including those
+    /// dropped inside the queue of the flow.
+    pub fn collect(
+        flow_count: usize,
+        served: &[(usize, usize, usize)],
+        output: &[Packet],
+        departures: &[usize],
+        drops: &[DropRecord],
+        dropped: impl Fn(FlowId) -> usize,
+    ) -> SchedulerStats {
+        let packets: Vec<PacketRecord> = served
+            .iter()
+            .zip(output)
+            .zip(departures)
+            .map(
+                |((&(flow_idx, arrival, dequeue), packet), &departure)| PacketRecord {
+                    flow: FlowId(flow_idx),
+                    name: packet.name.clone(),
+                    len: packet.len,
+                    marked: packet.ecn == Ecn::Ce,
+                    arrival,
+                    dequeue,
+                    departure,
+                },
+            )
+            .collect();
+
+        let flows: Vec<FlowStats> = (0..flow_count)
+            .map(FlowId)
+            .map(|flow| FlowStats {
+                dropped: dropped(flow),
+                ..FlowStats::from_records(packets.iter().filter(|r| r.flow == flow))
+            })
+            .collect();
+        let aggregate = FlowStats {
+            dropped: flows.iter().map(|f| f.dropped).sum(),
+            ..FlowStats::from_records(packets.iter())
+        };
+        SchedulerStats {
+            packets,
+            drops: drops.to_vec(),
+            flows,
+            aggregate,
+        }
+    }
+
+    /// The statistics of one flow.
+    pub fn flow(&self, flow: FlowId) -> &FlowStats {
+        &self.flows[flow.index()]
+    }
+
+    /// The mean flow completion time of the flows that sent packets.
+    pub fn mean_completion_time(&self) -> f64 {
+        let completed: Vec<usize> = self
+            .flows
+            .iter()
+            .filter(|f| f.packets > 0)
+            .map(|f| f.completion_time)
+            .collect();
+        if completed.is_empty() {
+            return 0f64;
+        }
+        completed.iter().sum::<usize>() as f64 / completed.len() as f64
+    }
+
+    /// Count the packets that departed more than their flow's budget
+    /// after they arrived as deadline misses.
+    pub fn count_deadline_misses(&mut self, budgets: &[usize]) {
+        for record in &self.packets {
+            if record.delay() > budgets[record.flow.index()] {
+                self.flows[record.flow.index()].deadline_misses += 1;
+                self.aggregate.deadline_misses += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        schedulers::sp::SPScheduler,
+        Ecn, FlowId, Packet, Scheduler,
+    };
+
+    use super::SchedulerStats;
+
+    #[test]
+    fn queue_series_test() {
+        // A burst of six packets at tick 0 and one more at tick 3,
+        // sampled every other tick.
+        let mut flow = VariableLengthFlow::new();
+        for p in 0..6 {
+            flow.packet_arrive(Packet::new(format!("p{}", p), 2), 0);
+        }
+        flow.packet_arrive(Packet::new("p6", 2), 3);
+        let mut sp = SPScheduler::new(1);
+        sp.add_flow(flow, 0);
+        sp.set_queue_sampling(Some(2));
+        sp.run();
+
+        // One packet is sent every two ticks, and p6 joins the queue.
+        let series = sp.queue_series();
+        assert_eq!(series.times(), &[0, 2, 4, 6, 8, 10, 12]);
+        assert_eq!(series.port(), &[1; 7]);
+        assert_eq!(series.flow(FlowId(0)), &[5, 4, 4, 3, 2, 1, 0]);
+        assert!(series.flow(FlowId(1)).is_empty());
+    }
+
+    #[test]
+    fn scheduler_stats_test() {
+        // Flow 0 sends ten packets with delays 1 to 10,
+        // flow 1 one marked packet with delay 20.
+        let mut served = Vec::new();
+        let mut output = Vec::new();
+        let mut departures = Vec::new();
+        for p in 0..10 {
+            served.push((0, p, p));
+            output.push(Packet::new(format!("a{}", p), 2));
+            departures.push(2 * p + 1);
+        }
+        served.push((1, 0, 15));
+        output.push(Packet::new("b", 4).with_ecn(Ecn::Ce));
+        departures.push(20);
+
+        let stats = SchedulerStats::collect(2, &served, &output, &departures, &[], |flow| {
+            flow.index() * 3
+        });
+        assert_eq!(stats.packets.len(), 11);
+        assert_eq!(stats.packets[10].delay(), 20);
+
+        let a = &stats.flows[0];
+        assert_eq!((a.packets, a.bytes, a.marked, a.dropped), (10, 20, 0, 0));
+        assert_eq!(a.mean_delay, 5.5);
+        assert_eq!(a.mean_queueing_delay, 0f64);
+        assert_eq!((a.median_delay, a.p95_delay, a.p99_delay), (5, 10, 10));
+        assert_eq!(a.max_delay, 10);
+        assert_eq!(a.jitter, 1f64);
+        assert_eq!(a.throughput, 20f64 / 19f64);
+        assert_eq!(a.completion_time, 19);
+
+        let b = &stats.flows[1];
+        assert_eq!((b.packets, b.marked, b.dropped), (1, 1, 3));
+        assert_eq!(b.jitter, 0f64);
+
+        let all = &stats.aggregate;
+        assert_eq!(
+            (all.packets, all.bytes, all.marked, all.dropped),
+            (11, 24, 1, 3)
+        );
+        assert_eq!(
+            (all.median_delay, all.p95_delay, all.max_delay),
+            (6, 20, 20)
+        );
+        assert_eq!(all.jitter, 1.9);
+        assert_eq!(all.completion_time, 20);
+        assert_eq!(stats.mean_completion_time(), 19.5);
+    }
+}
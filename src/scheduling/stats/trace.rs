@@ -0,0 +1,209 @@
+use std::{fmt, io};
+
+use crate::scheduling::FlowId;
+
+use super::SchedulerStats;
+
+/// What happened to a packet in a [`TraceEvent`].
+///
+/// Events of the same tick are ordered as listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TraceEventKind {
+    /// The packet finished transmitting and left the output port.
+    TransmissionEnd,
+    /// The packet arrived at the scheduler.
+    Arrival,
+    /// The scheduler picked the packet and handed it to the output port.
+    Dequeue,
+    /// The output port started transmitting the packet.
+    TransmissionStart,
+    /// The packet was dropped.
+    Drop,
+}
+
+impl fmt::Display for TraceEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceEventKind::TransmissionEnd => write!(f, "transmission_end"),
+            TraceEventKind::Arrival => write!(f, "arrival"),
+            TraceEventKind::Dequeue => write!(f, "dequeue"),
+            TraceEventKind::TransmissionStart => write!(f, "transmission_start"),
+            TraceEventKind::Drop => write!(f, "drop"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceEvent {
+    pub time: usize,
+    pub kind: TraceEventKind,
+    pub flow: FlowId,
+    /// The name of the packet.
+    pub packet: String,
+    pub len: usize,
+}
+
+/// Every event of a scheduler run, in time order.
+///
+/// Built from the [`SchedulerStats`] of the run. The output port
+/// transmits one packet at a time, so a packet starts transmitting when
+/// it is dequeued or when the packet before it ends, whichever is later.
+/// Packets dropped inside the queue of a flow do not appear.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Trace {
+    pub events: Vec<TraceEvent>,
+}
+
+impl Trace {
+    pub fn new(stats: &SchedulerStats) -> Trace {
+        let mut events = Vec::new();
+        let mut event = |time, kind, flow, packet: &str, len| {
+            events.push(TraceEvent {
+                time,
+                kind,
+                flow,
+                packet: packet.to_string(),
+                len,
+            })
+        };
+
+        let mut previous_end = 0;
+        for record in &stats.packets {
+            let (idx, name, len) = (record.flow, record.name.as_str(), record.len);
+            event(record.arrival, TraceEventKind::Arrival, idx, name, len);
+            event(record.dequeue, TraceEventKind::Dequeue, idx, name, len);
+            let start = record.dequeue.max(previous_end);
+            event(start, TraceEventKind::TransmissionStart, idx, name, len);
+            event(
+                record.departure,
+                TraceEventKind::TransmissionEnd,
+                idx,
+                name,
+                len,
+            );
+            previous_end = record.departure;
+        }
+        for drop in &stats.drops {
+            let (idx, name, len) = (drop.flow, drop.name.as_str(), drop.len);
+            event(drop.arrival, TraceEventKind::Arrival, idx, name, len);
+            event(drop.time, TraceEventKind::Drop, idx, name, len);
+        }
+
+        events.sort_by_key(|e| (e.time, e.kind));
+        Trace { events }
+    }
+
+    /// Write the events as CSV, with a header line
+    /// `time,event,flow,packet,len`.
+    pub fn write_csv(&self, mut writer: impl io::Write) -> io::Result<()> {
+        writeln!(writer, "time,event,flow,packet,len")?;
+        for e in &self.events {
+            writeln!(
+                writer,
+                "{},{},{},{},{}",
+                e.time,
+                e.kind,
+                e.flow,
+                csv_field(&e.packet),
+                e.len
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The events as CSV, see [`Trace::write_csv`].
+    pub fn to_csv(&self) -> String {
+        let mut csv = Vec::new();
+        self.write_csv(&mut csv).unwrap();
+        String::from_utf8(csv).unwrap()
+    }
+
+    /// Write the events as a JSON array.
+    #[cfg(feature = "serde")]
+    pub fn write_json(&self, writer: impl io::Write) -> serde_json::Result<()> {
+        serde_json::to_writer(writer, &self.events)
+    }
+
+    /// The events as a JSON array, see [`Trace::write_json`].
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(&self.events)
+    }
+}
+
+/// Quote a CSV field if it holds a separator, a quote or a line break.
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        schedulers::sp::SPScheduler,
+        Packet, Scheduler,
+    };
+
+    use super::{Trace, TraceEventKind};
+
+    fn traced_run() -> Trace {
+        let mut flow = VariableLengthFlow::new();
+        flow.packet_arrive(Packet::new("a", 1), 0);
+        flow.packet_arrive(Packet::new("big", 3), 0);
+        flow.packet_arrive(Packet::new("b,1", 1), 1);
+        let mut sp = SPScheduler::new(1);
+        sp.get_output_port().set_byte_capacity(Some(2));
+        sp.add_flow(flow, 0);
+        sp.run();
+        sp.trace()
+    }
+
+    #[test]
+    fn trace_test() {
+        // "big" does not fit in the port and is dropped when dequeued.
+        let trace = traced_run();
+        let events: Vec<(usize, TraceEventKind, &str)> = trace
+            .events
+            .iter()
+            .map(|e| (e.time, e.kind, e.packet.as_str()))
+            .collect();
+        use TraceEventKind::*;
+        assert_eq!(
+            events,
+            vec![
+                (0, Arrival, "a"),
+                (0, Arrival, "big"),
+                (0, Dequeue, "a"),
+                (0, TransmissionStart, "a"),
+                (1, TransmissionEnd, "a"),
+                (1, Arrival, "b,1"),
+                (1, Drop, "big"),
+                (2, Dequeue, "b,1"),
+                (2, TransmissionStart, "b,1"),
+                (3, TransmissionEnd, "b,1"),
+            ]
+        );
+
+        let csv = trace.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "time,event,flow,packet,len");
+        assert_eq!(lines[6], "1,arrival,0,\"b,1\",1");
+        assert_eq!(lines.len(), trace.events.len() + 1);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn trace_json_test() {
+        let trace = traced_run();
+        let json = trace.to_json().unwrap();
+        let events: Vec<super::TraceEvent> = serde_json::from_str(&json).unwrap();
+        assert_eq!(events, trace.events);
+    }
+}
@@ -0,0 +1,230 @@
+//! A device with several links: packets come in on input ports, are
+//! forwarded to an output port, and wait there in the queues of the
+//! scheduler of that port.
+
+use std::collections::BTreeMap;
+
+use crate::scheduling::{flow::VariableLengthFlow, Packet, Port, Scheduler};
+
+/// Maps packets to the output port they leave the [`Switch`] on.
+///
+/// A packet goes by its flow id if it has a route, then by its `dst` tag,
+/// then to the default port.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForwardingTable {
+    flows: BTreeMap<usize, usize>,
+    destinations: BTreeMap<String, usize>,
+    /// Port of the packets without a route, dropped if None.
+    pub default: Option<usize>,
+}
+
+impl ForwardingTable {
+    pub fn new(default: Option<usize>) -> ForwardingTable {
+        ForwardingTable {
+            default,
+            ..ForwardingTable::default()
+        }
+    }
+
+    /// Send the packets of the flow with the given id to `port`.
+    pub fn with_flow(mut self, flow_id: usize, port: usize) -> ForwardingTable {
+        self.flows.insert(flow_id, port);
+        self
+    }
+
+    /// Send the packets tagged with the destination `dst` to `port`.
+    pub fn with_destination(mut self, dst: impl Into<String>, port: usize) -> ForwardingTable {
+        self.destinations.insert(dst.into(), port);
+        self
+    }
+
+    /// The output port of `packet`, None if it has no route.
+    pub fn route(&self, packet: &Packet) -> Option<usize> {
+        packet
+            .flow_id
+            .and_then(|id| self.flows.get(&id))
+            .or_else(|| packet.tag("dst").and_then(|dst| self.destinations.get(dst)))
+            .copied()
+            .or(self.default)
+    }
+}
+
+/// A switch with input ports, a forwarding table and one scheduler per
+/// output port.
+///
+/// Packets are transmitted over their input port, then handed to the
+/// scheduler of their output port when they are fully received. There,
+/// every input port is a flow, so the scheduler shares the output link
+/// between the input ports by their weights.
+pub struct Switch {
+    inputs: Vec<Port>,
+    /// Weight of each input port at the output schedulers.
+    weights: Vec<f64>,
+    /// Packets arriving on each input port, and their arrival times.
+    arrivals: Vec<Vec<(Packet, usize)>>,
+    table: ForwardingTable,
+    outputs: Vec<Box<dyn Scheduler>>,
+    /// Packets received without a route, or to a port that does not exist.
+    unroutable: Vec<Packet>,
+}
+
+impl Switch {
+    pub fn new(table: ForwardingTable) -> Switch {
+        Switch {
+            inputs: Vec::new(),
+            weights: Vec::new(),
+            arrivals: Vec::new(),
+            table,
+            outputs: Vec::new(),
+            unroutable: Vec::new(),
+        }
+    }
+
+    /// Add an input port, returning its index.
+    pub fn add_input(&mut self, port: Port, weight: f64) -> usize {
+        self.inputs.push(port);
+        self.weights.push(weight);
+        self.arrivals.push(Vec::new());
+        self.inputs.len() - 1
+    }
+
+    /// Add an output port served by `scheduler`, returning its index.
+    /// The scheduler should have no flows, the switch adds them.
+    pub fn add_output(&mut self, scheduler: Box<dyn Scheduler>) -> usize {
+        self.outputs.push(scheduler);
+        self.outputs.len() - 1
+    }
+
+    /// Make a packet arrive on an input port at `time`.
+    pub fn receive(&mut self, input: usize, packet: Packet, time: usize) {
+        self.arrivals[input].push((packet, time));
+    }
+
+    /// Make the packets of a flow arrive on an input port.
+    pub fn receive_flow(&mut self, input: usize, flow: &VariableLengthFlow) {
+        self.arrivals[input].extend(flow.packet_states.iter().cloned());
+    }
+
+    pub fn input(&self, idx: usize) -> &Port {
+        &self.inputs[idx]
+    }
+
+    pub fn output(&self, idx: usize) -> &dyn Scheduler {
+        self.outputs[idx].as_ref()
+    }
+
+    pub fn table(&self) -> &ForwardingTable {
+        &self.table
+    }
+
+    /// The packets the switch could not forward.
+    pub fn unroutable(&self) -> &[Packet] {
+        &self.unroutable
+    }
+
+    /// Transmit the packets received over the input ports and run every
+    /// output scheduler to completion. Run the switch once, as the
+    /// forwarded packets are added to the output schedulers as new flows.
+    pub fn run(&mut self) {
+        let mut forwarded =
+            vec![vec![VariableLengthFlow::new(); self.inputs.len()]; self.outputs.len()];
+        for (input, port) in self.inputs.iter_mut().enumerate() {
+            let mut arrivals = std::mem::take(&mut self.arrivals[input]);
+            arrivals.sort_by_key(|&(_, time)| time);
+            for (packet, time) in arrivals {
+                port.advance(time.saturating_sub(port.timer));
+                // The port counts the packets it drops.
+                let _ = port.submit(packet);
+            }
+            port.proceed_rest();
+
+            for (packet, &time) in port.get_output().iter().zip(port.get_departure_times()) {
+                match self
+                    .table
+                    .route(packet)
+                    .filter(|&out| out < self.outputs.len())
+                {
+                    Some(out) => forwarded[out][input]
+                        .packet_states
+                        .push_back((packet.clone(), time)),
+                    None => self.unroutable.push(packet.clone()),
+                }
+            }
+        }
+
+        for (scheduler, flows) in self.outputs.iter_mut().zip(forwarded) {
+            for (flow, &weight) in flows.into_iter().zip(&self.weights) {
+                scheduler.add_flow(Box::new(flow), weight);
+            }
+            scheduler.run();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        schedulers::{drr::DRRScheduler, fifo::FIFOScheduler},
+        Packet, Port,
+    };
+
+    use super::{ForwardingTable, Switch};
+
+    #[test]
+    fn forwarding_table_test() {
+        let table = ForwardingTable::new(Some(2))
+            .with_flow(7, 0)
+            .with_destination("10.0.0.1", 1);
+        let packet = Packet::new("p", 1);
+        assert_eq!(table.route(&packet), Some(2));
+        assert_eq!(
+            table.route(&packet.clone().with_tag("dst", "10.0.0.1")),
+            Some(1)
+        );
+        // The flow id wins over the destination.
+        let both = packet.clone().with_tag("dst", "10.0.0.1").with_flow_id(7);
+        assert_eq!(table.route(&both), Some(0));
+        assert_eq!(ForwardingTable::new(None).route(&packet), None);
+    }
+
+    #[test]
+    fn switch_test() {
+        let table = ForwardingTable::new(None)
+            .with_destination("a", 0)
+            .with_destination("b", 1);
+        let mut switch = Switch::new(table);
+        let fast = switch.add_input(Port::new(0, 4), 1f64);
+        let slow = switch.add_input(Port::new(1, 1), 1f64);
+        switch.add_output(Box::new(FIFOScheduler::new(1)));
+        switch.add_output(Box::new(DRRScheduler::new(2)));
+
+        for p in 0..3 {
+            let to_a = Packet::new(format!("x{}", p), 2).with_tag("dst", "a");
+            switch.receive(fast, to_a, 0);
+            let to_b = Packet::new(format!("y{}", p), 2).with_tag("dst", "b");
+            switch.receive(slow, to_b, 0);
+        }
+        switch.receive(slow, Packet::new("z", 1).with_tag("dst", "a"), 0);
+        switch.receive(fast, Packet::new("lost", 1).with_tag("dst", "c"), 1);
+        switch.run();
+
+        // Over the fast input, a packet crosses in one tick.
+        assert_eq!(switch.input(fast).get_departure_times(), &vec![1, 2, 3, 4]);
+        assert_eq!(switch.input(slow).get_departure_times(), &vec![2, 4, 6, 7]);
+        let names = |out: usize| -> Vec<String> {
+            switch
+                .output(out)
+                .output()
+                .iter()
+                .map(|p| p.name.clone())
+                .collect()
+        };
+        assert_eq!(names(0), vec!["x0", "x1", "x2", "z"]);
+        assert_eq!(names(1), vec!["y0", "y1", "y2"]);
+        assert_eq!(switch.output(1).stats()[0].packets, 0);
+        assert_eq!(
+            switch.unroutable(),
+            &[Packet::new("lost", 1).with_tag("dst", "c")]
+        );
+    }
+}
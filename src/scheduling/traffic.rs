@@ -0,0 +1,2008 @@
+//! Generation of synthetic traffic, open-loop from packet sources and
+//! closed-loop from TCP sources reacting to the network.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::scheduling::{
+    flow::{OpenFlow, VariableLengthFlow},
+    loss::LossModel,
+    network::{Ack, Receiver, ReversePath},
+    Ecn, FlowId, Packet, Scheduler,
+};
+
+/// Seed of the sources that were not given one.
+pub const DEFAULT_TRAFFIC_SEED: u64 = 0;
+
+/// A generator of packets, giving them with their arrival times
+/// in time order.
+pub trait TrafficSource: Iterator<Item = (Packet, usize)> {
+    /// The tick the next packet arrives at,
+    /// None once the source has no packet left.
+    fn next_arrival(&self) -> Option<usize>;
+
+    /// A flow of the packets arriving before tick `end`.
+    /// The source carries on after them.
+    fn flow_until(&mut self, end: usize) -> VariableLengthFlow {
+        let mut flow = VariableLengthFlow::new();
+        while self.next_arrival().is_some_and(|time| time < end) {
+            flow.packet_states.extend(self.next());
+        }
+        flow
+    }
+
+    /// A flow of the next `count` packets.
+    fn flow_of(&mut self, count: usize) -> VariableLengthFlow {
+        // Arrivals come in order, so they need no sorting.
+        VariableLengthFlow {
+            packet_states: self.take(count).collect(),
+            closed: None,
+        }
+    }
+}
+
+/// The classic trimodal internet mix (IMIX): seven 64-byte packets for
+/// four 576-byte and one 1500-byte packet.
+pub const INTERNET_MIX: [(usize, f64); 3] = [(64, 7f64), (576, 4f64), (1500, 1f64)];
+
+/// Distribution of the lengths of generated packets.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SizeDistribution {
+    /// Every packet has the same length.
+    Fixed(usize),
+    /// Lengths drawn uniformly from `min..=max`.
+    Uniform(usize, usize),
+    /// Lengths drawn from a list of lengths and their relative weights,
+    /// such as a histogram measured on a real link.
+    Empirical(Vec<(usize, f64)>),
+}
+
+impl SizeDistribution {
+    /// The [`INTERNET_MIX`].
+    pub fn internet_mix() -> SizeDistribution {
+        SizeDistribution::Empirical(INTERNET_MIX.to_vec())
+    }
+
+    /// Read an empirical distribution from a histogram, one bin per line
+    /// holding a length and its count, separated by a comma or spaces.
+    /// Blank lines and lines starting with `#` are skipped.
+    pub fn from_histogram(reader: impl io::BufRead) -> io::Result<SizeDistribution> {
+        let invalid = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid histogram line: {}", line),
+            )
+        };
+        let mut bins = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let mut fields = trimmed
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|f| !f.is_empty());
+            let (Some(len), Some(count), None) = (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid(&line));
+            };
+            let len = len.parse().map_err(|_| invalid(&line))?;
+            let count: f64 = count.parse().map_err(|_| invalid(&line))?;
+            if count < 0f64 {
+                return Err(invalid(&line));
+            }
+            bins.push((len, count));
+        }
+        if bins.iter().all(|&(_, count)| count == 0f64) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "empty histogram",
+            ));
+        }
+        Ok(SizeDistribution::Empirical(bins))
+    }
+
+    /// The mean length of a packet.
+    pub fn mean(&self) -> f64 {
+        match self {
+            SizeDistribution::Fixed(len) => *len as f64,
+            SizeDistribution::Uniform(min, max) => (min + max) as f64 / 2f64,
+            SizeDistribution::Empirical(bins) => {
+                let total: f64 = bins.iter().map(|(_, weight)| weight).sum();
+                bins.iter()
+                    .map(|&(len, weight)| len as f64 * weight)
+                    .sum::<f64>()
+                    / total
+            }
+        }
+    }
+
+    /// Draw the length of a packet.
+    pub fn sample(&self, rng: &mut impl Rng) -> usize {
+        match self {
+            SizeDistribution::Fixed(len) => *len,
+            SizeDistribution::Uniform(min, max) => rng.gen_range(*min..=*max),
+            SizeDistribution::Empirical(bins) => {
+                let total: f64 = bins.iter().map(|(_, weight)| weight).sum();
+                let mut target = rng.gen::<f64>() * total;
+                for &(len, weight) in bins {
+                    if target < weight {
+                        return len;
+                    }
+                    target -= weight;
+                }
+                bins.last().expect("no packet size to choose from").0
+            }
+        }
+    }
+}
+
+/// Distribution of the lengths of the ON and OFF periods of an
+/// [`OnOffSource`], in ticks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Period {
+    Exponential {
+        mean: f64,
+    },
+    /// Heavy-tailed periods, with a shape between 1 and 2 for a finite
+    /// mean and an infinite variance.
+    Pareto {
+        shape: f64,
+        mean: f64,
+    },
+}
+
+impl Period {
+    fn sample(&self, rng: &mut StdRng) -> f64 {
+        match *self {
+            Period::Exponential { mean } => exponential(rng, mean),
+            Period::Pareto { shape, mean } => {
+                assert!(shape > 1f64, "a Pareto period needs a shape over 1");
+                let scale = mean * (shape - 1f64) / shape;
+                scale / (1f64 - rng.gen::<f64>()).powf(1f64 / shape)
+            }
+        }
+    }
+}
+
+/// Draw from the exponential distribution of the given mean.
+fn exponential(rng: &mut StdRng, mean: f64) -> f64 {
+    // 1 - u is within (0, 1], which keeps the logarithm finite.
+    -(1f64 - rng.gen::<f64>()).ln() * mean
+}
+
+/// A source of packets arriving as a Poisson process.
+///
+/// Inter-arrival times are exponentially distributed with a mean of
+/// `1 / rate` ticks, and a packet arrives at the tick its arrival time
+/// falls in. The packets are named by a prefix and their sequence
+/// number, and the same seed always gives the same packets.
+#[derive(Debug, Clone)]
+pub struct PoissonSource {
+    rate: f64,
+    size: SizeDistribution,
+    prefix: String,
+    rng: StdRng,
+    next_time: f64,
+    count: usize,
+}
+
+impl PoissonSource {
+    /// A source of `rate` packets per tick on average.
+    pub fn new(rate: f64, size: SizeDistribution) -> PoissonSource {
+        assert!(rate > 0f64, "a Poisson source needs a positive rate");
+        let mut source = PoissonSource {
+            rate,
+            size,
+            prefix: "p".to_string(),
+            rng: StdRng::seed_from_u64(DEFAULT_TRAFFIC_SEED),
+            next_time: 0f64,
+            count: 0,
+        };
+        source.next_time = source.inter_arrival();
+        source
+    }
+
+    /// Restart the source from `seed`.
+    pub fn with_seed(mut self, seed: u64) -> PoissonSource {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.next_time = self.inter_arrival();
+        self.count = 0;
+        self
+    }
+
+    /// Name the packets `prefix` followed by their sequence number.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> PoissonSource {
+        self.prefix = prefix.into();
+        self
+    }
+
+    fn inter_arrival(&mut self) -> f64 {
+        exponential(&mut self.rng, 1f64 / self.rate)
+    }
+}
+
+impl TrafficSource for PoissonSource {
+    fn next_arrival(&self) -> Option<usize> {
+        Some(self.next_time as usize)
+    }
+}
+
+/// The packets of the source and their arrival times, without end.
+impl Iterator for PoissonSource {
+    type Item = (Packet, usize);
+
+    fn next(&mut self) -> Option<(Packet, usize)> {
+        let time = self.next_time as usize;
+        let len = self.size.sample(&mut self.rng);
+        let packet = Packet::new(format!("{}{}", self.prefix, self.count), len);
+        self.count += 1;
+        self.next_time += self.inter_arrival();
+        Some((packet, time))
+    }
+}
+
+/// A source alternating between ON periods, sending packets at a
+/// constant rate, and silent OFF periods.
+///
+/// With heavy-tailed [`Period::Pareto`] periods, the aggregate of many
+/// such sources is self-similar: it stays bursty at every time scale.
+/// The source starts with an ON period at tick 0, and the same seed
+/// always gives the same packets.
+#[derive(Debug, Clone)]
+pub struct OnOffSource {
+    rate: f64,
+    on: Period,
+    off: Period,
+    size: SizeDistribution,
+    prefix: String,
+    rng: StdRng,
+    next_time: f64,
+    on_end: f64,
+    count: usize,
+}
+
+impl OnOffSource {
+    /// A source of `rate` packets per tick while ON.
+    pub fn new(rate: f64, on: Period, off: Period, size: SizeDistribution) -> OnOffSource {
+        assert!(rate > 0f64, "an ON/OFF source needs a positive rate");
+        OnOffSource {
+            rate,
+            on,
+            off,
+            size,
+            prefix: "p".to_string(),
+            rng: StdRng::seed_from_u64(DEFAULT_TRAFFIC_SEED),
+            next_time: 0f64,
+            on_end: 0f64,
+            count: 0,
+        }
+        .with_seed(DEFAULT_TRAFFIC_SEED)
+    }
+
+    /// Restart the source from `seed`.
+    pub fn with_seed(mut self, seed: u64) -> OnOffSource {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.next_time = 0f64;
+        self.on_end = self.on.sample(&mut self.rng);
+        self.count = 0;
+        self
+    }
+
+    /// Name the packets `prefix` followed by their sequence number.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> OnOffSource {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// The average number of packets per tick, ON and OFF periods together.
+    pub fn mean_rate(&self) -> f64 {
+        let mean = |period: Period| match period {
+            Period::Exponential { mean } | Period::Pareto { mean, .. } => mean,
+        };
+        self.rate * mean(self.on) / (mean(self.on) + mean(self.off))
+    }
+}
+
+impl TrafficSource for OnOffSource {
+    fn next_arrival(&self) -> Option<usize> {
+        Some(self.next_time as usize)
+    }
+}
+
+/// The packets of the source and their arrival times, without end.
+impl Iterator for OnOffSource {
+    type Item = (Packet, usize);
+
+    fn next(&mut self) -> Option<(Packet, usize)> {
+        let time = self.next_time as usize;
+        let len = self.size.sample(&mut self.rng);
+        let packet = Packet::new(format!("{}{}", self.prefix, self.count), len);
+        self.count += 1;
+        self.next_time += 1f64 / self.rate;
+        // Skip the OFF periods, and ON periods too short for a packet.
+        while self.next_time >= self.on_end {
+            let on_start = self.on_end + self.off.sample(&mut self.rng);
+            self.on_end = on_start + self.on.sample(&mut self.rng);
+            if self.next_time < on_start {
+                self.next_time = on_start;
+            }
+        }
+        Some((packet, time))
+    }
+}
+
+/// A Constant Bit Rate (CBR) source: packets of one length at a fixed
+/// interval, optionally delayed by a random jitter.
+///
+/// The jitter never reorders packets, a packet delayed past the nominal
+/// arrival of the next one makes that one arrive with it.
+#[derive(Debug, Clone)]
+pub struct CbrSource {
+    interval: usize,
+    packet_len: usize,
+    jitter: usize,
+    prefix: String,
+    rng: StdRng,
+    nominal: usize,
+    next_time: usize,
+    count: usize,
+}
+
+impl CbrSource {
+    /// A source of packets of `packet_len` bytes every `interval` ticks,
+    /// the first one at tick 0.
+    pub fn new(interval: usize, packet_len: usize) -> CbrSource {
+        assert!(
+            interval > 0,
+            "a CBR source needs an interval of at least one tick"
+        );
+        CbrSource {
+            interval,
+            packet_len,
+            jitter: 0,
+            prefix: "p".to_string(),
+            rng: StdRng::seed_from_u64(DEFAULT_TRAFFIC_SEED),
+            nominal: 0,
+            next_time: 0,
+            count: 0,
+        }
+    }
+
+    /// Start at tick `offset` instead of 0.
+    pub fn with_offset(mut self, offset: usize) -> CbrSource {
+        self.nominal = offset;
+        self.next_time = offset;
+        self
+    }
+
+    /// Delay every packet by up to `jitter` ticks, uniformly.
+    pub fn with_jitter(mut self, jitter: usize) -> CbrSource {
+        self.jitter = jitter;
+        self.next_time = self.jittered(self.next_time);
+        self
+    }
+
+    /// Draw the jitter from `seed`.
+    pub fn with_seed(mut self, seed: u64) -> CbrSource {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.next_time = self.jittered(self.nominal);
+        self
+    }
+
+    /// Name the packets `prefix` followed by their sequence number.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> CbrSource {
+        self.prefix = prefix.into();
+        self
+    }
+
+    fn jittered(&mut self, time: usize) -> usize {
+        time + self.rng.gen_range(0..=self.jitter)
+    }
+}
+
+impl TrafficSource for CbrSource {
+    fn next_arrival(&self) -> Option<usize> {
+        Some(self.next_time)
+    }
+}
+
+/// The packets of the source and their arrival times, without end.
+impl Iterator for CbrSource {
+    type Item = (Packet, usize);
+
+    fn next(&mut self) -> Option<(Packet, usize)> {
+        let time = self.next_time;
+        let packet = Packet::new(format!("{}{}", self.prefix, self.count), self.packet_len);
+        self.count += 1;
+        self.nominal += self.interval;
+        self.next_time = self.jittered(self.nominal).max(time);
+        Some((packet, time))
+    }
+}
+
+/// A Variable Bit Rate (VBR) source following a rate profile, such as
+/// one measured on a real link.
+///
+/// The profile lists from which tick on the source sends how many bytes
+/// per tick, and can be scaled as a whole. Packets are sent as soon as
+/// the bytes for them have accumulated, and the source ends once the
+/// rate stays at zero.
+#[derive(Debug, Clone)]
+pub struct VbrSource {
+    profile: Vec<(usize, f64)>,
+    scale: f64,
+    size: SizeDistribution,
+    prefix: String,
+    seed: u64,
+    rng: StdRng,
+    time: usize,
+    credit: f64,
+    count: usize,
+    next: Option<(Packet, usize)>,
+}
+
+impl VbrSource {
+    /// A source following `profile`, a list of ticks in increasing order
+    /// and the rates in bytes per tick taking effect on them.
+    /// The rate is zero before the first tick of the profile.
+    pub fn new(profile: Vec<(usize, f64)>, size: SizeDistribution) -> VbrSource {
+        assert!(
+            profile.windows(2).all(|w| w[0].0 < w[1].0),
+            "the ticks of a rate profile must be increasing"
+        );
+        VbrSource {
+            profile,
+            scale: 1f64,
+            size,
+            prefix: "p".to_string(),
+            seed: DEFAULT_TRAFFIC_SEED,
+            rng: StdRng::seed_from_u64(DEFAULT_TRAFFIC_SEED),
+            time: 0,
+            credit: 0f64,
+            count: 0,
+            next: None,
+        }
+        .restart()
+    }
+
+    /// Multiply every rate of the profile by `scale`.
+    pub fn with_scale(mut self, scale: f64) -> VbrSource {
+        self.scale = scale;
+        self.restart()
+    }
+
+    /// Draw the packet lengths from `seed`.
+    pub fn with_seed(mut self, seed: u64) -> VbrSource {
+        self.seed = seed;
+        self.restart()
+    }
+
+    /// Name the packets `prefix` followed by their sequence number.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> VbrSource {
+        self.prefix = prefix.into();
+        if let Some((packet, _)) = &mut self.next {
+            packet.name = format!("{}0", self.prefix);
+        }
+        self
+    }
+
+    fn restart(mut self) -> VbrSource {
+        self.rng = StdRng::seed_from_u64(self.seed);
+        self.time = 0;
+        self.credit = self.rate(0);
+        self.count = 0;
+        self.next = self.generate();
+        self
+    }
+
+    /// The scaled rate on tick `time`.
+    fn rate(&self, time: usize) -> f64 {
+        let idx = self.profile.partition_point(|&(tick, _)| tick <= time);
+        idx.checked_sub(1)
+            .map_or(0f64, |idx| self.profile[idx].1 * self.scale)
+    }
+
+    fn generate(&mut self) -> Option<(Packet, usize)> {
+        let len = self.size.sample(&mut self.rng);
+        while self.credit < len as f64 {
+            let last_change = self.profile.last().map_or(0, |&(tick, _)| tick);
+            if self.time >= last_change && self.rate(self.time) <= 0f64 {
+                return None;
+            }
+            self.time += 1;
+            self.credit += self.rate(self.time);
+        }
+        self.credit -= len as f64;
+        let packet = Packet::new(format!("{}{}", self.prefix, self.count), len);
+        self.count += 1;
+        Some((packet, self.time))
+    }
+}
+
+impl TrafficSource for VbrSource {
+    fn next_arrival(&self) -> Option<usize> {
+        self.next.as_ref().map(|(_, time)| *time)
+    }
+}
+
+impl Iterator for VbrSource {
+    type Item = (Packet, usize);
+
+    fn next(&mut self) -> Option<(Packet, usize)> {
+        let next = self.next.take()?;
+        self.next = self.generate();
+        Some(next)
+    }
+}
+
+/// A Markov-Modulated Poisson Process (MMPP) source.
+///
+/// The source moves between states following a continuous-time Markov
+/// chain and, while in a state, sends packets as a Poisson process of
+/// the rate of that state. A chain alternating between a quiet and a
+/// busy state gives traffic burstier than a plain Poisson source, with
+/// bursts lasting as long as the busy state. The source starts in the
+/// first state, and ends if it reaches a state it never leaves and
+/// that sends nothing.
+#[derive(Debug, Clone)]
+pub struct MmppSource {
+    rates: Vec<f64>,
+    transitions: Vec<Vec<f64>>,
+    size: SizeDistribution,
+    prefix: String,
+    rng: StdRng,
+    state: usize,
+    state_end: f64,
+    next_time: Option<f64>,
+    count: usize,
+}
+
+impl MmppSource {
+    /// A source with the given packets per tick in each state, moving from
+    /// state `i` to state `j` at rate `transitions[i][j]` per tick.
+    /// The rates from a state to itself are ignored.
+    pub fn new(rates: Vec<f64>, transitions: Vec<Vec<f64>>, size: SizeDistribution) -> MmppSource {
+        assert!(!rates.is_empty(), "an MMPP source needs a state");
+        assert!(
+            transitions.len() == rates.len() && transitions.iter().all(|t| t.len() == rates.len()),
+            "an MMPP source needs a transition rate between every two states"
+        );
+        assert!(
+            rates
+                .iter()
+                .chain(transitions.iter().flatten())
+                .all(|&r| r >= 0f64),
+            "the rates of an MMPP source cannot be negative"
+        );
+        MmppSource {
+            rates,
+            transitions,
+            size,
+            prefix: "p".to_string(),
+            rng: StdRng::seed_from_u64(DEFAULT_TRAFFIC_SEED),
+            state: 0,
+            state_end: 0f64,
+            next_time: None,
+            count: 0,
+        }
+        .with_seed(DEFAULT_TRAFFIC_SEED)
+    }
+
+    /// Restart the source from `seed`.
+    pub fn with_seed(mut self, seed: u64) -> MmppSource {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.state = 0;
+        self.state_end = self.sojourn(0f64);
+        self.count = 0;
+        self.next_time = self.arrival_after(0f64);
+        self
+    }
+
+    /// Name the packets `prefix` followed by their sequence number.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> MmppSource {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// The state the source is in when its next packet arrives.
+    pub fn state(&self) -> usize {
+        self.state
+    }
+
+    /// When the source leaves the current state, entered at `time`.
+    fn sojourn(&mut self, time: f64) -> f64 {
+        let leave_rate: f64 = self.leave_rates().sum();
+        if leave_rate > 0f64 {
+            time + exponential(&mut self.rng, 1f64 / leave_rate)
+        } else {
+            f64::INFINITY
+        }
+    }
+
+    fn leave_rates(&self) -> impl Iterator<Item = f64> + '_ {
+        let state = self.state;
+        self.transitions[state]
+            .iter()
+            .enumerate()
+            .map(move |(to, &rate)| if to == state { 0f64 } else { rate })
+    }
+
+    /// The arrival time of the first packet after `time`, moving
+    /// through the states on the way.
+    fn arrival_after(&mut self, mut time: f64) -> Option<f64> {
+        loop {
+            let rate = self.rates[self.state];
+            if rate > 0f64 {
+                let arrival = time + exponential(&mut self.rng, 1f64 / rate);
+                if arrival < self.state_end {
+                    return Some(arrival);
+                }
+            } else if self.state_end == f64::INFINITY {
+                return None;
+            }
+            // Arrivals are memoryless, so the one beyond the end of the
+            // state is forgotten and the next state draws its own.
+            time = self.state_end;
+            let leave_rates: Vec<f64> = self.leave_rates().collect();
+            let mut target = self.rng.gen::<f64>() * leave_rates.iter().sum::<f64>();
+            for (to, &rate) in leave_rates.iter().enumerate() {
+                if rate > 0f64 {
+                    self.state = to;
+                    if target < rate {
+                        break;
+                    }
+                    target -= rate;
+                }
+            }
+            self.state_end = self.sojourn(time);
+        }
+    }
+}
+
+impl TrafficSource for MmppSource {
+    fn next_arrival(&self) -> Option<usize> {
+        self.next_time.map(|time| time as usize)
+    }
+}
+
+impl Iterator for MmppSource {
+    type Item = (Packet, usize);
+
+    fn next(&mut self) -> Option<(Packet, usize)> {
+        let time = self.next_time?;
+        let len = self.size.sample(&mut self.rng);
+        let packet = Packet::new(format!("{}{}", self.prefix, self.count), len);
+        self.count += 1;
+        self.next_time = self.arrival_after(time);
+        Some((packet, time as usize))
+    }
+}
+
+/// Initial congestion window of a [`TcpSource`], in packets, as in RFC 6928.
+pub const DEFAULT_INITIAL_WINDOW: f64 = 10f64;
+
+/// Retransmission timeout of a [`TcpSource`] before its first round-trip
+/// sample, in ticks.
+pub const DEFAULT_INITIAL_RTO: usize = 100;
+
+/// Lower bound of the retransmission timeout of a [`TcpSource`], in ticks.
+pub const DEFAULT_MIN_RTO: usize = 10;
+
+/// Scaling constant of the CUBIC window curve, in packets per cubed
+/// round trip.
+const CUBIC_C: f64 = 0.4;
+
+/// Factor CUBIC multiplies its window by on a loss.
+const CUBIC_BETA: f64 = 0.7;
+
+/// Duplicate acknowledgements taken as the sign of a loss.
+const DUP_ACK_THRESHOLD: usize = 3;
+
+/// Gain of the DCTCP estimate of the fraction of marked packets, as in
+/// RFC 8257.
+const DCTCP_GAIN: f64 = 1f64 / 16f64;
+
+/// Gain of BBR while it looks for the bottleneck bandwidth, the smallest
+/// to double the delivery rate every round trip.
+const BBR_HIGH_GAIN: f64 = 2.885;
+
+/// Pacing gains BBR cycles through once it found the bandwidth, one round
+/// trip each: probing for more, draining the queue probing made, cruising.
+const BBR_GAIN_CYCLE: [f64; 8] = [1.25, 0.75, 1f64, 1f64, 1f64, 1f64, 1f64, 1f64];
+
+/// Gain of the BBR window over the estimated bandwidth-delay product once
+/// the bandwidth was found.
+const BBR_CWND_GAIN: f64 = 2f64;
+
+/// Round trips the BBR bandwidth estimate is the largest delivery rate of.
+const BBR_BW_ROUNDS: usize = 10;
+
+/// Round trips the BBR minimum round-trip time holds for, before the
+/// queue is drained to measure it again.
+const BBR_MIN_RTT_ROUNDS: usize = 100;
+
+/// Smallest window of BBR, to which it shrinks to drain the queue.
+const BBR_MIN_CWND: f64 = 4f64;
+
+/// How a [`TcpSource`] grows its window and backs off on congestion.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CongestionControl {
+    /// NewReno: one packet more per round trip, halved on a loss.
+    #[default]
+    Reno,
+    /// CUBIC, as in RFC 9438: the window follows a cubic curve of the
+    /// time since the last loss, plateauing around the window the loss
+    /// happened at, and is cut by 30% on a loss. Time is counted in
+    /// smoothed round trips rather than seconds, so the curve does not
+    /// depend on the length of a tick, and there is no Reno-friendly
+    /// region.
+    Cubic,
+    /// DCTCP, as in RFC 8257: the window grows as in Reno, but on
+    /// congestion marks it is cut in proportion to the fraction of
+    /// packets marked, estimated over every window, rather than halved.
+    /// Losses still halve it. The packets are always ECN-capable, and
+    /// meant for a queue marking on a threshold, see
+    /// [`Red::threshold`](super::aqm::Red::threshold).
+    Dctcp,
+    /// BBR, version 1: the source paces its packets at the bottleneck
+    /// bandwidth it estimated from the delivery rate, and keeps about
+    /// twice the bandwidth-delay product in flight, with the delay the
+    /// smallest round trip it saw. It ignores losses and congestion
+    /// marks, and keeps queues short whatever the buffer size.
+    Bbr,
+    /// An abstract additive-increase multiplicative-decrease control,
+    /// without slow start, to explore the space of parameters.
+    Aimd(Aimd),
+}
+
+/// Parameters of [`CongestionControl::Aimd`].
+///
+/// Reno in congestion avoidance is an increase of 1 and a decrease of
+/// 0.5, reacting to losses. Whatever the signals taken, lost packets are
+/// sent again, and a retransmission timeout still shrinks the window to
+/// a packet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Aimd {
+    /// Packets added to the window every round trip.
+    pub increase: f64,
+    /// Factor the window is multiplied by on congestion.
+    pub decrease: f64,
+    /// Whether losses signal congestion.
+    pub on_loss: bool,
+    /// Whether congestion marks signal congestion, in which case the
+    /// packets are ECN-capable.
+    pub on_mark: bool,
+}
+
+impl Aimd {
+    /// An increase of `increase` packets per round trip and a decrease by
+    /// a factor of `decrease` on losses and congestion marks alike.
+    pub fn new(increase: f64, decrease: f64) -> Aimd {
+        assert!(increase > 0f64, "AIMD needs a positive increase");
+        assert!(
+            decrease > 0f64 && decrease < 1f64,
+            "AIMD needs a decrease within (0, 1)"
+        );
+        Aimd {
+            increase,
+            decrease,
+            on_loss: true,
+            on_mark: true,
+        }
+    }
+}
+
+/// Phase of the BBR model of a [`TcpSource`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BbrMode {
+    /// Double the rate every round trip until it stops growing.
+    Startup,
+    /// Drain the queue that startup built.
+    Drain,
+    /// Cycle through the pacing gains at the bandwidth found.
+    ProbeBw,
+    /// Shrink the window to measure the round trip without queueing.
+    ProbeRtt,
+}
+
+/// The model BBR has of the path, from the delivery rate and the round
+/// trips of the acknowledged packets.
+#[derive(Debug, Clone)]
+struct Bbr {
+    mode: BbrMode,
+    /// Round trips so far, a round ending when a packet sent after its
+    /// start is acknowledged.
+    round: usize,
+    /// The packets delivered when the current round started.
+    round_delivered: usize,
+    /// Delivery rate samples, in packets per tick, with their round.
+    rate_samples: VecDeque<(usize, f64)>,
+    /// Smallest round trip seen and the round it was seen in.
+    min_rtt: Option<(f64, usize)>,
+    /// Largest bandwidth before the latest rounds without growth.
+    full_bw: f64,
+    full_bw_rounds: usize,
+    /// Index into the [`BBR_GAIN_CYCLE`] and the tick it started.
+    cycle: (usize, usize),
+    /// The round a probe of the round trip ends at, once the queue drained.
+    probe_rtt_end: Option<usize>,
+    /// The earliest tick the next packet may leave, paced.
+    next_send: f64,
+}
+
+impl Bbr {
+    fn new() -> Bbr {
+        Bbr {
+            mode: BbrMode::Startup,
+            round: 0,
+            round_delivered: 0,
+            rate_samples: VecDeque::new(),
+            min_rtt: None,
+            full_bw: 0f64,
+            full_bw_rounds: 0,
+            cycle: (0, 0),
+            probe_rtt_end: None,
+            next_send: 0f64,
+        }
+    }
+
+    /// The bottleneck bandwidth, in packets per tick.
+    fn btl_bw(&self) -> f64 {
+        self.rate_samples
+            .iter()
+            .map(|&(_, rate)| rate)
+            .fold(0f64, f64::max)
+    }
+
+    /// The bandwidth-delay product, in packets, once both are estimated.
+    fn bdp(&self) -> Option<f64> {
+        let (rtt, _) = self.min_rtt?;
+        Some(self.btl_bw() * rtt).filter(|&bdp| bdp > 0f64)
+    }
+
+    fn pacing_gain(&self) -> f64 {
+        match self.mode {
+            BbrMode::Startup => BBR_HIGH_GAIN,
+            BbrMode::Drain => 1f64 / BBR_HIGH_GAIN,
+            BbrMode::ProbeBw => BBR_GAIN_CYCLE[self.cycle.0],
+            BbrMode::ProbeRtt => 1f64,
+        }
+    }
+
+    /// The rate to pace the packets at, once the bandwidth is estimated.
+    fn pacing_rate(&self) -> Option<f64> {
+        Some(self.pacing_gain() * self.btl_bw()).filter(|&rate| rate > 0f64)
+    }
+
+    /// The window the model aims for, once it estimated the path.
+    fn target_cwnd(&self) -> Option<f64> {
+        let gain = match self.mode {
+            BbrMode::Startup | BbrMode::Drain => BBR_HIGH_GAIN,
+            BbrMode::ProbeBw | BbrMode::ProbeRtt => BBR_CWND_GAIN,
+        };
+        self.bdp().map(|bdp| (gain * bdp).max(BBR_MIN_CWND))
+    }
+
+    /// Take the acknowledgement at `time` of a packet sent at `sent`, when
+    /// `sent_delivered` packets were delivered, now that `delivered` are
+    /// with `in_flight` still outstanding.
+    fn on_ack(
+        &mut self,
+        time: usize,
+        sent: usize,
+        sent_delivered: usize,
+        delivered: usize,
+        in_flight: usize,
+    ) {
+        let round_start = sent_delivered >= self.round_delivered;
+        if round_start {
+            self.round += 1;
+            self.round_delivered = delivered;
+        }
+        let elapsed = (time - sent).max(1);
+        let rate = (delivered - sent_delivered) as f64 / elapsed as f64;
+        self.rate_samples.push_back((self.round, rate));
+        while self
+            .rate_samples
+            .front()
+            .is_some_and(|&(round, _)| round + BBR_BW_ROUNDS <= self.round)
+        {
+            self.rate_samples.pop_front();
+        }
+        let rtt = (time - sent) as f64;
+        let expired = self
+            .min_rtt
+            .is_some_and(|(_, round)| round + BBR_MIN_RTT_ROUNDS <= self.round);
+        if self.min_rtt.is_none_or(|(min, _)| rtt <= min) {
+            self.min_rtt = Some((rtt, self.round));
+        }
+
+        // Startup ends once the bandwidth grew by less than a quarter
+        // for three round trips.
+        if self.mode == BbrMode::Startup && round_start {
+            let btl_bw = self.btl_bw();
+            if btl_bw >= 1.25 * self.full_bw {
+                self.full_bw = btl_bw;
+                self.full_bw_rounds = 0;
+            } else {
+                self.full_bw_rounds += 1;
+                if self.full_bw_rounds >= 3 {
+                    self.mode = BbrMode::Drain;
+                }
+            }
+        }
+        if self.mode == BbrMode::Drain && self.bdp().is_some_and(|bdp| in_flight as f64 <= bdp) {
+            self.mode = BbrMode::ProbeBw;
+            self.cycle = (0, time);
+        }
+        if self.mode == BbrMode::ProbeBw {
+            let (index, start) = self.cycle;
+            if self
+                .min_rtt
+                .is_some_and(|(rtt, _)| (time - start) as f64 >= rtt)
+            {
+                self.cycle = ((index + 1) % BBR_GAIN_CYCLE.len(), time);
+            }
+        }
+        if expired && self.mode != BbrMode::ProbeRtt {
+            self.mode = BbrMode::ProbeRtt;
+            self.probe_rtt_end = None;
+        }
+        if self.mode == BbrMode::ProbeRtt {
+            match self.probe_rtt_end {
+                None if in_flight as f64 <= BBR_MIN_CWND => {
+                    self.probe_rtt_end = Some(self.round + 1);
+                }
+                Some(end) if self.round >= end => {
+                    if let Some((rtt, _)) = self.min_rtt {
+                        self.min_rtt = Some((rtt, self.round));
+                    }
+                    self.mode = if self.full_bw_rounds >= 3 {
+                        BbrMode::ProbeBw
+                    } else {
+                        BbrMode::Startup
+                    };
+                    self.cycle = (0, time);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// A closed-loop source modelling the sender of a TCP connection, whose
+/// packets are sent as its congestion window allows and which reacts to
+/// the acknowledgements coming back, see [`ClosedLoop`].
+///
+/// The window is counted in packets of one length. Losses are detected
+/// by three duplicate acknowledgements, recovered from as in NewReno,
+/// and by the retransmission timeout of RFC 6298, after which the source
+/// goes back to its oldest unacknowledged packet. An ECN-capable source
+/// backs off on congestion marks as on a loss, or as DCTCP does, at most
+/// once per window.
+/// The packets are named by a prefix and their sequence number, which
+/// they also carry as their `seq` tag.
+#[derive(Debug, Clone)]
+pub struct TcpSource {
+    control: CongestionControl,
+    packet_len: usize,
+    prefix: String,
+    ecn: bool,
+    /// Packets to deliver in all, None for a transfer without end.
+    size: Option<usize>,
+    cwnd: f64,
+    ssthresh: f64,
+    /// Oldest packet not acknowledged yet.
+    snd_una: usize,
+    /// Next packet to send, back to `snd_una` after a timeout.
+    snd_nxt: usize,
+    /// One past the highest packet sent so far.
+    snd_max: usize,
+    dup_acks: usize,
+    /// The packet whose acknowledgement ends the fast recovery under way.
+    recover: Option<usize>,
+    /// No new back-off before this packet is acknowledged.
+    cwr: usize,
+    /// No fast retransmit before this packet is acknowledged, as the
+    /// packets sent again after a timeout bring back duplicate
+    /// acknowledgements, as in RFC 6582.
+    rto_recover: usize,
+    /// A lost packet to send again before anything else.
+    retransmit: Option<usize>,
+    /// Send times of the outstanding packets sent only once, which alone
+    /// give round-trip samples, as in Karn's algorithm, with the packets
+    /// delivered by then.
+    send_times: BTreeMap<usize, (usize, usize)>,
+    srtt: Option<f64>,
+    rttvar: f64,
+    rto: usize,
+    min_rto: usize,
+    /// The time the retransmission timer was started at.
+    rto_start: usize,
+    /// CUBIC window at the last loss.
+    w_max: f64,
+    /// CUBIC start of the current growth and round trips to plateau.
+    epoch: Option<(usize, f64)>,
+    /// DCTCP estimate of the fraction of marked packets.
+    alpha: f64,
+    /// DCTCP packets acknowledged, and marked, in the current window,
+    /// which ends once this packet is acknowledged.
+    window_acked: usize,
+    window_marked: usize,
+    window_end: usize,
+    bbr: Option<Bbr>,
+    history: Vec<(usize, f64)>,
+    loss_events: usize,
+    timeouts: usize,
+    retransmits: usize,
+}
+
+impl TcpSource {
+    /// A source of packets of `packet_len` bytes sending without end.
+    pub fn new(control: CongestionControl, packet_len: usize) -> TcpSource {
+        TcpSource {
+            control,
+            packet_len,
+            prefix: "p".to_string(),
+            ecn: false,
+            size: None,
+            cwnd: DEFAULT_INITIAL_WINDOW,
+            ssthresh: f64::INFINITY,
+            snd_una: 0,
+            snd_nxt: 0,
+            snd_max: 0,
+            dup_acks: 0,
+            recover: None,
+            cwr: 0,
+            rto_recover: 0,
+            retransmit: None,
+            send_times: BTreeMap::new(),
+            srtt: None,
+            rttvar: 0f64,
+            rto: DEFAULT_INITIAL_RTO,
+            min_rto: DEFAULT_MIN_RTO,
+            rto_start: 0,
+            w_max: 0f64,
+            epoch: None,
+            alpha: 1f64,
+            window_acked: 0,
+            window_marked: 0,
+            window_end: 0,
+            bbr: (control == CongestionControl::Bbr).then(Bbr::new),
+            history: vec![(0, DEFAULT_INITIAL_WINDOW)],
+            loss_events: 0,
+            timeouts: 0,
+            retransmits: 0,
+        }
+    }
+
+    /// Stop once `packets` packets have been acknowledged.
+    pub fn with_size(mut self, packets: usize) -> TcpSource {
+        self.size = Some(packets);
+        self
+    }
+
+    /// Name the packets `prefix` followed by their sequence number.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> TcpSource {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Send ECN-capable packets, and back off on congestion marks.
+    pub fn with_ecn(mut self, ecn: bool) -> TcpSource {
+        self.ecn = ecn;
+        self
+    }
+
+    pub fn with_initial_window(mut self, packets: f64) -> TcpSource {
+        assert!(packets >= 1f64, "a window holds at least one packet");
+        self.cwnd = packets;
+        self.history = vec![(0, packets)];
+        self
+    }
+
+    pub fn with_min_rto(mut self, ticks: usize) -> TcpSource {
+        self.min_rto = ticks.max(1);
+        self
+    }
+
+    pub fn control(&self) -> CongestionControl {
+        self.control
+    }
+
+    /// The DCTCP estimate of the fraction of packets marked, starting
+    /// at 1.
+    pub fn dctcp_alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    /// The BBR estimate of the bottleneck bandwidth, in packets per tick.
+    pub fn bbr_bandwidth(&self) -> Option<f64> {
+        self.bbr.as_ref().map(Bbr::btl_bw)
+    }
+
+    /// The BBR estimate of the round trip without queueing, in ticks.
+    pub fn bbr_min_rtt(&self) -> Option<f64> {
+        self.bbr.as_ref()?.min_rtt.map(|(rtt, _)| rtt)
+    }
+
+    /// The rate BBR paces its packets at, in packets per tick.
+    pub fn pacing_rate(&self) -> Option<f64> {
+        self.bbr.as_ref()?.pacing_rate()
+    }
+
+    /// The congestion window, in packets.
+    pub fn cwnd(&self) -> f64 {
+        self.cwnd
+    }
+
+    pub fn ssthresh(&self) -> f64 {
+        self.ssthresh
+    }
+
+    /// The smoothed round-trip time, in ticks, once sampled.
+    pub fn srtt(&self) -> Option<f64> {
+        self.srtt
+    }
+
+    /// The retransmission timeout, in ticks.
+    pub fn rto(&self) -> usize {
+        self.rto
+    }
+
+    /// The packets delivered in order so far.
+    pub fn acked(&self) -> usize {
+        self.snd_una
+    }
+
+    /// Whether every packet of a sized transfer was acknowledged.
+    pub fn is_done(&self) -> bool {
+        self.size.is_some_and(|size| self.snd_una >= size)
+    }
+
+    /// The congestion window after each of its changes, with the tick of
+    /// the change.
+    pub fn cwnd_history(&self) -> &[(usize, f64)] {
+        &self.history
+    }
+
+    /// The losses detected by duplicate acknowledgements.
+    pub fn loss_events(&self) -> usize {
+        self.loss_events
+    }
+
+    /// The retransmission timeouts that expired.
+    pub fn timeouts(&self) -> usize {
+        self.timeouts
+    }
+
+    /// The packets sent more than once.
+    pub fn retransmits(&self) -> usize {
+        self.retransmits
+    }
+
+    /// The next packet to send at `time`, if the window allows one.
+    pub fn poll(&mut self, time: usize) -> Option<Packet> {
+        if let Some(seq) = self.retransmit.take() {
+            // The timer restarts, as the lost packet takes a round trip
+            // more to be acknowledged.
+            self.rto_start = time;
+            self.send_times.remove(&seq);
+            self.retransmits += 1;
+            return Some(self.packet(seq));
+        }
+        let mut window = match self.recover {
+            // Every duplicate acknowledgement is a packet that left the
+            // network, making room for another one.
+            Some(_) => self.cwnd + self.dup_acks as f64,
+            None => self.cwnd,
+        };
+        if let Some(bbr) = &self.bbr {
+            if bbr.next_send > time as f64 {
+                return None;
+            }
+            if bbr.mode == BbrMode::ProbeRtt {
+                window = window.min(BBR_MIN_CWND);
+            }
+        }
+        if self.size.is_some_and(|size| self.snd_nxt >= size)
+            || (self.snd_nxt - self.snd_una) as f64 >= window.floor()
+        {
+            return None;
+        }
+        if let Some(bbr) = &mut self.bbr {
+            if let Some(rate) = bbr.pacing_rate() {
+                bbr.next_send = bbr.next_send.max(time as f64) + 1f64 / rate;
+            }
+        }
+        if self.snd_una == self.snd_max {
+            self.rto_start = time;
+        }
+        let seq = self.snd_nxt;
+        self.snd_nxt += 1;
+        if seq < self.snd_max {
+            self.send_times.remove(&seq);
+            self.retransmits += 1;
+        } else {
+            self.snd_max = seq + 1;
+            self.send_times.insert(seq, (time, self.snd_una));
+        }
+        Some(self.packet(seq))
+    }
+
+    /// Take an acknowledgement arriving at `time`, of every packet before
+    /// the next one it expects.
+    pub fn on_ack(&mut self, ack: &Ack, time: usize) {
+        let (ack, ece) = (ack.next, ack.ece);
+        if ack > self.snd_una {
+            if let Some(&(sent, sent_delivered)) = self.send_times.get(&(ack - 1)) {
+                self.sample(time - sent);
+                let in_flight = self.snd_max - ack;
+                if let Some(bbr) = &mut self.bbr {
+                    bbr.on_ack(time, sent, sent_delivered, ack, in_flight);
+                }
+            }
+            self.send_times = self.send_times.split_off(&ack);
+            let acked = ack - self.snd_una;
+            if self.control == CongestionControl::Dctcp {
+                self.estimate_marking(acked, ece, ack);
+            }
+            self.snd_una = ack;
+            self.snd_nxt = self.snd_nxt.max(ack);
+            self.rto_start = time;
+            match self.recover {
+                Some(recover) if ack >= recover => {
+                    self.recover = None;
+                    self.dup_acks = 0;
+                    if self.bbr.is_none() {
+                        self.cwnd = self.ssthresh;
+                        self.history.push((time, self.cwnd));
+                    }
+                }
+                // A partial acknowledgement: the next hole was lost too.
+                // The window is deflated by the packets acknowledged,
+                // but for the one sent again.
+                Some(_) => {
+                    self.dup_acks = (self.dup_acks + 1).saturating_sub(acked);
+                    self.retransmit = Some(ack);
+                }
+                None => {
+                    self.dup_acks = 0;
+                    self.grow(acked, time);
+                }
+            }
+        } else if ack == self.snd_una && self.snd_una < self.snd_max {
+            self.dup_acks += 1;
+            if self.dup_acks == DUP_ACK_THRESHOLD
+                && self.recover.is_none()
+                && self.snd_una >= self.rto_recover
+            {
+                self.loss_events += 1;
+                self.back_off(time, false);
+                self.recover = Some(self.snd_max);
+                self.retransmit = Some(self.snd_una);
+            }
+        }
+        if ece && self.ecn_capable() && self.recover.is_none() && self.snd_una >= self.cwr {
+            self.back_off(time, true);
+        }
+    }
+
+    /// Expire the retransmission timer if it ran out by `time`.
+    pub fn on_tick(&mut self, time: usize) {
+        if self.snd_una == self.snd_max || time < self.rto_start + self.rto {
+            return;
+        }
+        self.timeouts += 1;
+        self.back_off(time, false);
+        self.cwnd = 1f64;
+        self.history.push((time, self.cwnd));
+        self.recover = None;
+        self.dup_acks = 0;
+        self.retransmit = None;
+        self.snd_nxt = self.snd_una;
+        self.rto_recover = self.snd_max;
+        self.rto = self.rto.saturating_mul(2);
+        self.rto_start = time;
+    }
+
+    fn packet(&self, seq: usize) -> Packet {
+        let packet = Packet::new(format!("{}{}", self.prefix, seq), self.packet_len)
+            .with_tag("seq", seq.to_string());
+        if self.ecn_capable() {
+            packet.with_ecn(Ecn::Ect)
+        } else {
+            packet
+        }
+    }
+
+    fn ecn_capable(&self) -> bool {
+        match self.control {
+            CongestionControl::Dctcp => true,
+            CongestionControl::Aimd(aimd) => self.ecn || aimd.on_mark,
+            _ => self.ecn,
+        }
+    }
+
+    /// Count `acked` packets acknowledged up to `ack`, marked if `ece`,
+    /// updating the DCTCP estimate at the end of every window.
+    fn estimate_marking(&mut self, acked: usize, ece: bool, ack: usize) {
+        self.window_acked += acked;
+        if ece {
+            self.window_marked += acked;
+        }
+        if ack > self.window_end {
+            let fraction = self.window_marked as f64 / self.window_acked as f64;
+            self.alpha = (1f64 - DCTCP_GAIN) * self.alpha + DCTCP_GAIN * fraction;
+            self.window_acked = 0;
+            self.window_marked = 0;
+            self.window_end = self.snd_max;
+        }
+    }
+
+    /// Update the round-trip estimates with a sample, as in RFC 6298.
+    fn sample(&mut self, rtt: usize) {
+        let rtt = rtt as f64;
+        let srtt = match self.srtt {
+            None => {
+                self.rttvar = rtt / 2f64;
+                rtt
+            }
+            Some(srtt) => {
+                self.rttvar = 0.75 * self.rttvar + 0.25 * (srtt - rtt).abs();
+                0.875 * srtt + 0.125 * rtt
+            }
+        };
+        self.srtt = Some(srtt);
+        self.rto = ((srtt + (4f64 * self.rttvar).max(1f64)).ceil() as usize).max(self.min_rto);
+    }
+
+    /// Open the window for `acked` newly acknowledged packets.
+    fn grow(&mut self, acked: usize, time: usize) {
+        let acked = acked as f64;
+        match self.control {
+            CongestionControl::Bbr => {
+                // The window grows as in slow start, up to the target of
+                // the model once the bandwidth was found.
+                let target = self
+                    .bbr
+                    .as_ref()
+                    .filter(|bbr| bbr.mode != BbrMode::Startup)
+                    .and_then(Bbr::target_cwnd);
+                self.cwnd =
+                    target.map_or(self.cwnd + acked, |target| (self.cwnd + acked).min(target));
+            }
+            CongestionControl::Aimd(aimd) => self.cwnd += aimd.increase * acked / self.cwnd,
+            _ if self.cwnd < self.ssthresh => self.cwnd += acked,
+            CongestionControl::Reno | CongestionControl::Dctcp => self.cwnd += acked / self.cwnd,
+            CongestionControl::Cubic => {
+                if self.epoch.is_none() {
+                    self.w_max = self.w_max.max(self.cwnd);
+                    let k = ((self.w_max - self.cwnd) / CUBIC_C).cbrt();
+                    self.epoch = Some((time, k));
+                }
+                let (start, k) = self.epoch.unwrap();
+                let rtt = self.srtt.unwrap_or(1f64).max(1f64);
+                // Aim for the window of one round trip from now.
+                let t = (time - start) as f64 / rtt + 1f64;
+                let target = CUBIC_C * (t - k).powi(3) + self.w_max;
+                if target > self.cwnd {
+                    self.cwnd += acked * (target - self.cwnd) / self.cwnd;
+                } else {
+                    self.cwnd += acked * 0.01 / self.cwnd;
+                }
+            }
+        }
+        self.history.push((time, self.cwnd));
+    }
+
+    /// Cut the window on a sign of congestion, once per window, `marked`
+    /// if the sign is a congestion mark rather than a loss.
+    fn back_off(&mut self, time: usize, marked: bool) {
+        self.ssthresh = match self.control {
+            CongestionControl::Bbr => return,
+            CongestionControl::Aimd(aimd) if marked && !aimd.on_mark => return,
+            // The window is kept through the recovery of the loss.
+            CongestionControl::Aimd(aimd) if !marked && !aimd.on_loss => self.cwnd,
+            CongestionControl::Aimd(aimd) => self.cwnd * aimd.decrease,
+            CongestionControl::Dctcp if marked => self.cwnd * (1f64 - self.alpha / 2f64),
+            CongestionControl::Reno | CongestionControl::Dctcp => self.cwnd / 2f64,
+            CongestionControl::Cubic => {
+                self.w_max = self.cwnd;
+                self.epoch = None;
+                self.cwnd * CUBIC_BETA
+            }
+        }
+        .max(2f64);
+        self.cwnd = self.ssthresh;
+        self.cwr = self.snd_max;
+        self.history.push((time, self.cwnd));
+    }
+}
+
+/// A connection of a [`ClosedLoop`], with the state of its receiver.
+#[derive(Debug, Clone)]
+struct Connection {
+    source: TcpSource,
+    flow: FlowId,
+    receiver: Receiver,
+    closed: bool,
+}
+
+/// TCP sources sharing a bottleneck scheduler, each packet acknowledged
+/// back to its source, so that the sources react to the queueing, drops
+/// and congestion marks of the scheduler and of its port.
+///
+/// A packet that leaves the scheduler may be lost on the way to its
+/// receiver, which acknowledges every packet with the next one it
+/// expects in order. The acknowledgements go back on a [`ReversePath`],
+/// by default one that delivers them `delay` ticks after the packet left,
+/// and the sources send as their windows and pacing allow on every tick.
+/// The scheduler may also carry flows of its own, as cross traffic.
+pub struct ClosedLoop {
+    scheduler: Box<dyn Scheduler>,
+    connections: Vec<Connection>,
+    reverse: ReversePath,
+    loss: Option<LossModel>,
+    /// The packets of the output handed to the receivers so far.
+    departed: usize,
+}
+
+impl ClosedLoop {
+    /// Close the loop over `scheduler`, with a round-trip delay of `delay`
+    /// ticks besides the time spent in the scheduler.
+    pub fn new(scheduler: Box<dyn Scheduler>, delay: usize) -> ClosedLoop {
+        ClosedLoop {
+            scheduler,
+            connections: Vec::new(),
+            reverse: ReversePath::new(delay),
+            loss: None,
+            departed: 0,
+        }
+    }
+
+    /// Send the acknowledgements back on `reverse`, in place of the
+    /// uncongested path of the delay given at creation.
+    pub fn set_reverse_path(&mut self, reverse: ReversePath) {
+        self.reverse = reverse;
+    }
+
+    pub fn reverse_path(&self) -> &ReversePath {
+        &self.reverse
+    }
+
+    /// Lose packets between the scheduler and the receivers.
+    pub fn set_loss(&mut self, loss: Option<LossModel>) {
+        self.loss = loss;
+    }
+
+    /// Add a source feeding a new flow of the scheduler with the given
+    /// weight, returning the identifier of the flow.
+    pub fn add_source(&mut self, source: TcpSource, weight: f64) -> FlowId {
+        let flow = self.scheduler.add_flow(Box::new(OpenFlow::new()), weight);
+        self.connections.push(Connection {
+            source,
+            flow,
+            receiver: Receiver::new(),
+            closed: false,
+        });
+        flow
+    }
+
+    /// The source feeding a flow.
+    pub fn source(&self, flow: FlowId) -> &TcpSource {
+        &self.connections[self.connection(flow).expect("the flow has a source")].source
+    }
+
+    pub fn scheduler(&self) -> &dyn Scheduler {
+        self.scheduler.as_ref()
+    }
+
+    /// Run until the timer of the scheduler reaches `time`. Returns false
+    /// if every flow drained before, once all sized transfers completed.
+    pub fn run_until(&mut self, time: usize) -> bool {
+        while self.scheduler.timer() < time {
+            let now = self.scheduler.timer();
+            for ack in self.reverse.receive(now) {
+                if let Some(idx) = self.connection(ack.flow) {
+                    self.connections[idx].source.on_ack(&ack, now);
+                }
+            }
+            for connection in &mut self.connections {
+                connection.source.on_tick(now);
+                while let Some(packet) = connection.source.poll(now) {
+                    let packet = packet.with_flow_id(connection.flow.index());
+                    self.scheduler
+                        .inject(connection.flow, packet, now)
+                        .expect("the flows of the scheduler take injected packets");
+                }
+                if connection.source.is_done() && !connection.closed {
+                    self.scheduler.close_flow(connection.flow);
+                    connection.closed = true;
+                }
+            }
+            if !self.scheduler.step() {
+                return false;
+            }
+            self.receive();
+        }
+        true
+    }
+
+    /// Hand the packets that just left the scheduler to their receivers.
+    fn receive(&mut self) {
+        let now = self.scheduler.timer();
+        for packet in &self.scheduler.output()[self.departed..] {
+            let Some(idx) = packet
+                .flow_id
+                .and_then(|flow| self.connection(FlowId(flow)))
+            else {
+                continue;
+            };
+            if self.loss.as_mut().is_some_and(|l| l.is_lost(packet.len)) {
+                continue;
+            }
+            let connection = &mut self.connections[idx];
+            if let Some(ack) = connection.receiver.receive(connection.flow, packet) {
+                self.reverse.send(ack, now);
+            }
+        }
+        self.departed = self.scheduler.output().len();
+    }
+
+    fn connection(&self, flow: FlowId) -> Option<usize> {
+        self.connections.iter().position(|c| c.flow == flow)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::scheduling::{
+        aqm::Red,
+        flow::Flow,
+        loss::LossModel,
+        schedulers::{fifo::FIFOScheduler, Scheduler},
+        Port,
+    };
+
+    use super::{
+        Aimd, CbrSource, ClosedLoop, CongestionControl, MmppSource, OnOffSource, Period,
+        PoissonSource, SizeDistribution, TcpSource, TrafficSource, VbrSource,
+    };
+
+    #[test]
+    fn poisson_source_test() {
+        let mut source = PoissonSource::new(0.5, SizeDistribution::Fixed(1));
+        let mut flow = source.flow_until(10_000);
+        // About one packet every other tick.
+        let count = flow.packet_states.len();
+        assert!((4_800..5_200).contains(&count), "{} packets", count);
+        assert!(flow
+            .packet_states
+            .make_contiguous()
+            .windows(2)
+            .all(|w| w[0].1 <= w[1].1));
+        assert_eq!(flow.packet_states[0].0.name, "p0");
+        assert!(source.next_arrival().unwrap() >= 10_000);
+
+        // The same seed gives the same packets.
+        let size = SizeDistribution::Empirical(vec![(1, 3f64), (10, 1f64)]);
+        let first = PoissonSource::new(2f64, size.clone())
+            .with_seed(7)
+            .flow_of(100);
+        let again = PoissonSource::new(2f64, size.clone())
+            .with_seed(7)
+            .flow_of(100);
+        let other = PoissonSource::new(2f64, size).with_seed(8).flow_of(100);
+        assert_eq!(first.packet_states, again.packet_states);
+        assert_ne!(first.packet_states, other.packet_states);
+        assert!(first
+            .packet_states
+            .iter()
+            .all(|(p, _)| p.len == 1 || p.len == 10));
+
+        let uniform = PoissonSource::new(1f64, SizeDistribution::Uniform(2, 4))
+            .with_prefix("u")
+            .flow_of(50);
+        assert!(uniform
+            .packet_states
+            .iter()
+            .all(|(p, _)| (2..=4).contains(&p.len) && p.name.starts_with('u')));
+
+        // The flow feeds a scheduler like any other.
+        let mut fifo = FIFOScheduler::new(1);
+        fifo.add_flow(uniform.clone());
+        fifo.run();
+        assert_eq!(fifo.output().len(), 50);
+        assert!(!uniform.empty());
+    }
+
+    #[test]
+    fn on_off_source_test() {
+        let on = Period::Exponential { mean: 10f64 };
+        let off = Period::Exponential { mean: 30f64 };
+        let mut source = OnOffSource::new(1f64, on, off, SizeDistribution::Fixed(1));
+        assert_eq!(source.mean_rate(), 0.25);
+        let flow = source.flow_until(40_000);
+        let count = flow.packet_states.len() as f64;
+        assert!((count / 40_000f64 - 0.25).abs() < 0.03, "{} packets", count);
+        // Packets come back to back while ON, with gaps in between.
+        let times: Vec<usize> = flow.packet_states.iter().map(|(_, t)| *t).collect();
+        assert_eq!(times[0], 0);
+        assert!(times.windows(2).filter(|w| w[1] - w[0] == 1).count() > times.len() / 2);
+        assert!(times.windows(2).any(|w| w[1] - w[0] > 30));
+
+        // Pareto periods are heavy-tailed: a few long bursts make up much
+        // of the traffic, so the longest burst dwarfs the mean one.
+        let pareto = |seed| {
+            let on = Period::Pareto {
+                shape: 1.2,
+                mean: 10f64,
+            };
+            OnOffSource::new(1f64, on, off, SizeDistribution::Fixed(1))
+                .with_seed(seed)
+                .flow_until(100_000)
+        };
+        let mut flow = pareto(3);
+        assert_eq!(flow.packet_states, pareto(3).packet_states);
+        let mut bursts = vec![1usize];
+        for w in flow.packet_states.make_contiguous().windows(2) {
+            if w[1].1 - w[0].1 == 1 {
+                *bursts.last_mut().unwrap() += 1;
+            } else {
+                bursts.push(1);
+            }
+        }
+        let mean = bursts.iter().sum::<usize>() as f64 / bursts.len() as f64;
+        assert!(*bursts.iter().max().unwrap() as f64 > 20f64 * mean);
+    }
+
+    #[test]
+    fn cbr_source_test() {
+        let times = |source: CbrSource| -> Vec<usize> { source.take(5).map(|(_, t)| t).collect() };
+        assert_eq!(
+            times(CbrSource::new(4, 2).with_offset(1)),
+            vec![1, 5, 9, 13, 17]
+        );
+
+        // Jitter delays packets by up to 3 ticks, keeping them in order.
+        let jittered: Vec<usize> = CbrSource::new(2, 1)
+            .with_jitter(3)
+            .with_seed(5)
+            .flow_until(1_000)
+            .packet_states
+            .iter()
+            .map(|(_, t)| *t)
+            .collect();
+        assert!(jittered.windows(2).all(|w| w[0] <= w[1]));
+        assert!(jittered
+            .iter()
+            .enumerate()
+            .all(|(p, &t)| (2 * p..=2 * p + 3).contains(&t)));
+        assert!(jittered.iter().enumerate().any(|(p, &t)| t != 2 * p));
+        assert_eq!(
+            times(CbrSource::new(2, 1).with_jitter(3).with_seed(5)),
+            jittered[..5]
+        );
+    }
+
+    #[test]
+    fn vbr_source_test() {
+        // 1 byte per tick, then 4, then silence.
+        let profile = vec![(0, 1f64), (4, 4f64), (6, 0f64)];
+        let source = VbrSource::new(profile.clone(), SizeDistribution::Fixed(2));
+        let packets: Vec<(String, usize)> = source.map(|(p, t)| (p.name, t)).collect();
+        let expected = [
+            ("p0", 1),
+            ("p1", 3),
+            ("p2", 4),
+            ("p3", 4),
+            ("p4", 5),
+            ("p5", 5),
+        ];
+        assert_eq!(
+            packets,
+            expected
+                .iter()
+                .map(|&(name, t)| (name.to_string(), t))
+                .collect::<Vec<_>>()
+        );
+
+        // Halving the rates halves the traffic.
+        let mut halved = VbrSource::new(profile, SizeDistribution::Fixed(2)).with_scale(0.5);
+        assert_eq!(halved.next_arrival(), Some(3));
+        assert_eq!(halved.flow_until(100).packet_states.len(), 3);
+        assert_eq!(halved.next_arrival(), None);
+    }
+
+    #[test]
+    fn mmpp_source_test() {
+        // A quiet and a busy state, each lasting 200 ticks on average.
+        let mmpp = |seed| {
+            MmppSource::new(
+                vec![0.1, 1.9],
+                vec![vec![0f64, 0.005], vec![0.005, 0f64]],
+                SizeDistribution::Fixed(1),
+            )
+            .with_seed(seed)
+            .flow_until(100_000)
+        };
+        let flow = mmpp(1);
+        assert_eq!(flow.packet_states, mmpp(1).packet_states);
+        let count = flow.packet_states.len() as f64;
+        assert!((count / 100_000f64 - 1f64).abs() < 0.1, "{} packets", count);
+
+        // Counts over windows of 50 ticks vary far more than the
+        // Poisson variance, which equals the mean.
+        let mut windows = vec![0f64; 2_000];
+        for (_, time) in &flow.packet_states {
+            windows[time / 50] += 1f64;
+        }
+        let mean = windows.iter().sum::<f64>() / windows.len() as f64;
+        let variance =
+            windows.iter().map(|c| (c - mean).powi(2)).sum::<f64>() / windows.len() as f64;
+        assert!(
+            variance > 10f64 * mean,
+            "variance {} for mean {}",
+            variance,
+            mean
+        );
+
+        // A silent state that is never left ends the source.
+        let mut ending = MmppSource::new(
+            vec![1f64, 0f64],
+            vec![vec![0f64, 0.01], vec![0f64, 0f64]],
+            SizeDistribution::Fixed(1),
+        );
+        assert!(ending.by_ref().count() > 0);
+        assert_eq!(ending.next_arrival(), None);
+        assert_eq!(ending.state(), 1);
+    }
+
+    #[test]
+    fn size_distribution_test() {
+        let imix = SizeDistribution::internet_mix();
+        assert_eq!(imix.mean(), (7 * 64 + 4 * 576 + 1500) as f64 / 12f64);
+        let mut rng = StdRng::seed_from_u64(0);
+        let lens: Vec<usize> = (0..12_000).map(|_| imix.sample(&mut rng)).collect();
+        let small = lens.iter().filter(|&&len| len == 64).count();
+        assert!((6_700..7_300).contains(&small), "{} small packets", small);
+        assert!(lens.iter().all(|len| [64, 576, 1500].contains(len)));
+
+        let histogram = "# len,count\n40, 3\n\n1500 1\n";
+        let empirical = SizeDistribution::from_histogram(histogram.as_bytes()).unwrap();
+        assert_eq!(
+            empirical,
+            SizeDistribution::Empirical(vec![(40, 3f64), (1500, 1f64)])
+        );
+        assert_eq!(empirical.mean(), 405f64);
+        assert!(SizeDistribution::from_histogram("40 x\n".as_bytes()).is_err());
+        assert!(SizeDistribution::from_histogram("40 1 2\n".as_bytes()).is_err());
+        assert!(SizeDistribution::from_histogram("".as_bytes()).is_err());
+        assert_eq!(SizeDistribution::Uniform(2, 4).mean(), 3f64);
+    }
+
+    /// A link of a packet per tick with a round trip of 21 ticks,
+    /// behind a port.
+    fn bottleneck(port: Port) -> ClosedLoop {
+        let mut fifo = FIFOScheduler::new(1);
+        *fifo.get_output_port() = port;
+        ClosedLoop::new(Box::new(fifo), 20)
+    }
+
+    /// The factors the window was cut by.
+    fn cuts(source: &TcpSource) -> Vec<f64> {
+        source
+            .cwnd_history()
+            .windows(2)
+            .filter(|w| w[1].1 < w[0].1)
+            .map(|w| w[1].1 / w[0].1)
+            .collect()
+    }
+
+    #[test]
+    fn tcp_reno_test() {
+        // A buffer of 10 packets, for 21 packets in flight on the path.
+        let mut path = bottleneck(Port::with_capacity(0, 1, 10));
+        let flow = path.add_source(TcpSource::new(CongestionControl::Reno, 1), 1f64);
+        assert!(path.run_until(5_000));
+
+        // Every loss halves the window, in the sawtooth of Reno, and the
+        // small buffer runs dry after each. Dropped packets are sent again
+        // once each.
+        let source = path.source(flow);
+        assert!(source.loss_events() > 5);
+        assert_eq!(source.timeouts(), 0);
+        assert!(cuts(source).iter().all(|&cut| cut == 0.5));
+        assert!((4_300..4_900).contains(&source.acked()));
+        assert_eq!(path.scheduler().stats()[0].dropped, source.retransmits());
+
+        // Random losses after the bottleneck are recovered from too,
+        // and a sized transfer ends.
+        let mut path = bottleneck(Port::new(0, 1));
+        path.set_loss(Some(LossModel::random(0.01)));
+        let flow = path.add_source(
+            TcpSource::new(CongestionControl::Reno, 1).with_size(2_000),
+            1f64,
+        );
+        assert!(!path.run_until(100_000));
+        let source = path.source(flow);
+        assert!(source.is_done());
+        assert_eq!(source.acked(), 2_000);
+        assert!(source.retransmits() > 10);
+    }
+
+    #[test]
+    fn tcp_cubic_test() {
+        let mut path = bottleneck(Port::with_capacity(0, 1, 10));
+        let reno = path.add_source(TcpSource::new(CongestionControl::Reno, 1), 1f64);
+        let mut alone = bottleneck(Port::with_capacity(0, 1, 10));
+        let cubic = alone.add_source(TcpSource::new(CongestionControl::Cubic, 1), 1f64);
+        path.run_until(5_000);
+        alone.run_until(5_000);
+
+        // CUBIC cuts its window by 30% only, and climbs back to where it
+        // lost packets faster, keeping the link busier.
+        let (reno, cubic) = (path.source(reno), alone.source(cubic));
+        assert!(cuts(cubic).iter().all(|&cut| (cut - 0.7).abs() < 1e-9));
+        assert!(cubic.loss_events() > 2 * reno.loss_events());
+        assert!(cubic.acked() > reno.acked());
+        assert!(cubic.acked() > 4_900);
+    }
+
+    #[test]
+    fn tcp_ecn_test() {
+        // RED marks the packets of an ECN-capable source instead of
+        // dropping them, which keeps the queue, and the delay, short.
+        let mut port = Port::new(0, 1);
+        port.set_red(Some(Red::new(3f64, 9f64, 0.1).with_weight(1f64)));
+        let mut path = bottleneck(port);
+        let flow = path.add_source(
+            TcpSource::new(CongestionControl::Reno, 1).with_ecn(true),
+            1f64,
+        );
+        path.run_until(5_000);
+
+        let source = path.source(flow);
+        let stats = &path.scheduler().stats()[0];
+        assert!(stats.marked > 0);
+        assert_eq!(stats.dropped, 0);
+        assert_eq!((source.loss_events(), source.retransmits()), (0, 0));
+        assert!(source.srtt().unwrap() < 25f64);
+
+        let mut drop_tail = bottleneck(Port::with_capacity(0, 1, 10));
+        let flow = drop_tail.add_source(TcpSource::new(CongestionControl::Reno, 1), 1f64);
+        drop_tail.run_until(5_000);
+        assert!(drop_tail.source(flow).srtt().unwrap() > 25f64);
+    }
+
+    #[test]
+    fn tcp_dctcp_test() {
+        // Two sources share a link marking above 5 queued packets, as in
+        // the DCTCP experiments.
+        let run = |control| {
+            let mut port = Port::new(0, 1);
+            port.set_red(Some(Red::threshold(5)));
+            let mut path = bottleneck(port);
+            let flows = ["a", "b"].map(|prefix| {
+                let source = TcpSource::new(control, 1)
+                    .with_ecn(true)
+                    .with_prefix(prefix);
+                path.add_source(source, 1f64)
+            });
+            path.run_until(10_000);
+            (path, flows)
+        };
+
+        // DCTCP cuts its window a little on every window with marks,
+        // keeping the queue about the threshold and the link busy.
+        let (path, flows) = run(CongestionControl::Dctcp);
+        let stats = path.scheduler().stats();
+        for (flow, stats) in flows.iter().zip(&stats) {
+            let source = path.source(*flow);
+            assert!((0.1..0.5).contains(&source.dctcp_alpha()));
+            assert!(cuts(source).iter().any(|&cut| cut > 0.8));
+            assert!(source.acked() > 4_800);
+            assert_eq!((stats.dropped, source.loss_events()), (0, 0));
+            assert!(stats.mean_delay < 8f64);
+        }
+
+        // Reno halves its window on marks and leaves the link idle.
+        let (path, flows) = run(CongestionControl::Reno);
+        let acked: usize = flows.iter().map(|&f| path.source(f).acked()).sum();
+        assert!(acked < 9_000);
+    }
+
+    #[test]
+    fn tcp_bbr_test() {
+        // A buffer of 100 packets, far more than the 21 in flight.
+        let run = |control| {
+            let mut fifo = FIFOScheduler::new(1);
+            *fifo.get_output_port() = Port::with_capacity(0, 1, 100);
+            fifo.set_queue_sampling(Some(100));
+            let mut path = ClosedLoop::new(Box::new(fifo), 20);
+            let flow = path.add_source(TcpSource::new(control, 1), 1f64);
+            path.run_until(10_000);
+            (path, flow)
+        };
+
+        // BBR finds the bandwidth and the round trip of the path, and
+        // paces its packets so that the queue stays almost empty.
+        let (path, flow) = run(CongestionControl::Bbr);
+        let source = path.source(flow);
+        assert_eq!(source.bbr_bandwidth(), Some(1f64));
+        assert_eq!(source.bbr_min_rtt(), Some(21f64));
+        assert!(source.pacing_rate().is_some());
+        assert!(source.acked() > 9_000);
+        let stats = &path.scheduler().stats()[0];
+        assert_eq!(stats.dropped, 0);
+        assert!(stats.mean_delay < 2f64);
+        let queue = path.scheduler().queue_series().port();
+        assert!(queue[5..].iter().all(|&len| len <= 10));
+
+        // CUBIC fills the buffer until it overflows.
+        let (path, flow) = run(CongestionControl::Cubic);
+        assert_eq!(path.source(flow).bbr_bandwidth(), None);
+        let stats = &path.scheduler().stats()[0];
+        assert!(stats.dropped > 0);
+        assert!(stats.mean_delay > 50f64);
+    }
+
+    #[test]
+    fn tcp_aimd_test() {
+        // Two sources halving their windows on losses, one adding two
+        // packets per round trip and the other one.
+        let mut path = bottleneck(Port::with_capacity(0, 1, 10));
+        let fast = path.add_source(
+            TcpSource::new(CongestionControl::Aimd(Aimd::new(2f64, 0.5)), 1),
+            1f64,
+        );
+        let slow = path.add_source(
+            TcpSource::new(CongestionControl::Aimd(Aimd::new(1f64, 0.5)), 1).with_prefix("q"),
+            1f64,
+        );
+        path.run_until(20_000);
+
+        // There is no slow start, and the larger increase takes the larger
+        // share, about the square root of the ratio of the increases.
+        let (fast, slow) = (path.source(fast), path.source(slow));
+        assert!((fast.cwnd_history()[1].1 - 10.2).abs() < 1e-9);
+        assert_eq!(fast.timeouts(), 0);
+        assert!(cuts(fast).iter().all(|&cut| cut == 0.5));
+        let share = fast.acked() as f64 / slow.acked() as f64;
+        assert!((1.2..1.7).contains(&share), "{} times the share", share);
+
+        // A source reacting to marks only, at a queue marking above 5
+        // packets, never fills the buffer.
+        let mut port = Port::with_capacity(0, 1, 10);
+        port.set_red(Some(Red::threshold(5)));
+        let mut path = bottleneck(port);
+        let aimd = Aimd {
+            on_loss: false,
+            ..Aimd::new(1f64, 0.8)
+        };
+        let flow = path.add_source(TcpSource::new(CongestionControl::Aimd(aimd), 1), 1f64);
+        path.run_until(10_000);
+        let source = path.source(flow);
+        assert!(cuts(source).iter().all(|&cut| (cut - 0.8).abs() < 1e-9));
+        assert_eq!(source.loss_events(), 0);
+        let stats = &path.scheduler().stats()[0];
+        assert!(stats.marked > 0);
+        assert_eq!(stats.dropped, 0);
+    }
+}
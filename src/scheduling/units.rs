@@ -0,0 +1,128 @@
+//! Real-valued simulation time and link rates.
+//!
+//! The tick-driven schedulers count time in whole ticks, but the length
+//! of a tick is up to the model: with a tick of a millisecond, a 1.5 Mbps
+//! link sends 187.5 bytes per tick. [`Time`] and [`Rate`] carry such
+//! values, and convert from and to physical units given the tick length.
+
+use std::ops::{Add, Sub};
+
+/// A point or span of simulation time, in ticks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Time(pub f64);
+
+impl Time {
+    pub fn from_ticks(ticks: usize) -> Time {
+        Time(ticks as f64)
+    }
+
+    /// The time of `secs` seconds, with ticks of `tick_secs` seconds.
+    pub fn from_secs(secs: f64, tick_secs: f64) -> Time {
+        assert!(tick_secs > 0f64, "a tick must last a positive time");
+        Time(secs / tick_secs)
+    }
+
+    pub fn ticks(self) -> f64 {
+        self.0
+    }
+
+    /// The first whole tick at or after this time.
+    pub fn ceil_ticks(self) -> usize {
+        self.0.ceil() as usize
+    }
+
+    pub fn as_secs(self, tick_secs: f64) -> f64 {
+        self.0 * tick_secs
+    }
+}
+
+impl From<usize> for Time {
+    fn from(ticks: usize) -> Time {
+        Time::from_ticks(ticks)
+    }
+}
+
+impl Add for Time {
+    type Output = Time;
+
+    fn add(self, other: Time) -> Time {
+        Time(self.0 + other.0)
+    }
+}
+
+impl Sub for Time {
+    type Output = Time;
+
+    fn sub(self, other: Time) -> Time {
+        Time(self.0 - other.0)
+    }
+}
+
+/// A transmission rate, in bytes per tick.
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rate(pub f64);
+
+impl Rate {
+    pub fn bytes_per_tick(bytes: f64) -> Rate {
+        assert!(bytes >= 0f64, "a rate cannot be negative");
+        Rate(bytes)
+    }
+
+    /// The rate of `bps` bits per second, with ticks of `tick_secs` seconds.
+    pub fn from_bps(bps: f64, tick_secs: f64) -> Rate {
+        assert!(tick_secs > 0f64, "a tick must last a positive time");
+        Rate::bytes_per_tick(bps / 8f64 * tick_secs)
+    }
+
+    pub fn from_mbps(mbps: f64, tick_secs: f64) -> Rate {
+        Rate::from_bps(mbps * 1e6, tick_secs)
+    }
+
+    pub fn as_bytes_per_tick(self) -> f64 {
+        self.0
+    }
+
+    pub fn as_bps(self, tick_secs: f64) -> f64 {
+        self.0 * 8f64 / tick_secs
+    }
+
+    /// The time to transmit `len` bytes, infinite at a zero rate.
+    pub fn transmission_time(self, len: usize) -> Time {
+        Time(len as f64 / self.0)
+    }
+
+    /// The number of bytes transmitted over `time`.
+    pub fn bytes_in(self, time: Time) -> f64 {
+        self.0 * time.0
+    }
+}
+
+impl From<usize> for Rate {
+    fn from(bytes: usize) -> Rate {
+        Rate(bytes as f64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Rate, Time};
+
+    #[test]
+    fn units_test() {
+        // 1.5 Mbps over 1 ms ticks.
+        let rate = Rate::from_mbps(1.5, 1e-3);
+        assert_eq!(rate.as_bytes_per_tick(), 187.5);
+        assert_eq!(rate.as_bps(1e-3), 1.5e6);
+        assert_eq!(rate.transmission_time(375), Time(2f64));
+        assert_eq!(rate.bytes_in(Time(0.5)), 93.75);
+
+        let time = Time::from_secs(0.0025, 1e-3);
+        assert_eq!(time.ticks(), 2.5);
+        assert_eq!(time.ceil_ticks(), 3);
+        assert_eq!(time + Time::from(2), Time(4.5));
+        assert_eq!(Time(4.5) - time, Time(2f64));
+        assert_eq!(Rate::from(3), Rate(3f64));
+    }
+}
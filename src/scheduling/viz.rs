@@ -0,0 +1,172 @@
+//! Rendering of scheduler runs to SVG.
+
+use std::{collections::VecDeque, fmt::Write as _, fs, io, path::Path};
+
+use crate::scheduling::stats::trace::{Trace, TraceEventKind};
+
+/// Width of one tick, in pixels.
+pub const TICK_WIDTH: usize = 12;
+
+/// Height of the row of a flow, in pixels.
+pub const ROW_HEIGHT: usize = 24;
+
+/// Width of the flow labels left of the rows, in pixels.
+const LABEL_WIDTH: usize = 64;
+
+/// Height of the time axis below the rows, in pixels.
+const AXIS_HEIGHT: usize = 24;
+
+/// Fill colors of the flows, reused once every flow got one.
+const PALETTE: [&str; 10] = [
+    "#4e79a7", "#f28e2b", "#59a14f", "#e15759", "#76b7b2", "#edc948", "#b07aa1", "#ff9da7",
+    "#9c755f", "#bab0ac",
+];
+
+/// Render the transmissions of a run as a timeline to an SVG file,
+/// see [`timeline_svg`].
+pub fn render_timeline(trace: &Trace, path: impl AsRef<Path>) -> io::Result<()> {
+    fs::write(path, timeline_svg(trace))
+}
+
+/// Render the transmissions of a run as a timeline, one row per flow.
+///
+/// Every packet is a box spanning the ticks it occupied the link,
+/// colored by flow and named in its tooltip. Drops are crosses at the
+/// tick they happened.
+pub fn timeline_svg(trace: &Trace) -> String {
+    let flow_count = trace
+        .events
+        .iter()
+        .map(|e| e.flow.index() + 1)
+        .max()
+        .unwrap_or(0);
+    let end = trace.events.iter().map(|e| e.time).max().unwrap_or(0);
+    let width = LABEL_WIDTH + (end + 1) * TICK_WIDTH;
+    let height = flow_count * ROW_HEIGHT + AXIS_HEIGHT;
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" font-family="sans-serif" font-size="12">"#
+    )
+    .unwrap();
+    for flow in 0..flow_count {
+        let y = flow * ROW_HEIGHT;
+        writeln!(
+            svg,
+            r#"<text x="4" y="{}">flow {flow}</text>"#,
+            y + ROW_HEIGHT * 2 / 3
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            r##"<line x1="{LABEL_WIDTH}" y1="{0}" x2="{width}" y2="{0}" stroke="#ddd"/>"##,
+            y + ROW_HEIGHT
+        )
+        .unwrap();
+    }
+
+    // The link transmits one packet at a time, so transmissions end
+    // in the order they started.
+    let mut started = VecDeque::new();
+    for event in &trace.events {
+        let x = LABEL_WIDTH + event.time * TICK_WIDTH;
+        let y = event.flow.index() * ROW_HEIGHT;
+        match event.kind {
+            TraceEventKind::TransmissionStart => started.push_back(event.time),
+            TraceEventKind::TransmissionEnd => {
+                let start = started
+                    .pop_front()
+                    .expect("a transmission ended before starting");
+                writeln!(
+                    svg,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" stroke="white"><title>{}</title></rect>"#,
+                    LABEL_WIDTH + start * TICK_WIDTH,
+                    y + 2,
+                    (event.time - start) * TICK_WIDTH,
+                    ROW_HEIGHT - 4,
+                    PALETTE[event.flow.index() % PALETTE.len()],
+                    escape(&event.packet)
+                )
+                .unwrap();
+            }
+            TraceEventKind::Drop => {
+                let (x0, x1) = (x - TICK_WIDTH / 3, x + TICK_WIDTH / 3);
+                let (y0, y1) = (y + ROW_HEIGHT / 3, y + ROW_HEIGHT * 2 / 3);
+                writeln!(
+                    svg,
+                    r#"<path d="M{x0} {y0}L{x1} {y1}M{x0} {y1}L{x1} {y0}" stroke="red" stroke-width="2"><title>{} dropped</title></path>"#,
+                    escape(&event.packet)
+                )
+                .unwrap();
+            }
+            _ => {}
+        }
+    }
+
+    // Label the axis every ten ticks.
+    let axis = flow_count * ROW_HEIGHT;
+    for time in (0..=end).step_by(10) {
+        let x = LABEL_WIDTH + time * TICK_WIDTH;
+        writeln!(
+            svg,
+            r##"<line x1="{x}" y1="0" x2="{x}" y2="{}" stroke="#999"/><text x="{x}" y="{}" text-anchor="middle">{time}</text>"##,
+            axis + 4,
+            axis + AXIS_HEIGHT - 6
+        )
+        .unwrap();
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        schedulers::rr::RRScheduler,
+        Packet, Scheduler,
+    };
+
+    use super::{render_timeline, timeline_svg, LABEL_WIDTH, TICK_WIDTH};
+
+    #[test]
+    fn timeline_test() {
+        let mut rr = RRScheduler::new(1);
+        for prefix in ["a", "b"] {
+            let mut flow = VariableLengthFlow::new();
+            for p in 0..3 {
+                flow.packet_arrive(Packet::new(format!("{}<{}>", prefix, p), 2), 0);
+            }
+            rr.add_flow(flow);
+        }
+        rr.run();
+        let trace = rr.trace();
+
+        // One box per packet, two ticks wide, alternating between the rows.
+        let svg = timeline_svg(&trace);
+        assert_eq!(svg.matches("<rect").count(), 6);
+        assert_eq!(svg.matches(r##"fill="#4e79a7""##).count(), 3);
+        let first = format!(
+            r#"<rect x="{}" y="2" width="{}""#,
+            LABEL_WIDTH,
+            2 * TICK_WIDTH
+        );
+        assert!(svg.contains(&first));
+        assert!(svg.contains("<title>b&lt;0&gt;</title>"));
+
+        let path = std::env::temp_dir().join("rnetv_timeline_test.svg");
+        render_timeline(&trace, &path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), svg);
+        fs::remove_file(path).unwrap();
+    }
+}
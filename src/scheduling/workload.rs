@@ -0,0 +1,253 @@
+//! Import and export of workloads, so that they can be shared and
+//! generated by external scripts.
+//!
+//! A workload is a list of packets, each given by
+//!
+//! - `flow`: the index of its flow, from 0,
+//! - `packet`: its name,
+//! - `len`: its length in bytes,
+//! - `arrival`: the tick it arrives at.
+//!
+//! As CSV, the packets follow a header line `flow,packet,len,arrival`,
+//! with names holding a comma, a quote or a line break quoted and their
+//! quotes doubled. As JSON, with the `serde` feature, the workload is an
+//! array of objects with these four fields. Packets can come in any order,
+//! and only the name and length of a packet are kept.
+
+use std::io;
+
+use crate::scheduling::{
+    flow::{Flow, VariableLengthFlow},
+    stats::trace::csv_field,
+    Packet,
+};
+
+const CSV_HEADER: &str = "flow,packet,len,arrival";
+
+/// A packet of a [`Workload`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WorkloadRecord {
+    pub flow: usize,
+    pub packet: String,
+    pub len: usize,
+    pub arrival: usize,
+}
+
+/// The packets of a set of flows, see the [module documentation](self)
+/// for the format.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Workload {
+    pub records: Vec<WorkloadRecord>,
+}
+
+impl Workload {
+    /// The packets of the flows, flow by flow.
+    pub fn from_flows(flows: &[VariableLengthFlow]) -> Workload {
+        let records = flows
+            .iter()
+            .enumerate()
+            .flat_map(|(flow, f)| {
+                f.packet_states
+                    .iter()
+                    .map(move |(packet, arrival)| WorkloadRecord {
+                        flow,
+                        packet: packet.name.clone(),
+                        len: packet.len,
+                        arrival: *arrival,
+                    })
+            })
+            .collect();
+        Workload { records }
+    }
+
+    /// One flow per flow index up to the highest one,
+    /// flows without packets being empty.
+    pub fn flows(&self) -> Vec<VariableLengthFlow> {
+        let count = self.records.iter().map(|r| r.flow + 1).max().unwrap_or(0);
+        let mut flows = vec![VariableLengthFlow::new(); count];
+        for r in &self.records {
+            flows[r.flow].packet_arrive(Packet::new(r.packet.clone(), r.len), r.arrival);
+        }
+        flows
+    }
+
+    pub fn write_csv(&self, mut writer: impl io::Write) -> io::Result<()> {
+        writeln!(writer, "{}", CSV_HEADER)?;
+        for r in &self.records {
+            writeln!(
+                writer,
+                "{},{},{},{}",
+                r.flow,
+                csv_field(&r.packet),
+                r.len,
+                r.arrival
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn read_csv(mut reader: impl io::Read) -> io::Result<Workload> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        let mut rows = csv_rows(&text)?.into_iter();
+        match rows.next() {
+            Some(header) if header.join(",") == CSV_HEADER => {}
+            _ => return Err(invalid(format!("expected the header {}", CSV_HEADER))),
+        }
+
+        let mut records = Vec::new();
+        for (line, row) in rows.enumerate() {
+            if row.len() == 1 && row[0].is_empty() {
+                continue;
+            }
+            let [flow, packet, len, arrival] = <[String; 4]>::try_from(row)
+                .map_err(|row| invalid(format!("row {}: {} fields", line + 1, row.len())))?;
+            let number = |field: &str| {
+                field
+                    .parse::<usize>()
+                    .map_err(|e| invalid(format!("row {}: {}: {}", line + 1, field, e)))
+            };
+            records.push(WorkloadRecord {
+                flow: number(&flow)?,
+                packet,
+                len: number(&len)?,
+                arrival: number(&arrival)?,
+            });
+        }
+        Ok(Workload { records })
+    }
+
+    /// Write the workload as a JSON array.
+    #[cfg(feature = "serde")]
+    pub fn write_json(&self, writer: impl io::Write) -> serde_json::Result<()> {
+        serde_json::to_writer(writer, self)
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn read_json(reader: impl io::Read) -> serde_json::Result<Workload> {
+        serde_json::from_reader(reader)
+    }
+}
+
+impl VariableLengthFlow {
+    /// Read the flows of a CSV workload, see [`Workload::read_csv`].
+    pub fn from_reader(reader: impl io::Read) -> io::Result<Vec<VariableLengthFlow>> {
+        Ok(Workload::read_csv(reader)?.flows())
+    }
+
+    /// Write flows as a CSV workload, see [`Workload::write_csv`].
+    pub fn to_writer(flows: &[VariableLengthFlow], writer: impl io::Write) -> io::Result<()> {
+        Workload::from_flows(flows).write_csv(writer)
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Split CSV text into rows of fields, undoing the quoting.
+fn csv_rows(text: &str) -> io::Result<Vec<Vec<String>>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(invalid("unterminated quoted field".to_string()));
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::scheduling::{
+        flow::{Flow, VariableLengthFlow},
+        Packet,
+    };
+
+    use super::{Workload, WorkloadRecord};
+
+    fn flows() -> Vec<VariableLengthFlow> {
+        let mut a = VariableLengthFlow::new();
+        a.packet_arrive(Packet::new("a0", 3), 0);
+        a.packet_arrive(Packet::new("a,\"1\"", 1), 4);
+        let mut c = VariableLengthFlow::new();
+        c.packet_arrive(Packet::new("c0", 2), 1);
+        vec![a, VariableLengthFlow::new(), c]
+    }
+
+    #[test]
+    fn workload_csv_test() {
+        let mut csv = Vec::new();
+        VariableLengthFlow::to_writer(&flows(), &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        // The format is shared with other tools and must not change.
+        assert_eq!(
+            csv,
+            "flow,packet,len,arrival\n0,a0,3,0\n0,\"a,\"\"1\"\"\",1,4\n2,c0,2,1\n"
+        );
+
+        let read = VariableLengthFlow::from_reader(csv.as_bytes()).unwrap();
+        assert_eq!(read.len(), 3);
+        for (read, written) in read.iter().zip(flows()) {
+            assert_eq!(read.packet_states, written.packet_states);
+        }
+
+        // Packets can come in any order, with Windows line endings.
+        let shuffled = "flow,packet,len,arrival\r\n1,b1,1,5\r\n1,b0,1,2\r\n";
+        let workload = Workload::read_csv(shuffled.as_bytes()).unwrap();
+        assert_eq!(
+            workload.records[0],
+            WorkloadRecord {
+                flow: 1,
+                packet: "b1".to_string(),
+                len: 1,
+                arrival: 5,
+            }
+        );
+        let read = workload.flows();
+        assert!(read[0].empty());
+        assert_eq!(read[1].peek_packet(2), Some(Packet::new("b0", 1)));
+
+        assert!(Workload::read_csv("flow,len\n".as_bytes()).is_err());
+        assert!(Workload::read_csv("flow,packet,len,arrival\n0,p,x,0\n".as_bytes()).is_err());
+        assert!(Workload::read_csv("flow,packet,len,arrival\n0,p,1\n".as_bytes()).is_err());
+        assert!(Workload::read_csv("flow,packet,len,arrival\n0,\"p,1,0\n".as_bytes()).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn workload_json_test() {
+        let workload = Workload::from_flows(&flows());
+        let mut json = Vec::new();
+        workload.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.starts_with(r#"[{"flow":0,"packet":"a0","len":3,"arrival":0},"#));
+        assert_eq!(Workload::read_json(json.as_bytes()).unwrap(), workload);
+    }
+}
@@ -0,0 +1,316 @@
+//! Interactive terminal view of a running simulation.
+//!
+//! The simulation is stepped tick by tick or runs freely, showing the
+//! queue of every flow, the per-flow state of the discipline, such as
+//! DRR deficit counters, and the output port.
+
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use tui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout},
+    style::{Modifier, Style},
+    text::Spans,
+    widgets::{Block, Borders, Paragraph, Row, Sparkline, Table},
+    Frame, Terminal,
+};
+
+use rnetv::scheduling::{
+    flow::{Flow, VariableLengthFlow},
+    schedulers::{
+        cbs::CBSScheduler, drr::DRRScheduler, dwrr::DWRRScheduler, fifo::FIFOScheduler,
+        rr::RRScheduler, scfq::SCFQScheduler, sfq::SFQScheduler, vc::VirtualClockScheduler,
+        wf2q::WF2QPlusScheduler, wfq::WFQScheduler,
+    },
+    FlowId, Packet, Scheduler,
+};
+
+/// The schedulers [`demo`] knows, by name.
+pub const DEMO_SCHEDULERS: [&str; 10] = [
+    "drr", "dwrr", "wfq", "wf2q", "sfq", "scfq", "vc", "cbs", "rr", "fifo",
+];
+
+/// Default time between two ticks while running freely.
+const DEFAULT_DELAY: Duration = Duration::from_millis(250);
+
+/// Number of departed packets listed under the output port.
+const RECENT_DEPARTURES: usize = 8;
+
+/// A simulation shown in the terminal.
+pub struct App {
+    name: String,
+    scheduler: Box<dyn Scheduler>,
+    weights: Vec<f64>,
+    running: bool,
+    finished: bool,
+    delay: Duration,
+}
+
+impl App {
+    /// Show a scheduler whose flows were added with the given weights.
+    pub fn new(
+        name: impl Into<String>,
+        mut scheduler: Box<dyn Scheduler>,
+        weights: Vec<f64>,
+    ) -> App {
+        scheduler.set_queue_sampling(Some(1));
+        App {
+            name: name.into(),
+            scheduler,
+            weights,
+            running: false,
+            finished: false,
+            delay: DEFAULT_DELAY,
+        }
+    }
+
+    /// Advance by one tick. Once the flows are drained,
+    /// the rest of the output port is sent at once.
+    pub fn step(&mut self) {
+        if self.finished {
+            return;
+        }
+        if !self.scheduler.step() {
+            self.scheduler.run();
+            self.finished = true;
+            self.running = false;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.scheduler.reset();
+        self.running = false;
+        self.finished = false;
+    }
+
+    /// React to a key. Returns false when the user quits.
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char(' ') => self.running = !self.running && !self.finished,
+            KeyCode::Char('n') | KeyCode::Right => {
+                self.running = false;
+                self.step();
+            }
+            KeyCode::Char('r') => self.reset(),
+            KeyCode::Char('+') => self.delay = (self.delay / 2).max(Duration::from_millis(10)),
+            KeyCode::Char('-') => self.delay = (self.delay * 2).min(Duration::from_secs(2)),
+            _ => {}
+        }
+        true
+    }
+
+    fn draw<B: Backend>(&self, f: &mut Frame<B>) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Min(5),
+                Constraint::Length(RECENT_DEPARTURES as u16 + 5),
+            ])
+            .split(f.size());
+
+        let mode = if self.finished {
+            "finished"
+        } else if self.running {
+            "running"
+        } else {
+            "paused"
+        };
+        let header = Paragraph::new(format!(
+            "{} | tick {} | {} | space: run/pause  n: step  r: reset  +/-: speed  q: quit",
+            self.name,
+            self.scheduler.timer(),
+            mode
+        ))
+        .block(Block::default().title("rnetv").borders(Borders::ALL));
+        f.render_widget(header, chunks[0]);
+
+        let state = self.scheduler.flow_state();
+        let series = self.scheduler.queue_series();
+        let stats = self.scheduler.stats();
+        let rows = (0..self.weights.len()).map(|idx| {
+            let queue = series.flow(FlowId(idx)).last().copied().unwrap_or(0);
+            let value = state
+                .as_ref()
+                .map_or(String::new(), |(_, values)| format!("{:.2}", values[idx]));
+            Row::new(vec![
+                idx.to_string(),
+                self.weights[idx].to_string(),
+                format!("{:>3} {}", queue, "█".repeat(queue)),
+                value,
+                stats[idx].packets.to_string(),
+                stats[idx].dropped.to_string(),
+            ])
+        });
+        let state_name = state.as_ref().map_or("", |(name, _)| name);
+        let table = Table::new(rows)
+            .header(
+                Row::new(vec![
+                    "flow", "weight", "queue", state_name, "sent", "dropped",
+                ])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+            )
+            .block(Block::default().title("flows").borders(Borders::ALL))
+            .widths(&[
+                Constraint::Length(5),
+                Constraint::Length(7),
+                Constraint::Min(20),
+                Constraint::Length(10),
+                Constraint::Length(6),
+                Constraint::Length(8),
+            ]);
+        f.render_widget(table, chunks[1]);
+
+        let port = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(chunks[2]);
+        let history: Vec<u64> = series.port().iter().map(|&len| len as u64).collect();
+        let queue = series.port().last().copied().unwrap_or(0);
+        let sparkline = Sparkline::default()
+            .block(
+                Block::default()
+                    .title(format!("output port queue: {}", queue))
+                    .borders(Borders::ALL),
+            )
+            .data(&history[history.len().saturating_sub(port[0].width as usize)..]);
+        f.render_widget(sparkline, port[0]);
+
+        let output = self.scheduler.output();
+        let recent: Vec<Spans> = output[output.len().saturating_sub(RECENT_DEPARTURES)..]
+            .iter()
+            .rev()
+            .map(|p| Spans::from(format!("{} ({} bytes)", p.name, p.len)))
+            .collect();
+        let departures = Paragraph::new(recent).block(
+            Block::default()
+                .title(format!("departed: {}", output.len()))
+                .borders(Borders::ALL),
+        );
+        f.render_widget(departures, port[1]);
+    }
+}
+
+/// Build one of the [`DEMO_SCHEDULERS`] on a small workload: three flows
+/// of packets of 3, 2 and 1 bytes with weights 1, 2 and 1, sent in bursts
+/// on a link of 1 byte per tick.
+pub fn demo(name: &str) -> Option<App> {
+    let mut scheduler: Box<dyn Scheduler> = match name {
+        "drr" => Box::new(DRRScheduler::new(1)),
+        "dwrr" => Box::new(DWRRScheduler::new(1)),
+        "wfq" => Box::new(WFQScheduler::new(1)),
+        "wf2q" => Box::new(WF2QPlusScheduler::new(1)),
+        "sfq" => Box::new(SFQScheduler::new(1)),
+        "scfq" => Box::new(SCFQScheduler::new(1)),
+        "vc" => Box::new(VirtualClockScheduler::new(1)),
+        "cbs" => Box::new(CBSScheduler::new(1)),
+        "rr" => Box::new(RRScheduler::new(1)),
+        "fifo" => Box::new(FIFOScheduler::new(1)),
+        _ => return None,
+    };
+    let weights = vec![1f64, 2f64, 1f64];
+    for (idx, (len, &weight)) in [3, 2, 1].into_iter().zip(&weights).enumerate() {
+        let mut flow = VariableLengthFlow::new();
+        for burst in 0..4 {
+            for p in 0..4 {
+                let name = format!("f{}_{}", idx, burst * 4 + p);
+                flow.packet_arrive(Packet::new(name, len), burst * 20 + idx);
+            }
+        }
+        scheduler.add_flow(Box::new(flow), weight);
+    }
+    Some(App::new(name, scheduler, weights))
+}
+
+/// Take over the terminal and show the simulation until the user quits.
+pub fn run(mut app: App) -> io::Result<()> {
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+    let result = event_loop(&mut terminal, &mut app);
+
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
+    terminal.show_cursor()?;
+    result
+}
+
+fn event_loop<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<()> {
+    let mut last_step = Instant::now();
+    loop {
+        terminal.draw(|f| app.draw(f))?;
+        let timeout = if app.running {
+            app.delay.saturating_sub(last_step.elapsed())
+        } else {
+            Duration::from_millis(250)
+        };
+        if event::poll(timeout)? {
+            if let Event::Key(key) = event::read()? {
+                if !app.handle_key(key.code) {
+                    return Ok(());
+                }
+            }
+        }
+        if app.running && last_step.elapsed() >= app.delay {
+            app.step();
+            last_step = Instant::now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crossterm::event::KeyCode;
+    use tui::{backend::TestBackend, Terminal};
+
+    use super::{demo, DEMO_SCHEDULERS};
+
+    #[test]
+    fn view_test() {
+        let mut app = demo("drr").unwrap();
+        for _ in 0..5 {
+            app.handle_key(KeyCode::Char('n'));
+        }
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|f| app.draw(f)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol.as_str())
+            .collect();
+        assert!(screen.contains("tick 5"));
+        assert!(screen.contains("deficit"));
+        assert!(screen.contains("f1_0 (2 bytes)"));
+
+        // Running to the end and resetting starts over.
+        while !app.finished {
+            app.step();
+        }
+        assert_eq!(app.scheduler.output().len(), 48);
+        assert!(app.handle_key(KeyCode::Char('r')));
+        assert_eq!(app.scheduler.timer(), 0);
+        assert!(!app.handle_key(KeyCode::Char('q')));
+
+        for name in DEMO_SCHEDULERS {
+            assert!(demo(name).is_some());
+        }
+    }
+}